@@ -0,0 +1,36 @@
+// Measures throughput and round-trip latency of a port with a loopback plug
+// (TX connected to RX) or an attached echo device.
+
+extern crate serial;
+
+use std::env;
+use std::io;
+use std::time::Duration;
+use serial::SerialPort;
+
+fn main() -> io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	if args.len() != 2 {
+		#[cfg(unix)]
+		println!("Usage: {} /dev/ttyN", args[0]);
+		#[cfg(windows)]
+		println!("Usage: {} COMn", args[0]);
+		return Ok(());
+	}
+
+	let port = SerialPort::open(&args[1], Some(Duration::from_millis(1000)))?;
+
+	let throughput = serial::bench::throughput(&port, 64 * 1024, 1024)?;
+	println!("throughput: {:.0} B/s ({} bytes in {:?}, {} mismatches)",
+		throughput.bytes_per_second(), throughput.bytes, throughput.duration,
+		throughput.mismatches);
+
+	for &payload_len in &[1, 16, 256] {
+		let latency = serial::bench::latency(&port, payload_len, 200)?;
+		println!("latency {:>3} B: min {:?}  p50 {:?}  p90 {:?}  p99 {:?}  max {:?}",
+			payload_len, latency.min(), latency.percentile(50.0),
+			latency.percentile(90.0), latency.percentile(99.0), latency.max());
+	}
+
+	Ok(())
+}
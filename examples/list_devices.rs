@@ -4,6 +4,6 @@ use std::io;
 use serial::SerialPort;
 
 fn main() -> io::Result<()> {
-	println!("Available DEVICEs: {:?}", SerialPort::list_devices());
+	println!("Available DEVICEs: {:?}", SerialPort::list_devices()?);
 	Ok(())
 }
@@ -0,0 +1,271 @@
+// Minimal terminal program that connects the console to a serial port. Keys
+// are sent to the port as they are typed and received data is printed as is
+// (or as hex). Press the escape key (Ctrl+] by default) followed by another
+// key for commands, e.g., Ctrl+] q to quit.
+
+extern crate serial;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(windows)]
+extern crate windows_sys;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use serial::SerialPort;
+
+const HELP: &str = "\
+Commands (press escape key first):
+  q  quit
+  r  toggle RTS
+  d  toggle DTR
+  b  send break (250 ms)
+  h  toggle hex mode
+  e  toggle local echo
+  ?  show this help
+  escape key again sends it to the port";
+
+struct Options {
+	path: String,
+	baud_rate: Option<u32>,
+	escape: u8,
+	hex: bool,
+	echo: bool
+}
+
+fn usage(program: &str) {
+	#[cfg(unix)]
+	println!("Usage: {} /dev/ttyN [--baud RATE] [--escape CHAR] [--hex] [--echo]", program);
+	#[cfg(windows)]
+	println!("Usage: {} COMn [--baud RATE] [--escape CHAR] [--hex] [--echo]", program);
+	println!("CHAR is a control character given by its letter, e.g., \"]\" for Ctrl+]");
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+	let mut options = Options {
+		path: args.get(1)?.clone(),
+		baud_rate: None,
+		escape: 0x1d,
+		hex: false,
+		echo: false
+	};
+
+	let mut iter = args[2..].iter();
+	while let Some(arg) = iter.next() {
+		match arg.as_str() {
+			"--baud" => options.baud_rate = Some(iter.next()?.parse().ok()?),
+			"--escape" => match iter.next()?.as_bytes() {
+				// map e.g. "]" to Ctrl+] (0x1d) and "a" to Ctrl+A (0x01)
+				&[c] if (0x40..0x80).contains(&c.to_ascii_uppercase()) =>
+					options.escape = c.to_ascii_uppercase() & 0x1f,
+				_ => return None
+			},
+			"--hex" => options.hex = true,
+			"--echo" => options.echo = true,
+			_ => return None
+		}
+	}
+
+	Some(options)
+}
+
+// print data as is or as hex, starting a new line after each line feed
+fn print(stdout: &mut impl Write, data: &[u8], hex: bool) -> io::Result<()> {
+	if hex {
+		for byte in data {
+			write!(stdout, "{:02x} ", byte)?;
+			if *byte == b'\n' {
+				stdout.write_all(b"\n")?;
+			}
+		}
+	} else {
+		stdout.write_all(data)?;
+	}
+	stdout.flush()
+}
+
+fn main() -> io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	let options = match parse_args(&args) {
+		Some(options) => options,
+		None => {
+			usage(&args[0]);
+			return Ok(());
+		}
+	};
+
+	let port = SerialPort::open(&options.path, Some(Duration::from_millis(100)))?;
+	if let Some(baud_rate) = options.baud_rate {
+		port.set_baud_rate(baud_rate)?;
+	}
+	// opening a port usually asserts both lines
+	let mut rts = true;
+	let mut dtr = true;
+
+	eprintln!("--- Connected to {}, press Ctrl+{} ? for help ---",
+		options.path, (options.escape | 0x40) as char);
+	let _raw_mode = RawMode::enable()?;
+
+	// print received data until quit. the read timeout allows checking the
+	// quit flag periodically.
+	let quit = Arc::new(AtomicBool::new(false));
+	let hex = Arc::new(AtomicBool::new(options.hex));
+	let reader = {
+		let mut port = port.try_clone()?;
+		let quit = quit.clone();
+		let hex = hex.clone();
+		thread::spawn(move || -> io::Result<()> {
+			let mut buf = [0u8; 1024];
+			let mut stdout = io::stdout();
+			while !quit.load(Ordering::Relaxed) {
+				match port.read(&mut buf) {
+					Ok(len) => print(&mut stdout, &buf[..len], hex.load(Ordering::Relaxed))?,
+					Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+					Err(e) => {
+						quit.store(true, Ordering::Relaxed);
+						return Err(e);
+					}
+				}
+			}
+			Ok(())
+		})
+	};
+
+	let mut port = &port;
+	let mut stdin = io::stdin();
+	let mut stdout = io::stdout();
+	let mut echo = options.echo;
+	let mut escaped = false;
+	let mut key = [0u8; 1];
+	while !quit.load(Ordering::Relaxed) {
+		if stdin.read(&mut key)? == 0 {
+			break;
+		}
+
+		if !escaped && key[0] == options.escape {
+			escaped = true;
+			continue;
+		}
+		let send_key = !escaped || key[0] == options.escape;
+		escaped = false;
+
+		if send_key {
+			port.write_all(&key)?;
+			if echo {
+				print(&mut stdout, &key, hex.load(Ordering::Relaxed))?;
+			}
+			continue;
+		}
+
+		let status = match key[0] {
+			b'q' | b'Q' => break,
+			// control lines are not supported by all ports (e.g., PTYs), so
+			// errors are reported without disconnecting
+			b'r' | b'R' => port.set_rts(!rts).map(|()| {
+				rts = !rts;
+				format!("RTS {}", if rts { "on" } else { "off" })
+			}).unwrap_or_else(|e| format!("setting RTS failed: {}", e)),
+			b'd' | b'D' => port.set_dtr(!dtr).map(|()| {
+				dtr = !dtr;
+				format!("DTR {}", if dtr { "on" } else { "off" })
+			}).unwrap_or_else(|e| format!("setting DTR failed: {}", e)),
+			b'b' | b'B' => port.set_break(true).and_then(|()| {
+				thread::sleep(Duration::from_millis(250));
+				port.set_break(false)
+			}).map(|()| "break sent".to_string())
+				.unwrap_or_else(|e| format!("sending break failed: {}", e)),
+			b'h' | b'H' => {
+				let enabled = !hex.load(Ordering::Relaxed);
+				hex.store(enabled, Ordering::Relaxed);
+				format!("hex mode {}", if enabled { "on" } else { "off" })
+			},
+			b'e' | b'E' => {
+				echo = !echo;
+				format!("local echo {}", if echo { "on" } else { "off" })
+			},
+			_ => {
+				eprintln!("\n{}", HELP);
+				continue;
+			}
+		};
+		eprintln!("\n--- {} ---", status);
+	}
+
+	quit.store(true, Ordering::Relaxed);
+	let res = reader.join().unwrap_or_else(|_| Err(io::Error::new(
+		io::ErrorKind::Other, "reader thread panicked")));
+	eprintln!("\n--- Disconnected ---");
+	res
+}
+
+// puts the console into raw mode (no line buffering, no echo, no signal
+// keys) and restores the previous mode when dropped
+#[cfg(unix)]
+struct RawMode(libc::termios);
+
+#[cfg(unix)]
+impl RawMode {
+	fn enable() -> io::Result<Self> {
+		let mut termios = unsafe { std::mem::zeroed() };
+		if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		let mut raw = termios;
+		unsafe { libc::cfmakeraw(&mut raw) };
+		// keep output processing, so "\n" still returns the carriage like
+		// on Windows
+		raw.c_oflag |= libc::OPOST;
+		if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		Ok(Self(termios))
+	}
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+	fn drop(&mut self) {
+		unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+	}
+}
+
+#[cfg(windows)]
+struct RawMode(windows_sys::Win32::Foundation::HANDLE, u32);
+
+#[cfg(windows)]
+impl RawMode {
+	fn enable() -> io::Result<Self> {
+		use windows_sys::Win32::System::Console::{
+			GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_ECHO_INPUT,
+			ENABLE_LINE_INPUT, ENABLE_PROCESSED_INPUT,
+			ENABLE_VIRTUAL_TERMINAL_INPUT, STD_INPUT_HANDLE
+		};
+
+		let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+		let mut mode = 0;
+		if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		// pass keys like Ctrl+C and cursor keys (as escape sequences) through
+		let raw = (mode & !(ENABLE_ECHO_INPUT | ENABLE_LINE_INPUT | ENABLE_PROCESSED_INPUT))
+			| ENABLE_VIRTUAL_TERMINAL_INPUT;
+		if unsafe { SetConsoleMode(handle, raw) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		Ok(Self(handle, mode))
+	}
+}
+
+#[cfg(windows)]
+impl Drop for RawMode {
+	fn drop(&mut self) {
+		unsafe { windows_sys::Win32::System::Console::SetConsoleMode(self.0, self.1) };
+	}
+}
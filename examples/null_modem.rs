@@ -0,0 +1,43 @@
+// Cross-connects two serial ports like a null-modem cable. On Unix, the second
+// port may be "pty", which creates a pseudoterminal and forwards to its slave
+// device instead, e.g., to test serial software without a second adapter.
+
+extern crate serial;
+
+use std::env;
+use std::io::{self, BufRead};
+use std::time::Duration;
+use serial::SerialPort;
+
+fn main() -> io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	if args.len() < 3 || args.len() > 4 {
+		#[cfg(unix)]
+		println!("Usage: {} /dev/ttyN {{/dev/ttyM|pty}} [LATENCY_MS]", args[0]);
+		#[cfg(windows)]
+		println!("Usage: {} COMn COMm [LATENCY_MS]", args[0]);
+		return Ok(());
+	}
+
+	let timeout = Some(Duration::from_millis(100));
+	let latency = args.get(3)
+		.map(|ms| ms.parse().expect("invalid latency"))
+		.map(Duration::from_millis);
+
+	let port_a = SerialPort::open(&args[1], timeout)?;
+	let port_b = match args[2].as_str() {
+		#[cfg(unix)]
+		"pty" => {
+			let (pty, path) = SerialPort::open_pty(timeout)?;
+			println!("Pseudoterminal slave device: {:?}", path);
+			pty
+		},
+		path => SerialPort::open(path, timeout)?
+	};
+
+	let bridge = serial::bridge::bridge(&port_a, &port_b, latency)?;
+	println!("Forwarding, press Enter to stop...");
+	io::stdin().lock().read_line(&mut String::new())?;
+
+	bridge.stop()
+}
@@ -0,0 +1,19 @@
+// Runs the regression checks of the testing module against a virtual port
+// pair, i.e., a pseudoterminal pair on Unix or a com0com pair on Windows (or
+// a pair connected in memory, if com0com is not installed).
+
+extern crate serial;
+
+use std::io;
+
+fn main() -> io::Result<()> {
+	#[cfg(windows)]
+	match serial::testing::com0com_pairs()?.first() {
+		Some((a, b)) => println!("Using com0com pair {:?} and {:?}", a, b),
+		None => println!("com0com is not installed, using ports connected in memory")
+	}
+
+	serial::testing::run_virtual_checks()?;
+	println!("All checks passed");
+	Ok(())
+}
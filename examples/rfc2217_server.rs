@@ -0,0 +1,27 @@
+// Shares a serial port over TCP with RFC 2217 control, e.g., for use with
+// pyserial's serial_for_url("rfc2217://HOST:PORT"). There is no
+// authentication, so bind to 127.0.0.1 unless remote access is intended.
+
+extern crate serial;
+
+use std::env;
+use std::io;
+use std::time::Duration;
+use serial::SerialPort;
+use serial::rfc2217::Rfc2217Server;
+
+fn main() -> io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	if args.len() != 3 {
+		#[cfg(unix)]
+		println!("Usage: {} /dev/ttyN BIND_ADDR:PORT", args[0]);
+		#[cfg(windows)]
+		println!("Usage: {} COMn BIND_ADDR:PORT", args[0]);
+		return Ok(());
+	}
+
+	let port = SerialPort::open(&args[1], Some(Duration::from_millis(100)))?;
+	let server = Rfc2217Server::bind(&args[2], port)?;
+	println!("Listening on {}", server.local_addr()?);
+	server.serve()
+}
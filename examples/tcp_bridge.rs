@@ -0,0 +1,41 @@
+// Pipes a serial port to a TCP socket without any protocol, like
+// "socat TCP:HOST:PORT /dev/ttyN". With "listen", it waits for a client to
+// connect to the given address instead, e.g., to share a serial console.
+
+extern crate serial;
+
+use std::env;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use serial::SerialPort;
+
+fn main() -> io::Result<()> {
+	let args: Vec<String> = env::args().collect();
+	if args.len() < 3 || args.len() > 4 || (args.len() == 4 && args[3] != "listen") {
+		#[cfg(unix)]
+		println!("Usage: {} /dev/ttyN HOST:PORT [listen]", args[0]);
+		#[cfg(windows)]
+		println!("Usage: {} COMn HOST:PORT [listen]", args[0]);
+		return Ok(());
+	}
+
+	let port = SerialPort::open(&args[1], Some(Duration::from_millis(100)))?;
+	let stream = if args.len() == 4 {
+		let listener = TcpListener::bind(&args[2])?;
+		println!("Waiting for connection on {}...", listener.local_addr()?);
+		let (stream, addr) = listener.accept()?;
+		println!("Connection from {}", addr);
+		stream
+	} else {
+		TcpStream::connect(&args[2])?
+	};
+
+	// forward until either side closes the connection or fails
+	let bridge = serial::bridge::tcp_bridge(&port, stream)?;
+	while !bridge.is_finished() {
+		thread::sleep(Duration::from_millis(100));
+	}
+	bridge.stop()
+}
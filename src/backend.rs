@@ -0,0 +1,453 @@
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::{sys, DataBits, FlowControl, LineMode, Parity, StopBits, TerminalOptions};
+
+// interface of alternative (i.e., non-OS) serial port implementations that
+// SerialPort::open() selects via a "scheme://" prefix of the device path.
+// optional functionality defaults to returning an Unsupported error.
+// timeouts are set via &self, so they can be changed while other threads
+// are blocked in read() or write() (see AtomicTimeout).
+pub trait Backend: Send + Sync {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize>;
+	fn write(&self, buf: &[u8]) -> io::Result<usize>;
+	// wait until all written data was transmitted (in contrast to purge(),
+	// which discards it)
+	fn flush(&self) -> io::Result<()>;
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>>;
+
+	// like read() and write(), but with the given timeout instead of the
+	// instance's (None blocks indefinitely). the timeout applies to this call
+	// only, so helpers enforcing an overall deadline (e.g.,
+	// SerialPort::read_exact_timeout()) do not affect clones sharing the
+	// timeouts. those helpers fail with Unsupported unless implemented.
+	fn read_with_timeout(&self, _buf: &mut [u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		Err(unsupported("reading with a per-call timeout"))
+	}
+
+	fn write_with_timeout(&self, _buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		Err(unsupported("writing with a per-call timeout"))
+	}
+
+	// like try_clone(), but timeouts changed on either instance apply to
+	// both instead of being copied once
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		Err(unsupported("sharing timeouts between clones"))
+	}
+
+	fn read_timeout(&self) -> Option<Duration>;
+	fn write_timeout(&self) -> Option<Duration>;
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+	fn set_rts(&self, _level: bool) -> io::Result<()> {
+		Err(unsupported("setting RTS"))
+	}
+
+	fn set_dtr(&self, _level: bool) -> io::Result<()> {
+		Err(unsupported("setting DTR"))
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		Err(unsupported("reading CTS"))
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		Err(unsupported("reading DSR"))
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		Err(unsupported("reading RI"))
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		Err(unsupported("reading CD"))
+	}
+
+	// block until any of CTS, DSR, RI, or CD changes or the timeout (None
+	// blocks indefinitely) expires. callers may fall back to polling the
+	// inputs if this is unsupported.
+	fn wait_modem_change(&self, _timeout: Option<Duration>) -> io::Result<()> {
+		Err(unsupported("waiting for modem status changes"))
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		Err(unsupported("reading baud rate"))
+	}
+
+	fn set_baud_rate(&self, _baud_rate: u32) -> io::Result<()> {
+		Err(unsupported("setting baud rate"))
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		Err(unsupported("reading data bits"))
+	}
+
+	fn set_data_bits(&self, _data_bits: DataBits) -> io::Result<()> {
+		Err(unsupported("setting data bits"))
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		Err(unsupported("reading parity"))
+	}
+
+	fn set_parity(&self, _parity: Parity) -> io::Result<()> {
+		Err(unsupported("setting parity"))
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		Err(unsupported("reading stop bits"))
+	}
+
+	fn set_stop_bits(&self, _stop_bits: StopBits) -> io::Result<()> {
+		Err(unsupported("setting stop bits"))
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		Err(unsupported("reading flow control"))
+	}
+
+	fn set_flow_control(&self, _flow_control: FlowControl) -> io::Result<()> {
+		Err(unsupported("setting flow control"))
+	}
+
+	// whether the modem control lines are ignored (see SerialPort::set_local())
+	fn local(&self) -> io::Result<bool> {
+		Err(unsupported("reading modem control mode"))
+	}
+
+	fn set_local(&self, _local: bool) -> io::Result<()> {
+		Err(unsupported("setting modem control mode"))
+	}
+
+	// whether bytes with parity errors are marked (see SerialPort::set_parity_marking())
+	fn parity_marking(&self) -> io::Result<bool> {
+		Err(unsupported("reading parity marking"))
+	}
+
+	fn set_parity_marking(&self, _mark: bool) -> io::Result<()> {
+		Err(unsupported("setting parity marking"))
+	}
+
+	fn set_canonical(&self, _mode: Option<LineMode>) -> io::Result<()> {
+		Err(unsupported("canonical mode"))
+	}
+
+	fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		Err(unsupported("reading terminal options"))
+	}
+
+	fn set_terminal_options(&self, _options: TerminalOptions) -> io::Result<()> {
+		Err(unsupported("setting terminal options"))
+	}
+
+	fn set_break(&self, _level: bool) -> io::Result<()> {
+		Err(unsupported("setting break"))
+	}
+
+	fn set_transmit_paused(&self, _paused: bool) -> io::Result<()> {
+		Err(unsupported("pausing transmission"))
+	}
+
+	// transmit byte ahead of queued output, if the backend supports it.
+	// otherwise, it is written like any other data.
+	fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		match self.write(&[byte])? {
+			0 => Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write byte")),
+			_ => Ok(())
+		}
+	}
+
+	// number of bytes received but not read, and written but not transmitted
+	fn bytes_to_read(&self) -> io::Result<usize> {
+		Err(unsupported("querying buffers"))
+	}
+
+	fn bytes_to_write(&self) -> io::Result<usize> {
+		Err(unsupported("querying buffers"))
+	}
+
+	fn purge(&self, _input: bool, _output: bool) -> io::Result<()> {
+		Err(unsupported("purging buffers"))
+	}
+
+	// acquire or release exclusive access (see SerialPort::lock())
+	fn lock(&self) -> io::Result<()> {
+		Err(unsupported("locking"))
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		Err(unsupported("unlocking"))
+	}
+}
+
+pub(crate) fn unsupported(what: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::Unsupported,
+		format!("{} is not supported by this backend", what))
+}
+
+// timeout storage that can be changed through a shared reference. durations
+// are stored as nanoseconds (clipped to about 584 years) and u64::MAX
+// represents None, i.e., no timeout.
+pub struct AtomicTimeout(Arc<AtomicU64>);
+
+impl AtomicTimeout {
+	pub fn new(timeout: Option<Duration>) -> Self {
+		Self(Arc::new(AtomicU64::new(Self::encode(timeout))))
+	}
+
+	// independent timeout with the same value, e.g., for regular clones
+	pub fn copy(&self) -> Self {
+		Self::new(self.load())
+	}
+
+	// timeout sharing its value with this one, i.e., storing to either of
+	// them changes both (see Backend::try_clone_shared())
+	pub fn share(&self) -> Self {
+		Self(self.0.clone())
+	}
+
+	fn encode(timeout: Option<Duration>) -> u64 {
+		match timeout {
+			None => u64::MAX,
+			Some(timeout) => timeout.as_nanos().min(u64::MAX as u128 - 1) as u64
+		}
+	}
+
+	pub fn load(&self) -> Option<Duration> {
+		match self.0.load(Ordering::Relaxed) {
+			u64::MAX => None,
+			nanos => Some(Duration::from_nanos(nanos))
+		}
+	}
+
+	pub fn store(&self, timeout: Option<Duration>) {
+		self.0.store(Self::encode(timeout), Ordering::Relaxed);
+	}
+}
+
+// opens a backend given the device path without "scheme://" prefix
+pub type OpenFn = fn(&str, Option<Duration>) -> io::Result<Box<dyn Backend>>;
+
+static REGISTRY: RwLock<Vec<(String, OpenFn)>> = RwLock::new(Vec::new());
+
+// register a backend for the given scheme, replacing any backend previously
+// registered for the same scheme (including built-in ones)
+pub fn register(scheme: &str, open: OpenFn) {
+	let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+	registry.retain(|(s, _)| s != scheme);
+	registry.push((scheme.to_string(), open));
+}
+
+// split "scheme://path" into scheme and path. schemes must start with a letter
+// followed by letters, digits, '+', '-', or '.' (see RFC 3986), which rules
+// out confusion with Unix or Windows device paths.
+pub(crate) fn split_scheme(dev_path: &str) -> Option<(&str, &str)> {
+	let (scheme, path) = dev_path.split_once("://")?;
+	let mut chars = scheme.chars();
+	if !chars.next()?.is_ascii_alphabetic() {
+		return None;
+	}
+	if !chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.') {
+		return None;
+	}
+	Some((scheme, path))
+}
+
+pub(crate) fn open(scheme: &str, path: &str, timeout: Option<Duration>)
+		-> io::Result<Box<dyn Backend>> {
+	// user-registered backends take precedence over built-in ones
+	let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+	if let Some((_, open)) = registry.iter().find(|(s, _)| s == scheme) {
+		return open(path, timeout);
+	}
+	drop(registry);
+
+	match scheme {
+		"replay" => crate::replay::open_backend(path, timeout),
+		#[cfg(feature = "mock")]
+		"mock" => crate::mock::open_backend(path, timeout),
+		#[cfg(feature = "ftdi")]
+		"ftdi" => crate::ftdi::open_backend(path, timeout),
+		#[cfg(feature = "usb")]
+		"usb" => crate::usb::open_backend(path, timeout),
+		_ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+			format!("no backend registered for scheme \"{}\"", scheme)))
+	}
+}
+
+impl Backend for sys::SerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		sys::SerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		sys::SerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		sys::SerialPort::read_with_timeout(self, buf, timeout)
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		sys::SerialPort::write_with_timeout(self, buf, timeout)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		sys::SerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		sys::SerialPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		sys::SerialPort::try_clone_shared(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		sys::SerialPort::read_timeout(self)
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		sys::SerialPort::write_timeout(self)
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		sys::SerialPort::set_read_timeout(self, timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		sys::SerialPort::set_write_timeout(self, timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		sys::SerialPort::set_rts(self, level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		sys::SerialPort::set_dtr(self, level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		sys::SerialPort::read_cts(self)
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		sys::SerialPort::read_dsr(self)
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		sys::SerialPort::read_ri(self)
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		sys::SerialPort::read_cd(self)
+	}
+
+	fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		sys::SerialPort::wait_modem_change(self, timeout)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		sys::SerialPort::baud_rate(self)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		sys::SerialPort::set_baud_rate(self, baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		sys::SerialPort::data_bits(self)
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		sys::SerialPort::set_data_bits(self, data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		sys::SerialPort::parity(self)
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		sys::SerialPort::set_parity(self, parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		sys::SerialPort::stop_bits(self)
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		sys::SerialPort::set_stop_bits(self, stop_bits)
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		sys::SerialPort::flow_control(self)
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		sys::SerialPort::set_flow_control(self, flow_control)
+	}
+
+	fn local(&self) -> io::Result<bool> {
+		sys::SerialPort::local(self)
+	}
+
+	fn set_local(&self, local: bool) -> io::Result<()> {
+		sys::SerialPort::set_local(self, local)
+	}
+
+	fn parity_marking(&self) -> io::Result<bool> {
+		sys::SerialPort::parity_marking(self)
+	}
+
+	fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		sys::SerialPort::set_parity_marking(self, mark)
+	}
+
+	fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		sys::SerialPort::set_canonical(self, mode)
+	}
+
+	fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		sys::SerialPort::terminal_options(self)
+	}
+
+	fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		sys::SerialPort::set_terminal_options(self, options)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		sys::SerialPort::set_break(self, level)
+	}
+
+	fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		sys::SerialPort::set_transmit_paused(self, paused)
+	}
+
+	fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		sys::SerialPort::transmit_immediate(self, byte)
+	}
+
+	fn bytes_to_read(&self) -> io::Result<usize> {
+		sys::SerialPort::bytes_to_read(self)
+	}
+
+	fn bytes_to_write(&self) -> io::Result<usize> {
+		sys::SerialPort::bytes_to_write(self)
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		sys::SerialPort::purge(self, input, output)
+	}
+
+	fn lock(&self) -> io::Result<()> {
+		sys::SerialPort::lock(self)
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		sys::SerialPort::unlock(self)
+	}
+}
@@ -0,0 +1,159 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::SerialPort;
+
+// measurements against an echo device or loopback plug, i.e., everything
+// written to the port must be received again unmodified
+
+#[derive(Clone, Copy, Debug)]
+pub struct Throughput {
+	pub bytes: usize,
+	pub duration: Duration,
+	// number of received bytes that differed from the transmitted ones
+	pub mismatches: usize
+}
+
+impl Throughput {
+	pub fn bytes_per_second(&self) -> f64 {
+		self.bytes as f64 / self.duration.as_secs_f64()
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Latency {
+	// round-trip times in ascending order
+	samples: Vec<Duration>
+}
+
+impl Latency {
+	pub fn samples(&self) -> &[Duration] {
+		&self.samples
+	}
+
+	pub fn min(&self) -> Duration {
+		self.samples[0]
+	}
+
+	pub fn max(&self) -> Duration {
+		self.samples[self.samples.len() - 1]
+	}
+
+	pub fn mean(&self) -> Duration {
+		let total: Duration = self.samples.iter().sum();
+		total / self.samples.len() as u32
+	}
+
+	// nearest-rank percentile, e.g., percentile(50.0) is the median
+	pub fn percentile(&self, p: f64) -> Duration {
+		let rank = (p.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil() as usize;
+		self.samples[rank.saturating_sub(1).min(self.samples.len() - 1)]
+	}
+}
+
+// deterministic test pattern that does not repeat with a period of 256,
+// so bytes slipping by a multiple of the buffer size are detected
+fn pattern(index: usize) -> u8 {
+	(index % 251) as u8
+}
+
+// write total bytes in chunks of chunk_size from a separate thread while
+// receiving the echo. the port's read timeout must exceed the time to echo
+// a single chunk, otherwise lost data causes the measurement to time out.
+pub fn throughput(port: &SerialPort, total: usize, chunk_size: usize) -> io::Result<Throughput> {
+	if chunk_size == 0 {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk size must not be zero"));
+	}
+
+	let mut writer = port.try_clone()?;
+	let start = Instant::now();
+	let thread = thread::spawn(move || -> io::Result<()> {
+		let mut chunk = Vec::with_capacity(chunk_size);
+		let mut offset = 0;
+		while offset < total {
+			let len = chunk_size.min(total - offset);
+			chunk.clear();
+			chunk.extend((offset..offset + len).map(pattern));
+			writer.write_all(&chunk)?;
+			offset += len;
+		}
+		Ok(())
+	});
+
+	let mut reader = port;
+	let mut buf = [0u8; 4096];
+	let mut received = 0;
+	let mut mismatches = 0;
+	let res = loop {
+		if received >= total {
+			break Ok(());
+		}
+
+		let max = buf.len().min(total - received);
+		match reader.read(&mut buf[..max]) {
+			Ok(0) => break Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+				"port was closed during throughput measurement")),
+			Ok(len) => {
+				mismatches += buf[..len].iter().enumerate()
+					.filter(|&(i, &byte)| byte != pattern(received + i))
+					.count();
+				received += len;
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => break Err(e)
+		}
+	};
+	let duration = start.elapsed();
+
+	// report write errors first, as they likely caused read errors
+	thread.join().unwrap_or_else(|_| Err(io::Error::new(
+		io::ErrorKind::Other, "throughput writer thread panicked")))?;
+	res?;
+
+	Ok(Throughput {
+		bytes: total,
+		duration,
+		mismatches
+	})
+}
+
+// measure the round-trip time of rounds payloads of payload_len bytes,
+// sending each payload only after the previous one was received
+pub fn latency(port: &SerialPort, payload_len: usize, rounds: usize) -> io::Result<Latency> {
+	if payload_len == 0 || rounds == 0 {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput,
+			"payload length and rounds must not be zero"));
+	}
+
+	// discard stale data that would be mistaken for an echo. backends
+	// without purge support are expected to start with an empty buffer.
+	match port.purge(true, false) {
+		Err(ref e) if e.kind() == io::ErrorKind::Unsupported => (),
+		res => res?
+	}
+
+	let mut port = port;
+	let mut payload = vec![0u8; payload_len];
+	let mut echo = vec![0u8; payload_len];
+	let mut samples = Vec::with_capacity(rounds);
+	for round in 0..rounds {
+		// vary payloads between rounds to detect late echoes
+		for (i, byte) in payload.iter_mut().enumerate() {
+			*byte = pattern(round + i);
+		}
+
+		let start = Instant::now();
+		port.write_all(&payload)?;
+		port.read_exact(&mut echo)?;
+		samples.push(start.elapsed());
+
+		if echo != payload {
+			return Err(io::Error::new(io::ErrorKind::InvalidData,
+				"echo does not match transmitted payload"));
+		}
+	}
+
+	samples.sort_unstable();
+	Ok(Latency { samples })
+}
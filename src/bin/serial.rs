@@ -0,0 +1,102 @@
+// Command line tool for quick diagnostics of serial ports:
+//   serial list                                  print available devices
+//   serial cat PORT [--baud RATE]                print received data
+//   serial send PORT [--baud RATE] [TEXT...]     send text (or stdin)
+
+extern crate serial;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::Duration;
+use serial::SerialPort;
+
+const USAGE: &str = "\
+Usage: serial list
+       serial cat PORT [--baud RATE]
+       serial send PORT [--baud RATE] [TEXT...]
+
+cat prints received data until the port is closed (or Ctrl+C is pressed).
+send transmits TEXT (separated by spaces) or, if omitted, all data read from
+standard input.";
+
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let res = match args.first().map(String::as_str) {
+		Some("list") if args.len() == 1 => list(),
+		Some("cat") if args.len() >= 2 => open(&args[1..])
+			.and_then(|(port, _)| cat(&port)),
+		Some("send") if args.len() >= 2 => open(&args[1..])
+			.and_then(|(port, text)| send(&port, &text)),
+		_ => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		}
+	};
+
+	if let Err(e) = res {
+		eprintln!("serial: {}", e);
+		process::exit(1);
+	}
+}
+
+// open the port given as first argument, apply the options, and return the
+// remaining arguments
+fn open(args: &[String]) -> io::Result<(SerialPort, Vec<String>)> {
+	let mut baud_rate = None;
+	let mut rest = Vec::new();
+	let mut iter = args[1..].iter();
+	while let Some(arg) = iter.next() {
+		match arg.as_str() {
+			"--baud" => baud_rate = Some(iter.next()
+				.and_then(|rate| rate.parse::<u32>().ok())
+				.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+					"--baud requires a numeric argument"))?),
+			_ => rest.push(arg.clone())
+		}
+	}
+
+	let port = SerialPort::open(&args[0], Some(Duration::from_millis(1000)))?;
+	if let Some(baud_rate) = baud_rate {
+		port.set_baud_rate(baud_rate)?;
+	}
+	Ok((port, rest))
+}
+
+fn list() -> io::Result<()> {
+	for device in SerialPort::devices()? {
+		println!("{}\t{:?}", device.path.to_string_lossy(), device.kind);
+	}
+	Ok(())
+}
+
+fn cat(mut port: &SerialPort) -> io::Result<()> {
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+	let mut buf = [0u8; 1024];
+	loop {
+		match port.read(&mut buf) {
+			Ok(0) => return Ok(()),
+			Ok(len) => {
+				stdout.write_all(&buf[..len])?;
+				stdout.flush()?;
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			// a disconnected device ends the output like end of file
+			Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+			Err(e) => return Err(e)
+		}
+	}
+}
+
+fn send(mut port: &SerialPort, text: &[String]) -> io::Result<()> {
+	if text.is_empty() {
+		let mut data = Vec::new();
+		io::stdin().read_to_end(&mut data)?;
+		port.write_all(&data)?;
+	} else {
+		port.write_all(text.join(" ").as_bytes())?;
+	}
+	Ok(())
+}
@@ -0,0 +1,360 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::SerialPort;
+
+// timeout of the endpoints' reads and writes, so stopping the bridge takes
+// effect within this interval
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// size of the buffer each direction reads into unless specified otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+// modifies the bytes forwarded in one direction in place, e.g., to convert
+// between protocols or to log them. clearing the buffer drops the bytes.
+pub type Transform = Box<dyn FnMut(&mut Vec<u8>) + Send>;
+
+type Endpoints = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+// something forward() can read from and write to concurrently
+pub trait Endpoint {
+	// separate reader and writer (e.g., clones) for both directions
+	fn split(&self) -> io::Result<Endpoints>;
+}
+
+// the clones poll, while the port's own timeouts remain unchanged
+impl Endpoint for SerialPort {
+	fn split(&self) -> io::Result<Endpoints> {
+		let reader = self.try_clone()?;
+		reader.set_read_timeout(Some(POLL_INTERVAL))?;
+		let writer = self.try_clone()?;
+		writer.set_write_timeout(Some(POLL_INTERVAL))?;
+		Ok((Box::new(reader), Box::new(writer)))
+	}
+}
+
+// the timeouts of the socket are overridden, since all its handles share
+// them, i.e., the stream passed in polls afterwards too
+impl Endpoint for TcpStream {
+	fn split(&self) -> io::Result<Endpoints> {
+		self.set_nodelay(true)?;
+		self.set_read_timeout(Some(POLL_INTERVAL))?;
+		self.set_write_timeout(Some(POLL_INTERVAL))?;
+		Ok((Box::new(self.try_clone()?), Box::new(self.try_clone()?)))
+	}
+}
+
+pub struct ForwardOptions {
+	// delay before forwarded bytes are written (in a separate thread per
+	// direction, so reading continues meanwhile). without latency, each
+	// direction writes the bytes directly from its read buffer.
+	pub latency: Option<Duration>,
+	pub buffer_size: usize,
+	pub a_to_b: Option<Transform>,
+	pub b_to_a: Option<Transform>
+}
+
+impl Default for ForwardOptions {
+	fn default() -> Self {
+		Self { latency: None, buffer_size: DEFAULT_BUFFER_SIZE, a_to_b: None, b_to_a: None }
+	}
+}
+
+// forwards bytes between two endpoints in both directions until stopped or
+// until an I/O error occurs in either direction. forwarding threads only
+// check for the stop request when a read times out, so readers and writers
+// passed to Bridge::new() should have a timeout to allow a timely shutdown
+// (those of forward() have).
+pub struct Bridge {
+	stop: Arc<AtomicBool>,
+	threads: Vec<JoinHandle<io::Result<()>>>
+}
+
+impl Bridge {
+	// spawn forwarding threads for a pair of endpoints, each given as a
+	// separate reader and writer (e.g., a port and its clone)
+	pub fn new<RA, WA, RB, WB>(a: (RA, WA), b: (RB, WB), latency: Option<Duration>)
+			-> Self
+			where RA: Read + Send + 'static, WA: Write + Send + 'static,
+			      RB: Read + Send + 'static, WB: Write + Send + 'static {
+		Self::with_buffer_size(a, b, latency, DEFAULT_BUFFER_SIZE)
+	}
+
+	// like new(), but each direction reads up to buffer_size bytes at once
+	pub fn with_buffer_size<RA, WA, RB, WB>(a: (RA, WA), b: (RB, WB), latency: Option<Duration>,
+			buffer_size: usize) -> Self
+			where RA: Read + Send + 'static, WA: Write + Send + 'static,
+			      RB: Read + Send + 'static, WB: Write + Send + 'static {
+		Self::with_options(a, b, ForwardOptions { latency, buffer_size, ..ForwardOptions::default() })
+	}
+
+	pub fn with_options<RA, WA, RB, WB>(a: (RA, WA), b: (RB, WB), options: ForwardOptions) -> Self
+			where RA: Read + Send + 'static, WA: Write + Send + 'static,
+			      RB: Read + Send + 'static, WB: Write + Send + 'static {
+		let stop = Arc::new(AtomicBool::new(false));
+		let mut threads = Vec::with_capacity(4);
+
+		let (a_reader, a_writer) = a;
+		let (b_reader, b_writer) = b;
+		let ForwardOptions { latency, buffer_size, a_to_b, b_to_a } = options;
+		spawn_direction(a_reader, b_writer, latency, buffer_size, a_to_b, &stop, &mut threads);
+		spawn_direction(b_reader, a_writer, latency, buffer_size, b_to_a, &stop, &mut threads);
+
+		Self { stop, threads }
+	}
+
+	// returns true if forwarding ceased in at least one direction
+	pub fn is_finished(&self) -> bool {
+		self.stop.load(Ordering::Relaxed)
+	}
+
+	// request all forwarding threads to stop, wait for them to terminate,
+	// and return the first error that occurred while forwarding (if any)
+	pub fn stop(mut self) -> io::Result<()> {
+		self.stop.store(true, Ordering::Relaxed);
+
+		let mut result = Ok(());
+		for t in self.threads.drain(..) {
+			let res = t.join().unwrap_or_else(|_| Err(io::Error::new(
+				io::ErrorKind::Other, "bridge thread panicked")));
+			if result.is_ok() {
+				result = res;
+			}
+		}
+		result
+	}
+}
+
+impl Drop for Bridge {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		for t in self.threads.drain(..) {
+			let _res = t.join();
+		}
+	}
+}
+
+// cross-connect two serial ports like a null-modem cable, i.e., everything
+// received on one port is transmitted on the other after an optional latency
+pub fn bridge(a: &SerialPort, b: &SerialPort, latency: Option<Duration>)
+		-> io::Result<Bridge> {
+	forward_with(a, b, ForwardOptions { latency, ..ForwardOptions::default() })
+}
+
+// connect a serial port to a TCP socket without any protocol (i.e., like
+// "socat TCP:host:port /dev/ttyN"), until either side closes or fails
+pub fn tcp_bridge(port: &SerialPort, stream: TcpStream) -> io::Result<Bridge> {
+	forward(port, &stream)
+}
+
+// forward bytes between two ports (or a port and a TCP stream) in both
+// directions until the returned bridge is stopped or either side fails
+pub fn forward<A, B>(a: &A, b: &B) -> io::Result<Bridge>
+		where A: Endpoint + ?Sized, B: Endpoint + ?Sized {
+	forward_with(a, b, ForwardOptions::default())
+}
+
+// like forward(), but e.g. transforming the bytes forwarded in either
+// direction for a protocol converter:
+//   let options = ForwardOptions {
+//       a_to_b: Some(Box::new(|data: &mut Vec<u8>| data.retain(|&byte| byte != b'\r'))),
+//       ..ForwardOptions::default()
+//   };
+//   let bridge = forward_with(&port, &stream, options)?;
+pub fn forward_with<A, B>(a: &A, b: &B, options: ForwardOptions) -> io::Result<Bridge>
+		where A: Endpoint + ?Sized, B: Endpoint + ?Sized {
+	Ok(Bridge::with_options(a.split()?, b.split()?, options))
+}
+
+// socket timeouts are reported as WouldBlock on Unix and TimedOut on Windows
+fn is_timeout(e: &io::Error) -> bool {
+	matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+// write data completely, retrying timeouts unless stopped
+fn write_forwarded<W: Write>(writer: &mut W, data: &[u8], stop: &AtomicBool) -> io::Result<()> {
+	let mut pos = 0;
+	while pos < data.len() {
+		match writer.write(&data[pos..]) {
+			Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero,
+				"failed to write forwarded data")),
+			Ok(len) => pos += len,
+			Err(ref e) if is_timeout(e) && !stop.load(Ordering::Relaxed) => (),
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		}
+	}
+	Ok(())
+}
+
+fn spawn_direction<R, W>(mut reader: R, mut writer: W, latency: Option<Duration>,
+		buffer_size: usize, mut transform: Option<Transform>, stop: &Arc<AtomicBool>,
+		threads: &mut Vec<JoinHandle<io::Result<()>>>)
+		where R: Read + Send + 'static, W: Write + Send + 'static {
+	let buffer_size = buffer_size.max(1);
+
+	// without latency, data is written from the read buffer directly
+	let latency = match latency {
+		Some(latency) => latency,
+		None => {
+			let stop = stop.clone();
+			threads.push(thread::spawn(move || {
+				let mut buf = vec![0u8; buffer_size];
+				let res = loop {
+					if stop.load(Ordering::Relaxed) {
+						break Ok(());
+					}
+
+					match reader.read(&mut buf) {
+						Ok(0) => break Ok(()),
+						Ok(len) => {
+							let res = match transform.as_mut() {
+								Some(transform) => {
+									buf.truncate(len);
+									transform(&mut buf);
+									let res = write_forwarded(&mut writer, &buf, &stop);
+									buf.resize(buffer_size, 0);
+									res
+								},
+								None => write_forwarded(&mut writer, &buf[..len], &stop)
+							};
+							if let Err(e) = res {
+								break Err(e);
+							}
+						},
+						Err(ref e) if is_timeout(e) => (),
+						Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+						Err(e) => break Err(e)
+					}
+				};
+
+				// terminate the entire bridge if one direction ceases
+				stop.store(true, Ordering::Relaxed);
+				res
+			}));
+			return;
+		}
+	};
+
+	// a separate writer thread releases data according to its deadline, so
+	// latency does not throttle reading
+	let (tx, rx) = mpsc::channel::<(Instant, Vec<u8>)>();
+
+	let stop_reader = stop.clone();
+	threads.push(thread::spawn(move || {
+		let mut buf = vec![0u8; buffer_size];
+		let res = loop {
+			if stop_reader.load(Ordering::Relaxed) {
+				break Ok(());
+			}
+
+			match reader.read(&mut buf) {
+				Ok(0) => break Ok(()),
+				Ok(len) => {
+					let deadline = Instant::now() + latency;
+					let mut data = buf[..len].to_vec();
+					if let Some(transform) = transform.as_mut() {
+						transform(&mut data);
+					}
+					// writer thread terminated, so stop forwarding
+					if tx.send((deadline, data)).is_err() {
+						break Ok(());
+					}
+				},
+				Err(ref e) if is_timeout(e) => (),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => break Err(e)
+			}
+		};
+
+		// terminate the entire bridge if one direction ceases
+		stop_reader.store(true, Ordering::Relaxed);
+		res
+	}));
+
+	let stop_writer = stop.clone();
+	threads.push(thread::spawn(move || {
+		// forward pending data even if stop was requested, until reader
+		// thread terminates and disconnects the channel
+		let res = (|| {
+			for (deadline, data) in rx {
+				let now = Instant::now();
+				if deadline > now {
+					thread::sleep(deadline - now);
+				}
+				write_forwarded(&mut writer, &data, &stop_writer)?;
+			}
+			Ok(())
+		})();
+
+		stop_writer.store(true, Ordering::Relaxed);
+		res
+	}));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	// devices connected to the ports of a bridge
+	fn devices() -> (SimulatedSerialPort, SerialPort, SimulatedSerialPort, SerialPort) {
+		let a = SimulatedSerialPort::new(None);
+		let b = SimulatedSerialPort::new(None);
+		let port_a = a.try_clone().unwrap().into_serial_port();
+		let port_b = b.try_clone().unwrap().into_serial_port();
+		(a, port_a, b, port_b)
+	}
+
+	// wait until the device received the expected bytes via the bridge
+	fn expect(device: &SimulatedSerialPort, expected: &[u8]) {
+		let start = Instant::now();
+		let mut received = Vec::new();
+		while received.len() < expected.len() && start.elapsed() < Duration::from_secs(5) {
+			received.extend(device.take_unmatched());
+			thread::sleep(Duration::from_millis(1));
+		}
+		assert_eq!(received, expected);
+	}
+
+	#[test]
+	fn forward_both_directions() {
+		let (a, port_a, b, port_b) = devices();
+		b.on(b"ping", b"pong", Duration::ZERO);
+		let bridge = bridge(&port_a, &port_b, None).unwrap();
+
+		a.send(b"ping", Duration::ZERO);
+		expect(&a, b"pong");
+		assert!(!bridge.is_finished());
+		bridge.stop().unwrap();
+
+		// the ports' own timeouts are not affected
+		assert_eq!(port_a.read_timeout(), None);
+		assert_eq!(port_b.write_timeout(), None);
+	}
+
+	#[test]
+	fn transform_and_latency() {
+		let (a, port_a, b, port_b) = devices();
+		let options = ForwardOptions {
+			latency: Some(Duration::from_millis(50)),
+			a_to_b: Some(Box::new(|data: &mut Vec<u8>| data.make_ascii_uppercase())),
+			b_to_a: Some(Box::new(|data: &mut Vec<u8>| data.clear())),
+			..ForwardOptions::default()
+		};
+		let bridge = forward_with(&port_a, &port_b, options).unwrap();
+
+		let start = Instant::now();
+		a.send(b"hello", Duration::ZERO);
+		b.send(b"dropped", Duration::ZERO);
+		expect(&b, b"HELLO");
+		assert!(start.elapsed() >= Duration::from_millis(50));
+		bridge.stop().unwrap();
+		assert!(a.take_unmatched().is_empty());
+	}
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{clock, PartialRead, SerialPort};
+
+struct State {
+	port: SerialPort,
+	// end of the previous transaction
+	idle_since: Option<Instant>
+}
+
+// serializes transactions of multiple threads on a half-duplex line (e.g.,
+// RS-485), so each response is read by the thread that sent the request.
+// clones share the port, e.g.:
+//   let bus = Bus::new(port, Duration::from_millis(5));
+//   let sensor = bus.clone();
+//   thread::spawn(move || sensor.transaction(b"\x01\x03\x00\x00\x00\x01\x84\x0a", &mut buf, timeout));
+// the turnaround delay is kept between the end of a transaction and the
+// next request, e.g., for devices switching their transceiver back to
+// receiving. input still pending from a previous transaction (e.g., a
+// response arriving after its timeout) is discarded before each request.
+#[derive(Clone)]
+pub struct Bus {
+	state: Arc<Mutex<State>>,
+	turnaround: Duration
+}
+
+impl Bus {
+	pub fn new(port: SerialPort, turnaround: Duration) -> Self {
+		Self {
+			state: Arc::new(Mutex::new(State { port, idle_since: None })),
+			turnaround
+		}
+	}
+
+	pub fn turnaround(&self) -> Duration {
+		self.turnaround
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// see SerialPort::transaction(). the timeout does not include waiting
+	// for other threads' transactions and the turnaround delay.
+	pub fn transaction(&self, request: &[u8], response: &mut [u8], timeout: Duration)
+			-> Result<(), PartialRead> {
+		self.with_port(|port| port.transaction(request, response, timeout))
+	}
+
+	// perform an exchange that transaction() does not cover (e.g., reading a
+	// response of variable length or a broadcast without response) with
+	// exclusive access to the port. stale input is not discarded.
+	pub fn with_port<F, R>(&self, f: F) -> R
+			where F: FnOnce(&mut SerialPort) -> R {
+		let mut state = self.lock();
+		if let Some(idle_since) = state.idle_since {
+			let wait = self.turnaround.saturating_sub(clock::elapsed(idle_since));
+			if !wait.is_zero() {
+				clock::sleep(wait);
+			}
+		}
+
+		let res = f(&mut state.port);
+		state.idle_since = Some(clock::now());
+		res
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io;
+	use std::thread;
+	use crate::clock::MockClock;
+	use crate::sim::SimulatedSerialPort;
+
+	#[test]
+	fn turnaround_between_transactions() {
+		let clock = MockClock::install();
+		let sim = SimulatedSerialPort::new(None);
+		sim.on(b"req", b"resp", Duration::from_millis(10));
+		let bus = Bus::new(sim.try_clone().unwrap().into_serial_port(),
+			Duration::from_millis(5));
+
+		let mut response = [0u8; 4];
+		bus.transaction(b"req", &mut response, Duration::from_secs(1)).unwrap();
+		assert_eq!(&response, b"resp");
+		assert_eq!(clock.elapsed(), Duration::from_millis(10));
+
+		// the next request waits for the turnaround delay
+		bus.transaction(b"req", &mut response, Duration::from_secs(1)).unwrap();
+		assert_eq!(clock.elapsed(), Duration::from_millis(25));
+
+		// a timeout reports the bytes received so far
+		sim.on(b"short", b"ab", Duration::ZERO);
+		let e = bus.transaction(b"short", &mut response, Duration::from_millis(100)).unwrap_err();
+		assert_eq!(e.received, 2);
+		assert_eq!(e.error.kind(), io::ErrorKind::TimedOut);
+		MockClock::uninstall();
+	}
+
+	// concurrent transactions do not interleave
+	#[test]
+	fn serialize_threads() {
+		let sim = SimulatedSerialPort::new(None);
+		sim.on(b"a?", b"a!", Duration::from_millis(1));
+		sim.on(b"b?", b"b!", Duration::from_millis(1));
+		let bus = Bus::new(sim.into_serial_port(), Duration::ZERO);
+
+		let threads: Vec<_> = [b'a', b'b'].iter().map(|&id| {
+			let bus = bus.clone();
+			thread::spawn(move || {
+				for _ in 0..20 {
+					let mut response = [0u8; 2];
+					bus.transaction(&[id, b'?'], &mut response, Duration::from_secs(5)).unwrap();
+					assert_eq!(response, [id, b'!']);
+				}
+			})
+		}).collect();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+	}
+}
@@ -0,0 +1,163 @@
+// source of time for deadline arithmetic (e.g., remaining timeouts), which
+// tests can replace with a MockClock (feature mock-clock, always available to
+// the crate's own tests) to check it deterministically without actually
+// waiting
+
+use std::sync::{Condvar, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(any(test, feature = "mock-clock"))]
+use std::cell::{Cell, RefCell};
+#[cfg(any(test, feature = "mock-clock"))]
+use std::rc::Rc;
+
+pub(crate) trait Clock {
+	fn now(&self) -> Instant;
+	fn sleep(&self, dur: Duration);
+
+	// timeout to pass to an OS wait (e.g., poll()) for the given timeout
+	fn os_timeout(&self, timeout: Option<Duration>) -> Option<Duration>;
+
+	// called after an OS wait timed out. returns whether the caller should
+	// recompute the remaining timeout and wait again, because the wait was
+	// only simulated.
+	fn os_timed_out(&self, timeout: Option<Duration>) -> bool;
+}
+
+struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+
+	fn sleep(&self, dur: Duration) {
+		thread::sleep(dur);
+	}
+
+	fn os_timeout(&self, timeout: Option<Duration>) -> Option<Duration> {
+		timeout
+	}
+
+	fn os_timed_out(&self, _timeout: Option<Duration>) -> bool {
+		false
+	}
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+thread_local! {
+	static MOCK_CLOCK: RefCell<Option<MockClock>> = const { RefCell::new(None) };
+}
+
+#[cfg(not(any(test, feature = "mock-clock")))]
+fn with_clock<R>(f: impl FnOnce(&dyn Clock) -> R) -> R {
+	f(&SystemClock)
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+fn with_clock<R>(f: impl FnOnce(&dyn Clock) -> R) -> R {
+	// the clock is cloned, so it is not borrowed while f() runs
+	match MOCK_CLOCK.with(|mock| mock.borrow().clone()) {
+		Some(mock) => f(&mock),
+		None => f(&SystemClock)
+	}
+}
+
+pub(crate) fn now() -> Instant {
+	with_clock(|clock| clock.now())
+}
+
+pub(crate) fn elapsed(since: Instant) -> Duration {
+	now().saturating_duration_since(since)
+}
+
+pub(crate) fn sleep(dur: Duration) {
+	with_clock(|clock| clock.sleep(dur));
+}
+
+pub(crate) fn os_timeout(timeout: Option<Duration>) -> Option<Duration> {
+	with_clock(|clock| clock.os_timeout(timeout))
+}
+
+pub(crate) fn os_timed_out(timeout: Option<Duration>) -> bool {
+	with_clock(|clock| clock.os_timed_out(timeout))
+}
+
+// wait on condvar for up to timeout like an OS wait, i.e., a MockClock
+// advances by the timeout instead of blocking if nothing notifies
+pub(crate) fn wait_timeout<'a, T>(condvar: &Condvar, guard: MutexGuard<'a, T>, timeout: Duration)
+		-> MutexGuard<'a, T> {
+	let wait = os_timeout(Some(timeout)).unwrap_or(timeout);
+	let (guard, res) = condvar.wait_timeout(guard, wait).unwrap_or_else(|e| e.into_inner());
+	if res.timed_out() {
+		os_timed_out(Some(timeout));
+	}
+	guard
+}
+
+// simulated time of the calling thread, which only advances explicitly or
+// when the crate sleeps or waits for the OS with a timeout. OS waits don't
+// block, but poll for readiness instead and time out immediately (waits
+// without timeout still block). other threads, e.g., of the reactor, keep
+// using the system clock, so tests can run in parallel, e.g.:
+//   let clock = MockClock::install();
+//   port.set_read_timeout(Some(Duration::from_secs(10)))?;
+//   assert!(port.read(&mut buf).is_err());  // returns immediately
+//   assert_eq!(clock.elapsed(), Duration::from_secs(10));
+//   MockClock::uninstall();
+#[cfg(any(test, feature = "mock-clock"))]
+#[derive(Clone)]
+pub struct MockClock {
+	start: Instant,
+	elapsed: Rc<Cell<Duration>>
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl MockClock {
+	// simulate time on the calling thread, starting at the current time
+	pub fn install() -> Self {
+		let clock = Self { start: Instant::now(), elapsed: Rc::default() };
+		MOCK_CLOCK.with(|mock| *mock.borrow_mut() = Some(clock.clone()));
+		clock
+	}
+
+	// use the system clock on the calling thread again
+	pub fn uninstall() {
+		MOCK_CLOCK.with(|mock| *mock.borrow_mut() = None);
+	}
+
+	pub fn advance(&self, dur: Duration) {
+		self.elapsed.set(self.elapsed.get() + dur);
+	}
+
+	// simulated time elapsed since the clock was installed
+	pub fn elapsed(&self) -> Duration {
+		self.elapsed.get()
+	}
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl Clock for MockClock {
+	fn now(&self) -> Instant {
+		self.start + self.elapsed.get()
+	}
+
+	fn sleep(&self, dur: Duration) {
+		self.advance(dur);
+	}
+
+	fn os_timeout(&self, timeout: Option<Duration>) -> Option<Duration> {
+		timeout.map(|_| Duration::ZERO)
+	}
+
+	fn os_timed_out(&self, timeout: Option<Duration>) -> bool {
+		match timeout {
+			Some(timeout) if !timeout.is_zero() => {
+				self.advance(timeout);
+				true
+			},
+			_ => false
+		}
+	}
+}
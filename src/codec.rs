@@ -0,0 +1,452 @@
+// framing of binary messages. the codecs only convert between payloads and
+// bytes, so they work with blocking ports (see read_frame() and
+// write_frame()) as well as with bytes received asynchronously (see
+// decode()), e.g.:
+//   let codec = LengthPrefixed::default();
+//   let mut rx = Vec::new();
+//   loop {
+//       rx.extend_from_slice(&buf[..port.read(&mut buf).await?]);
+//       while let Some(frame) = codec.decode(&mut rx)? {
+//           handle(frame);
+//       }
+//   }
+
+use std::io::{self, Read, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+	Big,
+	Little
+}
+
+// checksum of size bytes appended to each frame, computed over the header
+// and the payload. can also be used without a codec, e.g., for Modbus RTU:
+//   Checksum::CRC16_MODBUS.append(&mut request, 0);
+//   Checksum::CRC16_MODBUS.verify(&response)?;
+#[derive(Clone, Copy, Debug)]
+pub struct Checksum {
+	pub size: usize,
+	pub endian: Endian,
+	pub compute: fn(&[u8]) -> u32
+}
+
+impl Checksum {
+	pub const CRC8: Self = Self { size: 1, endian: Endian::Big, compute: |data| crc8(data) as u32 };
+	pub const CRC16_CCITT: Self = Self {
+		size: 2,
+		endian: Endian::Big,
+		compute: |data| crc16_ccitt(data) as u32
+	};
+	// transmitted low byte first, as specified by Modbus
+	pub const CRC16_MODBUS: Self = Self {
+		size: 2,
+		endian: Endian::Little,
+		compute: |data| crc16_modbus(data) as u32
+	};
+
+	// append the checksum of dst[start..] to dst
+	pub fn append(&self, dst: &mut Vec<u8>, start: usize) {
+		let value = (self.compute)(&dst[start..]);
+		put_uint(dst, value, self.size, self.endian);
+	}
+
+	// check the checksum at the end of frame
+	pub fn verify(&self, frame: &[u8]) -> io::Result<()> {
+		if frame.len() < self.size {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too short for checksum"));
+		}
+		let (data, checksum) = frame.split_at(frame.len() - self.size);
+		if get_uint(checksum, self.endian) != (self.compute)(data) & mask(self.size) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "frame checksum mismatch"));
+		}
+		Ok(())
+	}
+}
+
+// frames consisting of the payload length (header_size bytes), the payload,
+// and an optional checksum
+#[derive(Clone, Copy, Debug)]
+pub struct LengthPrefixed {
+	// 1 to 4 bytes
+	pub header_size: usize,
+	pub endian: Endian,
+	// longer payloads are rejected, e.g., to limit memory usage when the
+	// header is corrupted
+	pub max_len: usize,
+	pub checksum: Option<Checksum>
+}
+
+impl Default for LengthPrefixed {
+	fn default() -> Self {
+		Self { header_size: 2, endian: Endian::Big, max_len: 0xffff, checksum: None }
+	}
+}
+
+impl LengthPrefixed {
+	fn check(&self) -> io::Result<()> {
+		let checksum_size = self.checksum.map_or(1, |checksum| checksum.size);
+		if !(1..=4).contains(&self.header_size) || !(1..=4).contains(&checksum_size) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"header and checksum size must be 1 to 4 bytes"));
+		}
+		Ok(())
+	}
+
+	fn checksum_size(&self) -> usize {
+		self.checksum.map_or(0, |checksum| checksum.size)
+	}
+
+	fn check_len(&self, len: usize) -> io::Result<()> {
+		if len > self.max_len || len as u64 > mask(self.header_size) as u64 {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "frame length exceeds maximum"));
+		}
+		Ok(())
+	}
+
+	// append the frame containing payload to dst
+	pub fn encode(&self, payload: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+		self.check()?;
+		self.check_len(payload.len())?;
+
+		let start = dst.len();
+		put_uint(dst, payload.len() as u32, self.header_size, self.endian);
+		dst.extend_from_slice(payload);
+		if let Some(checksum) = self.checksum {
+			checksum.append(dst, start);
+		}
+		Ok(())
+	}
+
+	// remove the first frame from src and return its payload, or None if src
+	// does not contain a complete frame yet. invalid frames are removed, too
+	// (only their first byte if the length is invalid), so decoding can
+	// resynchronize by calling decode() again.
+	pub fn decode(&self, src: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+		self.check()?;
+		if src.len() < self.header_size {
+			return Ok(None);
+		}
+
+		let len = get_uint(&src[..self.header_size], self.endian) as usize;
+		if let Err(e) = self.check_len(len) {
+			src.remove(0);
+			return Err(e);
+		}
+		let frame_len = self.header_size + len + self.checksum_size();
+		if src.len() < frame_len {
+			return Ok(None);
+		}
+
+		let frame: Vec<u8> = src.drain(..frame_len).collect();
+		if let Some(checksum) = self.checksum {
+			checksum.verify(&frame)?;
+		}
+		Ok(Some(frame[self.header_size..self.header_size + len].to_vec()))
+	}
+
+	pub fn write_frame<W: Write>(&self, mut writer: W, payload: &[u8]) -> io::Result<()> {
+		let mut frame = Vec::with_capacity(self.header_size + payload.len() + self.checksum_size());
+		self.encode(payload, &mut frame)?;
+		writer.write_all(&frame)
+	}
+
+	// read a frame and return its payload. the reader's timeout applies to
+	// each read, so a frame may take longer in total.
+	pub fn read_frame<R: Read>(&self, mut reader: R) -> io::Result<Vec<u8>> {
+		self.check()?;
+		let mut frame = vec![0u8; self.header_size];
+		reader.read_exact(&mut frame)?;
+
+		let len = get_uint(&frame, self.endian) as usize;
+		self.check_len(len)?;
+		frame.resize(self.header_size + len + self.checksum_size(), 0);
+		reader.read_exact(&mut frame[self.header_size..])?;
+
+		if let Some(checksum) = self.checksum {
+			checksum.verify(&frame)?;
+		}
+		frame.truncate(self.header_size + len);
+		Ok(frame.split_off(self.header_size))
+	}
+}
+
+fn mask(size: usize) -> u32 {
+	match size {
+		4 => u32::MAX,
+		size => (1 << (8 * size)) - 1
+	}
+}
+
+fn put_uint(dst: &mut Vec<u8>, value: u32, size: usize, endian: Endian) {
+	let bytes = value.to_be_bytes();
+	let bytes = &bytes[4 - size..];
+	match endian {
+		Endian::Big => dst.extend_from_slice(bytes),
+		Endian::Little => dst.extend(bytes.iter().rev())
+	}
+}
+
+fn get_uint(bytes: &[u8], endian: Endian) -> u32 {
+	let fold = |value: u32, byte: &u8| value << 8 | *byte as u32;
+	match endian {
+		Endian::Big => bytes.iter().fold(0, fold),
+		Endian::Little => bytes.iter().rev().fold(0, fold)
+	}
+}
+
+// CRC-8 with polynomial 0x07 and initial value 0 (e.g., SMBus PEC)
+pub fn crc8(data: &[u8]) -> u8 {
+	data.iter().fold(0, |crc, &byte| {
+		(0..8).fold(crc ^ byte, |crc, _| {
+			if crc & 0x80 != 0 { crc << 1 ^ 0x07 } else { crc << 1 }
+		})
+	})
+}
+
+// CRC-16/CCITT-FALSE with polynomial 0x1021 and initial value 0xffff
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+	data.iter().fold(0xffff, |crc, &byte| {
+		(0..8).fold(crc ^ (byte as u16) << 8, |crc, _| {
+			if crc & 0x8000 != 0 { crc << 1 ^ 0x1021 } else { crc << 1 }
+		})
+	})
+}
+
+// CRC-16/MODBUS with polynomial 0x8005 (reflected) and initial value 0xffff
+pub fn crc16_modbus(data: &[u8]) -> u16 {
+	data.iter().fold(0xffff, |crc, &byte| {
+		(0..8).fold(crc ^ byte as u16, |crc, _| {
+			if crc & 0x0001 != 0 { crc >> 1 ^ 0xa001 } else { crc >> 1 }
+		})
+	})
+}
+
+// number of data bytes following a MIDI status byte
+fn midi_data_len(status: u8) -> usize {
+	match status {
+		0xc0..=0xdf | 0xf1 | 0xf3 => 1,
+		0x80..=0xef | 0xf2 => 2,
+		_ => 0
+	}
+}
+
+// splits a MIDI byte stream (see midi::configure()) into messages, each
+// starting with its status byte, e.g.:
+//   let mut framer = MidiFramer::default();
+//   while let Some(message) = framer.decode(&mut rx) {
+//       handle(message);
+//   }
+// messages sent with running status (i.e., omitting a repeated channel
+// status byte) are completed with it. real-time messages are returned as
+// soon as they were received, even within other messages. system exclusive
+// messages are returned including 0xf0 and 0xf7. data bytes without status
+// are dropped.
+#[derive(Clone, Debug, Default)]
+pub struct MidiFramer {
+	running_status: Option<u8>,
+	message: Vec<u8>
+}
+
+impl MidiFramer {
+	// remove the bytes of the first complete message from src and return it,
+	// or None once all of src was consumed
+	pub fn decode(&mut self, src: &mut Vec<u8>) -> Option<Vec<u8>> {
+		for (i, &byte) in src.iter().enumerate() {
+			if let Some(message) = self.push(byte) {
+				src.drain(..=i);
+				return Some(message);
+			}
+		}
+		src.clear();
+		None
+	}
+
+	// process a single received byte
+	pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+		match byte {
+			// real-time messages affect neither running status nor the
+			// message in progress
+			0xf8..=0xff => return Some(vec![byte]),
+			0xf7 if self.message.first() == Some(&0xf0) => {
+				self.message.push(byte);
+				return Some(std::mem::take(&mut self.message));
+			},
+			// end of exclusive without a system exclusive message
+			0xf7 => {
+				self.message.clear();
+				self.running_status = None;
+				return None;
+			},
+			// a status byte aborts an incomplete message
+			0x80..=0xf7 => {
+				self.message.clear();
+				self.message.push(byte);
+				// system common messages cancel running status
+				self.running_status = if byte < 0xf0 { Some(byte) } else { None };
+			},
+			_ => match (self.message.first(), self.running_status) {
+				(Some(_), _) => self.message.push(byte),
+				(None, Some(status)) => self.message.extend_from_slice(&[status, byte]),
+				(None, None) => return None
+			}
+		}
+
+		let status = self.message[0];
+		if status != 0xf0 && self.message.len() > midi_data_len(status) {
+			return Some(std::mem::take(&mut self.message));
+		}
+		None
+	}
+}
+
+// encodes MIDI messages for transmission, omitting repeated channel status
+// bytes (running status) if enabled
+#[derive(Clone, Debug, Default)]
+pub struct MidiEncoder {
+	pub running_status: bool,
+	last_status: Option<u8>
+}
+
+impl MidiEncoder {
+	pub fn new(running_status: bool) -> Self {
+		Self { running_status, last_status: None }
+	}
+
+	// append message (starting with its status byte) to dst
+	pub fn encode(&mut self, message: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+		let status = match message.first() {
+			Some(&status) if status >= 0x80 => status,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"MIDI message must start with a status byte"))
+		};
+
+		match status {
+			0xf8..=0xff => (),
+			0x80..=0xef if self.running_status && self.last_status == Some(status) => {
+				dst.extend_from_slice(&message[1..]);
+				return Ok(());
+			},
+			0x80..=0xef => self.last_status = Some(status),
+			_ => self.last_status = None
+		}
+		dst.extend_from_slice(message);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn crc_check_values() {
+		assert_eq!(crc8(b"123456789"), 0xf4);
+		assert_eq!(crc16_ccitt(b"123456789"), 0x29b1);
+		assert_eq!(crc16_modbus(b"123456789"), 0x4b37);
+	}
+
+	#[test]
+	fn checksum_append_and_verify() {
+		// Modbus RTU request reading holding register 0 of unit 1
+		let mut request = vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x01];
+		Checksum::CRC16_MODBUS.append(&mut request, 0);
+		assert_eq!(&request[6..], [0x84, 0x0a]);
+		Checksum::CRC16_MODBUS.verify(&request).unwrap();
+
+		request[2] ^= 1;
+		assert!(Checksum::CRC16_MODBUS.verify(&request).is_err());
+		assert!(Checksum::CRC8.verify(&[]).is_err());
+	}
+
+	#[test]
+	fn length_prefixed_round_trip() {
+		let codec = LengthPrefixed {
+			endian: Endian::Little,
+			checksum: Some(Checksum::CRC16_CCITT),
+			..LengthPrefixed::default()
+		};
+		let mut rx = Vec::new();
+		codec.encode(b"hello", &mut rx).unwrap();
+		codec.encode(b"", &mut rx).unwrap();
+		assert_eq!(&rx[..2], [5, 0]);
+
+		// incomplete frames are kept until the rest arrives
+		let tail = rx.split_off(4);
+		assert_eq!(codec.decode(&mut rx).unwrap(), None);
+		rx.extend_from_slice(&tail);
+		assert_eq!(codec.decode(&mut rx).unwrap(), Some(b"hello".to_vec()));
+		assert_eq!(codec.decode(&mut rx).unwrap(), Some(Vec::new()));
+		assert!(rx.is_empty());
+
+		let mut frame = Vec::new();
+		codec.write_frame(&mut frame, b"payload").unwrap();
+		assert_eq!(codec.read_frame(&frame[..]).unwrap(), b"payload");
+	}
+
+	#[test]
+	fn length_prefixed_resync() {
+		let codec = LengthPrefixed {
+			header_size: 1,
+			max_len: 4,
+			checksum: Some(Checksum::CRC8),
+			..LengthPrefixed::default()
+		};
+		// garbage with an invalid length, a frame with a corrupted checksum,
+		// and a valid frame
+		let mut rx = vec![0xff];
+		codec.encode(b"bad", &mut rx).unwrap();
+		*rx.last_mut().unwrap() ^= 1;
+		codec.encode(b"good", &mut rx).unwrap();
+
+		assert_eq!(codec.decode(&mut rx).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(codec.decode(&mut rx).unwrap_err().kind(), io::ErrorKind::InvalidData);
+		assert_eq!(codec.decode(&mut rx).unwrap(), Some(b"good".to_vec()));
+		assert!(rx.is_empty());
+
+		assert_eq!(codec.encode(b"too long", &mut rx).unwrap_err().kind(),
+			io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn midi_running_status() {
+		let mut framer = MidiFramer::default();
+		// note on, two more with running status interrupted by a timing
+		// clock, and a system exclusive message
+		let mut rx = vec![0x90, 0x3c, 0x40, 0x3e, 0xf8, 0x40, 0x40, 0x00, 0xf0, 0x7e, 0x7f, 0xf7];
+		let mut messages = Vec::new();
+		while let Some(message) = framer.decode(&mut rx) {
+			messages.push(message);
+		}
+		assert_eq!(messages, [
+			vec![0x90, 0x3c, 0x40],
+			vec![0xf8],
+			vec![0x90, 0x3e, 0x40],
+			vec![0x90, 0x40, 0x00],
+			vec![0xf0, 0x7e, 0x7f, 0xf7]
+		]);
+		assert!(rx.is_empty());
+
+		// system exclusive cancelled running status
+		assert_eq!(framer.push(0x3c), None);
+		assert_eq!(framer.push(0x40), None);
+	}
+
+	#[test]
+	fn midi_encoder_round_trip() {
+		let messages: [&[u8]; 4] = [&[0x90, 0x3c, 0x40], &[0x90, 0x3e, 0x40], &[0xf8], &[0x90, 0x40, 0x00]];
+		let mut encoder = MidiEncoder::new(true);
+		let mut tx = Vec::new();
+		for message in messages {
+			encoder.encode(message, &mut tx).unwrap();
+		}
+		assert_eq!(tx, [0x90, 0x3c, 0x40, 0x3e, 0x40, 0xf8, 0x40, 0x00]);
+		assert!(encoder.encode(&[0x3c], &mut tx).is_err());
+
+		let mut framer = MidiFramer::default();
+		let mut decoded = Vec::new();
+		while let Some(message) = framer.decode(&mut tx) {
+			decoded.push(message);
+		}
+		assert_eq!(decoded, messages);
+	}
+}
@@ -0,0 +1,266 @@
+// adapter implementing the trait of the serialport crate, so applications
+// using it can switch to this crate (and its timeouts that can be changed
+// while other threads block in read() or write()) without changing the
+// call sites, e.g., replace
+//   let mut port = serialport::new("/dev/ttyUSB0", 115200).open()?;
+// with
+//   let mut port = serial::compat::open("/dev/ttyUSB0", 115200, Duration::ZERO)?;
+// like with the serialport crate, a single timeout applies to both reads and
+// writes. an infinite timeout (None) is reported as Duration::MAX.
+
+use std::ffi::OsStr;
+use std::io;
+use std::time::Duration;
+
+use serialport::{ClearBuffer, Error, ErrorKind};
+
+use crate::{CallTimeout, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+// open a port with 8N1 and no flow control, like serialport::new().open()
+pub fn open<T>(dev_path: &T, baud_rate: u32, timeout: Duration)
+		-> serialport::Result<Box<dyn serialport::SerialPort>>
+		where T: AsRef<OsStr> + ?Sized {
+	let port = SerialPort::open(dev_path, Some(timeout))?;
+	port.set_baud_rate(baud_rate)?;
+	port.set_data_bits(DataBits::Eight)?;
+	port.set_parity(Parity::None)?;
+	port.set_stop_bits(StopBits::One)?;
+	port.set_flow_control(FlowControl::None)?;
+	Ok(Box::new(CompatSerialPort::new(port)))
+}
+
+pub struct CompatSerialPort {
+	port: SerialPort
+}
+
+impl CompatSerialPort {
+	pub fn new(port: SerialPort) -> Self {
+		Self { port }
+	}
+
+	pub fn get_ref(&self) -> &SerialPort {
+		&self.port
+	}
+
+	pub fn into_inner(self) -> SerialPort {
+		self.port
+	}
+}
+
+impl From<SerialPort> for CompatSerialPort {
+	fn from(port: SerialPort) -> Self {
+		Self::new(port)
+	}
+}
+
+fn data_bits_from(data_bits: DataBits) -> serialport::DataBits {
+	match data_bits {
+		DataBits::Five => serialport::DataBits::Five,
+		DataBits::Six => serialport::DataBits::Six,
+		DataBits::Seven => serialport::DataBits::Seven,
+		DataBits::Eight => serialport::DataBits::Eight
+	}
+}
+
+fn data_bits_into(data_bits: serialport::DataBits) -> DataBits {
+	match data_bits {
+		serialport::DataBits::Five => DataBits::Five,
+		serialport::DataBits::Six => DataBits::Six,
+		serialport::DataBits::Seven => DataBits::Seven,
+		serialport::DataBits::Eight => DataBits::Eight
+	}
+}
+
+// the serialport crate cannot express mark and space parity
+fn parity_from(parity: Parity) -> serialport::Result<serialport::Parity> {
+	match parity {
+		Parity::None => Ok(serialport::Parity::None),
+		Parity::Odd => Ok(serialport::Parity::Odd),
+		Parity::Even => Ok(serialport::Parity::Even),
+		Parity::Mark | Parity::Space => Err(Error::new(ErrorKind::InvalidInput,
+			"mark and space parity are not supported by the serialport crate"))
+	}
+}
+
+fn parity_into(parity: serialport::Parity) -> Parity {
+	match parity {
+		serialport::Parity::None => Parity::None,
+		serialport::Parity::Odd => Parity::Odd,
+		serialport::Parity::Even => Parity::Even
+	}
+}
+
+fn stop_bits_from(stop_bits: StopBits) -> serialport::StopBits {
+	match stop_bits {
+		StopBits::One => serialport::StopBits::One,
+		StopBits::Two => serialport::StopBits::Two
+	}
+}
+
+fn stop_bits_into(stop_bits: serialport::StopBits) -> StopBits {
+	match stop_bits {
+		serialport::StopBits::One => StopBits::One,
+		serialport::StopBits::Two => StopBits::Two
+	}
+}
+
+fn flow_control_from(flow_control: FlowControl) -> serialport::FlowControl {
+	match flow_control {
+		FlowControl::None => serialport::FlowControl::None,
+		FlowControl::Software => serialport::FlowControl::Software,
+		FlowControl::Hardware => serialport::FlowControl::Hardware
+	}
+}
+
+fn flow_control_into(flow_control: serialport::FlowControl) -> FlowControl {
+	match flow_control {
+		serialport::FlowControl::None => FlowControl::None,
+		serialport::FlowControl::Software => FlowControl::Software,
+		serialport::FlowControl::Hardware => FlowControl::Hardware
+	}
+}
+
+fn queued(bytes: usize) -> serialport::Result<u32> {
+	Ok(u32::try_from(bytes).unwrap_or(u32::MAX))
+}
+
+impl serialport::SerialPort for CompatSerialPort {
+	fn name(&self) -> Option<String> {
+		self.port.path.as_ref().map(|path| path.to_string_lossy().into_owned())
+	}
+
+	fn baud_rate(&self) -> serialport::Result<u32> {
+		Ok(self.port.baud_rate()?)
+	}
+
+	fn data_bits(&self) -> serialport::Result<serialport::DataBits> {
+		Ok(data_bits_from(self.port.data_bits()?))
+	}
+
+	fn flow_control(&self) -> serialport::Result<serialport::FlowControl> {
+		Ok(flow_control_from(self.port.flow_control()?))
+	}
+
+	fn parity(&self) -> serialport::Result<serialport::Parity> {
+		parity_from(self.port.parity()?)
+	}
+
+	fn stop_bits(&self) -> serialport::Result<serialport::StopBits> {
+		Ok(stop_bits_from(self.port.stop_bits()?))
+	}
+
+	fn timeout(&self) -> Duration {
+		self.port.read_timeout().unwrap_or(Duration::MAX)
+	}
+
+	fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+		Ok(self.port.set_baud_rate(baud_rate)?)
+	}
+
+	fn set_data_bits(&mut self, data_bits: serialport::DataBits) -> serialport::Result<()> {
+		Ok(self.port.set_data_bits(data_bits_into(data_bits))?)
+	}
+
+	fn set_flow_control(&mut self, flow_control: serialport::FlowControl) -> serialport::Result<()> {
+		Ok(self.port.set_flow_control(flow_control_into(flow_control))?)
+	}
+
+	fn set_parity(&mut self, parity: serialport::Parity) -> serialport::Result<()> {
+		Ok(self.port.set_parity(parity_into(parity))?)
+	}
+
+	fn set_stop_bits(&mut self, stop_bits: serialport::StopBits) -> serialport::Result<()> {
+		Ok(self.port.set_stop_bits(stop_bits_into(stop_bits))?)
+	}
+
+	fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+		let timeout = if timeout == Duration::MAX { None } else { Some(timeout) };
+		self.port.set_read_timeout(timeout)?;
+		Ok(self.port.set_write_timeout(timeout)?)
+	}
+
+	fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+		Ok(self.port.set_rts(level)?)
+	}
+
+	fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+		Ok(self.port.set_dtr(level)?)
+	}
+
+	fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+		Ok(self.port.read_cts()?)
+	}
+
+	fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+		Ok(self.port.read_dsr()?)
+	}
+
+	fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+		Ok(self.port.read_ri()?)
+	}
+
+	fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+		Ok(self.port.read_cd()?)
+	}
+
+	fn bytes_to_read(&self) -> serialport::Result<u32> {
+		queued(self.port.bytes_to_read()?)
+	}
+
+	fn bytes_to_write(&self) -> serialport::Result<u32> {
+		queued(self.port.bytes_to_write()?)
+	}
+
+	fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+		let (input, output) = match buffer_to_clear {
+			ClearBuffer::Input => (true, false),
+			ClearBuffer::Output => (false, true),
+			ClearBuffer::All => (true, true)
+		};
+		Ok(self.port.purge(input, output)?)
+	}
+
+	fn try_clone(&self) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+		Ok(Box::new(Self::new(self.port.try_clone()?)))
+	}
+
+	fn set_break(&self) -> serialport::Result<()> {
+		Ok(self.port.set_break(true)?)
+	}
+
+	fn clear_break(&self) -> serialport::Result<()> {
+		Ok(self.port.set_break(false)?)
+	}
+}
+
+impl io::Read for CompatSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.port.read_buffered(buf, CallTimeout::Port)
+	}
+}
+
+impl io::Read for &CompatSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.port.read_buffered(buf, CallTimeout::Port)
+	}
+}
+
+impl io::Write for CompatSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.port.write_coalesced(buf, CallTimeout::Port)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.port.flush_buffered()
+	}
+}
+
+impl io::Write for &CompatSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.port.write_coalesced(buf, CallTimeout::Port)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.port.flush_buffered()
+	}
+}
@@ -0,0 +1,111 @@
+// DMX512 transmission, e.g., for lighting controllers:
+//   let port = SerialPort::open("/dev/ttyUSB0", None)?;
+//   dmx::configure(&port)?;
+//   loop {
+//       dmx::send_dmx_frame(&port, &channels)?;
+//   }
+// each frame starts with a break and a mark after break, which are generated
+// via SerialPort::set_break() after the previous frame was transmitted. the
+// OS may extend both, which DMX512 permits (up to 1 s), but some USB adapters
+// report transmission complete early, which cuts off the previous frame.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+pub const BAUD_RATE: u32 = 250_000;
+pub const CHANNELS: usize = 512;
+// start code of frames containing dimmer levels
+pub const NULL_START_CODE: u8 = 0x00;
+
+// minimum durations of the line states preceding each frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmxTiming {
+	// at least 92 µs
+	pub break_time: Duration,
+	// at least 12 µs
+	pub mark_after_break: Duration
+}
+
+// values typical of commercial controllers
+impl Default for DmxTiming {
+	fn default() -> Self {
+		Self {
+			break_time: Duration::from_micros(176),
+			mark_after_break: Duration::from_micros(12)
+		}
+	}
+}
+
+// 250 kBd 8N2 without flow control
+pub fn configure(port: &SerialPort) -> io::Result<()> {
+	port.set_baud_rate(BAUD_RATE)?;
+	port.set_data_bits(DataBits::Eight)?;
+	port.set_parity(Parity::None)?;
+	port.set_stop_bits(StopBits::Two)?;
+	port.set_flow_control(FlowControl::None)
+}
+
+// transmit a frame of up to 512 slots after the given start code. returns
+// once the frame was queued for transmission, so the caller can prepare the
+// next one meanwhile.
+pub fn send_frame(port: &SerialPort, start_code: u8, slots: &[u8], timing: DmxTiming)
+		-> io::Result<()> {
+	if slots.len() > CHANNELS {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput,
+			"DMX512 frames contain at most 512 slots"));
+	}
+	let mut frame = Vec::with_capacity(1 + slots.len());
+	frame.push(start_code);
+	frame.extend_from_slice(slots);
+
+	// the break must not cut off the previous frame
+	let mut writer = port;
+	writer.flush()?;
+
+	port.set_break(true)?;
+	clock::sleep(timing.break_time);
+	port.set_break(false)?;
+	clock::sleep(timing.mark_after_break);
+
+	writer.write_all(&frame)
+}
+
+// transmit dimmer levels with the default timing
+pub fn send_dmx_frame(port: &SerialPort, channels: &[u8; CHANNELS]) -> io::Result<()> {
+	send_frame(port, NULL_START_CODE, channels, DmxTiming::default())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	#[test]
+	fn configure_8n2() {
+		let port = SimulatedSerialPort::new(None).into_serial_port();
+		configure(&port).unwrap();
+		assert_eq!(port.baud_rate().unwrap(), BAUD_RATE);
+		assert_eq!(port.stop_bits().unwrap(), StopBits::Two);
+
+		let e = send_frame(&port, NULL_START_CODE, &[0u8; CHANNELS + 1], DmxTiming::default())
+			.unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+	}
+
+	#[cfg(feature = "mock")]
+	#[test]
+	fn frame_follows_break() {
+		let mock = crate::MockSerialPort::new(None);
+		let port = SerialPort::from_backend(Box::new(mock.try_clone().unwrap()));
+		let mut channels = [0u8; CHANNELS];
+		channels[0] = 255;
+		send_dmx_frame(&port, &channels).unwrap();
+
+		let mut frame = vec![NULL_START_CODE];
+		frame.extend_from_slice(&channels);
+		assert_eq!(mock.take_tx_data(), frame);
+		assert!(!mock.is_break());
+	}
+}
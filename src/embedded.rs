@@ -0,0 +1,77 @@
+// implementations of the embedded-io traits, so code can be shared between
+// firmware and host tools. errors are std::io::Error, which implements
+// embedded_io::Error.
+
+#[cfg(feature = "embedded-io")]
+mod blocking {
+	use std::io::{self, Read, Write};
+
+	use crate::SerialPort;
+
+	impl embedded_io::ErrorType for SerialPort {
+		type Error = io::Error;
+	}
+
+	impl embedded_io::Read for SerialPort {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			Read::read(self, buf)
+		}
+	}
+
+	impl embedded_io::Write for SerialPort {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			Write::write(self, buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Write::flush(self)
+		}
+	}
+
+	impl embedded_io::ErrorType for &SerialPort {
+		type Error = io::Error;
+	}
+
+	impl embedded_io::Read for &SerialPort {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			Read::read(self, buf)
+		}
+	}
+
+	impl embedded_io::Write for &SerialPort {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			Write::write(self, buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Write::flush(self)
+		}
+	}
+}
+
+#[cfg(all(feature = "embedded-io-async", feature = "wasm", target_arch = "wasm32"))]
+mod web {
+	use std::io;
+
+	use crate::web::WebSerialPort;
+
+	impl embedded_io_async::ErrorType for WebSerialPort {
+		type Error = io::Error;
+	}
+
+	impl embedded_io_async::Read for WebSerialPort {
+		async fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			WebSerialPort::read(self, buf).await
+		}
+	}
+
+	impl embedded_io_async::Write for WebSerialPort {
+		async fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			WebSerialPort::write(self, buf).await
+		}
+
+		async fn flush(&mut self) -> io::Result<()> {
+			WebSerialPort::flush(self).await
+		}
+	}
+}
@@ -0,0 +1,470 @@
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::{DataBits, FlowControl, LineMode, Parity, SerialPort, StopBits, TerminalOptions};
+use crate::backend::Backend;
+
+// faults to inject, where probabilities range from 0.0 (never) to 1.0
+// (always). the same seed yields the same faults for the same sequence of
+// reads and writes, so failures are reproducible (unless clones are used
+// concurrently, which makes the sequence depend on scheduling).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Faults {
+	pub seed: u64,
+	// of a read or write failing with ErrorKind::TimedOut immediately
+	// without transferring data
+	pub timeout_probability: f64,
+	// of a read returning fewer bytes than requested (the remaining ones
+	// are returned by subsequent reads)
+	pub short_read_probability: f64,
+	// of a bit being flipped in a byte read or written
+	pub bit_error_probability: f64,
+	// number of bytes read and written after which the port behaves as if
+	// the device was removed, failing all I/O with ErrorKind::NotConnected
+	pub disconnect_after: Option<u64>
+}
+
+struct State {
+	faults: Faults,
+	// xorshift64* state, never zero
+	rng: u64,
+	transferred: u64
+}
+
+impl State {
+	fn new(faults: Faults) -> Self {
+		// scramble the seed via splitmix64, so similar seeds yield different
+		// sequences and zero is valid
+		let mut seed = faults.seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		seed = (seed ^ (seed >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		seed = (seed ^ (seed >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		seed ^= seed >> 31;
+		Self { faults, rng: seed.max(1), transferred: 0 }
+	}
+
+	fn next(&mut self) -> u64 {
+		self.rng ^= self.rng >> 12;
+		self.rng ^= self.rng << 25;
+		self.rng ^= self.rng >> 27;
+		self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+	}
+
+	// true with the given probability
+	fn chance(&mut self, probability: f64) -> bool {
+		probability > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+	}
+
+	// fail with a timeout or disconnect, or limit the number of bytes to be
+	// transferred to the remainder before disconnecting
+	fn admit(&mut self, len: usize) -> io::Result<usize> {
+		let remaining = match self.faults.disconnect_after {
+			Some(limit) if self.transferred >= limit => return Err(io::Error::new(
+				io::ErrorKind::NotConnected, "device disconnected (injected fault)")),
+			Some(limit) => usize::try_from(limit - self.transferred).unwrap_or(usize::MAX),
+			None => usize::MAX
+		};
+		if len > 0 && self.chance(self.faults.timeout_probability) {
+			return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out (injected fault)"));
+		}
+		Ok(len.min(remaining))
+	}
+
+	fn corrupt(&mut self, data: &mut [u8]) {
+		for byte in data {
+			if self.chance(self.faults.bit_error_probability) {
+				*byte ^= 1 << (self.next() % 8);
+			}
+		}
+	}
+}
+
+// wrapper that injects faults into the I/O of a serial port, e.g., to harden
+// protocol implementations against unreliable links. clones share the
+// faults, the random number generator, and the number of bytes transferred.
+pub struct FaultySerialPort {
+	port: SerialPort,
+	state: Arc<Mutex<State>>
+}
+
+impl FaultySerialPort {
+	pub fn new(port: SerialPort, faults: Faults) -> Self {
+		Self {
+			port,
+			state: Arc::new(Mutex::new(State::new(faults)))
+		}
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone()?,
+			state: self.state.clone()
+		})
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone_shared()?,
+			state: self.state.clone()
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	pub fn faults(&self) -> Faults {
+		self.lock().faults
+	}
+
+	// replace the faults, reseeding the random number generator and
+	// resetting the number of bytes transferred, e.g., to reconnect
+	pub fn set_faults(&self, faults: Faults) {
+		*self.lock() = State::new(faults);
+	}
+
+	// number of bytes read and written so far
+	pub fn transferred(&self) -> u64 {
+		self.lock().transferred
+	}
+
+	pub fn get_ref(&self) -> &SerialPort {
+		&self.port
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_via(buf, |buf| (&self.port).read(buf))
+	}
+
+	fn read_via<F>(&self, buf: &mut [u8], read: F) -> io::Result<usize>
+			where F: FnOnce(&mut [u8]) -> io::Result<usize> {
+		let len = {
+			let mut state = self.lock();
+			let len = state.admit(buf.len())?;
+			let probability = state.faults.short_read_probability;
+			if len > 1 && state.chance(probability) {
+				1 + (state.next() % (len as u64 - 1)) as usize
+			} else {
+				len
+			}
+		};
+
+		// the lock must not be held while blocking
+		let len = read(&mut buf[..len])?;
+		let mut state = self.lock();
+		state.transferred += len as u64;
+		state.corrupt(&mut buf[..len]);
+		Ok(len)
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_via(buf, |buf| (&self.port).write(buf))
+	}
+
+	fn write_via<F>(&self, buf: &[u8], write: F) -> io::Result<usize>
+			where F: FnOnce(&[u8]) -> io::Result<usize> {
+		let data = {
+			let mut state = self.lock();
+			let len = state.admit(buf.len())?;
+			let mut data = buf[..len].to_vec();
+			state.corrupt(&mut data);
+			data
+		};
+
+		let len = write(&data)?;
+		self.lock().transferred += len as u64;
+		Ok(len)
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		(&self.port).flush()
+	}
+}
+
+impl Backend for FaultySerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		FaultySerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		FaultySerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		self.read_via(buf, |buf| self.port.read_with_timeout(buf, timeout))
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		self.write_via(buf, |buf| self.port.write_with_timeout(buf, timeout))
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		FaultySerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		FaultySerialPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		FaultySerialPort::try_clone_shared(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.port.read_timeout()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.port.write_timeout()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_read_timeout(timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_write_timeout(timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.port.set_rts(level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.port.set_dtr(level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		self.port.read_cts()
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		self.port.read_dsr()
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		self.port.read_ri()
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		self.port.read_cd()
+	}
+
+	fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.wait_modem_change(timeout)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		self.port.baud_rate()
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.port.set_baud_rate(baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		self.port.data_bits()
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.port.set_data_bits(data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		self.port.parity()
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.port.set_parity(parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		self.port.stop_bits()
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.port.set_stop_bits(stop_bits)
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		self.port.flow_control()
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.port.set_flow_control(flow_control)
+	}
+
+	fn local(&self) -> io::Result<bool> {
+		self.port.local()
+	}
+
+	fn set_local(&self, local: bool) -> io::Result<()> {
+		self.port.set_local(local)
+	}
+
+	fn parity_marking(&self) -> io::Result<bool> {
+		self.port.parity_marking()
+	}
+
+	fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		self.port.set_parity_marking(mark)
+	}
+
+	fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		self.port.set_canonical(mode)
+	}
+
+	fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		self.port.terminal_options()
+	}
+
+	fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		self.port.set_terminal_options(options)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		self.port.set_break(level)
+	}
+
+	fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.port.set_transmit_paused(paused)
+	}
+
+	fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		self.port.transmit_immediate(byte)
+	}
+
+	fn bytes_to_read(&self) -> io::Result<usize> {
+		self.port.bytes_to_read()
+	}
+
+	fn bytes_to_write(&self) -> io::Result<usize> {
+		self.port.bytes_to_write()
+	}
+
+	fn lock(&self) -> io::Result<()> {
+		self.port.lock()
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		self.port.unlock()
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		self.port.purge(input, output)
+	}
+}
+
+impl io::Read for FaultySerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		FaultySerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &FaultySerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		FaultySerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for FaultySerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		FaultySerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		FaultySerialPort::flush(self)
+	}
+}
+
+impl io::Write for &FaultySerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		FaultySerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		FaultySerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	// a faulty port reading what the returned simulation sends and
+	// discarding what it writes
+	fn faulty(faults: Faults) -> (SimulatedSerialPort, FaultySerialPort) {
+		let sim = SimulatedSerialPort::new(Some(Duration::ZERO));
+		let port = sim.try_clone().unwrap().into_serial_port();
+		(sim, FaultySerialPort::new(port, faults))
+	}
+
+	#[test]
+	fn disconnect_after_limit() {
+		let (sim, port) = faulty(Faults { disconnect_after: Some(6), ..Faults::default() });
+		sim.send(b"abcd", Duration::ZERO);
+		let mut buf = [0u8; 8];
+		assert_eq!(port.read(&mut buf).unwrap(), 4);
+
+		// the write is cut short at the limit
+		assert_eq!(port.write(b"efgh").unwrap(), 2);
+		assert_eq!(port.transferred(), 6);
+		assert_eq!(port.write(b"ij").unwrap_err().kind(), io::ErrorKind::NotConnected);
+
+		// reconnect
+		port.set_faults(Faults::default());
+		assert_eq!(port.write(b"ij").unwrap(), 2);
+	}
+
+	#[test]
+	fn certain_faults() {
+		let (sim, port) = faulty(Faults {
+			timeout_probability: 1.0,
+			..Faults::default()
+		});
+		sim.send(b"abcd", Duration::ZERO);
+		let mut buf = [0u8; 4];
+		assert_eq!(port.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+		assert_eq!(port.write(b"abcd").unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+		// each byte has exactly one bit flipped
+		port.set_faults(Faults { bit_error_probability: 1.0, ..Faults::default() });
+		assert_eq!(port.read(&mut buf).unwrap(), 4);
+		for (byte, original) in buf.iter().zip(b"abcd") {
+			assert_eq!((byte ^ original).count_ones(), 1);
+		}
+	}
+
+	// the same seed yields the same faults
+	#[test]
+	fn reproducible_faults() {
+		let faults = Faults {
+			seed: 42,
+			short_read_probability: 0.5,
+			bit_error_probability: 0.1,
+			..Faults::default()
+		};
+		let data: Vec<u8> = (0..=255).collect();
+		let run = || {
+			let (sim, port) = faulty(faults);
+			sim.send(&data, Duration::ZERO);
+			let mut reads = Vec::new();
+			let mut buf = [0u8; 32];
+			while let Ok(len) = port.read(&mut buf) {
+				reads.push(buf[..len].to_vec());
+			}
+			reads
+		};
+
+		let reads = run();
+		assert_eq!(reads, run());
+		assert_eq!(reads.concat().len(), data.len());
+		assert!(reads.len() > data.len() / 32);
+		assert_ne!(reads.concat(), data);
+	}
+}
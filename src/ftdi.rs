@@ -0,0 +1,373 @@
+extern crate libftd2xx;
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use self::libftd2xx::{BitsPerWord, Ftdi, FtdiCommon, FtStatus};
+
+pub use self::libftd2xx::BitMode;
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use crate::backend::{AtomicTimeout, Backend};
+
+// interval of checking the receive queue while waiting for data
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// XON/XOFF characters of software flow control
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+// D2XX sets data bits, parity, and stop bits at once and cannot query them,
+// so the settings are cached to change them individually
+#[derive(Clone, Copy)]
+struct Framing {
+	data_bits: DataBits,
+	parity: Parity,
+	stop_bits: StopBits
+}
+
+impl Framing {
+	fn apply(&self, ftdi: &mut Ftdi) -> io::Result<()> {
+		let bits = match self.data_bits {
+			DataBits::Seven => BitsPerWord::Bits7,
+			DataBits::Eight => BitsPerWord::Bits8,
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"FTDI devices support 7 or 8 data bits only"))
+		};
+		let stop_bits = match self.stop_bits {
+			StopBits::One => libftd2xx::StopBits::Bits1,
+			StopBits::Two => libftd2xx::StopBits::Bits2
+		};
+		let parity = match self.parity {
+			Parity::None => libftd2xx::Parity::No,
+			Parity::Odd => libftd2xx::Parity::Odd,
+			Parity::Even => libftd2xx::Parity::Even,
+			Parity::Mark => libftd2xx::Parity::Mark,
+			Parity::Space => libftd2xx::Parity::Space
+		};
+		ftdi.set_data_characteristics(bits, stop_bits, parity).map_err(ft_error)
+	}
+}
+
+// FTDI device accessed via the D2XX driver instead of the virtual COM port
+// driver. the D2XX API requires exclusive access for every call, so calls of
+// all clones are serialized by a mutex, which is not held while waiting for
+// received data.
+pub struct FtdiPort {
+	ftdi: Arc<Mutex<Ftdi>>,
+	// locked after ftdi if both are locked
+	framing: Arc<Mutex<Framing>>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+fn ft_error(status: FtStatus) -> io::Error {
+	let kind = match status {
+		FtStatus::DEVICE_NOT_FOUND => io::ErrorKind::NotFound,
+		FtStatus::DEVICE_NOT_OPENED => io::ErrorKind::PermissionDenied,
+		FtStatus::INVALID_PARAMETER | FtStatus::INVALID_BAUD_RATE =>
+			io::ErrorKind::InvalidInput,
+		FtStatus::NOT_SUPPORTED => io::ErrorKind::Unsupported,
+		_ => io::ErrorKind::Other
+	};
+	io::Error::new(kind, format!("D2XX call failed: {:?}", status))
+}
+
+// D2XX treats a zero timeout as infinite, so zero write timeouts are rounded
+// up to the shortest one (reads do not use D2XX timeouts, see read())
+fn ft_timeout(timeout: Option<Duration>) -> Duration {
+	match timeout {
+		None => Duration::ZERO,
+		Some(dur) if dur < Duration::from_millis(1) => Duration::from_millis(1),
+		Some(dur) => dur
+	}
+}
+
+impl FtdiPort {
+	// open the FTDI device with the given USB serial number (e.g., as stored
+	// in its EEPROM)
+	pub fn open(serial_number: &str, timeout: Option<Duration>) -> io::Result<Self> {
+		let mut ftdi = Ftdi::with_serial_number(serial_number).map_err(ft_error)?;
+
+		// reset device to a known state (UART mode, empty buffers)
+		ftdi.set_bit_mode(0x00, BitMode::Reset).map_err(ft_error)?;
+		ftdi.purge_all().map_err(ft_error)?;
+		ftdi.set_timeouts(ft_timeout(timeout), ft_timeout(timeout))
+			.map_err(ft_error)?;
+		let framing = Framing {
+			data_bits: DataBits::Eight,
+			parity: Parity::None,
+			stop_bits: StopBits::One
+		};
+		framing.apply(&mut ftdi)?;
+
+		Ok(Self {
+			ftdi: Arc::new(Mutex::new(ftdi)),
+			framing: Arc::new(Mutex::new(framing)),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		})
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		Ok(Self {
+			ftdi: self.ftdi.clone(),
+			framing: self.framing.clone(),
+			timeout_read: AtomicTimeout::new(self.timeout_read.load()),
+			timeout_write: AtomicTimeout::new(self.timeout_write.load())
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Ftdi> {
+		self.ftdi.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn framing(&self) -> Framing {
+		*self.framing.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// apply the framing as changed by update and cache it if successful
+	fn update_framing<F: FnOnce(&mut Framing)>(&self, update: F) -> io::Result<()> {
+		let mut ftdi = self.lock();
+		let mut framing = self.framing.lock().unwrap_or_else(|e| e.into_inner());
+		let mut updated = *framing;
+		update(&mut updated);
+		updated.apply(&mut ftdi)?;
+		*framing = updated;
+		Ok(())
+	}
+
+	// D2XX timeouts apply to the device instead of the handle, so the write
+	// timeout must be reapplied if clones (or individual calls) use different
+	// ones
+	fn apply_write_timeout(ftdi: &mut Ftdi, timeout: Option<Duration>) -> io::Result<()> {
+		ftdi.set_timeouts(ft_timeout(None), ft_timeout(timeout)).map_err(ft_error)
+	}
+
+	pub fn serial_number(&self) -> io::Result<String> {
+		self.lock().device_info()
+			.map(|info| info.serial_number)
+			.map_err(ft_error)
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.lock().set_baud_rate(baud_rate).map_err(ft_error)
+	}
+
+	// switch between UART and bit-bang/MPSSE modes. mask selects outputs.
+	pub fn set_bit_mode(&self, mask: u8, mode: BitMode) -> io::Result<()> {
+		self.lock().set_bit_mode(mask, mode).map_err(ft_error)
+	}
+
+	pub fn set_latency_timer(&self, latency: Duration) -> io::Result<()> {
+		self.lock().set_latency_timer(latency).map_err(ft_error)
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		// FT_Read() only returns before the timeout expires if the buffer is
+		// full (like ReadFile() on Windows) and blocking in it would hold the
+		// lock, stalling writes and modem line calls of clones. so poll the
+		// receive queue without holding the lock and only read bytes that are
+		// already queued, which FT_Read() returns immediately. a zero timeout
+		// checks the queue once without blocking.
+		let entry = clock::now();
+		loop {
+			{
+				let mut ftdi = self.lock();
+				let queued = ftdi.queue_status().map_err(ft_error)?;
+				if queued > 0 {
+					let len = queued.min(buf.len());
+					let len = ftdi.read(&mut buf[..len]).map_err(ft_error)?;
+					if len > 0 {
+						return Ok(len);
+					}
+				}
+			}
+
+			let wait = match timeout {
+				None => POLL_INTERVAL,
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) if !remaining.is_zero() => remaining.min(POLL_INTERVAL),
+					_ => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"reading from FTDI device timed out"))
+				}
+			};
+			clock::sleep(wait);
+		}
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_with_timeout(buf, self.timeout_write.load())
+	}
+
+	// like write(), but with the given timeout instead of the write timeout
+	pub fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		let mut ftdi = self.lock();
+		Self::apply_write_timeout(&mut ftdi, timeout)?;
+
+		match ftdi.write(buf).map_err(ft_error)? {
+			0 if buf.is_empty() => Ok(0),
+			0 => Err(io::Error::new(io::ErrorKind::TimedOut,
+				"FT_Write() timed out (0 bytes written)")),
+			len => Ok(len)
+		}
+	}
+
+	// D2XX writes are passed to the device immediately
+	pub fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+}
+
+// open "ftdi://SERIALNUMBER" backend
+pub(crate) fn open_backend(path: &str, timeout: Option<Duration>)
+		-> io::Result<Box<dyn Backend>> {
+	FtdiPort::open(path, timeout).map(|port| Box::new(port) as Box<dyn Backend>)
+}
+
+impl Backend for FtdiPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		FtdiPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		FtdiPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		FtdiPort::read_with_timeout(self, buf, timeout)
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		FtdiPort::write_with_timeout(self, buf, timeout)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		FtdiPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		FtdiPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		let mut ftdi = self.lock();
+		if level { ftdi.set_rts() } else { ftdi.clear_rts() }.map_err(ft_error)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		let mut ftdi = self.lock();
+		if level { ftdi.set_dtr() } else { ftdi.clear_dtr() }.map_err(ft_error)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		self.lock().modem_status().map(|s| s.clear_to_send()).map_err(ft_error)
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		self.lock().modem_status().map(|s| s.data_set_ready()).map_err(ft_error)
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		self.lock().modem_status().map(|s| s.ring_indicator()).map_err(ft_error)
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		self.lock().modem_status().map(|s| s.carrier_detect()).map_err(ft_error)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		FtdiPort::set_baud_rate(self, baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		Ok(self.framing().data_bits)
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.update_framing(|framing| framing.data_bits = data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		Ok(self.framing().parity)
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.update_framing(|framing| framing.parity = parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		Ok(self.framing().stop_bits)
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.update_framing(|framing| framing.stop_bits = stop_bits)
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		let mut ftdi = self.lock();
+		match flow_control {
+			FlowControl::None => ftdi.set_flow_control_none(),
+			FlowControl::Software => ftdi.set_flow_control_xon_xoff(XON, XOFF),
+			FlowControl::Hardware => ftdi.set_flow_control_rts_cts()
+		}.map_err(ft_error)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		let mut ftdi = self.lock();
+		if level { ftdi.set_break_on() } else { ftdi.set_break_off() }.map_err(ft_error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn zero_timeouts_are_not_infinite() {
+		assert_eq!(ft_timeout(None), Duration::ZERO);
+		assert_eq!(ft_timeout(Some(Duration::ZERO)), Duration::from_millis(1));
+		assert_eq!(ft_timeout(Some(Duration::from_secs(2))), Duration::from_secs(2));
+	}
+
+	#[test]
+	fn error_kinds() {
+		assert_eq!(ft_error(FtStatus::DEVICE_NOT_FOUND).kind(), io::ErrorKind::NotFound);
+		assert_eq!(ft_error(FtStatus::INVALID_BAUD_RATE).kind(), io::ErrorKind::InvalidInput);
+		assert_eq!(ft_error(FtStatus::NOT_SUPPORTED).kind(), io::ErrorKind::Unsupported);
+	}
+}
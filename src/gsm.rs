@@ -0,0 +1,99 @@
+// GSM 07.10 multiplexing (CMUX) via the Linux n_gsm line discipline, see:
+// https://docs.kernel.org/driver-api/tty/n_gsm.html
+
+use std::ffi::OsString;
+use std::io;
+use std::time::Duration;
+
+use crate::SerialPort;
+
+// line disciplines, see SerialPort::set_line_discipline()
+pub const N_TTY: i32 = 0;
+pub const N_GSM0710: i32 = 21;
+
+// highest data link connection identifier. channel 0 is the control channel
+// used by the multiplexer itself.
+pub const MAX_CHANNEL: u8 = 63;
+
+// struct gsm_config of linux/gsmmux.h. fields use the kernel's units, e.g.,
+// t1 and t2 in 10 ms and t3 in seconds.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GsmConfig {
+	pub adaption: u32,
+	// 0 for basic option, 1 for advanced option
+	pub encapsulation: u32,
+	// whether this side starts the multiplexer, i.e., 1 when talking to a
+	// modem
+	pub initiator: u32,
+	pub t1: u32,
+	pub t2: u32,
+	pub t3: u32,
+	pub n2: u32,
+	pub mru: u32,
+	pub mtu: u32,
+	pub k: u32,
+	pub i: u32,
+	unused: [u32; 8]
+}
+
+// a port with the n_gsm line discipline attached. the multiplexer exists as
+// long as the port remains open, so it is owned by this struct.
+//
+// the modem must be switched to multiplexing mode first, usually with
+// AT+CMUX=0, e.g.:
+//   port.write_all(b"AT+CMUX=0\r")?;  // and wait for OK
+//   let mux = GsmMux::attach(port)?;
+//   let mut config = mux.config()?;
+//   config.initiator = 1;
+//   mux.set_config(&config)?;
+//   let channel = mux.open_channel(1, Some(Duration::from_secs(1)))?;
+pub struct GsmMux {
+	port: SerialPort
+}
+
+impl GsmMux {
+	// attach the line discipline with the kernel's default configuration.
+	// requires CAP_NET_ADMIN.
+	pub fn attach(port: SerialPort) -> io::Result<Self> {
+		port.set_line_discipline(N_GSM0710)?;
+		Ok(Self { port })
+	}
+
+	pub fn config(&self) -> io::Result<GsmConfig> {
+		self.port.os_port("GSM multiplexing")?.gsm_config()
+	}
+
+	// changing the configuration restarts the multiplexer
+	pub fn set_config(&self, config: &GsmConfig) -> io::Result<()> {
+		self.port.os_port("GSM multiplexing")?.set_gsm_config(config)
+	}
+
+	// path of the virtual TTY of channel (1 to MAX_CHANNEL), i.e.,
+	// /dev/gsmttyN, where N depends on the number of the multiplexer
+	pub fn channel_path(&self, channel: u8) -> io::Result<OsString> {
+		if channel == 0 || channel > MAX_CHANNEL {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"GSM multiplexer channels range from 1 to 63"));
+		}
+		let first = self.port.os_port("GSM multiplexing")?.gsm_first_minor()?;
+		Ok(format!("/dev/gsmtty{}", first - 1 + channel as u32).into())
+	}
+
+	// open the virtual TTY of channel, which the kernel establishes when it
+	// is opened
+	pub fn open_channel(&self, channel: u8, timeout: Option<Duration>) -> io::Result<SerialPort> {
+		SerialPort::open(&self.channel_path(channel)?, timeout)
+	}
+
+	pub fn get_ref(&self) -> &SerialPort {
+		&self.port
+	}
+
+	// restore the default line discipline, which closes the multiplexer
+	// and all of its channels
+	pub fn detach(self) -> io::Result<SerialPort> {
+		self.port.set_line_discipline(N_TTY)?;
+		Ok(self.port)
+	}
+}
@@ -0,0 +1,143 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{clock, SerialPort};
+
+// periodically writes a fixed byte sequence from a background thread, e.g.,
+// to keep devices or radio links from dropping an idle session. the thread
+// terminates on the first write error, which is returned by stop().
+pub struct Heartbeat {
+	// dropping the sender wakes the thread immediately
+	stop: Option<Sender<()>>,
+	finished: Arc<AtomicBool>,
+	thread: Option<JoinHandle<io::Result<()>>>
+}
+
+impl Heartbeat {
+	// write data every interval, starting after the first interval elapsed
+	pub fn new<W>(mut writer: W, data: Vec<u8>, interval: Duration) -> Self
+			where W: Write + Send + 'static {
+		let (stop, stopped) = mpsc::channel::<()>();
+		let finished = Arc::new(AtomicBool::new(false));
+
+		let thread_finished = finished.clone();
+		let thread = thread::spawn(move || {
+			// schedule relative to the start to avoid drifting by the time
+			// spent writing
+			let mut next = clock::now() + interval;
+			let res = loop {
+				let wait = next.saturating_duration_since(clock::now());
+				match stopped.recv_timeout(wait) {
+					Err(RecvTimeoutError::Timeout) => (),
+					Ok(()) | Err(RecvTimeoutError::Disconnected) => break Ok(())
+				}
+
+				if let Err(e) = writer.write_all(&data).and_then(|()| writer.flush()) {
+					break Err(e);
+				}
+
+				// skip beats missed due to blocking writes instead of
+				// sending them back-to-back
+				next += interval;
+				let now = clock::now();
+				if next < now {
+					next = now + interval;
+				}
+			};
+
+			thread_finished.store(true, Ordering::Relaxed);
+			res
+		});
+
+		Self {
+			stop: Some(stop),
+			finished,
+			thread: Some(thread)
+		}
+	}
+
+	// returns true if the heartbeat thread terminated (e.g., due to an error)
+	pub fn is_finished(&self) -> bool {
+		self.finished.load(Ordering::Relaxed)
+	}
+
+	// stop sending and return the error that terminated sending (if any)
+	pub fn stop(mut self) -> io::Result<()> {
+		self.stop.take();
+		match self.thread.take() {
+			Some(thread) => thread.join().unwrap_or_else(|_| Err(io::Error::new(
+				io::ErrorKind::Other, "heartbeat thread panicked"))),
+			None => Ok(())
+		}
+	}
+}
+
+impl Drop for Heartbeat {
+	fn drop(&mut self) {
+		self.stop.take();
+		if let Some(thread) = self.thread.take() {
+			let _res = thread.join();
+		}
+	}
+}
+
+// send data on a clone of the port every interval, while the port itself
+// remains usable for regular communication
+pub fn heartbeat(port: &SerialPort, data: &[u8], interval: Duration)
+		-> io::Result<Heartbeat> {
+	Ok(Heartbeat::new(port.try_clone()?, data.to_vec(), interval))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+
+	#[derive(Clone, Default)]
+	struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for SharedWriter {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			if buf.starts_with(b"fail") {
+				return Err(io::Error::new(io::ErrorKind::BrokenPipe, "device removed"));
+			}
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn periodic_writes() {
+		let writer = SharedWriter::default();
+		let heartbeat = Heartbeat::new(writer.clone(), b"ping".to_vec(),
+			Duration::from_millis(20));
+		thread::sleep(Duration::from_millis(110));
+		assert!(!heartbeat.is_finished());
+		heartbeat.stop().unwrap();
+
+		// stopping takes effect immediately
+		let written = writer.0.lock().unwrap().clone();
+		assert_eq!(written.len() % 4, 0);
+		assert!((3..=6).contains(&(written.len() / 4)), "{} beats", written.len() / 4);
+		thread::sleep(Duration::from_millis(40));
+		assert_eq!(writer.0.lock().unwrap().len(), written.len());
+	}
+
+	#[test]
+	fn write_errors_end_heartbeat() {
+		let heartbeat = Heartbeat::new(SharedWriter::default(), b"fail".to_vec(),
+			Duration::from_millis(1));
+		while !heartbeat.is_finished() {
+			thread::sleep(Duration::from_millis(1));
+		}
+		assert_eq!(heartbeat.stop().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+	}
+}
@@ -0,0 +1,206 @@
+// IEC 62056-21 (formerly IEC 61107) sessions with smart meters via an
+// optical probe, which start at 300 Bd 7E1 and may continue at a higher
+// baud rate proposed by the meter, e.g.:
+//   let mut port = SerialPort::open("/dev/ttyUSB0", Some(Duration::from_secs(2)))?;
+//   iec62056::configure(&port)?;
+//   let id = iec62056::sign_on(&mut port, "", Mode::DataReadout, Duration::from_secs(3))?;
+//   let readout = iec62056::read_data_block(&mut port, Duration::from_secs(10))?;
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+pub const INITIAL_BAUD_RATE: u32 = 300;
+
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+const ACK: u8 = 0x06;
+
+// option selected by the acknowledgement of protocol mode C
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+	DataReadout,
+	Programming
+}
+
+// identification message sent by the meter, e.g., "/ISK5MT174-0001"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identification {
+	// three letter manufacturer ID
+	pub manufacturer: String,
+	// baud rate identification character, which also indicates the protocol
+	// mode ('0' to '9' for mode C, 'A' to 'I' for mode B)
+	pub baud_rate_char: u8,
+	pub identification: String
+}
+
+impl Identification {
+	// baud rate proposed by the meter
+	pub fn baud_rate(&self) -> Option<u32> {
+		match self.baud_rate_char {
+			b'0' => Some(300),
+			b'1' | b'A' => Some(600),
+			b'2' | b'B' => Some(1200),
+			b'3' | b'C' => Some(2400),
+			b'4' | b'D' => Some(4800),
+			b'5' | b'E' => Some(9600),
+			b'6' | b'F' => Some(19200),
+			_ => None
+		}
+	}
+
+	fn parse(line: &[u8]) -> io::Result<Self> {
+		let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid identification message");
+		let line = std::str::from_utf8(line).map_err(|_| invalid())?;
+		let line = line.strip_prefix('/').ok_or_else(invalid)?;
+		if line.len() < 5 || !line.is_ascii() {
+			return Err(invalid());
+		}
+		Ok(Self {
+			manufacturer: line[..3].to_string(),
+			baud_rate_char: line.as_bytes()[3],
+			identification: line[4..].to_string()
+		})
+	}
+}
+
+// 300 Bd 7E1 without flow control
+pub fn configure(port: &SerialPort) -> io::Result<()> {
+	port.set_baud_rate(INITIAL_BAUD_RATE)?;
+	port.set_data_bits(DataBits::Seven)?;
+	port.set_parity(Parity::Even)?;
+	port.set_stop_bits(StopBits::One)?;
+	port.set_flow_control(FlowControl::None)
+}
+
+fn read_byte(port: &mut SerialPort, deadline: Instant) -> io::Result<u8> {
+	let remaining = deadline.saturating_duration_since(clock::now());
+	let mut byte = [0u8];
+	port.read_exact_timeout(&mut byte, remaining)?;
+	Ok(byte[0])
+}
+
+// read a line terminated by CR LF (which is not returned)
+fn read_line(port: &mut SerialPort, timeout: Duration) -> io::Result<Vec<u8>> {
+	let deadline = clock::now() + timeout;
+	let mut line = Vec::new();
+	while !line.ends_with(b"\r\n") {
+		line.push(read_byte(port, deadline)?);
+	}
+	line.truncate(line.len() - 2);
+	Ok(line)
+}
+
+// send the request message to the meter at address (empty for any), read
+// its identification, and switch to the baud rate it proposed. in protocol
+// mode C, the switch is acknowledged with the given mode first. the port
+// must be configured (see configure()) and the timeout limits reading the
+// identification.
+pub fn sign_on(port: &mut SerialPort, address: &str, mode: Mode, timeout: Duration)
+		-> io::Result<Identification> {
+	port.purge(true, false)?;
+	write!(port, "/?{}!\r\n", address)?;
+	let id = Identification::parse(&read_line(port, timeout)?)?;
+
+	match (id.baud_rate_char, id.baud_rate()) {
+		(b'0'..=b'9', Some(baud_rate)) => {
+			let option = match mode {
+				Mode::DataReadout => b'0',
+				Mode::Programming => b'1'
+			};
+			port.write_all(&[ACK, b'0', id.baud_rate_char, option, b'\r', b'\n'])?;
+			port.switch_baud_rate(baud_rate)?;
+		},
+		// protocol mode B switches without acknowledgement
+		(_, Some(baud_rate)) => port.switch_baud_rate(baud_rate)?,
+		// protocol mode A and unknown ones keep the initial baud rate
+		(_, None) => ()
+	}
+	Ok(id)
+}
+
+// read a data message (STX, data, ETX, and block check character) and return
+// the data after verifying the block check character
+pub fn read_data_block(port: &mut SerialPort, timeout: Duration) -> io::Result<Vec<u8>> {
+	let deadline = clock::now() + timeout;
+
+	// skip anything preceding the block, e.g., an echo of the request
+	while read_byte(port, deadline)? != STX {}
+
+	let mut data = Vec::new();
+	loop {
+		match read_byte(port, deadline)? {
+			ETX => break,
+			byte => data.push(byte)
+		}
+	}
+
+	// XOR of all bytes following STX, including ETX
+	let bcc = data.iter().fold(ETX, |bcc, byte| bcc ^ byte);
+	if read_byte(port, deadline)? & 0x7f != bcc & 0x7f {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "block check character mismatch"));
+	}
+	Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	// data message containing data, with its block check character
+	fn data_message(data: &[u8]) -> Vec<u8> {
+		let mut message = vec![STX];
+		message.extend_from_slice(data);
+		message.push(ETX);
+		message.push(data.iter().fold(ETX, |bcc, byte| bcc ^ byte));
+		message
+	}
+
+	#[test]
+	fn parse_identification() {
+		let id = Identification::parse(b"/ISK5MT174-0001").unwrap();
+		assert_eq!(id.manufacturer, "ISK");
+		assert_eq!(id.baud_rate_char, b'5');
+		assert_eq!(id.identification, "MT174-0001");
+		assert_eq!(id.baud_rate(), Some(9600));
+
+		for line in [&b"ISK5MT174"[..], b"/ISK5", b"/IS\xc3\xa45MT174"] {
+			assert!(Identification::parse(line).is_err());
+		}
+	}
+
+	#[test]
+	fn mode_c_readout() {
+		let sim = SimulatedSerialPort::new(None);
+		sim.on(b"/?12345678!\r\n", b"/ISK5MT174-0001\r\n", Duration::from_millis(1));
+		let readout = b"1.8.0(001234.5*kWh)\r\n!\r\n";
+		sim.on(&[ACK, b'0', b'5', b'0', b'\r', b'\n'], &data_message(readout), Duration::ZERO);
+
+		let mut port = sim.try_clone().unwrap().into_serial_port();
+		configure(&port).unwrap();
+		assert_eq!(port.baud_rate().unwrap(), INITIAL_BAUD_RATE);
+		assert_eq!(port.data_bits().unwrap(), DataBits::Seven);
+		assert_eq!(port.parity().unwrap(), Parity::Even);
+
+		let timeout = Duration::from_secs(5);
+		let id = sign_on(&mut port, "12345678", Mode::DataReadout, timeout).unwrap();
+		assert_eq!(id.manufacturer, "ISK");
+		assert_eq!(port.baud_rate().unwrap(), 9600);
+		assert_eq!(read_data_block(&mut port, timeout).unwrap(), readout);
+		assert!(sim.take_unmatched().is_empty());
+	}
+
+	#[test]
+	fn block_check_mismatch() {
+		let sim = SimulatedSerialPort::new(None);
+		let mut message = data_message(b"data");
+		*message.last_mut().unwrap() ^= 1;
+		sim.send(&message, Duration::ZERO);
+
+		let mut port = sim.into_serial_port();
+		let e = read_data_block(&mut port, Duration::from_secs(5)).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+	}
+}
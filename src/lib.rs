@@ -1,54 +1,2132 @@
 use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::io;
-use std::time::Duration;
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod clock;
 mod sys;
 
-pub struct SerialPort(sys::SerialPort);
+pub mod backend;
+pub mod bench;
+pub mod bridge;
+pub mod bus;
+pub mod codec;
+pub mod dmx;
+pub mod fault;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod gsm;
+pub mod heartbeat;
+pub mod iec62056;
+pub mod line;
+pub mod midi;
+pub mod modem;
+pub mod parity;
+pub mod reactor;
+pub mod replay;
+pub mod rfc2217;
+pub mod shared;
+pub mod sim;
+pub mod tap;
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-io-async"))]
+mod embedded;
+
+#[cfg(feature = "mock")]
+mod mock;
+
+#[cfg(feature = "mock")]
+pub use mock::MockSerialPort;
+
+#[cfg(feature = "mock-clock")]
+pub use clock::MockClock;
+
+#[cfg(feature = "serialport-compat")]
+pub mod compat;
+
+#[cfg(feature = "ftdi")]
+pub mod ftdi;
+
+#[cfg(feature = "profiles")]
+pub mod profiles;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "usb")]
+pub mod usb;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod web;
+
+use backend::{AtomicTimeout, Backend};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataBits {
+	Five,
+	Six,
+	Seven,
+	Eight
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+	None,
+	Odd,
+	Even,
+	Mark,
+	Space
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+	One,
+	Two
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlowControl {
+	None,
+	Software,
+	Hardware
+}
+
+// read or write timeout, spelling out what Option<Duration> (as accepted by
+// SerialPort::set_read_timeout()) leaves implicit: None blocks indefinitely
+// and a zero duration does not block at all. converts to and from
+// Option<Duration>, where Bounded(Duration::ZERO) becomes NonBlocking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timeout {
+	// wait until at least one byte was transferred
+	Blocking,
+	// transfer what is possible immediately or fail with ErrorKind::TimedOut
+	NonBlocking,
+	// wait until at least one byte was transferred or the duration elapsed
+	Bounded(Duration)
+}
+
+impl From<Option<Duration>> for Timeout {
+	fn from(timeout: Option<Duration>) -> Self {
+		match timeout {
+			None => Self::Blocking,
+			Some(timeout) if timeout.is_zero() => Self::NonBlocking,
+			Some(timeout) => Self::Bounded(timeout)
+		}
+	}
+}
+
+impl From<Duration> for Timeout {
+	fn from(timeout: Duration) -> Self {
+		Some(timeout).into()
+	}
+}
+
+impl From<Timeout> for Option<Duration> {
+	fn from(timeout: Timeout) -> Self {
+		match timeout {
+			Timeout::Blocking => None,
+			Timeout::NonBlocking => Some(Duration::ZERO),
+			Timeout::Bounded(timeout) => Some(timeout)
+		}
+	}
+}
+
+// how a serial device is attached, as far as enumeration can tell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeviceKind {
+	Unknown,
+	// UART on the mainboard or an expansion card
+	Native,
+	Usb,
+	// serial port profile (SPP) link, which may take several seconds to
+	// open while the radio connects (see SerialPort::open_timeout())
+	Bluetooth,
+	// virtual null-modem pair (e.g., com0com or tty0tty), whose line
+	// settings like the baud rate are meaningless
+	Virtual
+}
+
+// identification of a USB device, as far as enumeration can tell
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UsbInfo {
+	pub vendor_id: u16,
+	pub product_id: u16,
+	pub serial_number: Option<String>,
+	// physical location, which remains the same when replugging a device
+	// into the same socket, e.g., "1-3.2" (port 2 of the hub at port 3 of
+	// bus 1) on Linux and location information like "Port_#0002.Hub_#0003"
+	// on Windows (not available for all drivers)
+	pub port_path: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeviceInfo {
+	// path to open the device with, e.g., "/dev/ttyUSB0" or "COM3"
+	pub path: OsString,
+	pub kind: DeviceKind,
+	// None if the device is not attached via USB or the platform does not
+	// report its IDs (e.g., enumeration via /dev on macOS and the BSDs)
+	pub usb: Option<UsbInfo>,
+	// kernel driver bound to the device, e.g., "ftdi_sio", "cdc_acm", or
+	// "cp210x" on Linux and the driver's service (e.g., "usbser") on Windows,
+	// as workarounds for adapter quirks are often driver-specific
+	pub driver: Option<String>,
+	// see SerialPort::probe(), e.g., to grey out busy ports in a picker
+	pub status: PortStatus,
+	// identifier that survives reboots and renumbering of ports, e.g., to
+	// remember the port a user chose. the /dev/serial/by-id link on Linux
+	// (which can be opened like path) and the device instance ID (e.g.,
+	// "USB\VID_2341&PID_0043\85736323838351F0B1A1") on Windows.
+	pub id: Option<OsString>
+}
+
+impl DeviceInfo {
+	// open the described device like SerialPort::open(), but never treat its
+	// path as a backend URL
+	pub fn open(&self, timeout: Option<Duration>) -> io::Result<SerialPort> {
+		SerialPort::open_os(&self.path, timeout)
+	}
+}
+
+// result of SerialPort::probe(), also reported by enumeration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PortStatus {
+	Missing,
+	PermissionDenied,
+	// opened by another process (best effort). detected via the lock placed
+	// by SerialPort::open() on Linux and by failing to open the port without
+	// configuring it on Windows. not detected on other platforms.
+	Busy,
+	// no reason was found why opening the port would fail
+	Present
+}
+
+// selects devices by their USB IDs (see SerialPort::open_when_present()).
+// fields that are None match any value, so the default selector matches any
+// device, whereas setting a field only matches devices reporting USB IDs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceSelector {
+	pub vendor_id: Option<u16>,
+	pub product_id: Option<u16>,
+	pub serial_number: Option<String>
+}
+
+impl DeviceSelector {
+	pub fn matches(&self, info: &DeviceInfo) -> bool {
+		if *self == Self::default() {
+			return true;
+		}
+		let usb = match &info.usb {
+			Some(usb) => usb,
+			None => return false
+		};
+		self.vendor_id.map_or(true, |vendor_id| vendor_id == usb.vendor_id)
+			&& self.product_id.map_or(true, |product_id| product_id == usb.product_id)
+			&& self.serial_number.as_ref().map_or(true, |serial_number|
+				usb.serial_number.as_ref() == Some(serial_number))
+	}
+}
+
+// iterator over available devices (see SerialPort::devices())
+#[cfg(feature = "enumerate")]
+pub struct Devices(sys::Devices);
+
+#[cfg(feature = "enumerate")]
+impl Iterator for Devices {
+	type Item = DeviceInfo;
+
+	fn next(&mut self) -> Option<DeviceInfo> {
+		self.0.next()
+	}
+}
+
+// set of COM port events (see WaitCommEvent()), combined via bitwise or
+#[cfg(all(windows, feature = "experimental"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommEvents(u32);
+
+#[cfg(all(windows, feature = "experimental"))]
+impl CommEvents {
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-setcommmask
+	pub const RX_CHAR: Self = Self(0x0001);
+	pub const TX_EMPTY: Self = Self(0x0004);
+	pub const CTS: Self = Self(0x0008);
+	pub const DSR: Self = Self(0x0010);
+	pub const RLSD: Self = Self(0x0020);
+	pub const BREAK: Self = Self(0x0040);
+	pub const ERR: Self = Self(0x0080);
+	pub const RING: Self = Self(0x0100);
+
+	pub const fn empty() -> Self {
+		Self(0)
+	}
+
+	pub const fn all() -> Self {
+		Self(0x01fd)
+	}
+
+	// ignores unsupported events
+	pub const fn from_bits(bits: u32) -> Self {
+		Self(bits & Self::all().0)
+	}
+
+	pub const fn bits(self) -> u32 {
+		self.0
+	}
+
+	pub const fn is_empty(self) -> bool {
+		self.0 == 0
+	}
+
+	pub const fn contains(self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+#[cfg(all(windows, feature = "experimental"))]
+impl std::ops::BitOr for CommEvents {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+#[cfg(all(windows, feature = "experimental"))]
+impl std::ops::BitAnd for CommEvents {
+	type Output = Self;
+
+	fn bitand(self, rhs: Self) -> Self {
+		Self(self.0 & rhs.0)
+	}
+}
+
+// raw COMMTIMEOUTS fields in milliseconds for behaviors that read and write
+// timeouts cannot express, e.g., returning after an inter-byte gap
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommTimeouts {
+	pub read_interval: u32,
+	pub read_total_multiplier: u32,
+	pub read_total_constant: u32,
+	pub write_total_multiplier: u32,
+	pub write_total_constant: u32
+}
+
+// system-wide default line settings of a COM port (see
+// SerialPort::default_comm_config())
+#[cfg(windows)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefaultCommConfig {
+	pub baud_rate: u32,
+	pub data_bits: DataBits,
+	pub parity: Parity,
+	pub stop_bits: StopBits,
+	pub flow_control: FlowControl
+}
+
+// canonical (line-buffered) input, i.e., reads return at most one line,
+// which ends with NL or one of the additional terminators
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineMode {
+	// e.g., Some(b'\r') for devices terminating lines with CR only
+	pub eol: Option<u8>,
+	pub eol2: Option<u8>,
+	// DEL erases the last byte and Ctrl+U the whole line, as typed on an
+	// interactive console. Ctrl+D ends the line without being read, so it
+	// reads as end of file (0 bytes) on an empty line.
+	pub editing: bool
+}
+
+// terminal-style echo and output processing, all disabled by default (raw
+// mode), e.g., for terminal servers echoing what a user types
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TerminalOptions {
+	// send received bytes back (ECHO)
+	pub echo: bool,
+	// echo erased bytes as backspace, space, backspace in canonical mode
+	// with line editing (ECHOE)
+	pub echo_erase: bool,
+	// transmit NL as CR NL (ONLCR)
+	pub nl_to_crnl: bool
+}
+
+// returned by SerialPort::read_exact_timeout() and transaction() if the
+// buffer could not be filled.
+// converts into the underlying io::Error, e.g., for use with ?.
+#[derive(Debug)]
+pub struct PartialRead {
+	// number of bytes received into the start of buf
+	pub received: usize,
+	pub error: io::Error
+}
+
+impl fmt::Display for PartialRead {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} (received {} bytes)", self.error, self.received)
+	}
+}
+
+impl std::error::Error for PartialRead {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+impl From<PartialRead> for io::Error {
+	fn from(partial: PartialRead) -> Self {
+		partial.error
+	}
+}
+
+// returned (wrapped in an io::Error of ErrorKind::InvalidInput) by
+// SerialPort::open() for devices that are not serial ports, e.g., regular
+// files or directories. check for it via:
+//   e.get_ref().map_or(false, |e| e.is::<NotASerialPort>())
+#[derive(Debug)]
+pub struct NotASerialPort;
+
+impl fmt::Display for NotASerialPort {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("not a serial port")
+	}
+}
+
+impl std::error::Error for NotASerialPort {}
+
+impl From<NotASerialPort> for io::Error {
+	fn from(error: NotASerialPort) -> Self {
+		io::Error::new(io::ErrorKind::InvalidInput, error)
+	}
+}
+
+// limits the transmit rate for targets with tiny receive buffers, which
+// overflow when an adapter sends data back-to-back at full speed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WritePacing {
+	BytesPerSecond(u32),
+	ByteDelay(Duration)
+}
+
+impl WritePacing {
+	fn interval(self) -> Duration {
+		match self {
+			WritePacing::BytesPerSecond(rate) =>
+				Duration::from_secs(1) / rate.max(1),
+			WritePacing::ByteDelay(delay) => delay
+		}
+	}
+}
+
+// coalesces small writes (e.g., single bytes written by a formatter) into
+// larger ones, as each transfer to USB adapters carries considerable
+// overhead. bytes are buffered until capacity bytes are pending or interval
+// elapsed since the first of them was buffered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteCoalescing {
+	pub capacity: usize,
+	pub interval: Duration
+}
+
+// exponential backoff between attempts of SerialPort::open_with_retry(),
+// doubling the delay after each attempt from initial up to max
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+	pub initial: Duration,
+	pub max: Duration
+}
+
+impl Default for Backoff {
+	fn default() -> Self {
+		Self { initial: Duration::from_millis(10), max: Duration::from_millis(500) }
+	}
+}
+
+// how often the background writer checks whether all ports sharing a write
+// buffer were dropped while no bytes are pending
+const COALESCING_IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct SerialPort {
+	inner: Inner,
+	// path of OS serial ports opened by path, which allows reopening them
+	path: Option<OsString>,
+	// shared with clones, because pacing applies to the device
+	pacing: Arc<Mutex<Option<Pacer>>>,
+	// shared with clones, because buffered bytes were received by the device
+	read_buffer: Arc<SharedReadBuffer>,
+	// shared with clones, because buffered bytes must be written in order
+	write_buffer: Arc<SharedWriteBuffer>,
+	// settings applied via this port or its clones, restored by reopen()
+	settings: Arc<Mutex<Settings>>,
+	// shared with clones, because the last of them restores the settings
+	restore: Arc<Restore>
+}
+
+// settings of an OS serial port before open() configured them, which the
+// last of its clones to be dropped restores if enabled (see
+// SerialPort::set_restore_on_close())
+struct Restore {
+	original: Option<sys::SavedSettings>,
+	enabled: AtomicBool,
+	// number of ports sharing this, i.e., clones not dropped yet
+	ports: AtomicUsize
+}
+
+impl Restore {
+	fn new(original: Option<sys::SavedSettings>) -> Self {
+		Self { original, enabled: AtomicBool::new(false), ports: AtomicUsize::new(1) }
+	}
+}
+
+struct Pacer {
+	pacing: WritePacing,
+	// earliest time the next byte may be written
+	next: Option<Instant>
+}
+
+// timeout of a single read or write from or to the device
+#[derive(Clone, Copy)]
+enum CallTimeout {
+	// the port's read or write timeout
+	Port,
+	// the given timeout (None blocks indefinitely), e.g., the time remaining
+	// until the deadline of read_exact_timeout(), which must not change the
+	// port's timeouts shared with clones
+	Given(Option<Duration>)
+}
+
+// bytes read from the device in large chunks to serve small reads
+struct ReadBuffer {
+	data: Box<[u8]>,
+	start: usize,
+	end: usize,
+	// cleared when buffering was disabled while bytes were left, which are
+	// returned by subsequent reads before reading from the device again
+	refill: bool
+}
+
+impl ReadBuffer {
+	// buffer of the given capacity (or without refilling if None) holding
+	// the pending bytes, or None if there are neither
+	fn with_pending(pending: &[u8], capacity: Option<usize>) -> Option<Self> {
+		let len = pending.len();
+		match capacity {
+			None if len == 0 => None,
+			capacity => {
+				let mut data = vec![0u8; capacity.unwrap_or(0).max(len)].into_boxed_slice();
+				data[..len].copy_from_slice(pending);
+				Some(Self { data, start: 0, end: len, refill: capacity.is_some() })
+			}
+		}
+	}
+
+	fn pending(&self) -> &[u8] {
+		&self.data[self.start..self.end]
+	}
+
+	// move as many pending bytes to buf as fit
+	fn take(&mut self, buf: &mut [u8]) -> usize {
+		let len = buf.len().min(self.end - self.start);
+		buf[..len].copy_from_slice(&self.data[self.start..self.start + len]);
+		self.start += len;
+		len
+	}
+}
+
+#[derive(Default)]
+struct SharedReadBuffer {
+	state: Mutex<ReadState>,
+	// notifies readers waiting for another clone's read to complete
+	read: Condvar
+}
+
+#[derive(Default)]
+struct ReadState {
+	buffer: Option<ReadBuffer>,
+	// set while a clone reads from the device into the buffer (or bypassing
+	// it) without holding the lock, so queries and purges do not wait for
+	// I/O. other readers wait for it to keep the order of bytes.
+	reading: bool
+}
+
+#[derive(Default)]
+struct SharedWriteBuffer {
+	buffer: Mutex<Option<WriteBuffer>>,
+	// notifies the background writer of bytes becoming pending
+	pending: Condvar
+}
+
+// bytes written by the application, which a background thread writes to the
+// device once they are due
+struct WriteBuffer {
+	coalescing: WriteCoalescing,
+	data: Vec<u8>,
+	// time the first pending byte was buffered. the background writer
+	// compares it to the system clock, so it is not taken from clock::now(),
+	// whose MockClock applies to the calling thread only.
+	since: Option<Instant>,
+	// failure of the background writer, returned by the next write or flush
+	error: Option<io::Error>,
+	// clone of the port used for writing, which does not share the buffer,
+	// so dropping the last port sharing it writes the pending bytes
+	writer: SerialPort,
+	// stops the background writer of this buffer
+	stop: Arc<AtomicBool>
+}
+
+impl WriteBuffer {
+	fn write_pending(&mut self) -> io::Result<()> {
+		while !self.data.is_empty() {
+			match self.writer.write_paced(&self.data, CallTimeout::Port) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero,
+					"failed to write buffered data")),
+				Ok(len) => drop(self.data.drain(..len)),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+		self.since = None;
+		Ok(())
+	}
+
+	fn run(shared: Weak<SharedWriteBuffer>, stop: Arc<AtomicBool>) {
+		// upgrade only temporarily, so dropping all ports sharing the buffer
+		// drops it
+		while let Some(shared) = shared.upgrade() {
+			let mut guard = shared.buffer.lock().unwrap_or_else(|e| e.into_inner());
+			let buffer = match guard.as_mut() {
+				Some(buffer) if !stop.load(Ordering::Relaxed) => buffer,
+				_ => return
+			};
+
+			let now = Instant::now();
+			let timeout = match buffer.since {
+				Some(since) if now >= since + buffer.coalescing.interval => {
+					if let Err(e) = buffer.write_pending() {
+						// retry after another interval
+						buffer.error = Some(e);
+						buffer.since = Some(now);
+					}
+					continue;
+				},
+				Some(since) => since + buffer.coalescing.interval - now,
+				None => COALESCING_IDLE_INTERVAL
+			};
+			let _res = shared.pending.wait_timeout(guard, timeout);
+		}
+	}
+}
+
+impl Drop for WriteBuffer {
+	fn drop(&mut self) {
+		let _res = self.write_pending();
+		self.stop.store(true, Ordering::Relaxed);
+	}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Settings {
+	baud_rate: Option<u32>,
+	data_bits: Option<DataBits>,
+	parity: Option<Parity>,
+	stop_bits: Option<StopBits>,
+	flow_control: Option<FlowControl>,
+	local: Option<bool>,
+	parity_marking: Option<bool>,
+	canonical: Option<Option<LineMode>>,
+	terminal_options: Option<TerminalOptions>,
+	rts: Option<bool>,
+	dtr: Option<bool>
+}
+
+// OS serial ports are kept separate from other backends to allow access to
+// platform-specific functionality
+enum Inner {
+	Os(sys::SerialPort),
+	Backend(Box<dyn Backend>)
+}
+
+impl Inner {
+	fn as_backend(&self) -> &dyn Backend {
+		match self {
+			Inner::Os(port) => port,
+			Inner::Backend(backend) => backend.as_ref()
+		}
+	}
+}
+
+// placeholder for an OS serial port whose handle was closed by reopen(),
+// retaining the timeouts for another attempt if reopening failed
+struct Closed {
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+impl Closed {
+	fn error() -> io::Error {
+		io::Error::new(io::ErrorKind::NotConnected, "serial port is closed (reopening failed)")
+	}
+}
+
+impl Backend for Closed {
+	fn read(&self, _buf: &mut [u8]) -> io::Result<usize> {
+		Err(Self::error())
+	}
+
+	fn write(&self, _buf: &[u8]) -> io::Result<usize> {
+		Err(Self::error())
+	}
+
+	fn read_with_timeout(&self, _buf: &mut [u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		Err(Self::error())
+	}
+
+	fn write_with_timeout(&self, _buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		Err(Self::error())
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		Err(Self::error())
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		Err(Self::error())
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+}
 
 impl SerialPort {
+	fn from_inner(inner: Inner) -> Self {
+		Self {
+			inner,
+			path: None,
+			pacing: Arc::new(Mutex::new(None)),
+			read_buffer: Arc::default(),
+			write_buffer: Arc::default(),
+			settings: Arc::new(Mutex::new(Settings::default())),
+			restore: Arc::new(Restore::new(None))
+		}
+	}
+
+	// device paths of the form "scheme://path" open the backend registered
+	// for that scheme, all other paths open an OS serial port
 	pub fn open<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
 			where T: AsRef<OsStr> + ?Sized {
-		sys::SerialPort::open(dev_path, timeout).map(Self)
+		if let Some((scheme, path)) = dev_path.as_ref().to_str()
+				.and_then(backend::split_scheme) {
+			return backend::open(scheme, path, timeout)
+				.map(|backend| Self::from_inner(Inner::Backend(backend)));
+		}
+		Self::open_os(dev_path, timeout)
+	}
+
+	// open an OS device, even if its path looks like a backend URL
+	fn open_os<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		let (port, original) = sys::SerialPort::open_saving(dev_path, timeout)?;
+		let mut port = Self::from_inner(Inner::Os(port));
+		port.path = Some(dev_path.as_ref().to_os_string());
+		port.restore = Arc::new(Restore::new(original));
+		Ok(port)
+	}
+
+	// check whether a port exists and appears openable without the side
+	// effects of opening and configuring it like asserting DTR (resetting
+	// many microcontroller boards), e.g., for device pickers that refresh
+	// often. only Windows briefly opens the port to tell whether it is busy.
+	pub fn probe<T>(dev_path: &T) -> io::Result<PortStatus>
+			where T: AsRef<OsStr> + ?Sized {
+		if dev_path.as_ref().to_str().and_then(backend::split_scheme).is_some() {
+			return Err(backend::unsupported("probing"));
+		}
+		sys::SerialPort::probe(dev_path)
+	}
+
+	// like open(), but retry while the device does not exist or is busy until
+	// wait elapsed, as udev rules and Windows driver installation often only
+	// create the device node a few hundred milliseconds after hotplug. other
+	// errors are returned immediately, transient ones after wait elapsed.
+	pub fn open_with_retry<T>(dev_path: &T, timeout: Option<Duration>, backoff: Backoff,
+			wait: Duration) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		let deadline = clock::now() + wait;
+		let mut delay = backoff.initial;
+		loop {
+			match Self::open(dev_path, timeout) {
+				Err(e) if e.kind() == io::ErrorKind::NotFound || sys::is_busy(&e) => {
+					let now = clock::now();
+					if now >= deadline {
+						return Err(e);
+					}
+					clock::sleep(delay.min(deadline - now));
+					delay = (delay * 2).min(backoff.max);
+				},
+				res => return res
+			}
+		}
+	}
+
+	// like open(), but give up after open_timeout, e.g., for Bluetooth ports
+	// that block while connecting. opening continues in a background thread
+	// after timing out, and a port opened late is closed immediately.
+	pub fn open_timeout<T>(dev_path: &T, timeout: Option<Duration>, open_timeout: Duration)
+			-> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		let dev_path = dev_path.as_ref().to_os_string();
+		let (tx, rx) = mpsc::channel();
+		thread::spawn(move || {
+			let _res = tx.send(Self::open(&dev_path, timeout));
+		});
+
+		match rx.recv_timeout(open_timeout) {
+			Ok(res) => res,
+			Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+				io::ErrorKind::TimedOut, "opening serial port timed out")),
+			Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::Error::new(
+				io::ErrorKind::Other, "opening thread panicked"))
+		}
+	}
+
+	// open a port attached to a modem (or instrument signaling readiness via
+	// DCD) in modem-control mode (see set_local()) and wait until DCD is
+	// asserted or carrier_timeout elapses
+	pub fn open_carrier_wait<T>(dev_path: &T, timeout: Option<Duration>,
+			carrier_timeout: Duration) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		let port = Self::open(dev_path, timeout)?;
+		port.set_local(false)?;
+		port.wait_for_carrier(carrier_timeout)?;
+		Ok(port)
+	}
+
+	// wait until a device matching selector is present and open it, e.g., a
+	// board on a flashing rig that enumerates late after a reset. polls the
+	// device enumeration, so it works on platforms without hotplug
+	// notifications. fails with ErrorKind::TimedOut if no matching device
+	// appeared within wait.
+	#[cfg(feature = "enumerate")]
+	pub fn open_when_present(selector: &DeviceSelector, timeout: Option<Duration>, wait: Duration)
+			-> io::Result<Self> {
+		let deadline = clock::now() + wait;
+		loop {
+			if let Some(info) = Self::devices()?.find(|info| selector.matches(info)) {
+				return info.open(timeout);
+			}
+			let now = clock::now();
+			if now >= deadline {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"no matching device appeared before timeout"));
+			}
+			clock::sleep((deadline - now).min(Duration::from_millis(100)));
+		}
 	}
 
+	// poll DCD until it is asserted or the timeout elapses
+	pub fn wait_for_carrier(&self, timeout: Duration) -> io::Result<()> {
+		let deadline = clock::now() + timeout;
+		while !self.read_cd()? {
+			let now = clock::now();
+			if now >= deadline {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for carrier (DCD) timed out"));
+			}
+			clock::sleep((deadline - now).min(Duration::from_millis(10)));
+		}
+		Ok(())
+	}
+
+	// wrap a custom backend, e.g., one that is not registered for a scheme
+	pub fn from_backend(backend: Box<dyn Backend>) -> Self {
+		Self::from_inner(Inner::Backend(backend))
+	}
+
+	// open a pseudoterminal pair and return its master side along with the
+	// path of its slave device, which other processes can open like a TTY
+	#[cfg(unix)]
+	pub fn open_pty(timeout: Option<Duration>) -> io::Result<(Self, OsString)> {
+		sys::SerialPort::open_pty(timeout)
+			.map(|(port, path)| (Self::from_inner(Inner::Os(port)), path))
+	}
+
+	// clones share the device and its settings (e.g., the baud rate and
+	// write pacing), but timeouts are copied, so each clone can use its own
+	// (e.g., a reader thread blocking for seconds and a writer giving up
+	// quickly)
 	pub fn try_clone(&self) -> io::Result<Self> {
-		self.0.try_clone().map(Self)
+		let inner = match &self.inner {
+			Inner::Os(port) => port.try_clone().map(Inner::Os)?,
+			Inner::Backend(backend) => backend.try_clone().map(Inner::Backend)?
+		};
+		Ok(self.with_inner(inner))
+	}
+
+	// like try_clone(), but timeouts changed on the clone or this port apply
+	// to both, so they cannot get stale
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		let inner = match &self.inner {
+			Inner::Os(port) => port.try_clone_shared().map(Inner::Os)?,
+			Inner::Backend(backend) => backend.try_clone_shared().map(Inner::Backend)?
+		};
+		Ok(self.with_inner(inner))
+	}
+
+	fn with_inner(&self, inner: Inner) -> Self {
+		self.restore.ports.fetch_add(1, Ordering::Relaxed);
+		Self {
+			inner,
+			path: self.path.clone(),
+			pacing: self.pacing.clone(),
+			read_buffer: self.read_buffer.clone(),
+			write_buffer: self.write_buffer.clone(),
+			settings: self.settings.clone(),
+			restore: self.restore.clone()
+		}
+	}
+
+	// restore the settings the port had before open() configured it when
+	// the last of its clones is dropped (or closed via close()), e.g., so a
+	// tool does not permanently change a console port that other software
+	// depends on. requires a port opened by open(). disabled by default.
+	pub fn set_restore_on_close(&self, restore: bool) -> io::Result<()> {
+		if restore && self.restore.original.is_none() {
+			return Err(backend::unsupported("restoring settings on close"));
+		}
+		self.restore.enabled.store(restore, Ordering::Relaxed);
+		Ok(())
+	}
+
+	pub fn restore_on_close(&self) -> bool {
+		self.restore.enabled.load(Ordering::Relaxed)
+	}
+
+	// close the port after writing pending bytes and, if this is the last of
+	// its clones, restore its settings (see set_restore_on_close()). unlike
+	// dropping the port, this reports errors. the background writer of write
+	// coalescing counts as a clone, so disable coalescing first.
+	pub fn close(self) -> io::Result<()> {
+		self.flush_buffered()?;
+		// no clones can be created concurrently, as this port is owned
+		if self.restore.ports.load(Ordering::Acquire) == 1
+				&& self.restore.enabled.swap(false, Ordering::Relaxed) {
+			self.restore_original()?;
+		}
+		Ok(())
+	}
+
+	fn restore_original(&self) -> io::Result<()> {
+		match (&self.inner, &self.restore.original) {
+			(Inner::Os(port), Some(original)) => port.restore_settings(original),
+			_ => Ok(())
+		}
+	}
+
+	// reopen an OS serial port by its path after its handle became invalid,
+	// e.g., after the system resumed from sleep (indicated by I/O failing
+	// with ErrorKind::NotConnected on Windows). restores the timeouts and the
+	// settings applied via this port or its clones. clones keep using the
+	// invalid handle and must be cloned again from the reopened port.
+	pub fn reopen(&mut self) -> io::Result<()> {
+		// reopening may be retried after it failed previously
+		let path = match &self.path {
+			Some(path) => path.clone(),
+			None => return Err(backend::unsupported("reopening"))
+		};
+		let timeout_read = self.read_timeout();
+		let timeout_write = self.write_timeout();
+
+		// close the invalid handle first, because the device cannot be
+		// opened twice. the placeholder fails all I/O if reopening fails.
+		self.inner = Inner::Backend(Box::new(Closed {
+			timeout_read: AtomicTimeout::new(timeout_read),
+			timeout_write: AtomicTimeout::new(timeout_write)
+		}));
+		let port = sys::SerialPort::open(&path, timeout_read)?;
+		Backend::set_write_timeout(&port, timeout_write)?;
+		Self::apply_settings(&port, &self.lock_settings())?;
+		self.inner = Inner::Os(port);
+
+		// the background writer still uses the invalid handle
+		if let Some(coalescing) = self.write_coalescing() {
+			self.set_write_coalescing(Some(coalescing))?;
+		}
+		Ok(())
 	}
 
-	pub fn list_devices() -> Vec<OsString> {
-		sys::SerialPort::list_devices()
+	// apply the timeouts and the settings applied via this port or its clones
+	// again, e.g., after a child process used the port (see set_inheritable())
+	pub fn reapply_settings(&self) -> io::Result<()> {
+		let port = self.inner.as_backend();
+		port.set_read_timeout(port.read_timeout())?;
+		port.set_write_timeout(port.write_timeout())?;
+		Self::apply_settings(port, &self.lock_settings())
+	}
+
+	fn apply_settings(port: &dyn Backend, settings: &Settings) -> io::Result<()> {
+		if let Some(baud_rate) = settings.baud_rate {
+			port.set_baud_rate(baud_rate)?;
+		}
+		if let Some(data_bits) = settings.data_bits {
+			port.set_data_bits(data_bits)?;
+		}
+		if let Some(parity) = settings.parity {
+			port.set_parity(parity)?;
+		}
+		if let Some(stop_bits) = settings.stop_bits {
+			port.set_stop_bits(stop_bits)?;
+		}
+		if let Some(flow_control) = settings.flow_control {
+			port.set_flow_control(flow_control)?;
+		}
+		if let Some(local) = settings.local {
+			port.set_local(local)?;
+		}
+		if let Some(mark) = settings.parity_marking {
+			port.set_parity_marking(mark)?;
+		}
+		if let Some(mode) = settings.canonical {
+			port.set_canonical(mode)?;
+		}
+		if let Some(options) = settings.terminal_options {
+			port.set_terminal_options(options)?;
+		}
+		if let Some(level) = settings.rts {
+			port.set_rts(level)?;
+		}
+		if let Some(level) = settings.dtr {
+			port.set_dtr(level)?;
+		}
+		Ok(())
+	}
+
+	fn lock_settings(&self) -> MutexGuard<'_, Settings> {
+		self.settings.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// enumeration requires the enumerate feature (enabled by default), which
+	// links libudev on Linux
+	#[cfg(feature = "enumerate")]
+	pub fn list_devices() -> io::Result<Vec<OsString>> {
+		Ok(Self::devices()?
+			.map(|info| info.path)
+			.collect())
+	}
+
+	#[cfg(feature = "enumerate")]
+	pub fn list_device_info() -> io::Result<Vec<DeviceInfo>> {
+		Ok(Self::devices()?.collect())
+	}
+
+	// enumerate devices lazily, e.g., to stop at the first match without
+	// classifying the remaining ones. fails if the system cannot be queried
+	// (e.g., udev being unavailable in a container) or enumeration is
+	// unsupported on this platform.
+	#[cfg(feature = "enumerate")]
+	pub fn devices() -> io::Result<Devices> {
+		sys::SerialPort::devices().map(Devices)
+	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.inner.as_backend().read_timeout()
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.inner.as_backend().write_timeout()
+	}
+
+	// None blocks indefinitely. a zero timeout does not block at all, i.e.,
+	// reads and writes transfer what is possible immediately or fail with
+	// ErrorKind::TimedOut (same for set_write_timeout()). timeouts can be
+	// changed while other threads are blocked in read() or write(), which
+	// complete with the timeout they started with. on Windows, nonzero
+	// timeouts are rounded up to whole milliseconds, unless the experimental
+	// feature is enabled.
+	pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.inner.as_backend().set_read_timeout(timeout)
+	}
+
+	pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.inner.as_backend().set_write_timeout(timeout)
+	}
+
+	// like read_timeout() and friends, but with an explicit Timeout
+	pub fn read_timeout_mode(&self) -> Timeout {
+		self.read_timeout().into()
+	}
+
+	pub fn write_timeout_mode(&self) -> Timeout {
+		self.write_timeout().into()
+	}
+
+	pub fn set_read_timeout_mode(&self, timeout: Timeout) -> io::Result<()> {
+		self.set_read_timeout(timeout.into())
+	}
+
+	pub fn set_write_timeout_mode(&self, timeout: Timeout) -> io::Result<()> {
+		self.set_write_timeout(timeout.into())
+	}
+
+	pub fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.inner.as_backend().set_rts(level)?;
+		self.lock_settings().rts = Some(level);
+		Ok(())
+	}
+
+	pub fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.inner.as_backend().set_dtr(level)?;
+		self.lock_settings().dtr = Some(level);
+		Ok(())
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		self.inner.as_backend().read_cts()
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		self.inner.as_backend().read_dsr()
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		self.inner.as_backend().read_ri()
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		self.inner.as_backend().read_cd()
+	}
+
+	// block until any of CTS, DSR, RI, or CD changes or the timeout (None
+	// blocks indefinitely) expires. supported by OS ports on Linux and
+	// Windows, see modem::modem_status_changes() for a portable alternative.
+	// on Windows, waiting for events (via this, wait_any(), or a reactor) is
+	// limited to one clone of a port at a time and fails with AddrInUse
+	// otherwise.
+	pub fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.inner.as_backend().wait_modem_change(timeout)
+	}
+
+	// block until any of ports has data to read and return the indices of
+	// all readable ports, so a single thread can service multiple ports.
+	// errors (e.g., a disconnected device) count as readable on Unix, so the
+	// next read reports them. fails with TimedOut if no port becomes
+	// readable before timeout. supported by OS ports (except with the
+	// experimental feature on Windows). see wait_modem_change() for the
+	// restriction on Windows.
+	pub fn wait_any(ports: &[&SerialPort], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+		let ports = ports.iter()
+			.map(|port| match &port.inner {
+				Inner::Os(port) => Ok(port),
+				Inner::Backend(_) => Err(backend::unsupported("waiting for multiple ports"))
+			})
+			.collect::<io::Result<Vec<_>>>()?;
+		if ports.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "no ports to wait for"));
+		}
+		sys::SerialPort::wait_any(&ports, timeout)
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		self.inner.as_backend().baud_rate()
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.inner.as_backend().set_baud_rate(baud_rate)?;
+		self.lock_settings().baud_rate = Some(baud_rate);
+		Ok(())
+	}
+
+	// change the baud rate mid-session once the data written so far was
+	// transmitted (which set_baud_rate() would garble), e.g., after a
+	// protocol negotiated a higher rate. received data is kept, including
+	// the bytes in the read buffer.
+	pub fn switch_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.flush_buffered()?;
+		self.set_baud_rate(baud_rate)
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		self.inner.as_backend().data_bits()
+	}
+
+	pub fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.inner.as_backend().set_data_bits(data_bits)?;
+		self.lock_settings().data_bits = Some(data_bits);
+		Ok(())
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		self.inner.as_backend().parity()
+	}
+
+	pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.inner.as_backend().set_parity(parity)?;
+		self.lock_settings().parity = Some(parity);
+		Ok(())
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		self.inner.as_backend().stop_bits()
+	}
+
+	pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.inner.as_backend().set_stop_bits(stop_bits)?;
+		self.lock_settings().stop_bits = Some(stop_bits);
+		Ok(())
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		self.inner.as_backend().flow_control()
+	}
+
+	pub fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.inner.as_backend().set_flow_control(flow_control)?;
+		self.lock_settings().flow_control = Some(flow_control);
+		Ok(())
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		self.inner.as_backend().local()
+	}
+
+	// ports are opened in local mode, i.e., ignoring the modem control
+	// lines. otherwise, they are honored: on Unix, CLOCAL is cleared, so the
+	// TTY is hung up when DCD drops. on Windows, received bytes are discarded
+	// while DSR is deasserted, as there is no equivalent of CLOCAL.
+	pub fn set_local(&self, local: bool) -> io::Result<()> {
+		self.inner.as_backend().set_local(local)?;
+		self.lock_settings().local = Some(local);
+		Ok(())
+	}
+
+	pub fn parity_marking(&self) -> io::Result<bool> {
+		self.inner.as_backend().parity_marking()
+	}
+
+	// mark bytes received with parity errors in the data stream instead of
+	// passing them on like valid bytes, see parity::ParityDecoder for
+	// stripping the marks. requires parity to be enabled via set_parity().
+	pub fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		self.inner.as_backend().set_parity_marking(mark)?;
+		self.lock_settings().parity_marking = Some(mark);
+		Ok(())
+	}
+
+	// enable canonical mode with the given line terminators or disable it
+	// with None. supported by OS ports on Unix, where the kernel assembles
+	// lines. see line::LineSerialPort for other platforms and backends.
+	pub fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		self.inner.as_backend().set_canonical(mode)?;
+		self.lock_settings().canonical = Some(mode);
+		Ok(())
+	}
+
+	pub fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		self.inner.as_backend().terminal_options()
+	}
+
+	// supported by OS ports on Unix, see line::LineSerialPort for an
+	// emulation on other platforms and backends
+	pub fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		self.inner.as_backend().set_terminal_options(options)?;
+		self.lock_settings().terminal_options = Some(options);
+		Ok(())
+	}
+
+	pub fn set_break(&self, level: bool) -> io::Result<()> {
+		self.inner.as_backend().set_break(level)
+	}
+
+	// suspend transmission as if XOFF was received (and resume it as if XON
+	// was received), e.g., to throttle output in response to flow control
+	// messages of an application protocol. writes may block (and time out)
+	// while transmission is paused.
+	pub fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.inner.as_backend().set_transmit_paused(paused)
+	}
+
+	// transmit an urgent control character (e.g., XOFF) ahead of the output
+	// queued by the OS on Windows. elsewhere, it is only written ahead of the
+	// bytes pending due to write coalescing and without write pacing.
+	pub fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		self.inner.as_backend().transmit_immediate(byte)
+	}
+
+	// number of bytes received but not read yet (including those in the read
+	// buffer), e.g., to poll for input without blocking
+	pub fn bytes_to_read(&self) -> io::Result<usize> {
+		let buffered = self.lock_read_buffer().buffer.as_ref()
+			.map_or(0, |buffer| buffer.end - buffer.start);
+		Ok(buffered + self.inner.as_backend().bytes_to_read()?)
+	}
+
+	// number of bytes written but not transmitted yet (including those
+	// pending due to write coalescing)
+	pub fn bytes_to_write(&self) -> io::Result<usize> {
+		let pending = self.lock_write_buffer().as_ref().map_or(0, |buffer| buffer.data.len());
+		Ok(pending + self.inner.as_backend().bytes_to_write()?)
+	}
+
+	// acquire exclusive access to the port again after unlock(). OS ports are
+	// locked by open() already (via TIOCEXCL and flock() on Unix, and are
+	// always exclusive on Windows). fails if another process locked it.
+	pub fn lock(&self) -> io::Result<()> {
+		self.inner.as_backend().lock()
+	}
+
+	// release exclusive access while keeping the port open, e.g., so a
+	// supervising process can reserve a port and hand it to a child tool
+	// that opens it itself. unsupported on Windows, where child processes
+	// need to inherit the handle instead.
+	pub fn unlock(&self) -> io::Result<()> {
+		self.inner.as_backend().unlock()
+	}
+
+	// also discards the bytes in the read buffer
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		if input {
+			let mut state = self.lock_read_buffer();
+			match state.buffer.as_mut() {
+				Some(buffer) if buffer.refill => buffer.start = buffer.end,
+				_ => state.buffer = None
+			}
+		}
+		if output {
+			if let Some(buffer) = self.lock_write_buffer().as_mut() {
+				buffer.data.clear();
+				buffer.since = None;
+			}
+		}
+		self.inner.as_backend().purge(input, output)
+	}
+
+	// write buf in chunks of chunk_size bytes with a delay between chunks,
+	// e.g., for bootloaders that process each chunk before accepting more.
+	// unlike write_all(), the write timeout applies to the entire transfer
+	// (including delays) instead of each individual write.
+	pub fn write_chunked(&mut self, buf: &[u8], chunk_size: usize, gap: Duration) -> io::Result<()> {
+		if chunk_size == 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "chunk size must not be zero"));
+		}
+
+		let deadline = self.write_timeout().map(|timeout| clock::now() + timeout);
+		self.write_chunks(buf, chunk_size, gap, deadline)
+	}
+
+	fn write_chunks(&mut self, buf: &[u8], chunk_size: usize, gap: Duration,
+			deadline: Option<Instant>) -> io::Result<()> {
+		let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "chunked write timed out");
+
+		for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+			if i > 0 {
+				// fail early instead of sleeping past the deadline
+				if deadline.map_or(false, |deadline| clock::now() + gap >= deadline) {
+					return Err(timed_out());
+				}
+				clock::sleep(gap);
+			}
+
+			self.write_all_until(chunk, deadline)?;
+		}
+
+		Ok(())
+	}
+
+	fn write_all_until(&mut self, mut buf: &[u8], deadline: Option<Instant>) -> io::Result<()> {
+		while !buf.is_empty() {
+			// limit each write to the time remaining
+			let timeout = match deadline {
+				Some(deadline) => {
+					let remaining = deadline.checked_duration_since(clock::now())
+						.filter(|remaining| !remaining.is_zero())
+						.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "write timed out"))?;
+					CallTimeout::Given(Some(remaining))
+				},
+				None => CallTimeout::Port
+			};
+
+			match self.write_immediate(buf, timeout) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero,
+					"failed to write whole buffer")),
+				Ok(len) => buf = &buf[len..],
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+
+		Ok(())
+	}
+
+	// discard received data until the line has been idle for the given
+	// window, e.g., to synchronize on frame boundaries of protocols that
+	// delimit frames by silence. the read timeout limits the total wait and
+	// the number of discarded bytes is returned.
+	pub fn wait_for_silence(&mut self, window: Duration) -> io::Result<usize> {
+		let deadline = self.read_timeout().map(|timeout| clock::now() + timeout);
+		self.discard_until_silence(window, deadline)
+	}
+
+	fn discard_until_silence(&mut self, window: Duration, deadline: Option<Instant>)
+			-> io::Result<usize> {
+		let mut buf = [0u8; 256];
+		let mut discarded = 0;
+		let mut last_rx = clock::now();
+
+		loop {
+			// wait for the rest of the window, but not past the deadline
+			let mut wait = window.saturating_sub(clock::elapsed(last_rx));
+			if let Some(deadline) = deadline {
+				let remaining = deadline.saturating_duration_since(clock::now());
+				if remaining < wait {
+					if remaining.is_zero() {
+						return Err(io::Error::new(io::ErrorKind::TimedOut,
+							"line did not become silent before timeout"));
+					}
+					wait = remaining;
+				}
+			}
+			if wait.is_zero() {
+				return Ok(discarded);
+			}
+
+			match self.read_buffered(&mut buf, CallTimeout::Given(Some(wait))) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+					"port was closed while waiting for silence")),
+				Ok(len) => {
+					discarded += len;
+					last_rx = clock::now();
+				},
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+	}
+
+	// fill buf like read_exact(), but the timeout limits the entire read
+	// instead of each read from the port. on failure, the number of bytes
+	// received so far is reported along with the error.
+	pub fn read_exact_timeout(&mut self, buf: &mut [u8], timeout: Duration)
+			-> Result<(), PartialRead> {
+		let mut received = 0;
+		self.read_exact_until(buf, clock::now() + timeout, &mut received)
+			.map_err(|error| PartialRead { received, error })
+	}
+
+	fn read_exact_until(&mut self, buf: &mut [u8], deadline: Instant, received: &mut usize)
+			-> io::Result<()> {
+		while *received < buf.len() {
+			// limit each read to the time remaining
+			let remaining = deadline.checked_duration_since(clock::now())
+				.filter(|remaining| !remaining.is_zero())
+				.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "reading timed out"))?;
+
+			match self.read_buffered(&mut buf[*received..], CallTimeout::Given(Some(remaining))) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+					"port was closed before buffer was filled")),
+				Ok(len) => *received += len,
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+
+		Ok(())
+	}
+
+	// request a response of known length (e.g., from a SCPI or Modbus
+	// device): discard stale input, write request, wait until it was
+	// transmitted, and fill response. the timeout limits the entire
+	// transaction, except for waiting for transmission, which the OS does
+	// not limit. on failure, the number of bytes received so far is
+	// reported along with the error.
+	pub fn transaction(&mut self, request: &[u8], response: &mut [u8], timeout: Duration)
+			-> Result<(), PartialRead> {
+		let deadline = clock::now() + timeout;
+		let mut received = 0;
+		self.purge(true, false)
+			.and_then(|()| self.write_all_until(request, Some(deadline)))
+			.and_then(|()| self.flush_buffered())
+			.and_then(|()| self.read_exact_until(response, deadline, &mut received))
+			.map_err(|error| PartialRead { received, error })
+	}
+
+	// append received data to buf until the line has been idle for the given
+	// duration or the port was closed, e.g., to collect a response of unknown
+	// length. unlike read_to_end(), running out of data ends reading instead
+	// of failing it. returns the number of bytes appended, which remain in
+	// buf even if reading fails.
+	pub fn read_to_end_until_idle(&mut self, buf: &mut Vec<u8>, idle: Duration)
+			-> io::Result<usize> {
+		let start_len = buf.len();
+		self.read_until_idle(buf, idle).map(|()| buf.len() - start_len)
+	}
+
+	fn read_until_idle(&mut self, buf: &mut Vec<u8>, idle: Duration) -> io::Result<()> {
+		let mut chunk = [0u8; 1024];
+		let mut last_rx = clock::now();
+
+		loop {
+			let wait = idle.saturating_sub(clock::elapsed(last_rx));
+			if wait.is_zero() {
+				return Ok(());
+			}
+
+			match self.read_buffered(&mut chunk, CallTimeout::Given(Some(wait))) {
+				Ok(0) => return Ok(()),
+				Ok(len) => {
+					buf.extend_from_slice(&chunk[..len]);
+					last_rx = clock::now();
+				},
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+	}
+
+	// wait until any of the given events occurs and return the events that
+	// occurred. events that occurred since the previous wait (including
+	// waits internal to read()) may be reported, too.
+	#[cfg(all(windows, feature = "experimental"))]
+	pub fn wait_for_event(&self, events: CommEvents, timeout: Option<Duration>)
+			-> io::Result<CommEvents> {
+		match &self.inner {
+			Inner::Os(port) => port.wait_for_event(events, timeout),
+			Inner::Backend(_) => Err(backend::unsupported("waiting for events"))
+		}
+	}
+
+	#[cfg(windows)]
+	pub fn comm_timeouts(&self) -> io::Result<CommTimeouts> {
+		match &self.inner {
+			Inner::Os(port) => port.comm_timeouts(),
+			Inner::Backend(_) => Err(backend::unsupported("COMMTIMEOUTS"))
+		}
+	}
+
+	// apply raw COMMTIMEOUTS. read_timeout() and write_timeout() keep
+	// returning the previous values, and setting either of them replaces all
+	// fields again. reads returning no data still fail with TimedOut.
+	#[cfg(windows)]
+	pub fn set_comm_timeouts(&self, timeouts: CommTimeouts) -> io::Result<()> {
+		match &self.inner {
+			Inner::Os(port) => port.set_comm_timeouts(timeouts),
+			Inner::Backend(_) => Err(backend::unsupported("COMMTIMEOUTS"))
+		}
+	}
+
+	// system-wide defaults of a COM port (e.g., "COM3"), which apply to
+	// handles opened afterwards by applications that do not configure the
+	// port themselves (unlike open(), which always configures it)
+	#[cfg(windows)]
+	pub fn default_comm_config<T>(port_name: &T) -> io::Result<DefaultCommConfig>
+			where T: AsRef<OsStr> + ?Sized {
+		sys::default_comm_config(port_name.as_ref())
+	}
+
+	// e.g., for provisioning tools. usually requires administrator privileges.
+	#[cfg(windows)]
+	pub fn set_default_comm_config<T>(port_name: &T, config: &DefaultCommConfig) -> io::Result<()>
+			where T: AsRef<OsStr> + ?Sized {
+		sys::set_default_comm_config(port_name.as_ref(), config)
+	}
+
+	// let the user configure the port via the system's configuration dialog,
+	// owned by the window parent_hwnd (0 for none), and apply their choices.
+	// returns false if the dialog was cancelled. requires a port opened by
+	// path.
+	#[cfg(all(windows, feature = "config-dialog"))]
+	pub fn show_config_dialog(&self, parent_hwnd: isize) -> io::Result<bool> {
+		let port = self.os_port("the configuration dialog")?;
+		let path = self.path.as_ref().ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported,
+			"configuration dialog requires a port opened by path"))?;
+		let config = match port.config_dialog(path, parent_hwnd)? {
+			Some(config) => config,
+			None => return Ok(false)
+		};
+
+		// remember the choices like settings applied individually
+		let mut settings = self.lock_settings();
+		settings.baud_rate = Some(config.baud_rate);
+		settings.data_bits = Some(config.data_bits);
+		settings.parity = Some(config.parity);
+		settings.stop_bits = Some(config.stop_bits);
+		settings.flow_control = Some(config.flow_control);
+		Ok(true)
+	}
+
+	#[cfg(unix)]
+	pub fn retry_interrupted(&self) -> io::Result<bool> {
+		match &self.inner {
+			Inner::Os(port) => Ok(port.retry_interrupted()),
+			Inner::Backend(_) => Err(backend::unsupported("EINTR retry policy"))
+		}
+	}
+
+	// signals interrupting a blocking read or write make it fail with
+	// ErrorKind::Interrupted by default, e.g., so applications can shut
+	// down on SIGINT. if enabled, the call is retried transparently until
+	// it completes or the original timeout expires. applies to this
+	// instance only (clones inherit it).
+	#[cfg(unix)]
+	pub fn set_retry_interrupted(&self, retry: bool) -> io::Result<()> {
+		match &self.inner {
+			Inner::Os(port) => {
+				port.set_retry_interrupted(retry);
+				Ok(())
+			},
+			Inner::Backend(_) => Err(backend::unsupported("EINTR retry policy"))
+		}
+	}
+
+	// whether the OS port is kept open in child processes, which it is not by
+	// default (FD_CLOEXEC is set and handles are not inheritable)
+	pub fn inheritable(&self) -> io::Result<bool> {
+		self.os_port("inheritance")?.inheritable()
+	}
+
+	// pass the port to a child process (e.g., an external flasher), which
+	// uses the descriptor (see raw_fd()) or handle (see raw_handle()) passed
+	// to it, e.g., on its command line, and resume using it afterwards:
+	//   port.flush()?;
+	//   port.set_inheritable(true)?;
+	//   let status = Command::new("flasher").arg(format!("--fd={}", port.raw_fd()?)).status();
+	//   port.set_inheritable(false)?;
+	//   port.reapply_settings()?;
+	// the child shares the lock (see lock()) and, on Unix, the O_NONBLOCK
+	// flag, whereas the handle on Windows requires overlapped I/O. threads
+	// spawning other processes meanwhile pass the port to them, too.
+	pub fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+		self.os_port("inheritance")?.set_inheritable(inheritable)
+	}
+
+	#[cfg(unix)]
+	pub fn raw_fd(&self) -> io::Result<std::os::unix::io::RawFd> {
+		Ok(self.os_port("raw descriptors")?.raw_fd())
+	}
+
+	// the COM port handle (the experimental backend uses additional ones)
+	#[cfg(windows)]
+	pub fn raw_handle(&self) -> io::Result<std::os::windows::io::RawHandle> {
+		Ok(self.os_port("raw handles")?.raw_handle() as std::os::windows::io::RawHandle)
+	}
+
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn line_discipline(&self) -> io::Result<i32> {
+		self.os_port("line disciplines")?.line_discipline()
+	}
+
+	// attach a kernel line discipline, e.g., gsm::N_GSM0710 (see
+	// gsm::GsmMux), or gsm::N_TTY to restore the default. while another
+	// line discipline is attached, reading and writing the port usually
+	// fails or bypasses it.
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn set_line_discipline(&self, ldisc: i32) -> io::Result<()> {
+		self.os_port("line disciplines")?.set_line_discipline(ldisc)
+	}
+
+	pub(crate) fn os_port(&self, what: &str) -> io::Result<&sys::SerialPort> {
+		match &self.inner {
+			Inner::Os(port) => Ok(port),
+			Inner::Backend(_) => Err(backend::unsupported(what))
+		}
+	}
+
+	pub fn write_pacing(&self) -> Option<WritePacing> {
+		self.lock_pacing().as_ref().map(|pacer| pacer.pacing)
+	}
+
+	// pace all subsequent writes (including those of clones), or disable
+	// pacing with None
+	pub fn set_write_pacing(&self, pacing: Option<WritePacing>) {
+		*self.lock_pacing() = pacing.map(|pacing| Pacer { pacing, next: None });
+	}
+
+	fn lock_pacing(&self) -> MutexGuard<'_, Option<Pacer>> {
+		self.pacing.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	pub fn read_buffer_capacity(&self) -> Option<usize> {
+		self.lock_read_buffer().buffer.as_ref()
+			.filter(|buffer| buffer.refill)
+			.map(|buffer| buffer.data.len())
+	}
+
+	// serve reads from a buffer of capacity bytes, which each read from the
+	// device fills with as many bytes as are available, e.g., for parsers
+	// reading a few bytes at a time at high baud rates. reads at least as
+	// large as the buffer bypass it. applies to clones, too. bytes still
+	// buffered when disabling it with None are returned by subsequent reads.
+	pub fn set_read_buffer(&self, capacity: Option<usize>) -> io::Result<()> {
+		if capacity == Some(0) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"read buffer capacity must not be zero"));
+		}
+
+		let mut state = self.lock_read_buffer();
+		let pending = state.buffer.as_ref().map_or(&[][..], ReadBuffer::pending);
+		state.buffer = ReadBuffer::with_pending(pending, capacity);
+		Ok(())
+	}
+
+	fn lock_read_buffer(&self) -> MutexGuard<'_, ReadState> {
+		self.read_buffer.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// like read() and write(), but with the given timeout instead of the
+	// port's, e.g., for wrapping backends (see Backend::read_with_timeout())
+	pub(crate) fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		self.read_buffered(buf, CallTimeout::Given(timeout))
+	}
+
+	pub(crate) fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		self.write_coalesced(buf, CallTimeout::Given(timeout))
+	}
+
+	fn read_device(&self, buf: &mut [u8], timeout: CallTimeout) -> io::Result<usize> {
+		match timeout {
+			CallTimeout::Port => self.inner.as_backend().read(buf),
+			CallTimeout::Given(timeout) => self.inner.as_backend().read_with_timeout(buf, timeout)
+		}
+	}
+
+	fn write_device(&self, buf: &[u8], timeout: CallTimeout) -> io::Result<usize> {
+		match timeout {
+			CallTimeout::Port => self.inner.as_backend().write(buf),
+			CallTimeout::Given(timeout) => self.inner.as_backend().write_with_timeout(buf, timeout)
+		}
+	}
+
+	fn read_buffered(&self, buf: &mut [u8], timeout: CallTimeout) -> io::Result<usize> {
+		// the deadline of this call, which waiting for another clone's read
+		// counts towards like waiting for the turn to read from an OS port
+		let entry = clock::now();
+		let total = match timeout {
+			CallTimeout::Port => self.read_timeout(),
+			CallTimeout::Given(timeout) => timeout
+		};
+		let mut waited = false;
+		let mut state = self.lock_read_buffer();
+		loop {
+			let buffer = match state.buffer.as_mut() {
+				Some(buffer) if !buf.is_empty() => buffer,
+				_ => {
+					drop(state);
+					return self.read_device(buf, timeout);
+				}
+			};
+
+			if buffer.start < buffer.end {
+				return Ok(buffer.take(buf));
+			}
+			if !buffer.refill {
+				state.buffer = None;
+				drop(state);
+				return self.read_device(buf, timeout);
+			}
+			if !state.reading {
+				break;
+			}
+
+			// wait for the read of another clone
+			waited = true;
+			state = match total {
+				None => self.read_buffer.read.wait(state).unwrap_or_else(|e| e.into_inner()),
+				Some(total) => match total.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) if !remaining.is_zero() =>
+						clock::wait_timeout(&self.read_buffer.read, state, remaining),
+					_ => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"waiting for concurrent read timed out"))
+				}
+			};
+		}
+
+		// the time spent waiting is deducted from the device read, so the
+		// call completes by the deadline. the port's timeout is only replaced
+		// if there was a wait to deduct.
+		let timeout = match (timeout, total) {
+			(CallTimeout::Given(_), Some(total)) =>
+				CallTimeout::Given(Some(total.saturating_sub(clock::elapsed(entry)))),
+			(CallTimeout::Port, Some(total)) if waited =>
+				CallTimeout::Given(Some(total.saturating_sub(clock::elapsed(entry)))),
+			(timeout, _) => timeout
+		};
+
+		// read without holding the lock. reads at least as large as the
+		// buffer bypass it.
+		let buffer = state.buffer.as_mut().unwrap();
+		let bypass = buf.len() >= buffer.data.len();
+		let mut data = if bypass { Box::default() } else { mem::take(&mut buffer.data) };
+		state.reading = true;
+		drop(state);
+		let res = self.read_device(if bypass { buf } else { &mut data }, timeout);
+
+		let mut state = self.lock_read_buffer();
+		state.reading = false;
+		self.read_buffer.read.notify_all();
+		if bypass {
+			return res;
+		}
+		let len = match res {
+			Ok(len) => len,
+			Err(e) => {
+				// the box taken from the buffer is returned
+				if let Some(buffer) = state.buffer.as_mut().filter(|buffer| buffer.data.is_empty()) {
+					buffer.data = data;
+				}
+				return Err(e);
+			}
+		};
+
+		// the buffer may have been resized or disabled meanwhile (see
+		// set_read_buffer()), which keeps the bytes read pending
+		match state.buffer.as_mut() {
+			Some(buffer) if buffer.data.is_empty() => {
+				buffer.data = data;
+				buffer.start = 0;
+				buffer.end = len;
+			},
+			_ => {
+				let capacity = state.buffer.as_ref()
+					.filter(|buffer| buffer.refill)
+					.map(|buffer| buffer.data.len());
+				state.buffer = ReadBuffer::with_pending(&data[..len], capacity);
+			}
+		}
+		Ok(state.buffer.as_mut().map_or(0, |buffer| buffer.take(buf)))
+	}
+
+	pub fn write_coalescing(&self) -> Option<WriteCoalescing> {
+		self.lock_write_buffer().as_ref().map(|buffer| buffer.coalescing)
+	}
+
+	// coalesce subsequent small writes (including those of clones), which
+	// are then written by a background thread, or disable coalescing with
+	// None, which writes the pending bytes. writes at least as large as the
+	// buffer bypass it, as do write_unbuffered() and write_chunked(). flush()
+	// writes the pending bytes immediately.
+	pub fn set_write_coalescing(&self, coalescing: Option<WriteCoalescing>) -> io::Result<()> {
+		if coalescing.map_or(false, |coalescing| coalescing.capacity == 0) {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"write buffer capacity must not be zero"));
+		}
+
+		let mut guard = self.lock_write_buffer();
+		let coalescing = match coalescing {
+			Some(coalescing) => coalescing,
+			None => {
+				if let Some(buffer) = guard.as_mut() {
+					buffer.write_pending()?;
+				}
+				*guard = None;
+				return Ok(());
+			}
+		};
+
+		// the writer must not share the buffer, so try_clone*() won't do
+		let mut writer = self.try_clone_shared().or_else(|_| self.try_clone())?;
+		writer.write_buffer = Arc::default();
+		let stop = Arc::new(AtomicBool::new(false));
+		let shared = Arc::downgrade(&self.write_buffer);
+		let thread_stop = stop.clone();
+		thread::Builder::new()
+			.name("serial-write-coalescing".into())
+			.spawn(move || WriteBuffer::run(shared, thread_stop))?;
+
+		// keep the bytes pending, e.g., after reopen()
+		let (data, since) = match guard.as_mut() {
+			Some(buffer) => (mem::take(&mut buffer.data), buffer.since.take()),
+			None => (Vec::with_capacity(coalescing.capacity), None)
+		};
+		*guard = Some(WriteBuffer { coalescing, data, since, error: None, writer, stop });
+		Ok(())
+	}
+
+	fn lock_write_buffer(&self) -> MutexGuard<'_, Option<WriteBuffer>> {
+		self.write_buffer.buffer.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn write_coalesced(&self, buf: &[u8], timeout: CallTimeout) -> io::Result<usize> {
+		let mut guard = self.lock_write_buffer();
+		let buffer = match guard.as_mut() {
+			Some(buffer) if !buf.is_empty() => buffer,
+			_ => {
+				drop(guard);
+				return self.write_paced(buf, timeout);
+			}
+		};
+		if let Some(e) = buffer.error.take() {
+			return Err(e);
+		}
+
+		let capacity = buffer.coalescing.capacity;
+		if buffer.data.len() + buf.len() > capacity {
+			buffer.write_pending()?;
+		}
+		// the lock is held to keep the order of bytes written by clones
+		if buf.len() >= capacity {
+			return self.write_paced(buf, timeout);
+		}
+
+		if buffer.data.is_empty() {
+			buffer.since = Some(Instant::now());
+			self.write_buffer.pending.notify_one();
+		}
+		buffer.data.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	// write buf immediately (after the bytes pending due to write coalescing),
+	// e.g., for time-critical messages
+	pub fn write_unbuffered(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_immediate(buf, CallTimeout::Port)
+	}
+
+	fn write_immediate(&self, buf: &[u8], timeout: CallTimeout) -> io::Result<usize> {
+		let mut guard = self.lock_write_buffer();
+		if let Some(buffer) = guard.as_mut() {
+			if let Some(e) = buffer.error.take() {
+				return Err(e);
+			}
+			buffer.write_pending()?;
+		}
+		self.write_paced(buf, timeout)
+	}
+
+	fn flush_buffered(&self) -> io::Result<()> {
+		if let Some(buffer) = self.lock_write_buffer().as_mut() {
+			if let Some(e) = buffer.error.take() {
+				return Err(e);
+			}
+			buffer.write_pending()?;
+		}
+		self.inner.as_backend().flush()
+	}
+
+	fn write_paced(&self, buf: &[u8], timeout: CallTimeout) -> io::Result<usize> {
+		// the lock is held while sleeping to serialize writes of clones
+		let mut guard = self.lock_pacing();
+		let pacer = match guard.as_mut() {
+			Some(pacer) if !buf.is_empty() => pacer,
+			_ => return self.write_device(buf, timeout)
+		};
+		let interval = pacer.pacing.interval();
+
+		let now = clock::now();
+		let start = match pacer.next {
+			Some(next) if next > now => {
+				clock::sleep(next - now);
+				next
+			},
+			// don't send a burst to make up for idle time
+			_ => now
+		};
+
+		// write the bytes that became due while sleeping (sleep may overshoot
+		// considerably at high rates), but at least one
+		let elapsed = clock::elapsed(start).as_nanos();
+		let due = 1 + elapsed.checked_div(interval.as_nanos()).unwrap_or(u128::MAX);
+		let len = buf.len().min(usize::try_from(due).unwrap_or(usize::MAX));
+
+		let written = self.write_device(&buf[..len], timeout)?;
+		pacer.next = Some(start + interval * u32::try_from(written).unwrap_or(u32::MAX));
+		Ok(written)
+	}
+}
+
+// common interface of SerialPort and its test doubles (e.g., MockSerialPort),
+// so code can be generic over the transport
+pub trait SerialIo: io::Read + io::Write + Sized {
+	fn open<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized;
+	fn try_clone(&self) -> io::Result<Self>;
+
+	fn read_timeout(&self) -> Option<Duration>;
+	fn write_timeout(&self) -> Option<Duration>;
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+	fn set_rts(&self, level: bool) -> io::Result<()>;
+	fn set_dtr(&self, level: bool) -> io::Result<()>;
+	fn read_cts(&self) -> io::Result<bool>;
+	fn read_dsr(&self) -> io::Result<bool>;
+	fn read_ri(&self) -> io::Result<bool>;
+	fn read_cd(&self) -> io::Result<bool>;
+}
+
+impl Drop for SerialPort {
+	fn drop(&mut self) {
+		// the last clone restores the settings before its handle is closed
+		if self.restore.ports.fetch_sub(1, Ordering::AcqRel) == 1
+				&& self.restore.enabled.load(Ordering::Relaxed) {
+			let _res = self.restore_original();
+		}
+	}
+}
+
+impl SerialIo for SerialPort {
+	fn open<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		SerialPort::open(dev_path, timeout)
+	}
+
+	fn try_clone(&self) -> io::Result<Self> {
+		SerialPort::try_clone(self)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		SerialPort::read_timeout(self)
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		SerialPort::write_timeout(self)
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		SerialPort::set_read_timeout(self, timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		SerialPort::set_write_timeout(self, timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		SerialPort::set_rts(self, level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		SerialPort::set_dtr(self, level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		SerialPort::read_cts(self)
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		SerialPort::read_dsr(self)
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		SerialPort::read_ri(self)
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		SerialPort::read_cd(self)
 	}
 }
 
 impl io::Read for SerialPort {
 	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-		self.0.read(buf)
+		self.read_buffered(buf, CallTimeout::Port)
 	}
 }
 
 impl io::Read for &SerialPort {
 	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-		self.0.read(buf)
+		self.read_buffered(buf, CallTimeout::Port)
 	}
 }
 
 impl io::Write for SerialPort {
 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-		self.0.write(buf)
+		self.write_coalesced(buf, CallTimeout::Port)
 	}
 
 	fn flush(&mut self) -> io::Result<()> {
-		self.0.flush()
+		self.flush_buffered()
 	}
 }
 
 impl io::Write for &SerialPort {
 	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-		self.0.write(buf)
+		self.write_coalesced(buf, CallTimeout::Port)
 	}
 
 	fn flush(&mut self) -> io::Result<()> {
-		self.0.flush()
+		self.flush_buffered()
+	}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+	use std::io::{Read, Write};
+
+	// master side of a pseudoterminal and a port opened on its slave device
+	fn pty(timeout: Option<Duration>) -> (SerialPort, SerialPort) {
+		let (device, path) = SerialPort::open_pty(timeout).unwrap();
+		(device, SerialPort::open(&path, timeout).unwrap())
+	}
+
+	// deadline helpers pass their timeout per call instead of changing the
+	// port's, which clones sharing the timeouts would observe meanwhile
+	#[test]
+	fn deadline_helpers_keep_shared_timeout() {
+		let (_device, port) = pty(Some(Duration::from_millis(100)));
+		let shared = port.try_clone_shared().unwrap();
+		let mut port = port.try_clone_shared().unwrap();
+		let reader = thread::spawn(move || {
+			port.read_exact_timeout(&mut [0u8; 4], Duration::from_millis(300))
+		});
+
+		let start = Instant::now();
+		while start.elapsed() < Duration::from_millis(200) {
+			assert_eq!(shared.read_timeout(), Some(Duration::from_millis(100)));
+			thread::sleep(Duration::from_millis(5));
+		}
+		assert!(reader.join().unwrap().is_err());
+		assert_eq!(shared.read_timeout(), Some(Duration::from_millis(100)));
+	}
+
+	// the read buffer is not locked while a clone waits for data
+	#[test]
+	fn read_buffer_does_not_block_while_clone_reads() {
+		let (device, port) = pty(Some(Duration::from_secs(1)));
+		port.set_read_buffer(Some(64)).unwrap();
+		let clone = port.try_clone().unwrap();
+		let reader = thread::spawn(move || {
+			let mut buf = [0u8; 4];
+			(&clone).read(&mut buf).map(|len| buf[..len].to_vec())
+		});
+		thread::sleep(Duration::from_millis(100));
+
+		let start = Instant::now();
+		assert_eq!(port.bytes_to_read().unwrap(), 0);
+		port.purge(true, false).unwrap();
+		assert!(start.elapsed() < Duration::from_millis(50));
+
+		(&device).write_all(b"abcdefgh").unwrap();
+		assert_eq!(reader.join().unwrap().unwrap(), b"abcd");
+		let mut buf = [0u8; 4];
+		(&port).read_exact(&mut buf).unwrap();
+		assert_eq!(&buf, b"efgh");
 	}
 }
@@ -0,0 +1,728 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::clock;
+use crate::{DataBits, FlowControl, LineMode, Parity, SerialPort, StopBits, TerminalOptions};
+use crate::backend::Backend;
+
+// longest line assembled in canonical mode unless specified otherwise, like
+// the Linux N_TTY buffer. longer lines are returned in parts.
+pub const DEFAULT_MAX_LINE: usize = 4095;
+
+const DEL: u8 = 0x7f;
+const CTRL_U: u8 = 0x15;
+const CTRL_D: u8 = 0x04;
+
+// translation of received newlines, applied before assembling lines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputNewline {
+	Raw,
+	// for devices terminating lines with CR only (ICRNL)
+	CrToLf,
+	// for devices terminating lines with CR LF (IGNCR)
+	StripCr
+}
+
+impl InputNewline {
+	// translate data in place and return its new length
+	fn translate(self, data: &mut [u8]) -> usize {
+		match self {
+			InputNewline::Raw => data.len(),
+			InputNewline::CrToLf => {
+				for byte in data.iter_mut().filter(|byte| **byte == b'\r') {
+					*byte = b'\n';
+				}
+				data.len()
+			},
+			InputNewline::StripCr => {
+				let mut len = 0;
+				for i in 0..data.len() {
+					if data[i] != b'\r' {
+						data[len] = data[i];
+						len += 1;
+					}
+				}
+				len
+			}
+		}
+	}
+}
+
+// translation of transmitted newlines
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputNewline {
+	Raw,
+	// equivalent to TerminalOptions::nl_to_crnl (ONLCR)
+	LfToCrLf
+}
+
+struct State {
+	canonical: Option<LineMode>,
+	options: TerminalOptions,
+	input_newline: InputNewline,
+	output_newline: OutputNewline,
+	// received bytes that were not read yet. the first `complete` bytes
+	// form complete lines, the rest is the line being assembled.
+	pending: Vec<u8>,
+	complete: usize,
+	max_line: usize,
+	// set while a clone reads from the port without holding the lock, so
+	// writes and setters do not wait for input. other readers wait for it
+	// to assemble lines in order.
+	reading: bool
+}
+
+struct Shared {
+	state: Mutex<State>,
+	// notifies readers waiting for another clone's read to complete
+	read: Condvar
+}
+
+impl State {
+	fn is_terminator(mode: &LineMode, byte: u8) -> bool {
+		byte == b'\n' || Some(byte) == mode.eol || Some(byte) == mode.eol2
+	}
+
+	// assemble lines from data and return the bytes to echo
+	fn push(&mut self, mode: &LineMode, data: &[u8]) -> Vec<u8> {
+		let mut echo = Vec::new();
+		for &byte in data {
+			if mode.editing && byte == DEL {
+				if self.pending.len() > self.complete {
+					self.pending.pop();
+					if self.options.echo_erase {
+						echo.extend_from_slice(b"\x08 \x08");
+						continue;
+					}
+				}
+				echo.push(byte);
+				continue;
+			}
+			if mode.editing && byte == CTRL_U {
+				self.pending.truncate(self.complete);
+				echo.push(byte);
+				continue;
+			}
+			// end of file completes the line without being echoed. it is
+			// kept as marker, so reading an empty line returns 0 bytes.
+			if mode.editing && byte == CTRL_D {
+				self.pending.push(byte);
+				self.complete = self.pending.len();
+				continue;
+			}
+
+			echo.push(byte);
+			self.pending.push(byte);
+			if Self::is_terminator(mode, byte) || self.pending.len() - self.complete >= self.max_line {
+				self.complete = self.pending.len();
+			}
+		}
+		echo
+	}
+
+	// move (part of) the first complete line into buf. lines without
+	// terminator were split at max_line. a line ended by end of file is
+	// returned without the marker, i.e., an empty one as 0 bytes.
+	fn take_line(&mut self, buf: &mut [u8]) -> usize {
+		let (line_len, eof) = match self.canonical {
+			Some(mode) => match self.pending[..self.complete].iter()
+					.position(|&byte| Self::is_terminator(&mode, byte)
+						|| mode.editing && byte == CTRL_D) {
+				Some(pos) if self.pending[pos] == CTRL_D && mode.editing
+					&& pos <= self.max_line => (pos, true),
+				pos => (pos.map_or(self.complete, |pos| pos + 1).min(self.max_line), false)
+			},
+			None => (self.complete, false)
+		};
+		let len = line_len.min(buf.len());
+		buf[..len].copy_from_slice(&self.pending[..len]);
+		// the marker is removed along with the rest of the line
+		let drained = if eof && len == line_len { len + 1 } else { len };
+		self.pending.drain(..drained);
+		self.complete -= drained;
+		len
+	}
+
+	// remove end of file markers, which would be read as data once editing
+	// is disabled
+	fn strip_eof(&mut self) {
+		let markers = self.pending[..self.complete].iter()
+			.filter(|&&byte| byte == CTRL_D)
+			.count();
+		self.pending.retain(|&byte| byte != CTRL_D);
+		self.complete -= markers;
+	}
+}
+
+// software line discipline layered over a port for platforms or backends
+// without kernel support, e.g., canonical mode on Windows. clones share the
+// pending input, just like clones of a TTY share its line buffer.
+pub struct LineSerialPort {
+	port: SerialPort,
+	shared: Arc<Shared>
+}
+
+impl LineSerialPort {
+	pub fn new(port: SerialPort) -> Self {
+		Self::with_max_line(port, DEFAULT_MAX_LINE)
+	}
+
+	// like new(), but split lines longer than max_line bytes in canonical
+	// mode
+	pub fn with_max_line(port: SerialPort, max_line: usize) -> Self {
+		Self {
+			port,
+			shared: Arc::new(Shared {
+				state: Mutex::new(State {
+					canonical: None,
+					options: TerminalOptions::default(),
+					input_newline: InputNewline::Raw,
+					output_newline: OutputNewline::Raw,
+					pending: Vec::new(),
+					complete: 0,
+					max_line: max_line.max(1),
+					reading: false
+				}),
+				read: Condvar::new()
+			})
+		}
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone()?,
+			shared: self.shared.clone()
+		})
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone_shared()?,
+			shared: self.shared.clone()
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.shared.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	pub fn get_ref(&self) -> &SerialPort {
+		&self.port
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+
+	// like SerialPort::set_canonical(), but emulated. the read timeout
+	// applies to each chunk received while assembling a line. disabling
+	// canonical mode makes a partially assembled line readable.
+	pub fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		let mut state = self.lock();
+		let editing = |mode: Option<LineMode>| mode.map_or(false, |mode| mode.editing);
+		if editing(state.canonical) && !editing(mode) {
+			state.strip_eof();
+		}
+		state.canonical = mode;
+		if mode.is_none() {
+			state.complete = state.pending.len();
+		}
+		Ok(())
+	}
+
+	pub fn terminal_options(&self) -> TerminalOptions {
+		self.lock().options
+	}
+
+	// like SerialPort::set_terminal_options(), but emulated. bytes are
+	// echoed as they are received, i.e., while reading.
+	pub fn set_terminal_options(&self, options: TerminalOptions) {
+		self.lock().options = options;
+	}
+
+	pub fn newlines(&self) -> (InputNewline, OutputNewline) {
+		let state = self.lock();
+		(state.input_newline, state.output_newline)
+	}
+
+	// translate newlines of received and transmitted data, e.g., so
+	// applications can use "\n" regardless of the device's convention
+	pub fn set_newlines(&self, input: InputNewline, output: OutputNewline) {
+		let mut state = self.lock();
+		state.input_newline = input;
+		state.output_newline = output;
+	}
+
+	// translate NL to CR NL on output
+	fn nl_to_crnl(state: &State) -> bool {
+		state.options.nl_to_crnl || state.output_newline == OutputNewline::LfToCrLf
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_via(buf, self.port.read_timeout(),
+			|buf, timeout| self.port.read_with_timeout(buf, timeout))
+	}
+
+	fn read_via<F>(&self, buf: &mut [u8], timeout: Option<Duration>, read: F) -> io::Result<usize>
+			where F: Fn(&mut [u8], Option<Duration>) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		// wait for the read of another clone, which counts towards the
+		// timeout like waiting for the turn to read from an OS port
+		let entry = clock::now();
+		let mut state = self.lock();
+		while state.complete == 0 && state.reading {
+			state = match timeout {
+				None => self.shared.read.wait(state).unwrap_or_else(|e| e.into_inner()),
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) if !remaining.is_zero() =>
+						clock::wait_timeout(&self.shared.read, state, remaining),
+					_ => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"waiting for concurrent read timed out"))
+				}
+			};
+		}
+
+		// the time spent waiting is deducted from the first read. in
+		// canonical mode, the timeout applies to each chunk afterwards.
+		let mut chunk_timeout = timeout.map(|timeout| timeout.saturating_sub(clock::elapsed(entry)));
+		let mut chunk = [0u8; 256];
+		loop {
+			if state.complete > 0 {
+				return Ok(state.take_line(buf));
+			}
+			let mode = state.canonical;
+			state.reading = true;
+			drop(state);
+			let res = match mode {
+				None => self.receive_raw(buf, chunk_timeout, &read),
+				Some(mode) => self.receive_line(&mode, &mut chunk, chunk_timeout, &read)
+			};
+			state = self.lock();
+			state.reading = false;
+			self.shared.read.notify_all();
+			if let Some(len) = res? {
+				return Ok(len);
+			}
+			chunk_timeout = timeout;
+		}
+	}
+
+	// read into buf and translate newlines. returns None if only stripped
+	// bytes were received, which must not be mistaken for end of file.
+	fn receive_raw<F>(&self, buf: &mut [u8], timeout: Option<Duration>, read: &F)
+			-> io::Result<Option<usize>>
+			where F: Fn(&mut [u8], Option<Duration>) -> io::Result<usize> {
+		let len = read(buf, timeout)?;
+		let (echo, nl_to_crnl, input_newline) = {
+			let state = self.lock();
+			(state.options.echo, Self::nl_to_crnl(&state), state.input_newline)
+		};
+		let translated = input_newline.translate(&mut buf[..len]);
+		if echo {
+			self.write_all(&buf[..translated], nl_to_crnl)?;
+		}
+		Ok(if translated > 0 || len == 0 { Some(translated) } else { None })
+	}
+
+	// read a chunk and assemble lines from it. returns Some(0) on end of
+	// file without pending line.
+	fn receive_line<F>(&self, mode: &LineMode, chunk: &mut [u8], timeout: Option<Duration>,
+			read: &F) -> io::Result<Option<usize>>
+			where F: Fn(&mut [u8], Option<Duration>) -> io::Result<usize> {
+		let len = read(chunk, timeout)?;
+		let mut state = self.lock();
+		// end of file completes the pending line (if any)
+		if len == 0 {
+			if state.pending.len() == state.complete {
+				return Ok(Some(0));
+			}
+			state.complete = state.pending.len();
+			return Ok(None);
+		}
+
+		let len = state.input_newline.translate(&mut chunk[..len]);
+		let echoed = state.push(mode, &chunk[..len]);
+		// canonical mode may have been disabled meanwhile
+		if state.canonical.is_none() {
+			state.complete = state.pending.len();
+		}
+		let echo = state.options.echo;
+		let nl_to_crnl = Self::nl_to_crnl(&state);
+		drop(state);
+		if echo {
+			self.write_all(&echoed, nl_to_crnl)?;
+		}
+		Ok(None)
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_via(buf, |buf| (&self.port).write(buf))
+	}
+
+	fn write_via<F>(&self, buf: &[u8], write: F) -> io::Result<usize>
+			where F: Fn(&[u8]) -> io::Result<usize> {
+		let nl_to_crnl = Self::nl_to_crnl(&self.lock());
+		if !nl_to_crnl {
+			return write(buf);
+		}
+
+		// translated data is written completely, so the number of bytes
+		// written refers to buf
+		write_all_with(&write, buf, nl_to_crnl)?;
+		Ok(buf.len())
+	}
+
+	fn write_all(&self, buf: &[u8], nl_to_crnl: bool) -> io::Result<()> {
+		write_all_with(&|buf: &[u8]| (&self.port).write(buf), buf, nl_to_crnl)
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		(&self.port).flush()
+	}
+}
+
+// like Write::write_all() via the given write function, translating NL to
+// CR NL if nl_to_crnl is set
+fn write_all_with<F>(write: &F, buf: &[u8], nl_to_crnl: bool) -> io::Result<()>
+		where F: Fn(&[u8]) -> io::Result<usize> {
+	let write_all = |mut buf: &[u8]| {
+		while !buf.is_empty() {
+			match write(buf) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero,
+					"failed to write whole buffer")),
+				Ok(len) => buf = &buf[len..],
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+		Ok(())
+	};
+
+	if !nl_to_crnl {
+		return write_all(buf);
+	}
+	for (i, part) in buf.split(|&byte| byte == b'\n').enumerate() {
+		if i > 0 {
+			write_all(b"\r\n")?;
+		}
+		write_all(part)?;
+	}
+	Ok(())
+}
+
+impl Backend for LineSerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		LineSerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		LineSerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		self.read_via(buf, timeout, |buf, timeout| self.port.read_with_timeout(buf, timeout))
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		self.write_via(buf, |buf| self.port.write_with_timeout(buf, timeout))
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		LineSerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		LineSerialPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		LineSerialPort::try_clone_shared(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.port.read_timeout()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.port.write_timeout()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_read_timeout(timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_write_timeout(timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.port.set_rts(level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.port.set_dtr(level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		self.port.read_cts()
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		self.port.read_dsr()
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		self.port.read_ri()
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		self.port.read_cd()
+	}
+
+	fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.wait_modem_change(timeout)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		self.port.baud_rate()
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.port.set_baud_rate(baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		self.port.data_bits()
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.port.set_data_bits(data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		self.port.parity()
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.port.set_parity(parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		self.port.stop_bits()
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.port.set_stop_bits(stop_bits)
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		self.port.flow_control()
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.port.set_flow_control(flow_control)
+	}
+
+	fn local(&self) -> io::Result<bool> {
+		self.port.local()
+	}
+
+	fn set_local(&self, local: bool) -> io::Result<()> {
+		self.port.set_local(local)
+	}
+
+	fn parity_marking(&self) -> io::Result<bool> {
+		self.port.parity_marking()
+	}
+
+	fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		self.port.set_parity_marking(mark)
+	}
+
+	fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		LineSerialPort::set_canonical(self, mode)
+	}
+
+	fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		Ok(LineSerialPort::terminal_options(self))
+	}
+
+	fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		LineSerialPort::set_terminal_options(self, options);
+		Ok(())
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		self.port.set_break(level)
+	}
+
+	fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.port.set_transmit_paused(paused)
+	}
+
+	// sent as is, bypassing newline translation
+	fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		self.port.transmit_immediate(byte)
+	}
+
+	// includes the complete lines that were received, but not read yet
+	fn bytes_to_read(&self) -> io::Result<usize> {
+		let complete = self.lock().complete;
+		Ok(self.port.bytes_to_read()? + complete)
+	}
+
+	fn bytes_to_write(&self) -> io::Result<usize> {
+		self.port.bytes_to_write()
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		if input {
+			let mut state = self.lock();
+			state.pending.clear();
+			state.complete = 0;
+		}
+		self.port.purge(input, output)
+	}
+
+	fn lock(&self) -> io::Result<()> {
+		self.port.lock()
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		self.port.unlock()
+	}
+}
+
+impl io::Read for LineSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		LineSerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &LineSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		LineSerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for LineSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		LineSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		LineSerialPort::flush(self)
+	}
+}
+
+impl io::Write for &LineSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		LineSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		LineSerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::cell::RefCell;
+
+	use super::*;
+
+	#[test]
+	fn input_newline_translation() {
+		let mut data = *b"a\r\nb\rc";
+		assert_eq!(InputNewline::Raw.translate(&mut data), 6);
+		assert_eq!(&data, b"a\r\nb\rc");
+		assert_eq!(InputNewline::CrToLf.translate(&mut data), 6);
+		assert_eq!(&data, b"a\n\nb\nc");
+
+		let mut data = *b"a\r\nb\rc";
+		let len = InputNewline::StripCr.translate(&mut data);
+		assert_eq!(&data[..len], b"a\nbc");
+	}
+
+	#[test]
+	fn output_newline_translation() {
+		let written = RefCell::new(Vec::new());
+		// writes at most 2 bytes at a time, like a port with a full buffer
+		let write = |buf: &[u8]| {
+			let len = buf.len().min(2);
+			written.borrow_mut().extend_from_slice(&buf[..len]);
+			Ok(len)
+		};
+		write_all_with(&write, b"ab\ncde\n", true).unwrap();
+		assert_eq!(written.borrow().as_slice(), b"ab\r\ncde\r\n");
+
+		written.borrow_mut().clear();
+		write_all_with(&write, b"ab\n", false).unwrap();
+		assert_eq!(written.borrow().as_slice(), b"ab\n");
+	}
+
+	#[test]
+	fn canonical_editing() {
+		let mode = LineMode { eol: Some(b'\r'), eol2: None, editing: true };
+		let mut state = State {
+			canonical: Some(mode),
+			options: TerminalOptions { echo_erase: true, ..TerminalOptions::default() },
+			input_newline: InputNewline::Raw,
+			output_newline: OutputNewline::Raw,
+			pending: Vec::new(),
+			complete: 0,
+			max_line: 4,
+			reading: false
+		};
+		assert_eq!(state.push(&mode, b"ab\x7fc\rxy\x15z"), b"ab\x08 \x08c\rxy\x15z");
+
+		let mut buf = [0u8; 8];
+		assert_eq!(state.take_line(&mut buf), 3);
+		assert_eq!(&buf[..3], b"ac\r");
+		assert_eq!(state.take_line(&mut buf), 0);
+
+		// lines without terminator are split at max_line
+		state.push(&mode, b"defg");
+		assert_eq!(state.take_line(&mut buf), 4);
+		assert_eq!(&buf[..4], b"zdef");
+	}
+
+	#[test]
+	fn canonical_end_of_file() {
+		let mode = LineMode { eol: None, eol2: None, editing: true };
+		let mut state = State {
+			canonical: Some(mode),
+			options: TerminalOptions::default(),
+			input_newline: InputNewline::Raw,
+			output_newline: OutputNewline::Raw,
+			pending: Vec::new(),
+			complete: 0,
+			max_line: DEFAULT_MAX_LINE,
+			reading: false
+		};
+		// Ctrl+D is not echoed and ends a line without terminator
+		assert_eq!(state.push(&mode, b"ab\x04\x04c\n"), b"abc\n");
+
+		let mut buf = [0u8; 8];
+		assert_eq!(state.take_line(&mut buf[..1]), 1);
+		assert_eq!(state.take_line(&mut buf), 1);
+		assert_eq!(&buf[..1], b"b");
+		// on an empty line, it reads as end of file
+		assert_eq!(state.take_line(&mut buf), 0);
+		assert_eq!(state.take_line(&mut buf), 2);
+		assert_eq!(&buf[..2], b"c\n");
+		assert_eq!(state.complete, 0);
+
+		// the markers are removed once editing is disabled
+		state.push(&mode, b"d\x04\x04e");
+		state.strip_eof();
+		state.canonical = None;
+		assert_eq!(state.take_line(&mut buf), 1);
+		assert_eq!(&buf[..1], b"d");
+		assert_eq!(state.pending, b"e");
+	}
+}
@@ -0,0 +1,48 @@
+// MIDI over UART (e.g., a DIN-5 MIDI interface or a synthesizer's serial
+// port), whose messages can be split and encoded via codec::MidiFramer and
+// codec::MidiEncoder, e.g.:
+//   let port = SerialPort::open("/dev/ttyAMA0", None)?;
+//   midi::configure(&port)?;
+//   let mut framer = MidiFramer::default();
+
+use std::io;
+
+use crate::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+pub const BAUD_RATE: u32 = 31_250;
+
+// 31250 Bd 8N1 without flow control. fails if the adapter does not support
+// the baud rate, which is not among the standard rates, instead of
+// silently using the nearest one.
+pub fn configure(port: &SerialPort) -> io::Result<()> {
+	let unsupported = || io::Error::new(io::ErrorKind::Unsupported,
+		"adapter does not support the MIDI baud rate (31250 Bd)");
+	port.set_baud_rate(BAUD_RATE).map_err(|e| match e.kind() {
+		io::ErrorKind::InvalidInput => unsupported(),
+		_ => e
+	})?;
+	if port.baud_rate()? != BAUD_RATE {
+		return Err(unsupported());
+	}
+
+	port.set_data_bits(DataBits::Eight)?;
+	port.set_parity(Parity::None)?;
+	port.set_stop_bits(StopBits::One)?;
+	port.set_flow_control(FlowControl::None)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	#[test]
+	fn configure_8n1() {
+		let port = SimulatedSerialPort::new(None).into_serial_port();
+		configure(&port).unwrap();
+		assert_eq!(port.baud_rate().unwrap(), BAUD_RATE);
+		assert_eq!(port.data_bits().unwrap(), DataBits::Eight);
+		assert_eq!(port.parity().unwrap(), Parity::None);
+		assert_eq!(port.stop_bits().unwrap(), StopBits::One);
+	}
+}
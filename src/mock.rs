@@ -0,0 +1,646 @@
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialIo, StopBits};
+use crate::backend::{AtomicTimeout, Backend};
+
+// scripted RX events consumed by read() in FIFO order
+enum RxEvent {
+	Data(Vec<u8>),
+	Delay(Duration),
+	Timeout
+}
+
+struct State {
+	rx: VecDeque<RxEvent>,
+	tx: Vec<u8>,
+	tx_timeouts: usize,
+	// modem control lines (outputs set by the port, inputs by the test)
+	rts: bool,
+	dtr: bool,
+	cts: bool,
+	dsr: bool,
+	ri: bool,
+	cd: bool,
+	// line settings are only stored, as they do not affect the script
+	baud_rate: u32,
+	data_bits: DataBits,
+	parity: Parity,
+	stop_bits: StopBits,
+	flow_control: FlowControl,
+	local: bool,
+	brk: bool,
+	transmit_paused: bool
+}
+
+// in-memory serial port fed by scripted RX data that captures all TX data.
+// clones share the same script and TX capture, just like clones of a real
+// port share the same device.
+pub struct MockSerialPort {
+	state: Arc<Mutex<State>>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+impl MockSerialPort {
+	pub fn new(timeout: Option<Duration>) -> Self {
+		Self {
+			state: Arc::new(Mutex::new(State {
+				rx: VecDeque::new(),
+				tx: Vec::new(),
+				tx_timeouts: 0,
+				rts: false,
+				dtr: false,
+				cts: false,
+				dsr: false,
+				ri: false,
+				cd: false,
+				baud_rate: 9600,
+				data_bits: DataBits::Eight,
+				parity: Parity::None,
+				stop_bits: StopBits::One,
+				flow_control: FlowControl::None,
+				local: true,
+				brk: false,
+				transmit_paused: false
+			})),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		}
+	}
+
+	// the device path is ignored, opening a mock never fails
+	pub fn open<T>(_dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		Ok(Self::new(timeout))
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
+
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
+		Ok(Self {
+			state: self.state.clone(),
+			timeout_read,
+			timeout_write
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		// a panicking test thread must not render the mock unusable
+		match self.state.lock() {
+			Ok(guard) => guard,
+			Err(poisoned) => poisoned.into_inner()
+		}
+	}
+
+	// append data that subsequent read()s will return
+	pub fn push_rx_data(&self, data: &[u8]) {
+		self.lock().rx.push_back(RxEvent::Data(data.to_vec()));
+	}
+
+	// delay the next read() by the given duration, which times out if the
+	// delay exceeds the port's timeout
+	pub fn push_rx_delay(&self, delay: Duration) {
+		self.lock().rx.push_back(RxEvent::Delay(delay));
+	}
+
+	// make the next read() time out immediately
+	pub fn push_rx_timeout(&self) {
+		self.lock().rx.push_back(RxEvent::Timeout);
+	}
+
+	// make the next write() time out immediately without capturing data
+	pub fn push_tx_timeout(&self) {
+		self.lock().tx_timeouts += 1;
+	}
+
+	// return all data written so far and clear the TX capture
+	pub fn take_tx_data(&self) -> Vec<u8> {
+		std::mem::take(&mut self.lock().tx)
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		loop {
+			let mut state = self.lock();
+			match state.rx.pop_front() {
+				Some(RxEvent::Data(mut data)) => {
+					let len = data.len().min(buf.len());
+					buf[..len].copy_from_slice(&data[..len]);
+
+					// keep remainder for next read()
+					if len < data.len() {
+						state.rx.push_front(RxEvent::Data(data.split_off(len)));
+					}
+					return Ok(len);
+				},
+				Some(RxEvent::Delay(delay)) => {
+					// sleep without holding the lock, so clones can proceed
+					drop(state);
+					match timeout {
+						Some(timeout) if timeout < delay => {
+							clock::sleep(timeout);
+							self.lock().rx.push_front(RxEvent::Delay(delay - timeout));
+							return Err(io::Error::new(io::ErrorKind::TimedOut,
+								"reading from mock timed out"));
+						},
+						_ => clock::sleep(delay)
+					}
+				},
+				Some(RxEvent::Timeout) => {
+					return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"reading from mock timed out"));
+				},
+				// a real port would block forever without timeout, which is
+				// never desirable in a test, so report the exhausted script
+				None if timeout.is_none() => {
+					return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+						"mock RX script exhausted"));
+				},
+				None => {
+					return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"reading from mock timed out"));
+				}
+			}
+		}
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		let mut state = self.lock();
+		if state.tx_timeouts > 0 {
+			state.tx_timeouts -= 1;
+			return Err(io::Error::new(io::ErrorKind::TimedOut,
+				"writing to mock timed out"));
+		}
+
+		state.tx.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	// write timeouts are only stored, because writes never block
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	pub fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.lock().rts = level;
+		Ok(())
+	}
+
+	pub fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.lock().dtr = level;
+		Ok(())
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		Ok(self.lock().cts)
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		Ok(self.lock().dsr)
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		Ok(self.lock().ri)
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		Ok(self.lock().cd)
+	}
+
+	// inspect modem control outputs driven by the code under test
+	pub fn rts(&self) -> bool {
+		self.lock().rts
+	}
+
+	pub fn dtr(&self) -> bool {
+		self.lock().dtr
+	}
+
+	// drive modem control inputs observed by the code under test
+	pub fn set_cts(&self, level: bool) {
+		self.lock().cts = level;
+	}
+
+	pub fn set_dsr(&self, level: bool) {
+		self.lock().dsr = level;
+	}
+
+	pub fn set_ri(&self, level: bool) {
+		self.lock().ri = level;
+	}
+
+	pub fn set_cd(&self, level: bool) {
+		self.lock().cd = level;
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		Ok(self.lock().baud_rate)
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.lock().baud_rate = baud_rate;
+		Ok(())
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		Ok(self.lock().data_bits)
+	}
+
+	pub fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.lock().data_bits = data_bits;
+		Ok(())
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		Ok(self.lock().parity)
+	}
+
+	pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.lock().parity = parity;
+		Ok(())
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		Ok(self.lock().stop_bits)
+	}
+
+	pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.lock().stop_bits = stop_bits;
+		Ok(())
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		Ok(self.lock().flow_control)
+	}
+
+	pub fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.lock().flow_control = flow_control;
+		Ok(())
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		Ok(self.lock().local)
+	}
+
+	pub fn set_local(&self, local: bool) -> io::Result<()> {
+		self.lock().local = local;
+		Ok(())
+	}
+
+	pub fn set_break(&self, level: bool) -> io::Result<()> {
+		self.lock().brk = level;
+		Ok(())
+	}
+
+	// inspect break condition driven by the code under test
+	pub fn is_break(&self) -> bool {
+		self.lock().brk
+	}
+
+	// only stored, written data is captured regardless
+	pub fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.lock().transmit_paused = paused;
+		Ok(())
+	}
+
+	pub fn is_transmit_paused(&self) -> bool {
+		self.lock().transmit_paused
+	}
+
+	// discard scripted RX events and/or captured TX data
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		let mut state = self.lock();
+		if input {
+			state.rx.clear();
+		}
+		if output {
+			state.tx.clear();
+		}
+		Ok(())
+	}
+}
+
+impl SerialIo for MockSerialPort {
+	fn open<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		MockSerialPort::open(dev_path, timeout)
+	}
+
+	fn try_clone(&self) -> io::Result<Self> {
+		MockSerialPort::try_clone(self)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		MockSerialPort::read_timeout(self)
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		MockSerialPort::write_timeout(self)
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		MockSerialPort::set_read_timeout(self, timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		MockSerialPort::set_write_timeout(self, timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		MockSerialPort::set_rts(self, level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		MockSerialPort::set_dtr(self, level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		MockSerialPort::read_cts(self)
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		MockSerialPort::read_dsr(self)
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		MockSerialPort::read_ri(self)
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		MockSerialPort::read_cd(self)
+	}
+}
+
+// open "mock://data" backend that returns the given data on read()
+pub(crate) fn open_backend(path: &str, timeout: Option<Duration>)
+		-> io::Result<Box<dyn Backend>> {
+	let mock = MockSerialPort::new(timeout);
+	if !path.is_empty() {
+		mock.push_rx_data(path.as_bytes());
+	}
+	Ok(Box::new(mock))
+}
+
+impl Backend for MockSerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		MockSerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		MockSerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		MockSerialPort::read_with_timeout(self, buf, timeout)
+	}
+
+	// writes never block, so the timeout does not matter
+	fn write_with_timeout(&self, buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		MockSerialPort::write(self, buf)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		MockSerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		MockSerialPort::try_clone(self).map(|mock| Box::new(mock) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		MockSerialPort::try_clone_shared(self).map(|mock| Box::new(mock) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		MockSerialPort::read_timeout(self)
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		MockSerialPort::write_timeout(self)
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		MockSerialPort::set_read_timeout(self, timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		MockSerialPort::set_write_timeout(self, timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		MockSerialPort::set_rts(self, level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		MockSerialPort::set_dtr(self, level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		MockSerialPort::read_cts(self)
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		MockSerialPort::read_dsr(self)
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		MockSerialPort::read_ri(self)
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		MockSerialPort::read_cd(self)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		MockSerialPort::baud_rate(self)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		MockSerialPort::set_baud_rate(self, baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		MockSerialPort::data_bits(self)
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		MockSerialPort::set_data_bits(self, data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		MockSerialPort::parity(self)
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		MockSerialPort::set_parity(self, parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		MockSerialPort::stop_bits(self)
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		MockSerialPort::set_stop_bits(self, stop_bits)
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		MockSerialPort::flow_control(self)
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		MockSerialPort::set_flow_control(self, flow_control)
+	}
+
+	fn local(&self) -> io::Result<bool> {
+		MockSerialPort::local(self)
+	}
+
+	fn set_local(&self, local: bool) -> io::Result<()> {
+		MockSerialPort::set_local(self, local)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		MockSerialPort::set_break(self, level)
+	}
+
+	fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		MockSerialPort::set_transmit_paused(self, paused)
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		MockSerialPort::purge(self, input, output)
+	}
+}
+
+impl io::Read for MockSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		MockSerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &MockSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		MockSerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for MockSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		MockSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		MockSerialPort::flush(self)
+	}
+}
+
+impl io::Write for &MockSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		MockSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		MockSerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::clock::MockClock;
+
+	#[test]
+	fn scripted_reads_and_captured_writes() {
+		let port = MockSerialPort::new(None);
+		port.push_rx_data(b"hello");
+		port.push_rx_timeout();
+
+		let mut buf = [0u8; 3];
+		assert_eq!(port.read(&mut buf).unwrap(), 3);
+		assert_eq!(&buf, b"hel");
+		assert_eq!(port.read(&mut buf).unwrap(), 2);
+		assert_eq!(&buf[..2], b"lo");
+		assert_eq!(port.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+		assert_eq!(port.read(&mut buf).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+		port.push_tx_timeout();
+		assert_eq!(port.write(b"lost").unwrap_err().kind(), io::ErrorKind::TimedOut);
+		assert_eq!(port.write(b"ab").unwrap(), 2);
+		assert_eq!(port.write(b"cd").unwrap(), 2);
+		assert_eq!(port.take_tx_data(), b"abcd");
+		assert!(port.take_tx_data().is_empty());
+	}
+
+	#[test]
+	fn delays_time_out() {
+		let clock = MockClock::install();
+		let port = MockSerialPort::new(Some(Duration::from_millis(100)));
+		port.push_rx_delay(Duration::from_millis(150));
+		port.push_rx_data(b"x");
+
+		// the remainder of the delay is kept for the next read()
+		let mut buf = [0u8; 1];
+		assert_eq!(port.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+		assert_eq!(clock.elapsed(), Duration::from_millis(100));
+		assert_eq!(port.read(&mut buf).unwrap(), 1);
+		assert_eq!(clock.elapsed(), Duration::from_millis(150));
+		MockClock::uninstall();
+	}
+
+	#[test]
+	fn clones_share_script_and_lines() {
+		let port = MockSerialPort::new(None);
+		let clone = port.try_clone().unwrap();
+		let shared = port.try_clone_shared().unwrap();
+
+		clone.push_rx_data(b"a");
+		let mut buf = [0u8; 1];
+		assert_eq!(port.read(&mut buf).unwrap(), 1);
+		clone.write(b"b").unwrap();
+		assert_eq!(port.take_tx_data(), b"b");
+
+		port.set_rts(true).unwrap();
+		assert!(clone.rts());
+		clone.set_cts(true);
+		assert!(port.read_cts().unwrap());
+
+		// timeouts are copied by try_clone() and shared by try_clone_shared()
+		port.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+		assert_eq!(clone.read_timeout(), None);
+		assert_eq!(shared.read_timeout(), Some(Duration::from_millis(5)));
+	}
+}
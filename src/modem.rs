@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::SerialPort;
+
+// timeout of the background thread's waits for a change. TIOCMIWAIT on
+// Linux blocks without polling, but cannot be interrupted, so the thread
+// blocks until the next change. elsewhere (e.g., on Windows), it waits in
+// steps to check whether the watch was dropped.
+#[cfg(unix)]
+const WAIT_TIMEOUT: Option<Duration> = None;
+#[cfg(not(unix))]
+const WAIT_TIMEOUT: Option<Duration> = Some(Duration::from_millis(50));
+
+// levels of the modem status inputs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModemStatus {
+	pub cts: bool,
+	pub dsr: bool,
+	pub ri: bool,
+	pub cd: bool
+}
+
+impl ModemStatus {
+	pub fn read(port: &SerialPort) -> io::Result<Self> {
+		Ok(Self {
+			cts: port.read_cts()?,
+			dsr: port.read_dsr()?,
+			ri: port.read_ri()?,
+			cd: port.read_cd()?
+		})
+	}
+}
+
+struct State {
+	changes: VecDeque<io::Result<ModemStatus>>,
+	// the thread terminated after queuing its error (if any)
+	terminated: bool,
+	// the watch was dropped
+	stop: bool,
+	// the thread waits for a change without timeout
+	blocked: bool,
+	// tasks waiting in poll_recv()
+	wakers: Vec<Waker>
+}
+
+struct Shared {
+	state: Mutex<State>,
+	// notified whenever any of the state changed
+	signal: Condvar
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn update<F: FnOnce(&mut State)>(&self, f: F) {
+		let mut state = self.lock();
+		f(&mut state);
+		for waker in state.wakers.drain(..) {
+			waker.wake();
+		}
+		drop(state);
+		self.signal.notify_all();
+	}
+
+	// mark the thread as blocked for the wait without timeout. returns
+	// false if the watch was dropped meanwhile.
+	fn block(&self) -> bool {
+		let mut state = self.lock();
+		state.blocked = !state.stop;
+		state.blocked
+	}
+
+	fn unblock(&self) {
+		self.lock().blocked = false;
+	}
+
+	// wait up to timeout and return whether the watch was dropped
+	fn wait_stop(&self, timeout: Duration) -> bool {
+		let deadline = Instant::now() + timeout;
+		let mut state = self.lock();
+		while !state.stop {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if remaining.is_zero() {
+				break;
+			}
+			state = self.signal.wait_timeout(state, remaining)
+				.unwrap_or_else(|e| e.into_inner()).0;
+		}
+		state.stop
+	}
+}
+
+// receives the modem status whenever it changed, e.g., to react to handshake
+// lines without polling them in the I/O loop. a background thread waits on a
+// clone of the port via SerialPort::wait_modem_change() or, where that is
+// unsupported, polls the inputs. the thread terminates after reporting the
+// first error. async applications can await changed() or, with the usb or
+// wasm features, use the watch as futures_lite::Stream.
+// dropping the watch stops the thread and waits until it closed the clone,
+// so the device can be opened again right away. on Linux, however, the
+// thread blocks in TIOCMIWAIT, which cannot be interrupted, so it keeps the
+// clone open until the next change. on Windows, the thread claims waiting
+// for the port's events, so wait_modem_change(), SerialPort::wait_any(), or
+// a reactor fail with AddrInUse for the port while it is watched.
+pub struct ModemWatch {
+	shared: Arc<Shared>,
+	thread: Option<JoinHandle<()>>
+}
+
+impl ModemWatch {
+	// wait for the next change
+	pub fn recv(&self) -> io::Result<ModemStatus> {
+		self.next_change().unwrap_or_else(|| Err(terminated()))
+	}
+
+	// None once the thread terminated and all changes were received
+	fn next_change(&self) -> Option<io::Result<ModemStatus>> {
+		let mut state = self.shared.lock();
+		loop {
+			if let Some(res) = state.changes.pop_front() {
+				return Some(res);
+			}
+			if state.terminated {
+				return None;
+			}
+			state = self.shared.signal.wait(state).unwrap_or_else(|e| e.into_inner());
+		}
+	}
+
+	pub fn recv_timeout(&self, timeout: Duration) -> io::Result<ModemStatus> {
+		let deadline = Instant::now() + timeout;
+		let mut state = self.shared.lock();
+		loop {
+			if let Some(res) = state.changes.pop_front() {
+				return res;
+			}
+			if state.terminated {
+				return Err(terminated());
+			}
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if remaining.is_zero() {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for modem status change timed out"));
+			}
+			state = self.shared.signal.wait_timeout(state, remaining)
+				.unwrap_or_else(|e| e.into_inner()).0;
+		}
+	}
+
+	// returns None if the status did not change since the previous call
+	pub fn try_recv(&self) -> io::Result<Option<ModemStatus>> {
+		let mut state = self.shared.lock();
+		match state.changes.pop_front() {
+			Some(res) => res.map(Some),
+			None if state.terminated => Err(terminated()),
+			None => Ok(None)
+		}
+	}
+
+	// like recv(), but registers the task to be woken by the next change
+	// instead of blocking
+	pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<ModemStatus>> {
+		self.poll_change(cx).map(|res| res.unwrap_or_else(|| Err(terminated())))
+	}
+
+	fn poll_change(&self, cx: &mut Context<'_>) -> Poll<Option<io::Result<ModemStatus>>> {
+		let mut state = self.shared.lock();
+		if let Some(res) = state.changes.pop_front() {
+			return Poll::Ready(Some(res));
+		}
+		if state.terminated {
+			return Poll::Ready(None);
+		}
+		if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+			state.wakers.push(cx.waker().clone());
+		}
+		Poll::Pending
+	}
+
+	// wait for the next change asynchronously
+	pub fn changed(&self) -> Changed<'_> {
+		Changed { watch: self }
+	}
+}
+
+// yields changes until the thread terminated (after yielding its error)
+impl Iterator for ModemWatch {
+	type Item = io::Result<ModemStatus>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.next_change()
+	}
+}
+
+#[cfg(feature = "futures-lite")]
+impl futures_lite::Stream for ModemWatch {
+	type Item = io::Result<ModemStatus>;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		self.poll_change(cx)
+	}
+}
+
+impl Drop for ModemWatch {
+	fn drop(&mut self) {
+		let mut blocked = false;
+		self.shared.update(|state| {
+			state.stop = true;
+			blocked = state.blocked;
+		});
+		// a thread blocked indefinitely is detached and terminates after the
+		// next change
+		if let Some(thread) = self.thread.take().filter(|_| !blocked) {
+			let _res = thread.join();
+		}
+	}
+}
+
+// future returned by ModemWatch::changed()
+pub struct Changed<'a> {
+	watch: &'a ModemWatch
+}
+
+impl Future for Changed<'_> {
+	type Output = io::Result<ModemStatus>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		self.watch.poll_recv(cx)
+	}
+}
+
+fn terminated() -> io::Error {
+	io::Error::new(io::ErrorKind::BrokenPipe, "modem status watch terminated")
+}
+
+// watch the modem status of port, polling every poll_interval if waiting for
+// changes is unsupported. returns the current status along with the watch.
+// the thread compares the status after each wait (or poll), so changes are
+// reported unless they were reverted meanwhile (e.g., a short ring indicator
+// pulse).
+pub fn modem_status_changes(port: &SerialPort, poll_interval: Duration)
+		-> io::Result<(ModemStatus, ModemWatch)> {
+	let port = port.try_clone()?;
+	let initial = ModemStatus::read(&port)?;
+	let shared = Arc::new(Shared {
+		state: Mutex::new(State {
+			changes: VecDeque::new(),
+			terminated: false,
+			stop: false,
+			blocked: false,
+			wakers: Vec::new()
+		}),
+		signal: Condvar::new()
+	});
+
+	let thread_shared = shared.clone();
+	let thread = thread::spawn(move || {
+		let res = watch(&port, initial, poll_interval, &thread_shared);
+		// close the clone before the watch is reported as terminated
+		drop(port);
+		thread_shared.update(|state| {
+			if let Err(e) = res {
+				state.changes.push_back(Err(e));
+			}
+			state.terminated = true;
+		});
+	});
+
+	Ok((initial, ModemWatch { shared, thread: Some(thread) }))
+}
+
+// report changes until the watch was dropped or an error occurred
+fn watch(port: &SerialPort, mut status: ModemStatus, poll_interval: Duration, shared: &Shared)
+		-> io::Result<()> {
+	let mut poll = false;
+	loop {
+		if !poll {
+			if WAIT_TIMEOUT.is_none() && !shared.block() {
+				return Ok(());
+			}
+			let res = port.wait_modem_change(WAIT_TIMEOUT);
+			shared.unblock();
+			match res {
+				Ok(()) => (),
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+				Err(ref e) if e.kind() == io::ErrorKind::Unsupported => poll = true,
+				Err(e) => return Err(e)
+			}
+		}
+
+		let wait = if poll { poll_interval } else { Duration::ZERO };
+		if shared.wait_stop(wait) {
+			return Ok(());
+		}
+
+		let current = ModemStatus::read(port)?;
+		if current != status {
+			status = current;
+			shared.update(|state| state.changes.push_back(Ok(current)));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	#[cfg(feature = "mock")]
+	use crate::MockSerialPort;
+
+	const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+	// watching fails right away if the modem status cannot be read
+	#[test]
+	fn unreadable_status() {
+		let port = crate::sim::SimulatedSerialPort::new(None).into_serial_port();
+		let e = modem_status_changes(&port, POLL_INTERVAL).err().unwrap();
+		assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+	}
+
+	#[cfg(feature = "mock")]
+	#[test]
+	fn report_changes() {
+		let mock = MockSerialPort::new(None);
+		mock.set_dsr(true);
+		let port = SerialPort::from_backend(Box::new(mock.try_clone().unwrap()));
+		let (initial, watch) = modem_status_changes(&port, POLL_INTERVAL).unwrap();
+		assert_eq!(initial, ModemStatus { dsr: true, ..ModemStatus::default() });
+		assert!(watch.try_recv().unwrap().is_none());
+
+		mock.set_cts(true);
+		let status = watch.recv_timeout(Duration::from_secs(5)).unwrap();
+		assert_eq!(status, ModemStatus { cts: true, dsr: true, ..ModemStatus::default() });
+
+		mock.set_dsr(false);
+		let status = watch.recv_timeout(Duration::from_secs(5)).unwrap();
+		assert_eq!(status, ModemStatus { cts: true, ..ModemStatus::default() });
+
+		let e = watch.recv_timeout(POLL_INTERVAL * 4).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+	}
+
+	// the thread stops within the poll interval once the watch is dropped
+	#[cfg(feature = "mock")]
+	#[test]
+	fn drop_stops_thread() {
+		let mock = MockSerialPort::new(None);
+		let port = SerialPort::from_backend(Box::new(mock.try_clone().unwrap()));
+		let (_, watch) = modem_status_changes(&port, Duration::from_millis(50)).unwrap();
+		let shared = watch.shared.clone();
+
+		let start = Instant::now();
+		drop(watch);
+		assert!(start.elapsed() < Duration::from_secs(1));
+		assert!(shared.lock().terminated);
+	}
+}
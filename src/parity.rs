@@ -0,0 +1,130 @@
+// decoding of data received with parity marking enabled, see
+// SerialPort::set_parity_marking()
+
+// error character replacing bytes with parity errors on Windows
+pub(crate) const ERROR_CHAR: u8 = 0xff;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+	Data,
+	// received 0xff
+	Escape,
+	// received 0xff 0x00, the next byte had an error
+	Marked
+}
+
+// strips the marking from received data and reports the positions of bytes
+// received with errors. marking sequences may be split across reads, so one
+// decoder must be used for all data read from a port.
+//
+// on Unix, a byte with an error is received as 0xff 0x00 byte and a valid
+// 0xff as 0xff 0xff (PARMRK). a break is decoded as an erroneous 0x00. on
+// Windows, bytes with errors are replaced by 0xff, so their values are lost
+// and every 0xff is reported as erroneous, as valid ones are
+// indistinguishable. decoders for other platforms' marking can be created
+// with unix() and windows().
+#[derive(Clone, Debug)]
+pub struct ParityDecoder {
+	escaped: bool,
+	state: State
+}
+
+impl ParityDecoder {
+	#[cfg(not(windows))]
+	pub fn new() -> Self {
+		Self::unix()
+	}
+
+	#[cfg(windows)]
+	pub fn new() -> Self {
+		Self::windows()
+	}
+
+	pub fn unix() -> Self {
+		Self { escaped: true, state: State::Data }
+	}
+
+	pub fn windows() -> Self {
+		Self { escaped: false, state: State::Data }
+	}
+
+	// append the decoded data to out and the positions of erroneous bytes in
+	// out to errors
+	pub fn decode(&mut self, data: &[u8], out: &mut Vec<u8>, errors: &mut Vec<usize>) {
+		if !self.escaped {
+			for &byte in data {
+				if byte == ERROR_CHAR {
+					errors.push(out.len());
+				}
+				out.push(byte);
+			}
+			return;
+		}
+
+		for &byte in data {
+			self.state = match (self.state, byte) {
+				(State::Data, 0xff) => State::Escape,
+				(State::Data, _) => {
+					out.push(byte);
+					State::Data
+				},
+				(State::Escape, 0x00) => State::Marked,
+				(State::Escape, _) => {
+					// only 0xff is expected, pass anything else on unaltered
+					if byte != 0xff {
+						out.push(0xff);
+					}
+					out.push(byte);
+					State::Data
+				},
+				(State::Marked, _) => {
+					errors.push(out.len());
+					out.push(byte);
+					State::Data
+				}
+			};
+		}
+	}
+
+	// whether data ended within a marking sequence
+	pub fn is_pending(&self) -> bool {
+		self.state != State::Data
+	}
+
+	pub fn reset(&mut self) {
+		self.state = State::Data;
+	}
+}
+
+impl Default for ParityDecoder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unix_marking() {
+		let mut decoder = ParityDecoder::unix();
+		let (mut out, mut errors) = (Vec::new(), Vec::new());
+		// valid 0xff, erroneous 'b', and a marking split across reads
+		decoder.decode(b"a\xff\xff\xff\x00b\xff", &mut out, &mut errors);
+		assert!(decoder.is_pending());
+		decoder.decode(b"\x00c", &mut out, &mut errors);
+		assert!(!decoder.is_pending());
+		assert_eq!(out, b"a\xffbc");
+		assert_eq!(errors, [2, 3]);
+	}
+
+	#[test]
+	fn windows_marking() {
+		let mut decoder = ParityDecoder::windows();
+		let (mut out, mut errors) = (Vec::new(), Vec::new());
+		decoder.decode(b"a\xffb\xff", &mut out, &mut errors);
+		assert_eq!(out, b"a\xffb\xff");
+		assert_eq!(errors, [1, 3]);
+	}
+}
@@ -0,0 +1,349 @@
+// named port configurations loaded from a TOML file or environment
+// variables, so deployment-specific settings live outside the binary, e.g.:
+//   [plc]
+//   path = "/dev/ttyUSB0"
+//   baud_rate = 9600
+//   parity = "even"
+//   timeout = 0.5
+//
+//   [gps]
+//   usb = "1546:01a8"          # VID:PID in hexadecimal, requires feature usb
+//   serial_number = "0001"
+//
+// the same settings can be given as SERIAL_PROFILE_<NAME>_<KEY> environment
+// variables (e.g., SERIAL_PROFILE_PLC_BAUD_RATE=9600), where names are
+// lowercased. keys:
+//   path           device path passed to SerialPort::open()
+//   usb            VID:PID of a USB CDC-ACM device (if path is not given)
+//   serial_number  serial number of the USB device
+//   baud_rate      e.g., 115200
+//   data_bits      5, 6, 7, or 8
+//   parity         none, odd, even, mark, or space
+//   stop_bits      1 or 2
+//   flow_control   none, software, or hardware
+//   timeout        read and write timeout in seconds (blocking if omitted)
+//   read_timeout   overrides timeout for reads
+//   write_timeout  overrides timeout for writes
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+const ENV_PREFIX: &str = "SERIAL_PROFILE_";
+
+// more specific keys first, so SERIAL_PROFILE_PLC_READ_TIMEOUT sets
+// read_timeout of profile plc rather than timeout of profile plc_read
+const KEYS: [&str; 11] = ["serial_number", "write_timeout", "read_timeout", "flow_control",
+	"stop_bits", "data_bits", "baud_rate", "timeout", "parity", "path", "usb"];
+
+fn invalid(msg: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Profile {
+	pub path: Option<OsString>,
+	pub usb: Option<(u16, u16)>,
+	pub serial_number: Option<String>,
+	pub baud_rate: Option<u32>,
+	pub data_bits: Option<DataBits>,
+	pub parity: Option<Parity>,
+	pub stop_bits: Option<StopBits>,
+	pub flow_control: Option<FlowControl>,
+	pub read_timeout: Option<Duration>,
+	pub write_timeout: Option<Duration>
+}
+
+impl Profile {
+	// path the port is opened with, i.e., path or a usb:// path
+	pub fn device_path(&self) -> io::Result<OsString> {
+		match (&self.path, self.usb) {
+			(Some(path), _) => Ok(path.clone()),
+			(None, Some((vid, pid))) => Ok(match &self.serial_number {
+				Some(serial_number) => format!("usb://{:04x}:{:04x}/{}", vid, pid, serial_number),
+				None => format!("usb://{:04x}:{:04x}", vid, pid)
+			}.into()),
+			(None, None) => Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"profile specifies neither path nor usb"))
+		}
+	}
+
+	// open the port and apply the settings. settings that are not given
+	// remain unchanged.
+	pub fn open(&self) -> io::Result<SerialPort> {
+		let port = SerialPort::open(&self.device_path()?, self.read_timeout)?;
+		if self.write_timeout != self.read_timeout {
+			port.set_write_timeout(self.write_timeout)?;
+		}
+		if let Some(baud_rate) = self.baud_rate {
+			port.set_baud_rate(baud_rate)?;
+		}
+		if let Some(data_bits) = self.data_bits {
+			port.set_data_bits(data_bits)?;
+		}
+		if let Some(parity) = self.parity {
+			port.set_parity(parity)?;
+		}
+		if let Some(stop_bits) = self.stop_bits {
+			port.set_stop_bits(stop_bits)?;
+		}
+		if let Some(flow_control) = self.flow_control {
+			port.set_flow_control(flow_control)?;
+		}
+		Ok(port)
+	}
+
+	// set key to the value given as string, as in environment variables
+	fn set(&mut self, key: &str, value: &str) -> io::Result<()> {
+		let bad_value = || invalid(format!("invalid value \"{}\" for {}", value, key));
+		let timeout = || value.parse::<f64>().ok()
+			.filter(|secs| secs.is_finite() && *secs >= 0.0)
+			.map(Duration::from_secs_f64)
+			.ok_or_else(bad_value);
+
+		match key {
+			"path" => self.path = Some(value.into()),
+			"usb" => {
+				let (vid, pid) = value.split_once(':').ok_or_else(bad_value)?;
+				self.usb = Some((u16::from_str_radix(vid, 16).map_err(|_| bad_value())?,
+					u16::from_str_radix(pid, 16).map_err(|_| bad_value())?));
+			},
+			"serial_number" => self.serial_number = Some(value.into()),
+			"baud_rate" => self.baud_rate = Some(value.parse().map_err(|_| bad_value())?),
+			"data_bits" => self.data_bits = Some(match value {
+				"5" => DataBits::Five,
+				"6" => DataBits::Six,
+				"7" => DataBits::Seven,
+				"8" => DataBits::Eight,
+				_ => return Err(bad_value())
+			}),
+			"parity" => self.parity = Some(match value.to_ascii_lowercase().as_str() {
+				"none" => Parity::None,
+				"odd" => Parity::Odd,
+				"even" => Parity::Even,
+				"mark" => Parity::Mark,
+				"space" => Parity::Space,
+				_ => return Err(bad_value())
+			}),
+			"stop_bits" => self.stop_bits = Some(match value {
+				"1" => StopBits::One,
+				"2" => StopBits::Two,
+				_ => return Err(bad_value())
+			}),
+			"flow_control" => self.flow_control = Some(match value.to_ascii_lowercase().as_str() {
+				"none" => FlowControl::None,
+				"software" => FlowControl::Software,
+				"hardware" => FlowControl::Hardware,
+				_ => return Err(bad_value())
+			}),
+			"timeout" => {
+				let timeout = timeout()?;
+				self.read_timeout = Some(timeout);
+				self.write_timeout = Some(timeout);
+			},
+			"read_timeout" => self.read_timeout = Some(timeout()?),
+			"write_timeout" => self.write_timeout = Some(timeout()?),
+			_ => return Err(invalid(format!("unknown profile setting \"{}\"", key)))
+		}
+		Ok(())
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profiles(HashMap<String, Profile>);
+
+impl Profiles {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// load profiles from a TOML file with a table per profile
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Self::from_toml(&fs::read_to_string(path)?)
+	}
+
+	pub fn from_toml(toml: &str) -> io::Result<Self> {
+		let table = match toml.parse::<toml::Value>() {
+			Ok(toml::Value::Table(table)) => table,
+			Ok(_) => return Err(invalid("expected a table of profiles".into())),
+			Err(e) => return Err(invalid(e.to_string()))
+		};
+
+		let mut profiles = Self::new();
+		for (name, settings) in table {
+			let settings = match settings {
+				toml::Value::Table(settings) => settings,
+				_ => return Err(invalid(format!("profile \"{}\" is not a table", name)))
+			};
+			let profile = profiles.0.entry(name.clone()).or_default();
+			// "timeout" must not override the more specific ones
+			let mut settings: Vec<_> = settings.into_iter().collect();
+			settings.sort_by_key(|(key, _)| key != "timeout");
+			for (key, value) in settings {
+				let value = match value {
+					toml::Value::String(value) => value,
+					toml::Value::Integer(value) => value.to_string(),
+					toml::Value::Float(value) => value.to_string(),
+					_ => return Err(invalid(format!("invalid type for {} of profile \"{}\"",
+						key, name)))
+				};
+				profile.set(&key, &value).map_err(|e|
+					invalid(format!("profile \"{}\": {}", name, e)))?;
+			}
+		}
+		Ok(profiles)
+	}
+
+	// load profiles from SERIAL_PROFILE_<NAME>_<KEY> environment variables
+	pub fn from_env() -> io::Result<Self> {
+		let mut profiles = Self::new();
+		profiles.apply_env()?;
+		Ok(profiles)
+	}
+
+	// add or override settings with those from environment variables, e.g.,
+	// to adjust profiles loaded from a file for a particular deployment
+	pub fn apply_env(&mut self) -> io::Result<()> {
+		// unlike env::vars(), variables that are not valid UTF-8 do not
+		// panic. they are skipped unless they are profile settings.
+		let mut vars = Vec::new();
+		for (var, value) in env::vars_os() {
+			let var = match var.into_string() {
+				Ok(var) if var.starts_with(ENV_PREFIX) => var,
+				_ => continue
+			};
+			let value = value.into_string()
+				.map_err(|_| invalid(format!("{} is not valid UTF-8", var)))?;
+			vars.push((var, value));
+		}
+		// "timeout" must not override the more specific ones
+		vars.sort_by_key(|(var, _)| !var.ends_with("_TIMEOUT")
+			|| var.ends_with("_READ_TIMEOUT") || var.ends_with("_WRITE_TIMEOUT"));
+
+		for (var, value) in vars {
+			let rest = &var[ENV_PREFIX.len()..];
+			let (name, key) = KEYS.iter()
+				.find_map(|key| rest.strip_suffix(&key.to_ascii_uppercase())
+					.and_then(|name| name.strip_suffix('_'))
+					.filter(|name| !name.is_empty())
+					.map(|name| (name.to_ascii_lowercase(), *key)))
+				.ok_or_else(|| invalid(format!("unknown profile setting in {}", var)))?;
+			self.0.entry(name).or_default().set(key, &value)
+				.map_err(|e| invalid(format!("{}: {}", var, e)))?;
+		}
+		Ok(())
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Profile> {
+		self.0.get(name)
+	}
+
+	pub fn insert(&mut self, name: &str, profile: Profile) {
+		self.0.insert(name.into(), profile);
+	}
+
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.0.keys().map(String::as_str)
+	}
+
+	// open the port of the named profile
+	pub fn open(&self, name: &str) -> io::Result<SerialPort> {
+		self.get(name)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+				format!("no profile named \"{}\"", name)))?
+			.open()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_toml() {
+		let profiles = Profiles::from_toml(r#"
+			[plc]
+			path = "/dev/ttyUSB0"
+			baud_rate = 9600
+			parity = "Even"
+			read_timeout = 2
+			timeout = 0.5
+
+			[gps]
+			usb = "1546:01a8"
+			serial_number = "0001"
+		"#).unwrap();
+
+		let plc = profiles.get("plc").unwrap();
+		assert_eq!(plc.device_path().unwrap(), "/dev/ttyUSB0");
+		assert_eq!(plc.baud_rate, Some(9600));
+		assert_eq!(plc.parity, Some(Parity::Even));
+		assert_eq!(plc.data_bits, None);
+		// the specific timeout takes precedence regardless of the order
+		assert_eq!(plc.read_timeout, Some(Duration::from_secs(2)));
+		assert_eq!(plc.write_timeout, Some(Duration::from_millis(500)));
+
+		let gps = profiles.get("gps").unwrap();
+		assert_eq!(gps.usb, Some((0x1546, 0x01a8)));
+		assert_eq!(gps.device_path().unwrap(), "usb://1546:01a8/0001");
+
+		let mut names: Vec<_> = profiles.names().collect();
+		names.sort_unstable();
+		assert_eq!(names, ["gps", "plc"]);
+	}
+
+	#[test]
+	fn reject_invalid_settings() {
+		for toml in ["[a]\nbaud_rate = \"fast\"", "[a]\ndata_bits = 9", "[a]\nspeed = 9600",
+				"[a]\ntimeout = -1", "[a]\nusb = \"1546\"", "a = 1", "[a]\npath = true"] {
+			let e = Profiles::from_toml(toml).unwrap_err();
+			assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{}", toml);
+		}
+
+		let profiles = Profiles::from_toml("[empty]").unwrap();
+		let e = profiles.get("empty").unwrap().device_path().unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+		assert_eq!(profiles.open("missing").err().unwrap().kind(), io::ErrorKind::NotFound);
+	}
+
+	#[test]
+	fn environment_overrides() {
+		let mut profiles = Profiles::from_toml("[env_test]\nbaud_rate = 9600").unwrap();
+		env::set_var("SERIAL_PROFILE_ENV_TEST_BAUD_RATE", "115200");
+		env::set_var("SERIAL_PROFILE_ENV_TEST_TIMEOUT", "1");
+		env::set_var("SERIAL_PROFILE_ENV_TEST_WRITE_TIMEOUT", "0.25");
+		let res = profiles.apply_env();
+		env::remove_var("SERIAL_PROFILE_ENV_TEST_BAUD_RATE");
+		env::remove_var("SERIAL_PROFILE_ENV_TEST_TIMEOUT");
+		env::remove_var("SERIAL_PROFILE_ENV_TEST_WRITE_TIMEOUT");
+		res.unwrap();
+
+		let profile = profiles.get("env_test").unwrap();
+		assert_eq!(profile.baud_rate, Some(115_200));
+		assert_eq!(profile.read_timeout, Some(Duration::from_secs(1)));
+		assert_eq!(profile.write_timeout, Some(Duration::from_millis(250)));
+	}
+
+	#[cfg(feature = "mock")]
+	#[test]
+	fn open_applies_settings() {
+		let profiles = Profiles::from_toml(r#"
+			[mock]
+			path = "mock://"
+			baud_rate = 19200
+			stop_bits = 2
+			timeout = 1
+		"#).unwrap();
+		let port = profiles.open("mock").unwrap();
+		assert_eq!(port.baud_rate().unwrap(), 19200);
+		assert_eq!(port.stop_bits().unwrap(), StopBits::Two);
+		assert_eq!(port.read_timeout(), Some(Duration::from_secs(1)));
+	}
+}
@@ -0,0 +1,139 @@
+// Python bindings, e.g., for lab automation scripts. unlike pySerial, ports
+// can be cloned and used from multiple threads concurrently, e.g.:
+//   import serial
+//   port = serial.SerialPort("/dev/ttyUSB0", timeout=1.0)
+//   reader = port.try_clone()
+//   threading.Thread(target=lambda: print(reader.read(64))).start()
+//   port.write(b"hello")
+//
+// timeouts are given in seconds, where None blocks indefinitely. timed out
+// reads and writes raise TimeoutError, just like the Rust API returns
+// ErrorKind::TimedOut. blocking calls release the GIL.
+
+// false positives in code generated by #[pymethods]
+#![allow(clippy::useless_conversion)]
+
+use std::time::Duration;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::SerialPort;
+
+fn duration(secs: Option<f64>) -> PyResult<Option<Duration>> {
+	match secs {
+		None => Ok(None),
+		Some(secs) if secs.is_finite() && secs >= 0.0 => Ok(Some(Duration::from_secs_f64(secs))),
+		Some(_) => Err(PyValueError::new_err("timeout must be a non-negative number or None"))
+	}
+}
+
+#[pyclass(name = "SerialPort", frozen)]
+struct PySerialPort {
+	port: SerialPort
+}
+
+#[pymethods]
+impl PySerialPort {
+	#[new]
+	#[pyo3(signature = (path, timeout=None))]
+	fn open(py: Python<'_>, path: &str, timeout: Option<f64>) -> PyResult<Self> {
+		let timeout = duration(timeout)?;
+		let port = py.allow_threads(|| SerialPort::open(path, timeout))?;
+		Ok(Self { port })
+	}
+
+	// clones share the device, but have separate timeouts
+	fn try_clone(&self) -> PyResult<Self> {
+		Ok(Self { port: self.port.try_clone()? })
+	}
+
+	// read up to size bytes, returning as soon as any were received
+	#[pyo3(signature = (size=1))]
+	fn read<'py>(&self, py: Python<'py>, size: usize) -> PyResult<Bound<'py, PyBytes>> {
+		let mut buf = vec![0u8; size];
+		let len = py.allow_threads(|| std::io::Read::read(&mut &self.port, &mut buf))?;
+		Ok(PyBytes::new_bound(py, &buf[..len]))
+	}
+
+	// write all of data
+	fn write(&self, py: Python<'_>, data: &[u8]) -> PyResult<usize> {
+		py.allow_threads(|| std::io::Write::write_all(&mut &self.port, data))?;
+		Ok(data.len())
+	}
+
+	// wait until all written data was transmitted
+	fn flush(&self, py: Python<'_>) -> PyResult<()> {
+		py.allow_threads(|| std::io::Write::flush(&mut &self.port))?;
+		Ok(())
+	}
+
+	#[getter]
+	fn read_timeout(&self) -> Option<f64> {
+		self.port.read_timeout().map(|timeout| timeout.as_secs_f64())
+	}
+
+	#[setter]
+	fn set_read_timeout(&self, timeout: Option<f64>) -> PyResult<()> {
+		Ok(self.port.set_read_timeout(duration(timeout)?)?)
+	}
+
+	#[getter]
+	fn write_timeout(&self) -> Option<f64> {
+		self.port.write_timeout().map(|timeout| timeout.as_secs_f64())
+	}
+
+	#[setter]
+	fn set_write_timeout(&self, timeout: Option<f64>) -> PyResult<()> {
+		Ok(self.port.set_write_timeout(duration(timeout)?)?)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> PyResult<()> {
+		Ok(self.port.set_baud_rate(baud_rate)?)
+	}
+
+	fn set_rts(&self, level: bool) -> PyResult<()> {
+		Ok(self.port.set_rts(level)?)
+	}
+
+	fn set_dtr(&self, level: bool) -> PyResult<()> {
+		Ok(self.port.set_dtr(level)?)
+	}
+
+	fn set_break(&self, level: bool) -> PyResult<()> {
+		Ok(self.port.set_break(level)?)
+	}
+
+	fn read_cts(&self) -> PyResult<bool> {
+		Ok(self.port.read_cts()?)
+	}
+
+	fn read_dsr(&self) -> PyResult<bool> {
+		Ok(self.port.read_dsr()?)
+	}
+
+	fn read_ri(&self) -> PyResult<bool> {
+		Ok(self.port.read_ri()?)
+	}
+
+	fn read_cd(&self) -> PyResult<bool> {
+		Ok(self.port.read_cd()?)
+	}
+}
+
+#[pyfunction]
+fn list_devices() -> PyResult<Vec<String>> {
+	Ok(SerialPort::list_devices()?
+		.into_iter()
+		.map(|path| path.to_string_lossy().into_owned())
+		.collect())
+}
+
+#[pymodule]
+#[pyo3(name = "serial")]
+fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+	module.add_class::<PySerialPort>()?;
+	module.add_function(wrap_pyfunction!(list_devices, module)?)?;
+	Ok(())
+}
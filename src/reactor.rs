@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::SerialPort;
+use crate::sys;
+
+// size of each thread's buffer, i.e., the maximum number of bytes passed to
+// a callback at once, unless specified otherwise
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+
+type Callback = Box<dyn FnMut(io::Result<&[u8]>) + Send>;
+
+// identifies a port registered with a reactor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Token(usize);
+
+// multiplexes reading from many ports onto a small pool of threads, e.g., for
+// terminal servers handling hundreds of ports, which would otherwise need a
+// blocked thread per port. received data is delivered to a callback or
+// channel per port, whereas writing remains up to the application. uses epoll
+// on Linux and an I/O completion port on Windows. other platforms and
+// backends are unsupported.
+pub struct Reactor {
+	shared: Arc<Shared>,
+	threads: Vec<JoinHandle<()>>
+}
+
+struct Shared {
+	poller: sys::Poller,
+	registrations: Mutex<HashMap<usize, Arc<Registration>>>,
+	next_token: AtomicUsize,
+	buffer_size: usize
+}
+
+struct Registration {
+	// clone of the registered port with a zero read timeout, so servicing it
+	// never blocks
+	port: SerialPort,
+	callback: Mutex<Callback>
+}
+
+impl Reactor {
+	pub fn new(threads: usize) -> io::Result<Self> {
+		Self::with_buffer_size(threads, DEFAULT_BUFFER_SIZE)
+	}
+
+	// like new(), but each thread reads up to buffer_size bytes at once
+	pub fn with_buffer_size(threads: usize, buffer_size: usize) -> io::Result<Self> {
+		if threads == 0 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"reactor requires at least one thread"));
+		}
+
+		let shared = Arc::new(Shared {
+			poller: sys::Poller::new()?,
+			registrations: Mutex::new(HashMap::new()),
+			next_token: AtomicUsize::new(0),
+			buffer_size: buffer_size.max(1)
+		});
+		let mut reactor = Self { shared, threads: Vec::with_capacity(threads) };
+		for _ in 0..threads {
+			let shared = reactor.shared.clone();
+			// dropping the reactor on error stops the threads spawned so far
+			reactor.threads.push(thread::Builder::new()
+				.name("serial-reactor".into())
+				.spawn(move || shared.run())?);
+		}
+		Ok(reactor)
+	}
+
+	// call callback with the data received by port (which is cloned, so the
+	// application can keep writing to it) from one of the reactor's threads.
+	// errors, e.g., a disconnected device, are delivered to the callback as
+	// well, after which the port is deregistered. callbacks should return
+	// quickly, as they occupy a thread of the pool.
+	pub fn register<F>(&self, port: &SerialPort, callback: F) -> io::Result<Token>
+			where F: FnMut(io::Result<&[u8]>) + Send + 'static {
+		let clone = port.try_clone()?;
+		clone.set_read_timeout(Some(Duration::ZERO))?;
+
+		let token = self.shared.next_token.fetch_add(1, Ordering::Relaxed);
+		let registration = Arc::new(Registration {
+			port: clone,
+			callback: Mutex::new(Box::new(callback))
+		});
+
+		// the port may become ready right after adding it, so it must be
+		// found by then
+		let mut registrations = self.shared.lock();
+		let os_port = registration.port.os_port("the reactor")?;
+		self.shared.poller.add(os_port, token)?;
+		registrations.insert(token, registration);
+		Ok(Token(token))
+	}
+
+	// like register(), but deliver received data via a channel. the port
+	// remains registered until an error was received or it is deregistered,
+	// even if the receiver is dropped.
+	pub fn register_channel(&self, port: &SerialPort)
+			-> io::Result<(Token, Receiver<io::Result<Vec<u8>>>)> {
+		let (sender, receiver) = mpsc::channel();
+		let token = self.register(port, move |res| {
+			let _res = sender.send(res.map(<[u8]>::to_vec));
+		})?;
+		Ok((token, receiver))
+	}
+
+	// stop servicing a port. its callback is not called anymore once this
+	// returns, unless it is running concurrently. ports deregistered after an
+	// error are ignored.
+	pub fn deregister(&self, token: Token) -> io::Result<()> {
+		self.shared.deregister(token.0)
+	}
+}
+
+impl Drop for Reactor {
+	fn drop(&mut self) {
+		// complete pending notifications before the ports are closed. the
+		// threads ignore notifications of deregistered ports meanwhile.
+		let tokens: Vec<usize> = self.shared.lock().keys().copied().collect();
+		for token in tokens {
+			let _res = self.shared.deregister(token);
+		}
+
+		let _res = self.shared.poller.shutdown();
+		for thread in self.threads.drain(..) {
+			let _res = thread.join();
+		}
+	}
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, HashMap<usize, Arc<Registration>>> {
+		self.registrations.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn deregister(&self, token: usize) -> io::Result<()> {
+		let mut registrations = self.lock();
+		match registrations.remove(&token) {
+			Some(registration) => {
+				let os_port = registration.port.os_port("the reactor")?;
+				self.poller.remove(os_port, token)
+			},
+			None => Ok(())
+		}
+	}
+
+	fn run(&self) {
+		let mut buf = vec![0u8; self.buffer_size];
+		loop {
+			let token = match self.poller.wait() {
+				Ok(Some(token)) => token,
+				// shutdown or failure of the poller
+				Ok(None) | Err(_) => return
+			};
+			let registration = match self.lock().get(&token) {
+				Some(registration) => registration.clone(),
+				None => continue
+			};
+			self.service(token, &registration, &mut buf);
+		}
+	}
+
+	// read until no more data is available, because Windows only notifies
+	// about bytes received after rearming, and rearm the port
+	fn service(&self, token: usize, registration: &Registration, buf: &mut [u8]) {
+		let mut callback = registration.callback.lock().unwrap_or_else(|e| e.into_inner());
+		let error = loop {
+			match (&registration.port).read(buf) {
+				Ok(0) => break None,
+				Ok(len) => callback(Ok(&buf[..len])),
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break None,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => break Some(e)
+			}
+		};
+
+		let error = error.or_else(|| {
+			// the port may have been deregistered meanwhile
+			let registrations = self.lock();
+			if !registrations.contains_key(&token) {
+				return None;
+			}
+			registration.port.os_port("the reactor")
+				.and_then(|os_port| self.poller.rearm(os_port, token))
+				.err()
+		});
+		if let Some(error) = error {
+			callback(Err(error));
+			drop(callback);
+			let _res = self.deregister(token);
+		}
+	}
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use crate::sim::SimulatedSerialPort;
+
+	#[test]
+	fn deliver_received_data() {
+		let reactor = Reactor::new(2).unwrap();
+		let mut ports = Vec::new();
+		let mut receivers = Vec::new();
+		for _ in 0..4 {
+			let (device, path) = SerialPort::open_pty(None).unwrap();
+			let port = SerialPort::open(&path, None).unwrap();
+			let (token, receiver) = reactor.register_channel(&port).unwrap();
+			ports.push((device, port, token));
+			receivers.push(receiver);
+		}
+
+		for (i, (device, _, _)) in ports.iter().enumerate() {
+			(&*device).write_all(format!("port {}", i).as_bytes()).unwrap();
+		}
+		for (i, receiver) in receivers.iter().enumerate() {
+			let mut received = Vec::new();
+			while received.len() < 6 {
+				received.extend(receiver.recv_timeout(Duration::from_secs(5)).unwrap().unwrap());
+			}
+			assert_eq!(received, format!("port {}", i).as_bytes());
+		}
+
+		// deregistered ports are not serviced anymore
+		let (device, _, token) = &ports[0];
+		reactor.deregister(*token).unwrap();
+		(&*device).write_all(b"ignored").unwrap();
+		assert!(receivers[0].recv_timeout(Duration::from_millis(100)).is_err());
+	}
+
+	#[test]
+	fn unsupported_ports() {
+		assert_eq!(Reactor::new(0).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+		let reactor = Reactor::new(1).unwrap();
+		let port = SimulatedSerialPort::new(None).into_serial_port();
+		let e = reactor.register(&port, |_| ()).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+	}
+}
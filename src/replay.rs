@@ -0,0 +1,607 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::SerialPort;
+use crate::backend::{AtomicTimeout, Backend};
+use crate::tap::{Direction, RAW_HEADER_LEN};
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+const PCAPNG_OPT_EPB_FLAGS: u16 = 2;
+
+// a single transfer of a capture, with the timestamp relative to an arbitrary
+// but fixed point in time (e.g., the Unix epoch)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+	pub timestamp: Duration,
+	pub direction: Direction,
+	pub data: Vec<u8>
+}
+
+// load a capture written by TapSerialPort in any format, detecting it from
+// the file's content
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Record>> {
+	let mut reader = BufReader::new(File::open(path)?);
+	let head = reader.fill_buf()?;
+	if head.len() >= 4 && head[..4] == PCAPNG_SECTION_HEADER.to_ne_bytes() {
+		parse_pcapng(reader)
+	} else if head.first().map_or(false, |b| b.is_ascii_digit()) {
+		// both text formats start with a timestamp, but hex dumps give the
+		// length instead of the data on the first line
+		let line = head.split(|&byte| byte == b'\n').next().unwrap_or_default();
+		if line.ends_with(b" bytes") || line.ends_with(b" bytes\r") {
+			parse_hexdump(reader)
+		} else {
+			parse_hex(reader)
+		}
+	} else {
+		parse_raw(reader)
+	}
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// read exactly buf.len() bytes, returning false on EOF before the first byte
+// (which never happens for empty buffers)
+fn read_record<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+	let mut pos = 0;
+	while pos < buf.len() {
+		match reader.read(&mut buf[pos..]) {
+			Ok(0) if pos == 0 => return Ok(false),
+			Ok(0) => return Err(invalid_data("truncated capture record")),
+			Ok(len) => pos += len,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		}
+	}
+	Ok(true)
+}
+
+pub fn parse_raw<R: Read>(mut reader: R) -> io::Result<Vec<Record>> {
+	let mut records = Vec::new();
+	let mut header = [0u8; RAW_HEADER_LEN];
+	while read_record(&mut reader, &mut header)? {
+		let mut micros = [0u8; 8];
+		micros.copy_from_slice(&header[0..8]);
+		let direction = match header[8] {
+			0 => Direction::Rx,
+			1 => Direction::Tx,
+			_ => return Err(invalid_data("invalid direction in raw capture"))
+		};
+		let mut len = [0u8; 4];
+		len.copy_from_slice(&header[9..13]);
+
+		// the buffer grows with the data actually read, so a corrupt length
+		// cannot allocate more than the remaining input
+		let len = u64::from(u32::from_le_bytes(len));
+		let mut data = Vec::new();
+		if (&mut reader).take(len).read_to_end(&mut data)? as u64 != len {
+			return Err(invalid_data("truncated capture record"));
+		}
+		records.push(Record {
+			timestamp: Duration::from_micros(u64::from_le_bytes(micros)),
+			direction,
+			data
+		});
+	}
+	Ok(records)
+}
+
+// timestamp and direction starting a line of the text formats
+fn parse_line_header<'a, I>(timestamp: &str, fields: &mut I) -> io::Result<(Duration, Direction)>
+		where I: Iterator<Item = &'a str> {
+	let (secs, micros) = timestamp.split_once('.').unwrap_or((timestamp, "0"));
+	let secs: u64 = secs.parse().map_err(|_| invalid_data("invalid timestamp in text capture"))?;
+	let micros: u32 = micros.parse().map_err(|_| invalid_data("invalid timestamp in text capture"))?;
+	let direction = match fields.next() {
+		Some("RX") => Direction::Rx,
+		Some("TX") => Direction::Tx,
+		_ => return Err(invalid_data("invalid direction in text capture"))
+	};
+	Ok((Duration::from_secs(secs) + Duration::from_micros(micros as u64), direction))
+}
+
+pub fn parse_hex<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+	let mut records = Vec::new();
+	for line in reader.lines() {
+		let line = line?;
+		let mut fields = line.split_whitespace();
+		let timestamp = match fields.next() {
+			Some(timestamp) => timestamp,
+			// skip empty lines
+			None => continue
+		};
+
+		let (timestamp, direction) = parse_line_header(timestamp, &mut fields)?;
+		let data = fields
+			.map(|byte| u8::from_str_radix(byte, 16))
+			.collect::<Result<Vec<u8>, _>>()
+			.map_err(|_| invalid_data("invalid data in hex capture"))?;
+
+		records.push(Record { timestamp, direction, data });
+	}
+	Ok(records)
+}
+
+// parse the header lines with timestamp, direction, and length and the hex
+// columns of the following dump lines. the ASCII column is ignored.
+pub fn parse_hexdump<R: BufRead>(reader: R) -> io::Result<Vec<Record>> {
+	let mut records = Vec::new();
+	let mut lines = reader.lines();
+	while let Some(line) = lines.next() {
+		let line = line?;
+		let mut fields = line.split_whitespace();
+		let timestamp = match fields.next() {
+			Some(timestamp) => timestamp,
+			// skip empty lines
+			None => continue
+		};
+
+		let (timestamp, direction) = parse_line_header(timestamp, &mut fields)?;
+		let len: usize = match (fields.next(), fields.next()) {
+			(Some(len), Some("bytes")) => len.parse()
+				.map_err(|_| invalid_data("invalid length in hex dump capture"))?,
+			_ => return Err(invalid_data("invalid header in hex dump capture"))
+		};
+
+		// each dump line holds up to 16 bytes in 8 columns of 4 digits
+		// following the 9 characters of the offset
+		let mut data = Vec::new();
+		while data.len() < len {
+			let line = lines.next()
+				.ok_or_else(|| invalid_data("truncated hex dump capture"))??;
+			let digits: String = line.get(9..line.len().min(49))
+				.ok_or_else(|| invalid_data("invalid data in hex dump capture"))?
+				.split_whitespace()
+				.collect();
+			if digits.len() != 2 * (len - data.len()).min(16) {
+				return Err(invalid_data("invalid data in hex dump capture"));
+			}
+			for i in (0..digits.len()).step_by(2) {
+				data.push(u8::from_str_radix(&digits[i..i + 2], 16)
+					.map_err(|_| invalid_data("invalid data in hex dump capture"))?);
+			}
+		}
+
+		records.push(Record { timestamp, direction, data });
+	}
+	Ok(records)
+}
+
+// only enhanced packet blocks are evaluated, assuming microsecond timestamps
+// and that packets without direction flag are received data
+pub fn parse_pcapng<R: Read>(mut reader: R) -> io::Result<Vec<Record>> {
+	let mut records = Vec::new();
+	let mut big_endian = cfg!(target_endian = "big");
+	let mut header = [0u8; 8];
+	while read_record(&mut reader, &mut header)? {
+		// the section header's block type is a palindrome, so its byte order
+		// is only known after reading the byte order magic
+		let mut body = Vec::new();
+		if header[0..4] == PCAPNG_SECTION_HEADER.to_ne_bytes() {
+			let mut magic = [0u8; 4];
+			if !read_record(&mut reader, &mut magic)? {
+				return Err(invalid_data("truncated pcapng block"));
+			}
+			big_endian = u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC;
+			body.extend_from_slice(&magic);
+		}
+
+		let u16_from = |b: &[u8]| if big_endian {
+			u16::from_be_bytes([b[0], b[1]])
+		} else {
+			u16::from_le_bytes([b[0], b[1]])
+		};
+		let u32_from = |b: &[u8]| if big_endian {
+			u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+		} else {
+			u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+		};
+
+		let block_type = u32_from(&header[0..4]);
+		let block_len = u32_from(&header[4..8]) as usize;
+		if block_len < 12 + body.len() || block_len % 4 != 0 {
+			return Err(invalid_data("invalid pcapng block length"));
+		}
+		// read block body and trailing length, but drop the latter. the
+		// buffer grows with the data actually read, so a corrupt length
+		// cannot allocate more than the remaining input.
+		let remaining = (block_len - 8 - body.len()) as u64;
+		if (&mut reader).take(remaining).read_to_end(&mut body)? as u64 != remaining {
+			return Err(invalid_data("truncated pcapng block"));
+		}
+		body.truncate(block_len - 12);
+
+		if block_type != PCAPNG_ENHANCED_PACKET {
+			continue;
+		}
+		if body.len() < 20 {
+			return Err(invalid_data("truncated pcapng packet"));
+		}
+
+		let timestamp = (u32_from(&body[4..8]) as u64) << 32 | u32_from(&body[8..12]) as u64;
+		let captured_len = u32_from(&body[12..16]) as usize;
+		let padded_len = (captured_len + 3) & !3;
+		if body.len() < 20 + padded_len {
+			return Err(invalid_data("truncated pcapng packet"));
+		}
+		let data = body[20..20 + captured_len].to_vec();
+
+		let mut direction = Direction::Rx;
+		let mut options = &body[20 + padded_len..];
+		while options.len() >= 4 {
+			let code = u16_from(&options[0..2]);
+			let len = u16_from(&options[2..4]) as usize;
+			let padded_len = (len + 3) & !3;
+			if code == PCAPNG_OPT_ENDOFOPT || options.len() < 4 + padded_len {
+				break;
+			}
+			if code == PCAPNG_OPT_EPB_FLAGS && len == 4 && u32_from(&options[4..8]) & 0b11 == 0b10 {
+				direction = Direction::Tx;
+			}
+			options = &options[4 + padded_len..];
+		}
+
+		records.push(Record {
+			timestamp: Duration::from_micros(timestamp),
+			direction,
+			data
+		});
+	}
+	Ok(records)
+}
+
+struct State {
+	records: VecDeque<Record>,
+	// wall-clock time corresponding to a capture timestamp, which is moved
+	// along whenever the application writes, so responses are replayed
+	// relative to the requests that triggered them
+	anchor: (Instant, Duration),
+	speed: f64,
+	verify: bool
+}
+
+// plays back a capture to the application: received data becomes available
+// for reading with its original timing (relative to the preceding write),
+// and written data is matched against the captured transmissions. clones
+// share the same playback.
+pub struct ReplaySerialPort {
+	state: Arc<(Mutex<State>, Condvar)>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+impl ReplaySerialPort {
+	pub fn new(records: Vec<Record>, timeout: Option<Duration>) -> Self {
+		let start = records.first().map(|record| record.timestamp).unwrap_or_default();
+		Self {
+			state: Arc::new((Mutex::new(State {
+				records: records.into(),
+				anchor: (Instant::now(), start),
+				speed: 1.0,
+				verify: false
+			}), Condvar::new())),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		}
+	}
+
+	pub fn open<P: AsRef<Path>>(path: P, timeout: Option<Duration>) -> io::Result<Self> {
+		load(path).map(|records| Self::new(records, timeout))
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
+
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
+		Ok(Self {
+			state: self.state.clone(),
+			timeout_read,
+			timeout_write
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// speed up (> 1.0) or slow down (< 1.0) playback
+	pub fn set_speed(&self, speed: f64) {
+		assert!(speed > 0.0, "playback speed must be positive");
+		let mut state = self.lock();
+		// keep position on the capture's timeline
+		let now = Instant::now();
+		let position = state.anchor.1 + now.saturating_duration_since(state.anchor.0)
+			.mul_f64(state.speed);
+		state.anchor = (now, position);
+		state.speed = speed;
+	}
+
+	// fail writes that do not match the captured transmissions
+	pub fn set_verify(&self, verify: bool) {
+		self.lock().verify = verify;
+	}
+
+	// number of captured transfers not yet replayed
+	pub fn remaining(&self) -> usize {
+		self.lock().records.len()
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		let (_, condvar) = &*self.state;
+		let mut state = self.lock();
+		loop {
+			let now = Instant::now();
+			// received data becomes available at its (scaled) capture time,
+			// while pending transmissions block until the application writes
+			let due = match state.records.front() {
+				Some(record) if record.direction == Direction::Rx => {
+					let offset = record.timestamp.saturating_sub(state.anchor.1);
+					Some(state.anchor.0 + offset.div_f64(state.speed))
+				},
+				Some(_) => None,
+				// the end of the capture reads as end of file
+				None => return Ok(0)
+			};
+
+			match due {
+				Some(due) if due <= now => {
+					let record = state.records.front_mut().unwrap();
+					let len = record.data.len().min(buf.len());
+					buf[..len].copy_from_slice(&record.data[..len]);
+					record.data.drain(..len);
+					if record.data.is_empty() {
+						state.records.pop_front();
+						condvar.notify_all();
+					}
+					return Ok(len);
+				},
+				_ => ()
+			}
+
+			let wait_until = match (due, deadline) {
+				(Some(due), Some(deadline)) => Some(due.min(deadline)),
+				(due, deadline) => due.or(deadline)
+			};
+			if deadline.map_or(false, |deadline| deadline <= now) {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"reading from replay timed out"));
+			}
+			state = match wait_until {
+				Some(wait_until) => condvar.wait_timeout(state, wait_until - now)
+					.unwrap_or_else(|e| e.into_inner()).0,
+				None => condvar.wait(state).unwrap_or_else(|e| e.into_inner())
+			};
+		}
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		let (_, condvar) = &*self.state;
+		let mut state = self.lock();
+		let verify = state.verify;
+
+		let mut pos = 0;
+		while pos < buf.len() {
+			// match against the next captured transmission, which may be
+			// preceded by received data the application did not read yet
+			let index = match state.records.iter()
+					.position(|record| record.direction == Direction::Tx) {
+				Some(index) => index,
+				None if verify => return Err(io::Error::new(io::ErrorKind::InvalidData,
+					"write beyond end of replay")),
+				None => break
+			};
+
+			let record = &mut state.records[index];
+			let len = record.data.len().min(buf.len() - pos);
+			if verify && record.data[..len] != buf[pos..pos + len] {
+				return Err(io::Error::new(io::ErrorKind::InvalidData,
+					"write does not match replay"));
+			}
+			record.data.drain(..len);
+			let timestamp = record.timestamp;
+			if record.data.is_empty() {
+				state.records.remove(index);
+			}
+			state.anchor = (Instant::now(), timestamp);
+			pos += len;
+		}
+
+		condvar.notify_all();
+		Ok(buf.len())
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// open "replay://path/to/capture" backend
+pub(crate) fn open_backend(path: &str, timeout: Option<Duration>)
+		-> io::Result<Box<dyn Backend>> {
+	ReplaySerialPort::open(path, timeout).map(|port| Box::new(port) as Box<dyn Backend>)
+}
+
+impl Backend for ReplaySerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		ReplaySerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		ReplaySerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		ReplaySerialPort::read_with_timeout(self, buf, timeout)
+	}
+
+	// writes never block
+	fn write_with_timeout(&self, buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		ReplaySerialPort::write(self, buf)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		ReplaySerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		ReplaySerialPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		ReplaySerialPort::try_clone_shared(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	// write timeouts are only stored, because writes never block
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+}
+
+impl io::Read for ReplaySerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		ReplaySerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &ReplaySerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		ReplaySerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for ReplaySerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		ReplaySerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		ReplaySerialPort::flush(self)
+	}
+}
+
+impl io::Write for &ReplaySerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		ReplaySerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		ReplaySerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn record(direction: Direction, data: &[u8]) -> Record {
+		Record { timestamp: Duration::ZERO, direction, data: data.to_vec() }
+	}
+
+	#[test]
+	fn parse_text_formats() {
+		let hex = "1690000000.000001 TX 48 69\n\n1690000000.500000 RX 0d 0a\n";
+		let records = parse_hex(hex.as_bytes()).unwrap();
+		assert_eq!(records.len(), 2);
+		assert_eq!(records[0].timestamp, Duration::new(1_690_000_000, 1_000));
+		assert_eq!(records[0].direction, Direction::Tx);
+		assert_eq!(records[0].data, b"Hi");
+		assert_eq!(records[1].data, b"\r\n");
+
+		let hexdump = concat!(
+			"1690000000.000001 RX 18 bytes\n",
+			"00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef\n",
+			"00000010: 0d0a                                     ..\n");
+		let records = parse_hexdump(hexdump.as_bytes()).unwrap();
+		assert_eq!(records, vec![Record {
+			timestamp: Duration::new(1_690_000_000, 1_000),
+			direction: Direction::Rx,
+			data: b"0123456789abcdef\r\n".to_vec()
+		}]);
+
+		let truncated = "1690000000.000001 RX 18 bytes\n00000000: 3031\n";
+		assert!(parse_hexdump(truncated.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn load_detects_hexdump() {
+		let path = std::env::temp_dir().join(format!("replay-{}.txt", std::process::id()));
+		std::fs::write(&path, concat!("1.000000 TX 2 bytes\n",
+			"00000000: 4869                                     Hi\n")).unwrap();
+		let res = load(&path);
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(res.unwrap()[0].data, b"Hi");
+	}
+
+	#[test]
+	fn corrupt_lengths_are_bounded() {
+		// a raw record claiming 4 GiB of data followed by only 3 bytes
+		let mut raw = vec![0u8; RAW_HEADER_LEN];
+		raw[9..13].copy_from_slice(&u32::MAX.to_le_bytes());
+		raw.extend_from_slice(b"abc");
+		let e = parse_raw(&raw[..]).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn replay_reads_and_verifies() {
+		let port = ReplaySerialPort::new(vec![
+			record(Direction::Tx, b"ping"),
+			record(Direction::Rx, b"pong")
+		], Some(Duration::from_millis(10)));
+		port.set_verify(true);
+
+		// the reply is held back until the request was written
+		let mut buf = [0u8; 8];
+		let e = port.read(&mut buf).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+		assert!(port.write(b"pang").is_err());
+		assert_eq!(port.write(b"ping").unwrap(), 4);
+		assert_eq!(port.read(&mut buf).unwrap(), 4);
+		assert_eq!(&buf[..4], b"pong");
+		assert_eq!(port.remaining(), 0);
+
+		// the end of the capture reads as end of file regardless of timeout
+		assert_eq!(port.read(&mut buf).unwrap(), 0);
+		assert_eq!(port.read_with_timeout(&mut buf, None).unwrap(), 0);
+	}
+}
@@ -0,0 +1,587 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+// telnet commands and options, see RFC 854, RFC 856, and RFC 858
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const OPT_BINARY: u8 = 0;
+const OPT_SGA: u8 = 3;
+const OPT_COM_PORT: u8 = 44;
+
+// COM-PORT-OPTION client commands (server replies add 100), see RFC 2217
+const CPO_SIGNATURE: u8 = 0;
+const CPO_SET_BAUDRATE: u8 = 1;
+const CPO_SET_DATASIZE: u8 = 2;
+const CPO_SET_PARITY: u8 = 3;
+const CPO_SET_STOPSIZE: u8 = 4;
+const CPO_SET_CONTROL: u8 = 5;
+const CPO_NOTIFY_MODEMSTATE: u8 = 7;
+const CPO_FLOWCONTROL_SUSPEND: u8 = 8;
+const CPO_FLOWCONTROL_RESUME: u8 = 9;
+const CPO_SET_LINESTATE_MASK: u8 = 10;
+const CPO_SET_MODEMSTATE_MASK: u8 = 11;
+const CPO_PURGE_DATA: u8 = 12;
+const CPO_SERVER_OFFSET: u8 = 100;
+
+const SIGNATURE: &[u8] = b"rust-serial";
+
+// serial port polling interval, which bounds the delay of modem state
+// notifications and of stopping the forwarding thread
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// exposes a serial port to one TCP client at a time. clients may change line
+// settings and control lines via the telnet COM-PORT-OPTION (RFC 2217), e.g.,
+// using pyserial's "rfc2217://host:port" URLs. there is no authentication, so
+// the bind address determines who can access the port.
+pub struct Rfc2217Server {
+	listener: TcpListener,
+	port: SerialPort
+}
+
+// state shared by the TCP receiving and the serial receiving thread
+struct Session {
+	tcp: Mutex<TcpStream>,
+	stop: AtomicBool,
+	suspended: AtomicBool,
+	modemstate_mask: AtomicU8
+}
+
+impl Session {
+	fn lock(&self) -> MutexGuard<'_, TcpStream> {
+		self.tcp.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn send(&self, data: &[u8]) -> io::Result<()> {
+		self.lock().write_all(data)
+	}
+
+	fn send_option(&self, cmd: u8, option: u8) -> io::Result<()> {
+		self.send(&[IAC, cmd, option])
+	}
+
+	fn send_subnegotiation(&self, cmd: u8, data: &[u8]) -> io::Result<()> {
+		let mut msg = vec![IAC, SB, OPT_COM_PORT, cmd + CPO_SERVER_OFFSET];
+		escape_iac(data, &mut msg);
+		msg.extend_from_slice(&[IAC, SE]);
+		self.send(&msg)
+	}
+}
+
+// double all IAC bytes, as required for data and subnegotiation parameters
+fn escape_iac(data: &[u8], out: &mut Vec<u8>) {
+	for &byte in data {
+		out.push(byte);
+		if byte == IAC {
+			out.push(IAC);
+		}
+	}
+}
+
+// telnet receive state machine
+enum RxState {
+	Data,
+	Iac,
+	Option(u8),
+	Subnegotiation,
+	SubnegotiationIac
+}
+
+// per-connection telnet option and control line state
+struct Connection<'a> {
+	port: &'a SerialPort,
+	session: Arc<Session>,
+	state: RxState,
+	subnegotiation: Vec<u8>,
+	local_options: [bool; 256],
+	remote_options: [bool; 256],
+	dtr: bool,
+	rts: bool,
+	brk: bool
+}
+
+impl Rfc2217Server {
+	// listen on the given address(es), e.g., "127.0.0.1:2217" to restrict
+	// access to local clients or "0.0.0.0:2217" to allow remote clients
+	pub fn bind<A: ToSocketAddrs>(addr: A, port: SerialPort) -> io::Result<Self> {
+		Ok(Self {
+			listener: TcpListener::bind(addr)?,
+			port
+		})
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.listener.local_addr()
+	}
+
+	// accept and serve clients one after another. only fails if accepting
+	// connections fails, errors of individual clients end their connection.
+	pub fn serve(&self) -> io::Result<()> {
+		loop {
+			let (stream, _addr) = self.listener.accept()?;
+			let _res = self.serve_client(stream);
+		}
+	}
+
+	// serve a single client until it disconnects or an I/O error occurs
+	pub fn serve_client(&self, stream: TcpStream) -> io::Result<()> {
+		stream.set_nodelay(true)?;
+		let mut reader = stream.try_clone()?;
+		let session = Arc::new(Session {
+			tcp: Mutex::new(stream),
+			stop: AtomicBool::new(false),
+			suspended: AtomicBool::new(false),
+			modemstate_mask: AtomicU8::new(0)
+		});
+
+		let port_reader = self.port.try_clone()?;
+		port_reader.set_read_timeout(Some(POLL_INTERVAL))?;
+		let session_reader = session.clone();
+		let thread = thread::spawn(move || {
+			let res = forward_serial(&port_reader, &session_reader);
+			// unblock the TCP receiving thread
+			session_reader.stop.store(true, Ordering::Relaxed);
+			let _res = session_reader.lock().shutdown(Shutdown::Both);
+			res
+		});
+
+		let mut conn = Connection {
+			port: &self.port,
+			session: session.clone(),
+			state: RxState::Data,
+			subnegotiation: Vec::new(),
+			local_options: [false; 256],
+			remote_options: [false; 256],
+			dtr: true,
+			rts: true,
+			brk: false
+		};
+		let res = conn.run(&mut reader);
+
+		session.stop.store(true, Ordering::Relaxed);
+		let res_thread = thread.join().unwrap_or_else(|_| Err(io::Error::new(
+			io::ErrorKind::Other, "RFC 2217 forwarding thread panicked")));
+
+		// report the error that caused the session to end. a TCP error is
+		// merely a consequence of the shutdown if forwarding failed first.
+		match (res_thread, res) {
+			(Err(e), _) => Err(e),
+			(Ok(()), res) => res
+		}
+	}
+}
+
+// forward serial data to the client and notify it of modem state changes
+fn forward_serial(port: &SerialPort, session: &Session) -> io::Result<()> {
+	let mut buf = [0u8; 1024];
+	let mut msg = Vec::with_capacity(2 * buf.len());
+	let mut modemstate = 0u8;
+	let mut reader = port;
+
+	while !session.stop.load(Ordering::Relaxed) {
+		let mask = session.modemstate_mask.load(Ordering::Relaxed);
+		if mask != 0 {
+			let state = read_modemstate(port, modemstate);
+			// notify if a line changed whose delta or state bit is masked
+			let delta = state & 0x0F;
+			if (delta | delta << 4) & mask != 0 {
+				session.send_subnegotiation(CPO_NOTIFY_MODEMSTATE, &[state & mask])?;
+			}
+			modemstate = state & 0xF0;
+		}
+
+		// leave data in the serial port's buffer while the client is
+		// unable to receive it
+		if session.suspended.load(Ordering::Relaxed) {
+			clock::sleep(POLL_INTERVAL);
+			continue;
+		}
+
+		match reader.read(&mut buf) {
+			Ok(len) => {
+				msg.clear();
+				escape_iac(&buf[..len], &mut msg);
+				session.send(&msg)?;
+			},
+			Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		}
+	}
+	Ok(())
+}
+
+// current modem state with delta bits relative to the previous state.
+// backends that cannot read a modem status line report it as inactive.
+fn read_modemstate(port: &SerialPort, previous: u8) -> u8 {
+	let mut state = 0u8;
+	if port.read_cts().unwrap_or(false) { state |= 0x10; }
+	if port.read_dsr().unwrap_or(false) { state |= 0x20; }
+	if port.read_ri().unwrap_or(false) { state |= 0x40; }
+	if port.read_cd().unwrap_or(false) { state |= 0x80; }
+
+	let delta = state ^ previous;
+	if delta & 0x10 != 0 { state |= 0x01; }
+	if delta & 0x20 != 0 { state |= 0x02; }
+	// trailing edge of ring indicator only
+	if delta & previous & 0x40 != 0 { state |= 0x04; }
+	if delta & 0x80 != 0 { state |= 0x08; }
+	state
+}
+
+impl Connection<'_> {
+	fn run(&mut self, reader: &mut TcpStream) -> io::Result<()> {
+		// offer a binary, full-duplex channel and the COM port control option
+		for &option in &[OPT_BINARY, OPT_SGA, OPT_COM_PORT] {
+			self.local_options[option as usize] = true;
+			self.session.send_option(WILL, option)?;
+		}
+		for &option in &[OPT_BINARY, OPT_SGA] {
+			self.remote_options[option as usize] = true;
+			self.session.send_option(DO, option)?;
+		}
+
+		let mut buf = [0u8; 1024];
+		let mut data = Vec::with_capacity(buf.len());
+		loop {
+			let len = match reader.read(&mut buf) {
+				Ok(0) => return Ok(()),
+				Ok(len) => len,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				// shutdown by the forwarding thread
+				Err(_) if self.session.stop.load(Ordering::Relaxed) => return Ok(()),
+				Err(e) => return Err(e)
+			};
+
+			data.clear();
+			for &byte in &buf[..len] {
+				self.receive(byte, &mut data)?;
+			}
+			if !data.is_empty() {
+				let mut port = self.port;
+				port.write_all(&data)?;
+			}
+		}
+	}
+
+	// process a single byte received from the client, collecting data bytes
+	// that are to be written to the serial port
+	fn receive(&mut self, byte: u8, data: &mut Vec<u8>) -> io::Result<()> {
+		self.state = match self.state {
+			RxState::Data if byte == IAC => RxState::Iac,
+			RxState::Data => {
+				data.push(byte);
+				RxState::Data
+			},
+			RxState::Iac => match byte {
+				// escaped 0xFF data byte
+				IAC => {
+					data.push(IAC);
+					RxState::Data
+				},
+				WILL | WONT | DO | DONT => RxState::Option(byte),
+				SB => {
+					self.subnegotiation.clear();
+					RxState::Subnegotiation
+				},
+				// other commands (e.g., NOP, break, are you there) are ignored
+				_ => RxState::Data
+			},
+			RxState::Option(cmd) => {
+				self.negotiate(cmd, byte)?;
+				RxState::Data
+			},
+			RxState::Subnegotiation if byte == IAC => RxState::SubnegotiationIac,
+			RxState::Subnegotiation => {
+				self.subnegotiation.push(byte);
+				RxState::Subnegotiation
+			},
+			RxState::SubnegotiationIac => match byte {
+				IAC => {
+					self.subnegotiation.push(IAC);
+					RxState::Subnegotiation
+				},
+				SE => {
+					let subnegotiation = std::mem::take(&mut self.subnegotiation);
+					if let [OPT_COM_PORT, cmd, ref params @ ..] = subnegotiation[..] {
+						self.com_port_command(cmd, params)?;
+					}
+					RxState::Data
+				},
+				// invalid sequence, abort subnegotiation
+				_ => RxState::Data
+			}
+		};
+		Ok(())
+	}
+
+	// answer option requests without acknowledging options that are already
+	// enabled/disabled, which avoids negotiation loops (see RFC 854)
+	fn negotiate(&mut self, cmd: u8, option: u8) -> io::Result<()> {
+		let supported = matches!(option, OPT_BINARY | OPT_SGA | OPT_COM_PORT);
+		match cmd {
+			WILL if !self.remote_options[option as usize] => {
+				self.remote_options[option as usize] = supported;
+				self.session.send_option(if supported { DO } else { DONT }, option)
+			},
+			WONT if self.remote_options[option as usize] => {
+				self.remote_options[option as usize] = false;
+				self.session.send_option(DONT, option)
+			},
+			DO if !self.local_options[option as usize] => {
+				self.local_options[option as usize] = supported;
+				self.session.send_option(if supported { WILL } else { WONT }, option)
+			},
+			DONT if self.local_options[option as usize] => {
+				self.local_options[option as usize] = false;
+				self.session.send_option(WONT, option)
+			},
+			_ => Ok(())
+		}
+	}
+
+	// apply a COM-PORT-OPTION command to the serial port and reply with the
+	// resulting setting. settings the port fails to apply or report are
+	// answered with the requested value, as RFC 2217 has no error replies.
+	fn com_port_command(&mut self, cmd: u8, params: &[u8]) -> io::Result<()> {
+		let port = self.port;
+		match (cmd, params) {
+			(CPO_SIGNATURE, []) => self.session.send_subnegotiation(cmd, SIGNATURE),
+			// client announced its signature, which needs no reply
+			(CPO_SIGNATURE, _) => Ok(()),
+			(CPO_SET_BAUDRATE, &[b0, b1, b2, b3]) => {
+				let requested = u32::from_be_bytes([b0, b1, b2, b3]);
+				if requested != 0 {
+					let _res = port.set_baud_rate(requested);
+				}
+				let actual = port.baud_rate().unwrap_or(requested);
+				self.session.send_subnegotiation(cmd, &actual.to_be_bytes())
+			},
+			(CPO_SET_DATASIZE, &[requested]) => {
+				let data_bits = match requested {
+					5 => Some(DataBits::Five),
+					6 => Some(DataBits::Six),
+					7 => Some(DataBits::Seven),
+					8 => Some(DataBits::Eight),
+					_ => None
+				};
+				if let Some(data_bits) = data_bits {
+					let _res = port.set_data_bits(data_bits);
+				}
+				let actual = match port.data_bits() {
+					Ok(DataBits::Five) => 5,
+					Ok(DataBits::Six) => 6,
+					Ok(DataBits::Seven) => 7,
+					Ok(DataBits::Eight) => 8,
+					Err(_) => requested
+				};
+				self.session.send_subnegotiation(cmd, &[actual])
+			},
+			(CPO_SET_PARITY, &[requested]) => {
+				let parity = match requested {
+					1 => Some(Parity::None),
+					2 => Some(Parity::Odd),
+					3 => Some(Parity::Even),
+					4 => Some(Parity::Mark),
+					5 => Some(Parity::Space),
+					_ => None
+				};
+				if let Some(parity) = parity {
+					let _res = port.set_parity(parity);
+				}
+				let actual = match port.parity() {
+					Ok(Parity::None) => 1,
+					Ok(Parity::Odd) => 2,
+					Ok(Parity::Even) => 3,
+					Ok(Parity::Mark) => 4,
+					Ok(Parity::Space) => 5,
+					Err(_) => requested
+				};
+				self.session.send_subnegotiation(cmd, &[actual])
+			},
+			(CPO_SET_STOPSIZE, &[requested]) => {
+				// 1.5 stop bits (3) are not supported
+				let stop_bits = match requested {
+					1 => Some(StopBits::One),
+					2 => Some(StopBits::Two),
+					_ => None
+				};
+				if let Some(stop_bits) = stop_bits {
+					let _res = port.set_stop_bits(stop_bits);
+				}
+				let actual = match port.stop_bits() {
+					Ok(StopBits::One) => 1,
+					Ok(StopBits::Two) => 2,
+					Err(_) => requested
+				};
+				self.session.send_subnegotiation(cmd, &[actual])
+			},
+			(CPO_SET_CONTROL, &[requested]) => {
+				let actual = self.set_control(requested);
+				self.session.send_subnegotiation(cmd, &[actual])
+			},
+			(CPO_FLOWCONTROL_SUSPEND, _) => {
+				self.session.suspended.store(true, Ordering::Relaxed);
+				Ok(())
+			},
+			(CPO_FLOWCONTROL_RESUME, _) => {
+				self.session.suspended.store(false, Ordering::Relaxed);
+				Ok(())
+			},
+			// line state notifications (overrun, parity errors, etc.) are
+			// not available, so the mask is merely acknowledged
+			(CPO_SET_LINESTATE_MASK, &[mask]) =>
+				self.session.send_subnegotiation(cmd, &[mask]),
+			(CPO_SET_MODEMSTATE_MASK, &[mask]) => {
+				self.session.modemstate_mask.store(mask, Ordering::Relaxed);
+				self.session.send_subnegotiation(cmd, &[mask])
+			},
+			(CPO_PURGE_DATA, &[requested]) => {
+				let _res = match requested {
+					1 => port.purge(true, false),
+					2 => port.purge(false, true),
+					3 => port.purge(true, true),
+					_ => Ok(())
+				};
+				self.session.send_subnegotiation(cmd, &[requested])
+			},
+			// ignore unknown commands and malformed parameters
+			_ => Ok(())
+		}
+	}
+
+	// SET-CONTROL covers flow control, break, DTR, and RTS. the latter three
+	// cannot be read back from the port, so their last state is tracked.
+	fn set_control(&mut self, requested: u8) -> u8 {
+		let port = self.port;
+		match requested {
+			// query/set outbound flow control
+			0..=3 => {
+				let flow_control = match requested {
+					1 => Some(FlowControl::None),
+					2 => Some(FlowControl::Software),
+					3 => Some(FlowControl::Hardware),
+					_ => None
+				};
+				if let Some(flow_control) = flow_control {
+					let _res = port.set_flow_control(flow_control);
+				}
+				match port.flow_control() {
+					Ok(FlowControl::None) => 1,
+					Ok(FlowControl::Software) => 2,
+					Ok(FlowControl::Hardware) => 3,
+					Err(_) if requested == 0 => 1,
+					Err(_) => requested
+				}
+			},
+			5 | 6 => {
+				if port.set_break(requested == 5).is_ok() {
+					self.brk = requested == 5;
+				}
+				if self.brk { 5 } else { 6 }
+			},
+			4 => if self.brk { 5 } else { 6 },
+			8 | 9 => {
+				if port.set_dtr(requested == 8).is_ok() {
+					self.dtr = requested == 8;
+				}
+				if self.dtr { 8 } else { 9 }
+			},
+			7 => if self.dtr { 8 } else { 9 },
+			11 | 12 => {
+				if port.set_rts(requested == 11).is_ok() {
+					self.rts = requested == 11;
+				}
+				if self.rts { 11 } else { 12 }
+			},
+			10 => if self.rts { 11 } else { 12 },
+			// inbound flow control is configured along with outbound flow
+			// control, so report the matching setting
+			13..=16 => match port.flow_control() {
+				Ok(FlowControl::Software) => 15,
+				Ok(FlowControl::Hardware) => 16,
+				_ => 14
+			},
+			// DCD/DTR/DSR flow control is not supported
+			_ => requested
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::Backend;
+	use crate::sim::SimulatedSerialPort;
+
+	fn expect(client: &mut TcpStream, expected: &[u8]) {
+		let mut buf = vec![0u8; expected.len()];
+		client.read_exact(&mut buf).unwrap();
+		assert_eq!(buf, expected);
+	}
+
+	#[test]
+	fn session() {
+		let sim = SimulatedSerialPort::new(None);
+		sim.on(b"ab\xff", b"\xffok", Duration::ZERO);
+		let server = Rfc2217Server::bind("127.0.0.1:0",
+			sim.try_clone().unwrap().into_serial_port()).unwrap();
+		let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+		client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		let thread = thread::spawn(move || {
+			let (stream, _addr) = server.listener.accept()?;
+			server.serve_client(stream)
+		});
+
+		expect(&mut client, &[IAC, WILL, OPT_BINARY, IAC, WILL, OPT_SGA, IAC, WILL, OPT_COM_PORT,
+			IAC, DO, OPT_BINARY, IAC, DO, OPT_SGA]);
+
+		// unsupported options are refused, enabled ones not acknowledged again
+		client.write_all(&[IAC, DO, 1, IAC, DO, OPT_BINARY]).unwrap();
+		expect(&mut client, &[IAC, WONT, 1]);
+
+		// 115200 baud and 7 data bits
+		client.write_all(&[IAC, SB, OPT_COM_PORT, CPO_SET_BAUDRATE, 0, 1, 0xc2, 0, IAC, SE,
+			IAC, SB, OPT_COM_PORT, CPO_SET_DATASIZE, 7, IAC, SE]).unwrap();
+		expect(&mut client, &[IAC, SB, OPT_COM_PORT, CPO_SET_BAUDRATE + CPO_SERVER_OFFSET,
+			0, 1, 0xc2, 0, IAC, SE]);
+		expect(&mut client, &[IAC, SB, OPT_COM_PORT, CPO_SET_DATASIZE + CPO_SERVER_OFFSET,
+			7, IAC, SE]);
+		assert_eq!(Backend::baud_rate(&sim).unwrap(), 115_200);
+		assert_eq!(Backend::data_bits(&sim).unwrap(), DataBits::Seven);
+
+		// data is escaped in both directions
+		client.write_all(&[b'a', b'b', IAC, IAC]).unwrap();
+		expect(&mut client, &[IAC, IAC, b'o', b'k']);
+
+		client.shutdown(Shutdown::Both).unwrap();
+		thread.join().unwrap().unwrap();
+		assert!(sim.take_unmatched().is_empty());
+	}
+
+	#[cfg(feature = "mock")]
+	#[test]
+	fn modemstate_deltas() {
+		let mock = crate::MockSerialPort::new(None);
+		let port = SerialPort::from_backend(Box::new(mock.try_clone().unwrap()));
+		mock.set_cts(true);
+		assert_eq!(read_modemstate(&port, 0x00), 0x11);
+		assert_eq!(read_modemstate(&port, 0x10), 0x10);
+
+		// only the trailing edge of the ring indicator is reported
+		mock.set_ri(true);
+		assert_eq!(read_modemstate(&port, 0x10), 0x50);
+		mock.set_ri(false);
+		assert_eq!(read_modemstate(&port, 0x50), 0x14);
+	}
+}
@@ -0,0 +1,250 @@
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// size of the chunks read by the reader thread unless specified otherwise
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+struct Subscription {
+	tx: SyncSender<Vec<u8>>,
+	lagged: Arc<AtomicBool>
+}
+
+// owns the read side of a port and distributes all received data to any
+// number of subscribers, which (unlike port clones) see the same byte
+// stream. the reader thread only checks for the stop request when a read
+// times out, so the reader should be opened with a timeout.
+pub struct SharedReader {
+	subscriptions: Arc<Mutex<Vec<Subscription>>>,
+	stop: Arc<AtomicBool>,
+	thread: Option<JoinHandle<io::Result<()>>>
+}
+
+// receiving end of a SharedReader. reading returns end of file once the
+// SharedReader stopped and all data was consumed.
+pub struct Subscriber {
+	rx: Receiver<Vec<u8>>,
+	pending: Vec<u8>,
+	lagged: Arc<AtomicBool>,
+	timeout: Option<Duration>
+}
+
+fn lock(subscriptions: &Mutex<Vec<Subscription>>) -> MutexGuard<'_, Vec<Subscription>> {
+	subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl SharedReader {
+	pub fn new<R>(reader: R) -> Self
+			where R: Read + Send + 'static {
+		Self::with_chunk_size(reader, DEFAULT_CHUNK_SIZE)
+	}
+
+	// read chunks of up to chunk_size bytes, each of which is delivered to
+	// the subscribers as one message, e.g., larger ones for multi-Mbaud
+	// links to keep up with fewer reads
+	pub fn with_chunk_size<R>(mut reader: R, chunk_size: usize) -> Self
+			where R: Read + Send + 'static {
+		let subscriptions = Arc::new(Mutex::new(Vec::<Subscription>::new()));
+		let stop = Arc::new(AtomicBool::new(false));
+
+		let thread_subscriptions = subscriptions.clone();
+		let thread_stop = stop.clone();
+		let thread = thread::spawn(move || {
+			let mut buf = vec![0u8; chunk_size.max(1)];
+			let res = loop {
+				if thread_stop.load(Ordering::Relaxed) {
+					break Ok(());
+				}
+
+				match reader.read(&mut buf) {
+					Ok(0) => break Ok(()),
+					Ok(len) => {
+						// a slow subscriber must not stall the others, so it
+						// misses data instead and is notified about it
+						lock(&thread_subscriptions).retain(|sub| {
+							match sub.tx.try_send(buf[..len].to_vec()) {
+								Ok(()) => true,
+								Err(TrySendError::Full(_)) => {
+									sub.lagged.store(true, Ordering::Relaxed);
+									true
+								},
+								Err(TrySendError::Disconnected(_)) => false
+							}
+						});
+					},
+					Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+					Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+					Err(e) => break Err(e)
+				}
+			};
+
+			// disconnect all subscribers, so they receive end of file
+			thread_stop.store(true, Ordering::Relaxed);
+			lock(&thread_subscriptions).clear();
+			res
+		});
+
+		Self {
+			subscriptions,
+			stop,
+			thread: Some(thread)
+		}
+	}
+
+	// subscribe to data received from now on, buffering up to the given
+	// number of reads (i.e., chunks of up to the reader's chunk size). a
+	// capacity of 0 buffers 1 read, since a subscriber without buffer would
+	// lag behind permanently.
+	pub fn subscribe(&self, capacity: usize, timeout: Option<Duration>) -> Subscriber {
+		let (tx, rx) = mpsc::sync_channel(capacity.max(1));
+		let lagged = Arc::new(AtomicBool::new(false));
+
+		// subscribers of a stopped reader are disconnected immediately
+		let mut subscriptions = lock(&self.subscriptions);
+		if !self.stop.load(Ordering::Relaxed) {
+			subscriptions.push(Subscription { tx, lagged: lagged.clone() });
+		}
+
+		Subscriber {
+			rx,
+			pending: Vec::new(),
+			lagged,
+			timeout
+		}
+	}
+
+	// returns true if the reader thread terminated (e.g., due to an error)
+	pub fn is_finished(&self) -> bool {
+		self.stop.load(Ordering::Relaxed)
+	}
+
+	// stop reading and return the error that terminated reading (if any)
+	pub fn stop(mut self) -> io::Result<()> {
+		self.stop.store(true, Ordering::Relaxed);
+		match self.thread.take() {
+			Some(thread) => thread.join().unwrap_or_else(|_| Err(io::Error::new(
+				io::ErrorKind::Other, "shared reader thread panicked"))),
+			None => Ok(())
+		}
+	}
+}
+
+impl Drop for SharedReader {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() {
+			let _res = thread.join();
+		}
+	}
+}
+
+impl Subscriber {
+	pub fn timeout(&self) -> Option<Duration> {
+		self.timeout
+	}
+
+	pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+		self.timeout = timeout;
+	}
+}
+
+impl io::Read for Subscriber {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		// report data loss once, so the consumer can resynchronize
+		if self.lagged.swap(false, Ordering::Relaxed) {
+			return Err(io::Error::new(io::ErrorKind::Other,
+				"subscriber lagged behind, received data was dropped"));
+		}
+
+		if self.pending.is_empty() {
+			let res = match self.timeout {
+				Some(timeout) => self.rx.recv_timeout(timeout),
+				None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+			};
+			self.pending = match res {
+				Ok(data) => data,
+				Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(
+					io::ErrorKind::TimedOut, "reading from subscriber timed out")),
+				Err(RecvTimeoutError::Disconnected) => return Ok(0)
+			};
+		}
+
+		let len = self.pending.len().min(buf.len());
+		buf[..len].copy_from_slice(&self.pending[..len]);
+		self.pending.drain(..len);
+		Ok(len)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sim::SimulatedSerialPort;
+
+	struct FailingReader;
+
+	impl Read for FailingReader {
+		fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+			Err(io::Error::new(io::ErrorKind::BrokenPipe, "device removed"))
+		}
+	}
+
+	fn read_all(subscriber: &mut Subscriber, len: usize) -> Vec<u8> {
+		let mut data = vec![0u8; len];
+		subscriber.read_exact(&mut data).unwrap();
+		data
+	}
+
+	#[test]
+	fn subscribers_see_same_stream() {
+		let sim = SimulatedSerialPort::new(Some(Duration::from_millis(10)));
+		let reader = SharedReader::new(sim.try_clone().unwrap());
+		let timeout = Some(Duration::from_secs(5));
+		let mut first = reader.subscribe(8, timeout);
+		let mut second = reader.subscribe(8, timeout);
+
+		sim.send(b"hello", Duration::ZERO);
+		assert_eq!(read_all(&mut first, 5), b"hello");
+		assert_eq!(read_all(&mut second, 5), b"hello");
+
+		// subscribers only receive data from their subscription on
+		let mut late = reader.subscribe(8, Some(Duration::from_millis(50)));
+		assert_eq!(late.read(&mut [0u8; 8]).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+		// end of file once stopped
+		reader.stop().unwrap();
+		assert_eq!(first.read(&mut [0u8; 8]).unwrap(), 0);
+		assert_eq!(late.read(&mut [0u8; 8]).unwrap(), 0);
+	}
+
+	#[test]
+	fn lagging_subscriber_is_notified() {
+		let sim = SimulatedSerialPort::new(Some(Duration::from_millis(10)));
+		let reader = SharedReader::new(sim.try_clone().unwrap());
+		let mut subscriber = reader.subscribe(1, Some(Duration::from_secs(5)));
+
+		// separate reads, of which the second does not fit the buffer
+		sim.send(b"first", Duration::ZERO);
+		sim.send(b"second", Duration::from_millis(50));
+		while sim.pending_replies() > 0 {
+			thread::sleep(Duration::from_millis(10));
+		}
+		thread::sleep(Duration::from_millis(10));
+
+		let e = subscriber.read(&mut [0u8; 8]).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::Other);
+		assert_eq!(read_all(&mut subscriber, 5), b"first");
+	}
+
+	#[test]
+	fn errors_end_reading() {
+		let reader = SharedReader::new(FailingReader);
+		let mut subscriber = reader.subscribe(1, None);
+		assert_eq!(subscriber.read(&mut [0u8; 8]).unwrap(), 0);
+		assert!(reader.is_finished());
+		assert_eq!(reader.stop().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+	}
+}
@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{clock, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use crate::backend::{AtomicTimeout, Backend};
+
+#[derive(Clone)]
+struct Rule {
+	request: Vec<u8>,
+	reply: Vec<u8>,
+	delay: Duration,
+	// removed after matching once
+	once: bool
+}
+
+struct State {
+	rules: Vec<Rule>,
+	// replies (and unsolicited data) ordered by the time they become
+	// available for reading
+	rx: VecDeque<(Instant, Vec<u8>)>,
+	// written bytes that may still be the beginning of a request
+	pending: Vec<u8>,
+	// written bytes that did not match any request
+	unmatched: Vec<u8>,
+	// line settings are only stored, as they do not affect the script
+	baud_rate: u32,
+	data_bits: DataBits,
+	parity: Parity,
+	stop_bits: StopBits,
+	flow_control: FlowControl,
+	rts: bool,
+	dtr: bool
+}
+
+impl State {
+	// reply to the requests contained in the written bytes
+	fn process(&mut self, now: Instant) {
+		loop {
+			// earliest match, preferring rules added first
+			let found = self.rules.iter().enumerate()
+				.filter(|(_, rule)| !rule.request.is_empty())
+				.filter_map(|(index, rule)| self.pending.windows(rule.request.len())
+					.position(|window| window == &rule.request[..])
+					.map(|pos| (pos, index)))
+				.min();
+			let (pos, index) = match found {
+				Some(found) => found,
+				None => break
+			};
+
+			let rule = if self.rules[index].once {
+				self.rules.remove(index)
+			} else {
+				self.rules[index].clone()
+			};
+			self.unmatched.extend_from_slice(&self.pending[..pos]);
+			self.pending.drain(..pos + rule.request.len());
+			self.schedule(now + rule.delay, rule.reply);
+		}
+
+		// only keep bytes that may be completed to a request by later writes
+		let keep = self.rules.iter()
+			.map(|rule| rule.request.len().saturating_sub(1))
+			.max()
+			.unwrap_or(0);
+		let excess = self.pending.len().saturating_sub(keep);
+		self.unmatched.extend(self.pending.drain(..excess));
+	}
+
+	fn schedule(&mut self, due: Instant, data: Vec<u8>) {
+		if data.is_empty() {
+			return;
+		}
+		// data becoming available at the same time keeps its order
+		let index = self.rx.iter().position(|(other, _)| *other > due).unwrap_or(self.rx.len());
+		self.rx.insert(index, (due, data));
+	}
+}
+
+// simulates a device driven by a script of requests and replies, e.g., for
+// integration tests of protocol implementations with realistic timing:
+//   let sim = SimulatedSerialPort::new(Some(Duration::from_secs(1)));
+//   sim.on(b"AT\r", b"\r\nOK\r\n", Duration::from_millis(20));
+//   let port = sim.try_clone()?.into_serial_port();
+// written bytes are matched against the requests, whereupon the reply
+// becomes available for reading after the delay. clones share the script,
+// so a test can modify it while the code under test uses the port. reads
+// without timeout block until a reply is due, like a real port.
+pub struct SimulatedSerialPort {
+	state: Arc<(Mutex<State>, Condvar)>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+impl SimulatedSerialPort {
+	pub fn new(timeout: Option<Duration>) -> Self {
+		Self {
+			state: Arc::new((Mutex::new(State {
+				rules: Vec::new(),
+				rx: VecDeque::new(),
+				pending: Vec::new(),
+				unmatched: Vec::new(),
+				baud_rate: 9600,
+				data_bits: DataBits::Eight,
+				parity: Parity::None,
+				stop_bits: StopBits::One,
+				flow_control: FlowControl::None,
+				rts: false,
+				dtr: false
+			}), Condvar::new())),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		}
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
+
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
+		Ok(Self {
+			state: self.state.clone(),
+			timeout_read,
+			timeout_write
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn add_rule(&self, request: &[u8], reply: &[u8], delay: Duration, once: bool) {
+		let mut state = self.lock();
+		state.rules.push(Rule { request: request.to_vec(), reply: reply.to_vec(), delay, once });
+		// the request may already be pending
+		state.process(clock::now());
+		self.state.1.notify_all();
+	}
+
+	// reply every time request was written
+	pub fn on(&self, request: &[u8], reply: &[u8], delay: Duration) {
+		self.add_rule(request, reply, delay, false);
+	}
+
+	// reply the first time request was written only, e.g., to script a
+	// sequence of different replies to the same request
+	pub fn on_once(&self, request: &[u8], reply: &[u8], delay: Duration) {
+		self.add_rule(request, reply, delay, true);
+	}
+
+	// make data available for reading after the delay without a request,
+	// e.g., for a greeting or unsolicited notifications
+	pub fn send(&self, data: &[u8], delay: Duration) {
+		self.lock().schedule(clock::now() + delay, data.to_vec());
+		self.state.1.notify_all();
+	}
+
+	// return the written bytes that did not match any request and clear them
+	pub fn take_unmatched(&self) -> Vec<u8> {
+		std::mem::take(&mut self.lock().unmatched)
+	}
+
+	// number of replies not read completely yet (including those not due)
+	pub fn pending_replies(&self) -> usize {
+		self.lock().rx.len()
+	}
+
+	// modem control outputs driven by the code under test
+	pub fn rts(&self) -> bool {
+		self.lock().rts
+	}
+
+	pub fn dtr(&self) -> bool {
+		self.lock().dtr
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		let deadline = timeout.map(|timeout| clock::now() + timeout);
+		let (_, condvar) = &*self.state;
+		let mut state = self.lock();
+		loop {
+			let now = clock::now();
+			let due = state.rx.front().map(|(due, _)| *due);
+			if due.map_or(false, |due| due <= now) {
+				let (_, data) = state.rx.front_mut().unwrap();
+				let len = data.len().min(buf.len());
+				buf[..len].copy_from_slice(&data[..len]);
+				data.drain(..len);
+				if data.is_empty() {
+					state.rx.pop_front();
+				}
+				return Ok(len);
+			}
+
+			if deadline.map_or(false, |deadline| deadline <= now) {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"reading from simulation timed out"));
+			}
+			let wait_until = match (due, deadline) {
+				(Some(due), Some(deadline)) => Some(due.min(deadline)),
+				(due, deadline) => due.or(deadline)
+			};
+			state = match wait_until {
+				Some(wait_until) => clock::wait_timeout(condvar, state, wait_until - now),
+				None => condvar.wait(state).unwrap_or_else(|e| e.into_inner())
+			};
+		}
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		let mut state = self.lock();
+		state.pending.extend_from_slice(buf);
+		state.process(clock::now());
+		self.state.1.notify_all();
+		Ok(buf.len())
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	// discard replies not read yet and/or written bytes not matched yet
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		let mut state = self.lock();
+		if input {
+			state.rx.clear();
+		}
+		if output {
+			state.pending.clear();
+		}
+		Ok(())
+	}
+}
+
+impl Backend for SimulatedSerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		SimulatedSerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		SimulatedSerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		SimulatedSerialPort::read_with_timeout(self, buf, timeout)
+	}
+
+	// writes never block
+	fn write_with_timeout(&self, buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		SimulatedSerialPort::write(self, buf)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		SimulatedSerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		SimulatedSerialPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		SimulatedSerialPort::try_clone_shared(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	// write timeouts are only stored, because writes never block
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.lock().rts = level;
+		Ok(())
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.lock().dtr = level;
+		Ok(())
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		Ok(self.lock().baud_rate)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.lock().baud_rate = baud_rate;
+		Ok(())
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		Ok(self.lock().data_bits)
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.lock().data_bits = data_bits;
+		Ok(())
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		Ok(self.lock().parity)
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.lock().parity = parity;
+		Ok(())
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		Ok(self.lock().stop_bits)
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.lock().stop_bits = stop_bits;
+		Ok(())
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		Ok(self.lock().flow_control)
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.lock().flow_control = flow_control;
+		Ok(())
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		SimulatedSerialPort::purge(self, input, output)
+	}
+}
+
+impl io::Read for SimulatedSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		SimulatedSerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &SimulatedSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		SimulatedSerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for SimulatedSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		SimulatedSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		SimulatedSerialPort::flush(self)
+	}
+}
+
+impl io::Write for &SimulatedSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		SimulatedSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		SimulatedSerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::clock::MockClock;
+
+	#[test]
+	fn delayed_reply_with_mock_clock() {
+		let clock = MockClock::install();
+		let sim = SimulatedSerialPort::new(Some(Duration::from_secs(1)));
+		sim.on(b"ping", b"pong", Duration::from_secs(5));
+		sim.write(b"ping").unwrap();
+
+		// the reply is not due before the read times out
+		let mut buf = [0u8; 8];
+		let e = sim.read(&mut buf).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+		assert_eq!(clock.elapsed(), Duration::from_secs(1));
+
+		// a longer timeout returns the reply as soon as it is due
+		assert_eq!(sim.read_with_timeout(&mut buf, Some(Duration::from_secs(10))).unwrap(), 4);
+		assert_eq!(&buf[..4], b"pong");
+		assert_eq!(clock.elapsed(), Duration::from_secs(5));
+		MockClock::uninstall();
+	}
+
+	#[test]
+	fn requests_split_across_writes() {
+		let sim = SimulatedSerialPort::new(Some(Duration::ZERO));
+		sim.on(b"AT\r", b"OK\r", Duration::ZERO);
+		sim.on_once(b"ATI\r", b"v1\r", Duration::ZERO);
+
+		// bytes preceding a request are unmatched, a partial request is kept
+		sim.write(b"xxAT").unwrap();
+		assert_eq!(sim.pending_replies(), 0);
+		sim.write(b"\rATI\r").unwrap();
+		assert_eq!(sim.take_unmatched(), b"xx");
+		assert_eq!(sim.pending_replies(), 2);
+
+		// the second identification request is not answered anymore
+		sim.write(b"ATI\r").unwrap();
+		assert_eq!(sim.pending_replies(), 2);
+
+		let mut buf = [0u8; 16];
+		assert_eq!(sim.read(&mut buf).unwrap(), 3);
+		assert_eq!(&buf[..3], b"OK\r");
+		assert_eq!(sim.read(&mut buf).unwrap(), 3);
+		assert_eq!(&buf[..3], b"v1\r");
+		assert_eq!(sim.read(&mut buf).unwrap_err().kind(), io::ErrorKind::TimedOut);
+	}
+
+	#[test]
+	fn replies_ordered_by_due_time() {
+		let clock = MockClock::install();
+		let sim = SimulatedSerialPort::new(None);
+		sim.on(b"slow", b"2", Duration::from_millis(20));
+		sim.on(b"fast", b"1", Duration::from_millis(10));
+		sim.send(b"0", Duration::ZERO);
+		sim.write(b"slowfast").unwrap();
+		assert_eq!(sim.pending_replies(), 3);
+
+		let mut buf = [0u8; 1];
+		for (expected, elapsed) in [(b"0", 0), (b"1", 10), (b"2", 20)] {
+			sim.read(&mut buf).unwrap();
+			assert_eq!(&buf, expected);
+			assert_eq!(clock.elapsed(), Duration::from_millis(elapsed));
+		}
+		MockClock::uninstall();
+	}
+
+	// a rule added after its request was written replies right away
+	#[test]
+	fn rules_match_pending_requests() {
+		let sim = SimulatedSerialPort::new(Some(Duration::ZERO));
+		sim.on(b"long request", b"", Duration::ZERO);
+		sim.write(b"ping").unwrap();
+		sim.on(b"ping", b"pong", Duration::ZERO);
+		let mut buf = [0u8; 4];
+		assert_eq!(sim.read(&mut buf).unwrap(), 4);
+		assert!(sim.take_unmatched().is_empty());
+	}
+}
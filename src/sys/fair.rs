@@ -0,0 +1,107 @@
+// FIFO of threads reading from (or writing to) a port or its clones. threads
+// poll()ing the same TTY are all woken up by received data (or free space in
+// the output queue) and race for read() (or write()), which may starve some
+// of them indefinitely. instead, only the thread at the front of the queue
+// performs I/O, so threads are served in the order they started, like with
+// the mutexes of the (experimental) Windows backend.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::clock;
+
+#[derive(Default)]
+pub struct FairQueue {
+	tickets: Mutex<Tickets>,
+	turn: Condvar
+}
+
+#[derive(Default)]
+struct Tickets {
+	waiting: VecDeque<u64>,
+	next: u64
+}
+
+// the front of a FairQueue, which passes the turn on when dropped
+pub struct Turn<'a> {
+	queue: &'a FairQueue,
+	ticket: u64
+}
+
+impl FairQueue {
+	fn lock(&self) -> MutexGuard<'_, Tickets> {
+		self.tickets.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// wait until all threads that entered earlier are done, or give up after
+	// timeout (None waits indefinitely)
+	pub fn enter(&self, timeout: Option<Duration>) -> Option<Turn<'_>> {
+		let entry = clock::now();
+		let mut tickets = self.lock();
+		let ticket = tickets.next;
+		tickets.next += 1;
+		tickets.waiting.push_back(ticket);
+
+		while tickets.waiting.front() != Some(&ticket) {
+			tickets = match timeout {
+				None => self.turn.wait(tickets).unwrap_or_else(|e| e.into_inner()),
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) if !remaining.is_zero() =>
+						clock::wait_timeout(&self.turn, tickets, remaining),
+					_ => {
+						// the front remains unchanged, so nobody is notified
+						tickets.waiting.retain(|&other| other != ticket);
+						return None;
+					}
+				}
+			};
+		}
+		Some(Turn { queue: self, ticket })
+	}
+}
+
+impl Turn<'_> {
+	// whether other threads are waiting for their turn, e.g., to pass it on
+	// after part of a large write instead of writing all of it at once
+	#[cfg_attr(not(all(windows, feature = "experimental")), allow(dead_code))]
+	pub fn contended(&self) -> bool {
+		self.queue.lock().waiting.len() > 1
+	}
+}
+
+impl Drop for Turn<'_> {
+	fn drop(&mut self) {
+		let mut tickets = self.queue.lock();
+		tickets.waiting.retain(|&other| other != self.ticket);
+		drop(tickets);
+		self.queue.turn.notify_all();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::clock::MockClock;
+
+	#[test]
+	fn waiting_counts_towards_timeout() {
+		let queue = FairQueue::default();
+		let turn = queue.enter(None).unwrap();
+		assert!(!turn.contended());
+
+		// the wait for the turn times out after the simulated timeout
+		let clock = MockClock::install();
+		assert!(queue.enter(Some(Duration::ZERO)).is_none());
+		assert_eq!(clock.elapsed(), Duration::ZERO);
+		assert!(queue.enter(Some(Duration::from_secs(10))).is_none());
+		assert_eq!(clock.elapsed(), Duration::from_secs(10));
+		MockClock::uninstall();
+
+		// threads that gave up do not block the queue
+		drop(turn);
+		assert!(queue.enter(Some(Duration::ZERO)).is_some());
+	}
+}
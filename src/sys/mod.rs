@@ -1,12 +1,27 @@
+#[cfg(any(unix, windows))]
+mod fair;
+
 #[cfg(unix)]
 mod unix;
 
+#[cfg(all(unix, feature = "enumerate"))]
+mod unix_devices;
+
 #[cfg(all(windows, not(feature = "experimental")))]
 mod windows;
 
 #[cfg(all(windows, feature = "experimental"))]
 mod windows_experimental;
 
+#[cfg(windows)]
+mod windows_dcb;
+
+#[cfg(windows)]
+mod windows_devices;
+
+#[cfg(target_arch = "wasm32")]
+mod unsupported;
+
 #[cfg(unix)]
 pub use unix::*;
 
@@ -16,5 +31,17 @@ pub use windows::*;
 #[cfg(all(windows, feature = "experimental"))]
 pub use windows_experimental::*;
 
-#[cfg(not(any(unix, windows)))]
-compile_error!("This crate supports Unix and Windows only.");
+#[cfg(target_arch = "wasm32")]
+pub use unsupported::*;
+
+#[cfg(all(any(unix, windows), feature = "usb"))]
+pub(crate) use fair::FairQueue;
+
+#[cfg(windows)]
+pub use windows_devices::{com0com_pairs, is_busy};
+
+#[cfg(windows)]
+pub use windows_dcb::{default_comm_config, set_default_comm_config};
+
+#[cfg(not(any(unix, windows, target_arch = "wasm32")))]
+compile_error!("This crate supports Unix, Windows, and WebAssembly only.");
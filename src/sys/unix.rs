@@ -1,95 +1,347 @@
 extern crate libc;
-#[cfg(target_os = "linux")]
-extern crate udev;
 
-use std::ffi::{CString, OsStr, OsString};
+use std::ffi::{CStr, CString, OsStr, OsString};
 use std::io;
 use std::mem;
-use std::os::unix::ffi::OsStrExt;
-use std::time::{Duration, Instant};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use libc::{c_int, c_void, speed_t, tcflag_t};
 
-use libc::{c_int, c_void, INT_MAX};
+use crate::{DataBits, FlowControl, LineMode, NotASerialPort, Parity, PortStatus, StopBits,
+	TerminalOptions};
+use crate::backend::AtomicTimeout;
+use crate::clock;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::gsm::GsmConfig;
+use super::fair::FairQueue;
+#[cfg(feature = "enumerate")]
+use super::unix_devices;
+#[cfg(feature = "enumerate")]
+pub use super::unix_devices::Devices;
+
+// wait for events of pollfd until timeout (None blocks indefinitely) with
+// the resolution the OS allows. ppoll() accepts nanoseconds, whereas poll()
+// is rounded up to milliseconds, so it never returns before the timeout.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn poll(pollfds: &mut [libc::pollfd], timeout: Option<Duration>) -> c_int {
+	let timespec = timeout.map(|timeout| libc::timespec {
+		tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+		tv_nsec: timeout.subsec_nanos() as _
+	});
+	let timespec_ptr = timespec.as_ref().map_or(ptr::null(), |timespec| timespec);
+	unsafe {
+		libc::ppoll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timespec_ptr, ptr::null())
+	}
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn poll(pollfds: &mut [libc::pollfd], timeout: Option<Duration>) -> c_int {
+	let timeout_ms = match timeout {
+		None => -1,
+		Some(timeout) => ((timeout.as_nanos() + 999_999) / 1_000_000)
+			.min(c_int::MAX as u128) as c_int
+	};
+	unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms) }
+}
+
+// interval of polling the modem status interrupt counters, see
+// SerialPort::wait_modem_change()
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// struct serial_icounter_struct of linux/serial.h, which libc lacks
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+struct SerialIcounter {
+	cts: c_int,
+	dsr: c_int,
+	rng: c_int,
+	dcd: c_int,
+	rx: c_int,
+	tx: c_int,
+	frame: c_int,
+	overrun: c_int,
+	parity: c_int,
+	brk: c_int,
+	buf_overrun: c_int,
+	reserved: [c_int; 9]
+}
+
+// whether opening failed because another process uses the device, i.e.,
+// locked it via TIOCEXCL or flock()
+pub fn is_busy(error: &io::Error) -> bool {
+	error.raw_os_error() == Some(libc::EBUSY)
+}
 
 pub struct SerialPort {
 	fd: c_int,
-	timeout_read: Option<Duration>,
-	timeout_write: Option<Duration>
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout,
+	// retry poll(), read(), and write() failing with EINTR instead of
+	// returning ErrorKind::Interrupted
+	retry_interrupted: AtomicBool,
+	// shared with clones, so their readers and writers take turns (see
+	// FairQueue)
+	read_queue: Arc<FairQueue>,
+	write_queue: Arc<FairQueue>
 }
 
+
 const TTY_FLAGS: c_int = libc::O_RDWR
                        | libc::O_CLOEXEC
                        | libc::O_NOCTTY
                        | libc::O_NONBLOCK;
 
+// POSIX only supports a fixed set of baud rates via speed_t constants
+const BAUD_RATES: &[(u32, speed_t)] = &[
+	(50, libc::B50), (75, libc::B75), (110, libc::B110), (134, libc::B134),
+	(150, libc::B150), (200, libc::B200), (300, libc::B300), (600, libc::B600),
+	(1200, libc::B1200), (1800, libc::B1800), (2400, libc::B2400),
+	(4800, libc::B4800), (9600, libc::B9600), (19200, libc::B19200),
+	(38400, libc::B38400), (57600, libc::B57600), (115200, libc::B115200),
+	(230400, libc::B230400),
+	#[cfg(target_os = "linux")] (460800, libc::B460800),
+	#[cfg(target_os = "linux")] (500000, libc::B500000),
+	#[cfg(target_os = "linux")] (576000, libc::B576000),
+	#[cfg(target_os = "linux")] (921600, libc::B921600),
+	#[cfg(target_os = "linux")] (1000000, libc::B1000000),
+	#[cfg(target_os = "linux")] (1152000, libc::B1152000),
+	#[cfg(target_os = "linux")] (1500000, libc::B1500000),
+	#[cfg(target_os = "linux")] (2000000, libc::B2000000),
+	#[cfg(target_os = "linux")] (2500000, libc::B2500000),
+	#[cfg(target_os = "linux")] (3000000, libc::B3000000),
+	#[cfg(target_os = "linux")] (3500000, libc::B3500000),
+	#[cfg(target_os = "linux")] (4000000, libc::B4000000),
+];
+
+// names of the hardware settings that differ between requested and applied
+// termios. the remaining flags are handled by the line discipline, which
+// applies them all.
+fn refused_settings(requested: &libc::termios, applied: &libc::termios) -> Vec<&'static str> {
+	#[cfg(target_os = "linux")]
+	const PARITY: libc::tcflag_t = libc::PARENB | libc::PARODD | libc::CMSPAR;
+	#[cfg(not(target_os = "linux"))]
+	const PARITY: libc::tcflag_t = libc::PARENB | libc::PARODD;
+
+	let mut refused = Vec::new();
+	if unsafe { libc::cfgetospeed(requested) != libc::cfgetospeed(applied)
+			|| libc::cfgetispeed(requested) != libc::cfgetispeed(applied) } {
+		refused.push("baud rate");
+	}
+	let cflag = |mask| (requested.c_cflag & mask, applied.c_cflag & mask);
+	for (name, mask) in [
+		("data bits", libc::CSIZE),
+		("parity", PARITY),
+		("stop bits", libc::CSTOPB),
+		("hardware flow control", libc::CRTSCTS),
+		("modem control (CLOCAL)", libc::CLOCAL)
+	] {
+		let (requested, applied) = cflag(mask);
+		if requested != applied {
+			refused.push(name);
+		}
+	}
+	refused
+}
+
+// whether a process holds the flock() placed by SerialPort::open() on the
+// device, as listed in /proc/locks by device and inode, e.g.:
+//   1: FLOCK  ADVISORY  WRITE 4242 00:05:1033 0 EOF
+// https://man7.org/linux/man-pages/man5/proc_locks.5.html
+#[cfg(target_os = "linux")]
+fn is_locked(metadata: &std::fs::Metadata) -> io::Result<bool> {
+	use std::os::unix::fs::MetadataExt;
+
+	let id = format!("{:02x}:{:02x}:{}",
+		libc::major(metadata.dev()), libc::minor(metadata.dev()), metadata.ino());
+	let locks = std::fs::read_to_string("/proc/locks")?;
+	Ok(locks.lines().any(|line| {
+		let mut fields = line.split_whitespace();
+		fields.any(|field| field == "FLOCK") && fields.any(|field| field == id)
+	}))
+}
+
+// settings of a port before it was configured by open_saving()
+pub type SavedSettings = libc::termios;
+
 impl SerialPort {
 	pub fn open<T>(dev_path: &T, timeout: Option<Duration>) -> io::Result<Self>
 			where T: AsRef<OsStr> + ?Sized {
+		Self::open_saving(dev_path, timeout).map(|(port, _)| port)
+	}
+
+	// like open(), but also return the settings the port had before
+	pub fn open_saving<T>(dev_path: &T, timeout: Option<Duration>)
+			-> io::Result<(Self, Option<SavedSettings>)>
+			where T: AsRef<OsStr> + ?Sized {
 		let dev_cstr = CString::new(dev_path.as_ref().as_bytes()).unwrap();
+		// O_NONBLOCK keeps open() from blocking until DCD is asserted, which
+		// is the default for dial-in devices like /dev/tty.* on macOS. the
+		// callout devices (/dev/cu.*) never wait and are therefore preferred.
 		let fd = unsafe { libc::open(dev_cstr.as_ptr(), TTY_FLAGS, 0) };
 		if fd < 0 {
+			let error = io::Error::last_os_error();
+			if error.raw_os_error() == Some(libc::EISDIR) {
+				return Err(NotASerialPort.into());
+			}
+			return Err(error);
+		}
+
+		// wrap fd immediately, so it is closed by drop() on error
+		let port = Self {
+			fd,
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout),
+			retry_interrupted: AtomicBool::new(false),
+			read_queue: Arc::default(),
+			write_queue: Arc::default()
+		};
+
+		// reject regular files, FIFOs, and other devices, for which the
+		// ioctl()s below would fail with confusing errors
+		if unsafe { libc::isatty(fd) } != 1 {
+			return Err(NotASerialPort.into());
+		}
+
+		port.lock()?;
+
+		// set raw mode, speed, and timeout settings ("polling read"). CLOCAL
+		// makes reads and writes ignore DCD, so dial-in devices behave like
+		// callout devices once opened. the speed must be set via cfsetspeed(),
+		// because only Linux encodes it in c_cflag, whereas macOS and the
+		// BSDs store it separately (B38400 would set unrelated c_cflag bits).
+		// http://man7.org/linux/man-pages/man3/termios.3.html
+		let original = port.get_termios()?;
+		let mut termios: libc::termios = unsafe { mem::zeroed() };
+		termios.c_cflag = libc::CS8 | libc::CLOCAL | libc::CREAD;
+		if unsafe { libc::cfsetspeed(&mut termios, libc::B38400) } != 0 {
 			return Err(io::Error::last_os_error());
 		}
+		port.set_termios(&termios)?;
 
-		// get exclusive TTY access
-		// http://man7.org/linux/man-pages/man4/tty_ioctl.4.html
-		if unsafe { libc::ioctl(fd, libc::TIOCEXCL) } != 0 {
+		Ok((port, Some(original)))
+	}
+
+	pub fn probe<T>(dev_path: &T) -> io::Result<PortStatus>
+			where T: AsRef<OsStr> + ?Sized {
+		use std::os::unix::fs::FileTypeExt;
+
+		let metadata = match std::fs::metadata(dev_path.as_ref()) {
+			Ok(metadata) => metadata,
+			Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(PortStatus::Missing),
+			Err(e) => return Err(e)
+		};
+		if !metadata.file_type().is_char_device() {
+			return Err(NotASerialPort.into());
+		}
+
+		// https://man7.org/linux/man-pages/man2/access.2.html
+		let dev_cstr = CString::new(dev_path.as_ref().as_bytes()).unwrap();
+		if unsafe { libc::access(dev_cstr.as_ptr(), libc::R_OK | libc::W_OK) } != 0 {
+			let error = io::Error::last_os_error();
+			return match error.raw_os_error() {
+				Some(libc::EACCES) | Some(libc::EROFS) => Ok(PortStatus::PermissionDenied),
+				_ => Err(error)
+			};
+		}
+
+		#[cfg(target_os = "linux")]
+		if is_locked(&metadata).unwrap_or(false) {
+			return Ok(PortStatus::Busy);
+		}
+		Ok(PortStatus::Present)
+	}
+
+	pub fn open_pty(timeout: Option<Duration>) -> io::Result<(Self, OsString)> {
+		// open pseudoterminal master, see:
+		// https://man7.org/linux/man-pages/man3/posix_openpt.3.html
+		let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+		if fd < 0 {
 			return Err(io::Error::last_os_error());
 		}
 
-		// requesting exclusive TTY access via TIOCEXCL above is insufficient to
-		// avoid simultaneous access by users with CAP_SYS_ADMIN, which allows
-		// to bypass TIOCEXCL. therefore, use flock() to place an additional
-		// exclusive advisory lock on the TTY device.
-		// https://stackoverflow.com/questions/49636520/how-do-you-check-if-a-serial-port-is-open-in-linux/49687230#49687230
-		// https://stackoverflow.com/questions/30316722/what-is-the-best-practice-for-locking-serial-ports-and-other-devices-in-linux/34937038#34937038
-		// https://man7.org/linux/man-pages/man2/flock.2.html
-		if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } != 0 {
-			// TODO: indicate that file is locked on EWOULDBLOCK
+		// wrap fd immediately, so it is closed by drop() on error
+		let port = Self {
+			fd,
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout),
+			retry_interrupted: AtomicBool::new(false),
+			read_queue: Arc::default(),
+			write_queue: Arc::default()
+		};
+
+		// posix_openpt() does not support O_CLOEXEC and O_NONBLOCK on all
+		// platforms, so set them afterwards
+		if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		if unsafe { libc::fcntl(fd, libc::F_SETFL, TTY_FLAGS) } != 0 {
 			return Err(io::Error::last_os_error());
 		}
 
-		// set raw mode, speed, and timeout settings ("polling read"), see:
-		// http://man7.org/linux/man-pages/man3/termios.3.html
+		// unlock slave device
+		// https://man7.org/linux/man-pages/man3/grantpt.3.html
+		// https://man7.org/linux/man-pages/man3/unlockpt.3.html
+		if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		// set raw mode for the pseudoterminal pair, so data passes unaltered
 		let mut termios: libc::termios = unsafe { mem::zeroed() };
-		termios.c_cflag = libc::B38400 | libc::CS8 | libc::CLOCAL | libc::CREAD;
+		if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		unsafe { libc::cfmakeraw(&mut termios) };
 		if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
 			return Err(io::Error::last_os_error());
 		}
 
-		Ok(Self {
-			fd,
-			timeout_read: timeout,
-			timeout_write: timeout
-		})
-	}
+		// get slave device path. ptsname() is not thread-safe, but its
+		// reentrant variant ptsname_r() is a non-portable extension.
+		// https://man7.org/linux/man-pages/man3/ptsname.3.html
+		#[cfg(target_os = "linux")]
+		let slave_path = {
+			let mut buf = [0 as libc::c_char; 128];
+			let res = unsafe { libc::ptsname_r(fd, buf.as_mut_ptr(), buf.len()) };
+			if res != 0 {
+				return Err(io::Error::from_raw_os_error(res));
+			}
+			unsafe { CStr::from_ptr(buf.as_ptr()) }
+				.to_bytes().to_vec()
+		};
+		#[cfg(not(target_os = "linux"))]
+		let slave_path = {
+			let name = unsafe { libc::ptsname(fd) };
+			if name.is_null() {
+				return Err(io::Error::last_os_error());
+			}
+			unsafe { CStr::from_ptr(name) }
+				.to_bytes().to_vec()
+		};
 
-	#[cfg(not(target_os = "linux"))]
-	pub fn list_devices() -> Vec<OsString> {
-		unimplemented!("Enumerating serial devices is only supported on Linux");
+		Ok((port, OsString::from_vec(slave_path)))
 	}
 
-	#[cfg(target_os = "linux")]
-	pub fn list_devices() -> Vec<OsString> {
-		let mut devices: Vec<OsString> = Vec::new();
-
-		// iterate over all TTY devices
-		let mut enumerator = udev::Enumerator::new().unwrap();
-		enumerator.match_subsystem("tty").unwrap();
-		for device in enumerator.scan_devices().unwrap() {
-			// skip this device if it doesn't have a device name (e.g. /dev/ttyACM0)
-			let devname = match device.property_value("DEVNAME") {
-				Some(id_model) => id_model,
-				None => continue
-			};
+	#[cfg(feature = "enumerate")]
+	pub fn devices() -> io::Result<Devices> {
+		unix_devices::devices()
+	}
 
-			// add to device list
-			devices.push(devname.to_os_string());
-		}
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
 
-		devices
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
 	}
 
-	pub fn try_clone(&self) -> io::Result<Self> {
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
 		// duplicate file descriptor (F_DUPFD_CLOEXEC requires POSIX.1-2008)
 		let fd = unsafe { libc::fcntl(self.fd, libc::F_DUPFD_CLOEXEC, 0) };
 		if fd < 0 {
@@ -109,36 +361,64 @@ impl SerialPort {
 
 		Ok(Self {
 			fd,
-			timeout_read: self.timeout_read,
-			timeout_write: self.timeout_write
+			timeout_read,
+			timeout_write,
+			retry_interrupted: AtomicBool::new(self.retry_interrupted()),
+			read_queue: self.read_queue.clone(),
+			write_queue: self.write_queue.clone()
 		})
 	}
 
+	fn is_retried(&self, error: &io::Error) -> bool {
+		error.kind() == io::ErrorKind::Interrupted && self.retry_interrupted()
+	}
+
 	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout_read: Option<Duration>)
+			-> io::Result<usize> {
 		let mut pollfd = libc::pollfd {
 			fd: self.fd,
 			events: libc::POLLIN,
 			revents: 0
 		};
 
-		let entry = Instant::now();
+		// wait for the turn of this thread (see FairQueue), which counts
+		// towards the timeout. a zero timeout times out immediately if
+		// another thread is reading.
+		let entry = clock::now();
+		let _turn = self.read_queue.enter(timeout_read)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut,
+				"reading from TTY timed out"))?;
 		loop {
-			// compute read timeout in ms, accounting for time already elapsed
-			let elapsed = entry.elapsed();
-			let timeout_ms: c_int = match self.timeout_read {
-				None => -1,
-				Some(timeout) if elapsed > timeout => {
-					return Err(io::Error::new(io::ErrorKind::TimedOut,
-						"reading from TTY timed out"));
-				},
-				Some(timeout) if timeout - elapsed <= Duration::from_millis(1) => 1,
-				Some(timeout) if timeout - elapsed >= Duration::from_millis(INT_MAX as u64) => INT_MAX,
-				Some(timeout) => (timeout - elapsed).as_millis() as c_int
+			// compute remaining read timeout, accounting for time already
+			// elapsed. zero timeout polls without blocking, so the read times
+			// out immediately unless the TTY is ready.
+			let timeout = match timeout_read {
+				None => None,
+				Some(timeout) if timeout.is_zero() => Some(timeout),
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) => Some(remaining),
+					None => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"reading from TTY timed out"))
+				}
 			};
 
 			// block until data is available or timeout occurs
-			match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
-				-1 => return Err(io::Error::last_os_error()),
+			match poll(slice::from_mut(&mut pollfd), clock::os_timeout(timeout)) {
+				-1 => {
+					// retrying recomputes the remaining timeout, so signals
+					// do not extend the deadline
+					let error = io::Error::last_os_error();
+					if !self.is_retried(&error) {
+						return Err(error);
+					}
+					continue;
+				},
+				0 if clock::os_timed_out(timeout) => continue,
 				0 => return Err(io::Error::new(io::ErrorKind::TimedOut,
 						"reading from TTY timed out")),
 				_ => ()
@@ -150,9 +430,9 @@ impl SerialPort {
 					"TTY was closed or disconnected"));
 			}
 
-			// try to read() from tty. if multiple threads poll() in parallel,
-			// they are released simultaneously and race for the read(), which
-			// will likely succeed only on one thread.
+			// try to read() from tty. threads reading via this port or its
+			// clones take turns, but the read() may still fail if another
+			// process reads from the TTY (e.g., root bypassing TIOCEXCL).
 			let len = unsafe {
 				libc::read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len())
 			};
@@ -163,11 +443,14 @@ impl SerialPort {
 				// https://man7.org/linux/man-pages/man3/termios.3.html
 				-1 => {
 					let error = io::Error::last_os_error();
-					if error.kind() != io::ErrorKind::WouldBlock {
+					if error.kind() != io::ErrorKind::WouldBlock && !self.is_retried(&error) {
 						return Err(error);
 					}
 				},
-				0 if buf.len() == 0 => return Ok(0),
+				0 if buf.is_empty() => return Ok(0),
+				// in canonical mode, end of file (VEOF on an empty line) is
+				// read as 0 bytes, just like from a regular file
+				0 if self.get_termios()?.c_lflag & libc::ICANON != 0 => return Ok(0),
 				0 => (),
 				_ => return Ok(len as usize)
 			}
@@ -175,30 +458,50 @@ impl SerialPort {
 	}
 
 	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_with_timeout(buf, self.timeout_write.load())
+	}
+
+	// like write(), but with the given timeout instead of the write timeout
+	pub fn write_with_timeout(&self, buf: &[u8], timeout_write: Option<Duration>)
+			-> io::Result<usize> {
 		let mut pollfd = libc::pollfd {
 			fd: self.fd,
 			events: libc::POLLOUT,
 			revents: 0
 		};
 
-		let entry = Instant::now();
+		// like for read(). each write() takes a turn, so a large write_all()
+		// takes turns with the other writers instead of starving them.
+		let entry = clock::now();
+		let _turn = self.write_queue.enter(timeout_write)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut,
+				"writing to TTY timed out"))?;
 		loop {
-			// compute write timeout in ms, accounting for time already elapsed
-			let elapsed = entry.elapsed();
-			let timeout_ms: c_int = match self.timeout_write {
-				None => -1,
-				Some(timeout) if elapsed > timeout => {
-					return Err(io::Error::new(io::ErrorKind::TimedOut,
-						"writing to TTY timed out"));
-				},
-				Some(timeout) if timeout - elapsed <= Duration::from_millis(1) => 1,
-				Some(timeout) if timeout - elapsed >= Duration::from_millis(INT_MAX as u64) => INT_MAX,
-				Some(timeout) => (timeout - elapsed).as_millis() as c_int
+			// compute remaining write timeout, accounting for time already
+			// elapsed. zero timeout polls without blocking, so the write times
+			// out immediately unless the TTY is ready.
+			let timeout = match timeout_write {
+				None => None,
+				Some(timeout) if timeout.is_zero() => Some(timeout),
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) => Some(remaining),
+					None => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"writing to TTY timed out"))
+				}
 			};
 
 			// block until tty becomes writable or timeout occurs
-			match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
-				-1 => return Err(io::Error::last_os_error()),
+			match poll(slice::from_mut(&mut pollfd), clock::os_timeout(timeout)) {
+				-1 => {
+					// retrying recomputes the remaining timeout, so signals
+					// do not extend the deadline
+					let error = io::Error::last_os_error();
+					if !self.is_retried(&error) {
+						return Err(error);
+					}
+					continue;
+				},
+				0 if clock::os_timed_out(timeout) => continue,
 				0 => return Err(io::Error::new(io::ErrorKind::TimedOut,
 						"writing to TTY timed out")),
 				_ => ()
@@ -210,10 +513,9 @@ impl SerialPort {
 					"TTY was closed or disconnected"));
 			}
 
-			// try to write() to tty. if multiple threads poll() in parallel,
-			// they are released simultaneously and race for the write(), which
-			// may not succeed on all threads if the TTY's output buffer is
-			// full.
+			// try to write() to tty. threads writing via this port or its
+			// clones take turns, so the write() only fails if another process
+			// filled the TTY's output buffer meanwhile.
 			let len = unsafe {
 				libc::write(self.fd, buf.as_ptr() as *const c_void, buf.len())
 			};
@@ -221,11 +523,11 @@ impl SerialPort {
 			match len {
 				-1 => {
 					let error = io::Error::last_os_error();
-					if error.kind() != io::ErrorKind::WouldBlock {
+					if error.kind() != io::ErrorKind::WouldBlock && !self.is_retried(&error) {
 						return Err(error);
 					}
 				},
-				0 if buf.len() == 0 => return Ok(0),
+				0 if buf.is_empty() => return Ok(0),
 				// FIXME: does len == 0 indicate timeout just like for read()?
 				0 => (),
 				_ => return Ok(len as usize)
@@ -233,12 +535,674 @@ impl SerialPort {
 		}
 	}
 
+	// wait until all written data was transmitted. fsync() is meaningless
+	// for TTYs and fails on some drivers. like FlushFileBuffers() on
+	// Windows, tcdrain() ignores the write timeout.
 	pub fn flush(&self) -> io::Result<()> {
-		match unsafe { libc::fsync(self.fd) } {
+		loop {
+			match unsafe { libc::tcdrain(self.fd) } {
+				-1 => {
+					let err = io::Error::last_os_error();
+					if !self.is_retried(&err) {
+						return Err(err);
+					}
+				},
+				0 => return Ok(()),
+				_ if cfg!(debug_assertions) => panic!("tcdrain() returned invalid value"),
+				_ => unreachable!()
+			}
+		}
+	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	// timeouts are evaluated by read() and write() via poll(), so there is
+	// no need to configure anything at the OS level
+	pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	pub fn raw_fd(&self) -> c_int {
+		self.fd
+	}
+
+	// whether the descriptor is kept open in child processes, i.e.,
+	// FD_CLOEXEC is not set
+	// https://man7.org/linux/man-pages/man2/fcntl.2.html
+	pub fn inheritable(&self) -> io::Result<bool> {
+		match unsafe { libc::fcntl(self.fd, libc::F_GETFD) } {
+			-1 => Err(io::Error::last_os_error()),
+			flags => Ok(flags & libc::FD_CLOEXEC == 0)
+		}
+	}
+
+	pub fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+		let flags = match unsafe { libc::fcntl(self.fd, libc::F_GETFD) } {
+			-1 => return Err(io::Error::last_os_error()),
+			flags if inheritable => flags & !libc::FD_CLOEXEC,
+			flags => flags | libc::FD_CLOEXEC
+		};
+		match unsafe { libc::fcntl(self.fd, libc::F_SETFD, flags) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	pub fn retry_interrupted(&self) -> bool {
+		self.retry_interrupted.load(Ordering::Relaxed)
+	}
+
+	pub fn set_retry_interrupted(&self, retry: bool) {
+		self.retry_interrupted.store(retry, Ordering::Relaxed);
+	}
+
+	// wait until any of ports is readable, see crate::SerialPort::wait_any().
+	// an error or hang up counts as readable, so the next read reports it.
+	pub fn wait_any(ports: &[&Self], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+		let mut pollfds: Vec<libc::pollfd> = ports.iter()
+			.map(|port| libc::pollfd { fd: port.fd, events: libc::POLLIN, revents: 0 })
+			.collect();
+		match poll(&mut pollfds, timeout) {
+			-1 => Err(io::Error::last_os_error()),
+			0 => Err(io::Error::new(io::ErrorKind::TimedOut,
+				"waiting for TTYs timed out")),
+			_ => Ok(pollfds.iter()
+				.enumerate()
+				.filter(|(_, pollfd)| pollfd.revents != 0)
+				.map(|(index, _)| index)
+				.collect())
+		}
+	}
+
+	// set or clear modem control output lines, see:
+	// https://man7.org/linux/man-pages/man2/ioctl_tty.2.html
+	fn set_modem_bits(&self, bits: c_int, level: bool) -> io::Result<()> {
+		let request = if level { libc::TIOCMBIS } else { libc::TIOCMBIC };
+		match unsafe { libc::ioctl(self.fd, request, &bits) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	fn get_modem_bits(&self) -> io::Result<c_int> {
+		let mut bits: c_int = 0;
+		match unsafe { libc::ioctl(self.fd, libc::TIOCMGET, &mut bits) } {
 			-1 => Err(io::Error::last_os_error()),
-			0 => Ok(()),
-			_ if cfg!(debug_assertions) => panic!("fsync() returned invalid value"),
-			_ => unreachable!()
+			_ => Ok(bits)
+		}
+	}
+
+	pub fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.set_modem_bits(libc::TIOCM_RTS, level)
+	}
+
+	pub fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.set_modem_bits(libc::TIOCM_DTR, level)
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		self.get_modem_bits().map(|bits| bits & libc::TIOCM_CTS != 0)
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		self.get_modem_bits().map(|bits| bits & libc::TIOCM_DSR != 0)
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		self.get_modem_bits().map(|bits| bits & libc::TIOCM_RI != 0)
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		self.get_modem_bits().map(|bits| bits & libc::TIOCM_CD != 0)
+	}
+
+	// block until any modem status input (CTS, DSR, RI, or CD) changes or the
+	// timeout expires. TIOCMIWAIT cannot be interrupted, so waits with a
+	// timeout poll the interrupt counters (TIOCGICOUNT) instead, which also
+	// count changes occurring between two polls.
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		let timeout = match timeout {
+			Some(timeout) => timeout,
+			None => return self.wait_modem_interrupt()
+		};
+
+		let entry = clock::now();
+		let initial = self.modem_counters()?;
+		loop {
+			let remaining = timeout.saturating_sub(clock::elapsed(entry));
+			clock::sleep(remaining.min(MODEM_POLL_INTERVAL));
+			if self.modem_counters()? != initial {
+				return Ok(());
+			}
+			if remaining <= MODEM_POLL_INTERVAL {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for modem status change timed out"));
+			}
+		}
+	}
+
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	fn wait_modem_interrupt(&self) -> io::Result<()> {
+		let mask = libc::TIOCM_CTS | libc::TIOCM_DSR | libc::TIOCM_RI | libc::TIOCM_CD;
+		loop {
+			match unsafe { libc::ioctl(self.fd, libc::TIOCMIWAIT, mask) } {
+				-1 => {
+					let error = io::Error::last_os_error();
+					match error.raw_os_error() {
+						// drivers without modem status interrupts, e.g., PTYs
+						Some(libc::ENOTTY) | Some(libc::EINVAL) => return Err(io::Error::new(
+							io::ErrorKind::Unsupported, "device has no modem status interrupts")),
+						_ if !self.is_retried(&error) => return Err(error),
+						_ => ()
+					}
+				},
+				_ => return Ok(())
+			}
+		}
+	}
+
+	// number of changes of CTS, DSR, RI, and CD. drivers without interrupt
+	// counters (e.g., PTYs) fail with ENOTTY or EINVAL.
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	fn modem_counters(&self) -> io::Result<[c_int; 4]> {
+		let mut icount: SerialIcounter = unsafe { mem::zeroed() };
+		if unsafe { libc::ioctl(self.fd, libc::TIOCGICOUNT, &mut icount) } == -1 {
+			let error = io::Error::last_os_error();
+			return match error.raw_os_error() {
+				Some(libc::ENOTTY) | Some(libc::EINVAL) => Err(io::Error::new(
+					io::ErrorKind::Unsupported, "device has no modem status interrupt counters")),
+				_ => Err(error)
+			};
+		}
+		Ok([icount.cts, icount.dsr, icount.rng, icount.dcd])
+	}
+
+	// TIOCMIWAIT is Linux-specific
+	#[cfg(not(any(target_os = "linux", target_os = "android")))]
+	pub fn wait_modem_change(&self, _timeout: Option<Duration>) -> io::Result<()> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"waiting for modem status changes is not supported on this platform"))
+	}
+
+	// http://man7.org/linux/man-pages/man3/termios.3.html
+	fn get_termios(&self) -> io::Result<libc::termios> {
+		let mut termios: libc::termios = unsafe { mem::zeroed() };
+		if unsafe { libc::tcgetattr(self.fd, &mut termios) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(termios)
+	}
+
+	// tcsetattr() succeeds if any of the changes was applied, so the settings
+	// are read back to detect those the driver refused, e.g., an unsupported
+	// baud rate replaced by the nearest supported one
+	fn set_termios(&self, termios: &libc::termios) -> io::Result<()> {
+		if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, termios) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let applied = self.get_termios()?;
+		let refused = refused_settings(termios, &applied);
+		if !refused.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+				"TTY driver refused to change {}", refused.join(", "))));
+		}
+		Ok(())
+	}
+
+	// restore settings returned by open_saving() as they were, i.e., without
+	// verifying them like set_termios()
+	pub fn restore_settings(&self, settings: &SavedSettings) -> io::Result<()> {
+		if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, settings) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	}
+
+	// read-modify-write termios settings
+	fn update_termios<F>(&self, update: F) -> io::Result<()>
+			where F: FnOnce(&mut libc::termios) -> io::Result<()> {
+		let mut termios = self.get_termios()?;
+		update(&mut termios)?;
+		self.set_termios(&termios)
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		let termios = self.get_termios()?;
+		let speed = unsafe { libc::cfgetospeed(&termios) };
+		match BAUD_RATES.iter().find(|&&(_, s)| s == speed) {
+			Some(&(baud_rate, _)) => Ok(baud_rate),
+			// on macOS and the BSDs speed_t is the baud rate itself, e.g.,
+			// after setting a non-standard rate
+			#[cfg(not(target_os = "linux"))]
+			None => Ok(speed as u32),
+			#[cfg(all(target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64"))))]
+			None if speed == libc::BOTHER => Ok(self.get_termios2()?.c_ospeed),
+			#[cfg(target_os = "linux")]
+			None => Err(io::Error::new(io::ErrorKind::Other,
+				"TTY uses unknown baud rate"))
+		}
+	}
+
+	// Linux supports arbitrary baud rates (if supported by the driver) via
+	// BOTHER, which glibc's termios cannot express. subsequent tcsetattr()
+	// calls keep the rate, as they do not change the speed fields.
+	// https://man7.org/linux/man-pages/man2/TCSETS2.2const.html
+	#[cfg(all(target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64"))))]
+	fn get_termios2(&self) -> io::Result<libc::termios2> {
+		let mut termios2: libc::termios2 = unsafe { mem::zeroed() };
+		if unsafe { libc::ioctl(self.fd, libc::TCGETS2, &mut termios2) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(termios2)
+	}
+
+	#[cfg(all(target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64"))))]
+	fn set_custom_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		let mut termios2 = self.get_termios2()?;
+		termios2.c_cflag &= !(libc::CBAUD | libc::CIBAUD);
+		termios2.c_cflag |= libc::BOTHER;
+		termios2.c_ispeed = baud_rate;
+		termios2.c_ospeed = baud_rate;
+		if unsafe { libc::ioctl(self.fd, libc::TCSETS2, &termios2) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// drivers round custom rates to the closest one their divisors can
+		// generate, which baud_rate() reports. UARTs tolerate deviations of
+		// a few percent, larger ones mean the rate cannot be approximated.
+		let actual = self.get_termios2()?.c_ospeed;
+		if u64::from(actual.abs_diff(baud_rate)) * 100 > u64::from(baud_rate) * 3 {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"TTY driver refused to change baud rate"));
+		}
+		Ok(())
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		let speed = match BAUD_RATES.iter().find(|&&(b, _)| b == baud_rate) {
+			Some(&(_, speed)) => speed,
+			// the BSDs accept arbitrary rates, if supported by the driver.
+			// macOS rejects them in tcsetattr() and requires IOSSIOSPEED,
+			// which is reset by subsequent tcsetattr() calls.
+			#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+			None => baud_rate as speed_t,
+			#[cfg(all(target_os = "linux", not(any(target_arch = "powerpc", target_arch = "powerpc64"))))]
+			None => return self.set_custom_baud_rate(baud_rate),
+			#[cfg(any(target_os = "macos", target_os = "ios",
+				all(target_os = "linux", any(target_arch = "powerpc", target_arch = "powerpc64"))))]
+			None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"baud rate not supported"))
+		};
+
+		self.update_termios(|termios| {
+			if unsafe { libc::cfsetspeed(termios, speed) } != 0 {
+				return Err(io::Error::last_os_error());
+			}
+			Ok(())
+		})
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		match self.get_termios()?.c_cflag & libc::CSIZE {
+			libc::CS5 => Ok(DataBits::Five),
+			libc::CS6 => Ok(DataBits::Six),
+			libc::CS7 => Ok(DataBits::Seven),
+			_ => Ok(DataBits::Eight)
+		}
+	}
+
+	pub fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		let size: tcflag_t = match data_bits {
+			DataBits::Five => libc::CS5,
+			DataBits::Six => libc::CS6,
+			DataBits::Seven => libc::CS7,
+			DataBits::Eight => libc::CS8
+		};
+		self.update_termios(|termios| {
+			termios.c_cflag = (termios.c_cflag & !libc::CSIZE) | size;
+			Ok(())
+		})
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		let cflag = self.get_termios()?.c_cflag;
+		#[cfg(target_os = "linux")]
+		if cflag & libc::CMSPAR != 0 && cflag & libc::PARENB != 0 {
+			return Ok(if cflag & libc::PARODD != 0 { Parity::Mark } else { Parity::Space });
+		}
+		Ok(match (cflag & libc::PARENB != 0, cflag & libc::PARODD != 0) {
+			(false, _) => Parity::None,
+			(true, true) => Parity::Odd,
+			(true, false) => Parity::Even
+		})
+	}
+
+	pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		// mark/space parity (CMSPAR) is a non-POSIX extension
+		#[cfg(target_os = "linux")]
+		let (cmspar, flags) = (libc::CMSPAR, match parity {
+			Parity::None => 0,
+			Parity::Odd => libc::PARENB | libc::PARODD,
+			Parity::Even => libc::PARENB,
+			Parity::Mark => libc::PARENB | libc::PARODD | libc::CMSPAR,
+			Parity::Space => libc::PARENB | libc::CMSPAR
+		});
+		#[cfg(not(target_os = "linux"))]
+		let (cmspar, flags) = (0, match parity {
+			Parity::None => 0,
+			Parity::Odd => libc::PARENB | libc::PARODD,
+			Parity::Even => libc::PARENB,
+			Parity::Mark | Parity::Space => return Err(io::Error::new(
+				io::ErrorKind::InvalidInput, "mark/space parity not supported"))
+		});
+
+		self.update_termios(|termios| {
+			termios.c_cflag &= !(libc::PARENB | libc::PARODD | cmspar);
+			termios.c_cflag |= flags;
+			Ok(())
+		})
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		match self.get_termios()?.c_cflag & libc::CSTOPB {
+			0 => Ok(StopBits::One),
+			_ => Ok(StopBits::Two)
+		}
+	}
+
+	pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.update_termios(|termios| {
+			match stop_bits {
+				StopBits::One => termios.c_cflag &= !libc::CSTOPB,
+				StopBits::Two => termios.c_cflag |= libc::CSTOPB
+			}
+			Ok(())
+		})
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		let termios = self.get_termios()?;
+		if termios.c_cflag & libc::CRTSCTS != 0 {
+			Ok(FlowControl::Hardware)
+		} else if termios.c_iflag & (libc::IXON | libc::IXOFF) != 0 {
+			Ok(FlowControl::Software)
+		} else {
+			Ok(FlowControl::None)
+		}
+	}
+
+	pub fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.update_termios(|termios| {
+			termios.c_cflag &= !libc::CRTSCTS;
+			termios.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY);
+			match flow_control {
+				FlowControl::None => (),
+				FlowControl::Software => termios.c_iflag |= libc::IXON | libc::IXOFF,
+				FlowControl::Hardware => termios.c_cflag |= libc::CRTSCTS
+			}
+			Ok(())
+		})
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		Ok(self.get_termios()?.c_cflag & libc::CLOCAL != 0)
+	}
+
+	// CLOCAL ignores the modem control lines. without it, the TTY is hung
+	// up when DCD drops, i.e., reads return end of file.
+	pub fn set_local(&self, local: bool) -> io::Result<()> {
+		self.update_termios(|termios| {
+			if local {
+				termios.c_cflag |= libc::CLOCAL;
+			} else {
+				termios.c_cflag &= !libc::CLOCAL;
+			}
+			Ok(())
+		})
+	}
+
+	pub fn parity_marking(&self) -> io::Result<bool> {
+		let mask = libc::INPCK | libc::PARMRK;
+		Ok(self.get_termios()?.c_iflag & mask == mask)
+	}
+
+	// with INPCK and PARMRK, a byte received with a parity or framing error
+	// is read as 0xff 0x00 byte and a valid 0xff as 0xff 0xff. a break is
+	// read as 0xff 0x00 0x00. IGNPAR and ISTRIP would defeat the marking.
+	pub fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		self.update_termios(|termios| {
+			termios.c_iflag &= !(libc::INPCK | libc::PARMRK | libc::IGNPAR | libc::ISTRIP);
+			if mark {
+				termios.c_iflag |= libc::INPCK | libc::PARMRK;
+			}
+			Ok(())
+		})
+	}
+
+	// in canonical mode, the kernel assembles lines and read() returns at
+	// most one line. when it is disabled, VMIN and VTIME (which may share
+	// their slots with VEOF and VEOL) are reset to zero for polling reads.
+	pub fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		// character disabling a special character, e.g., 0 on Linux and
+		// 0xff on macOS
+		let disabled = match unsafe { libc::fpathconf(self.fd, libc::_PC_VDISABLE) } {
+			-1 => 0,
+			vdisable => vdisable as libc::cc_t
+		};
+		self.update_termios(|termios| {
+			match mode {
+				None => {
+					termios.c_lflag &= !libc::ICANON;
+					termios.c_cc[libc::VMIN] = 0;
+					termios.c_cc[libc::VTIME] = 0;
+				},
+				Some(mode) => {
+					termios.c_lflag |= libc::ICANON;
+					termios.c_cc[libc::VEOL] = mode.eol.unwrap_or(disabled);
+					termios.c_cc[libc::VEOL2] = mode.eol2.unwrap_or(disabled);
+					let (erase, kill, eof) = if mode.editing {
+						(0x7f, 0x15, 0x04)
+					} else {
+						(disabled, disabled, disabled)
+					};
+					termios.c_cc[libc::VERASE] = erase;
+					termios.c_cc[libc::VKILL] = kill;
+					termios.c_cc[libc::VEOF] = eof;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	pub fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		let termios = self.get_termios()?;
+		Ok(TerminalOptions {
+			echo: termios.c_lflag & libc::ECHO != 0,
+			echo_erase: termios.c_lflag & libc::ECHOE != 0,
+			nl_to_crnl: termios.c_oflag & libc::OPOST != 0 && termios.c_oflag & libc::ONLCR != 0
+		})
+	}
+
+	// output processing (OPOST) is only enabled as required by ONLCR, so
+	// all other output is transmitted unaltered
+	pub fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		self.update_termios(|termios| {
+			termios.c_lflag &= !(libc::ECHO | libc::ECHOE);
+			termios.c_oflag &= !(libc::OPOST | libc::ONLCR);
+			if options.echo {
+				termios.c_lflag |= libc::ECHO;
+			}
+			if options.echo_erase {
+				termios.c_lflag |= libc::ECHOE;
+			}
+			if options.nl_to_crnl {
+				termios.c_oflag |= libc::OPOST | libc::ONLCR;
+			}
+			Ok(())
+		})
+	}
+
+	// https://man7.org/linux/man-pages/man2/ioctl_tty.2.html
+	pub fn set_break(&self, level: bool) -> io::Result<()> {
+		let request = if level { libc::TIOCSBRK } else { libc::TIOCCBRK };
+		match unsafe { libc::ioctl(self.fd, request) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// https://man7.org/linux/man-pages/man3/tcflow.3.html
+	pub fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		let action = if paused { libc::TCOOFF } else { libc::TCOON };
+		match unsafe { libc::tcflow(self.fd, action) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// the TTY layer cannot reorder its output queue, so the byte is queued
+	// like any other data
+	pub fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		match self.write(&[byte])? {
+			0 => Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write byte")),
+			_ => Ok(())
+		}
+	}
+
+	// https://man7.org/linux/man-pages/man2/ioctl_tty.2.html
+	pub fn bytes_to_read(&self) -> io::Result<usize> {
+		self.queued(false)
+	}
+
+	pub fn bytes_to_write(&self) -> io::Result<usize> {
+		self.queued(true)
+	}
+
+	fn queued(&self, output: bool) -> io::Result<usize> {
+		let request = if output { libc::TIOCOUTQ } else { libc::FIONREAD };
+		let mut queued: c_int = 0;
+		match unsafe { libc::ioctl(self.fd, request, &mut queued) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(queued as usize)
+		}
+	}
+
+	pub fn lock(&self) -> io::Result<()> {
+		// get exclusive TTY access. on macOS and the BSDs, root may still
+		// open the TTY, similar to CAP_SYS_ADMIN on Linux (see below).
+		// http://man7.org/linux/man-pages/man4/tty_ioctl.4.html
+		if unsafe { libc::ioctl(self.fd, libc::TIOCEXCL) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		// requesting exclusive TTY access via TIOCEXCL above is insufficient to
+		// avoid simultaneous access by users with CAP_SYS_ADMIN, which allows
+		// to bypass TIOCEXCL. therefore, use flock() to place an additional
+		// exclusive advisory lock on the TTY device.
+		// https://stackoverflow.com/questions/49636520/how-do-you-check-if-a-serial-port-is-open-in-linux/49687230#49687230
+		// https://stackoverflow.com/questions/30316722/what-is-the-best-practice-for-locking-serial-ports-and-other-devices-in-linux/34937038#34937038
+		// https://man7.org/linux/man-pages/man2/flock.2.html
+		if unsafe { libc::flock(self.fd, libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+			let error = io::Error::last_os_error();
+			// another process holds the lock, which is reported like a TTY
+			// locked via TIOCEXCL, so is_busy() covers both
+			if error.raw_os_error() == Some(libc::EWOULDBLOCK) {
+				return Err(io::Error::from_raw_os_error(libc::EBUSY));
+			}
+			return Err(error);
+		}
+		Ok(())
+	}
+
+	// release the flock() first, so the TTY is never unlocked while another
+	// process still cannot acquire the lock
+	pub fn unlock(&self) -> io::Result<()> {
+		if unsafe { libc::flock(self.fd, libc::LOCK_UN) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		match unsafe { libc::ioctl(self.fd, libc::TIOCNXCL) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// discard data received but not read and/or written but not transmitted
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		let queue = match (input, output) {
+			(false, false) => return Ok(()),
+			(true, false) => libc::TCIFLUSH,
+			(false, true) => libc::TCOFLUSH,
+			(true, true) => libc::TCIOFLUSH
+		};
+		match unsafe { libc::tcflush(self.fd, queue) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// https://man7.org/linux/man-pages/man2/TIOCSETD.2const.html
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn line_discipline(&self) -> io::Result<c_int> {
+		let mut ldisc: c_int = 0;
+		match unsafe { libc::ioctl(self.fd, libc::TIOCGETD, &mut ldisc) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(ldisc)
+		}
+	}
+
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn set_line_discipline(&self, ldisc: c_int) -> io::Result<()> {
+		match unsafe { libc::ioctl(self.fd, libc::TIOCSETD, &ldisc) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// https://docs.kernel.org/driver-api/tty/n_gsm.html
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn gsm_config(&self) -> io::Result<GsmConfig> {
+		let mut config: GsmConfig = unsafe { mem::zeroed() };
+		let request = libc::_IOR::<GsmConfig>(b'G' as u32, 0);
+		match unsafe { libc::ioctl(self.fd, request, &mut config) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(config)
+		}
+	}
+
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn set_gsm_config(&self, config: &GsmConfig) -> io::Result<()> {
+		let request = libc::_IOW::<GsmConfig>(b'G' as u32, 1);
+		match unsafe { libc::ioctl(self.fd, request, config) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// minor number of the first virtual TTY of the multiplexer, i.e., of
+	// channel 1. kernels before 5.17 lack GSMIOC_GETFIRST and support
+	// only a single multiplexer.
+	#[cfg(any(target_os = "linux", target_os = "android"))]
+	pub fn gsm_first_minor(&self) -> io::Result<u32> {
+		let mut first: u32 = 0;
+		let request = libc::_IOR::<u32>(b'G' as u32, 4);
+		match unsafe { libc::ioctl(self.fd, request, &mut first) } {
+			-1 => match io::Error::last_os_error() {
+				ref e if e.raw_os_error() == Some(libc::ENOTTY) => Ok(1),
+				e => Err(e)
+			},
+			_ => Ok(first)
 		}
 	}
 }
@@ -249,3 +1213,135 @@ impl Drop for SerialPort {
 		debug_assert_eq!(_res, 0);
 	}
 }
+
+// readiness notification for reactor::Reactor. ports are registered one-shot,
+// so a ready port is reported to a single thread and must be rearmed after
+// it was serviced.
+// https://man7.org/linux/man-pages/man7/epoll.7.html
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct Poller {
+	epoll: c_int,
+	// eventfd signaled by shutdown(), registered level-triggered, so it
+	// releases all waiting threads
+	shutdown: c_int
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SHUTDOWN_TOKEN: u64 = u64::MAX;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Poller {
+	pub fn new() -> io::Result<Self> {
+		let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+		if epoll == -1 {
+			return Err(io::Error::last_os_error());
+		}
+		let shutdown = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+		if shutdown == -1 {
+			let error = io::Error::last_os_error();
+			unsafe { libc::close(epoll) };
+			return Err(error);
+		}
+
+		// wrap fds immediately, so they are closed by drop() on error
+		let poller = Self { epoll, shutdown };
+		poller.control(libc::EPOLL_CTL_ADD, shutdown, libc::EPOLLIN as u32, SHUTDOWN_TOKEN)?;
+		Ok(poller)
+	}
+
+	fn control(&self, op: c_int, fd: c_int, events: u32, data: u64) -> io::Result<()> {
+		let mut event = libc::epoll_event { events, u64: data };
+		match unsafe { libc::epoll_ctl(self.epoll, op, fd, &mut event) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	pub fn add(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		self.control(libc::EPOLL_CTL_ADD, port.fd,
+			(libc::EPOLLIN | libc::EPOLLONESHOT) as u32, token as u64)
+	}
+
+	pub fn rearm(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		self.control(libc::EPOLL_CTL_MOD, port.fd,
+			(libc::EPOLLIN | libc::EPOLLONESHOT) as u32, token as u64)
+	}
+
+	pub fn remove(&self, port: &SerialPort, _token: usize) -> io::Result<()> {
+		self.control(libc::EPOLL_CTL_DEL, port.fd, 0, 0)
+	}
+
+	// block until a port is ready and return its token, or None after
+	// shutdown(). errors and hang ups count as ready.
+	pub fn wait(&self) -> io::Result<Option<usize>> {
+		let mut event = libc::epoll_event { events: 0, u64: 0 };
+		loop {
+			match unsafe { libc::epoll_wait(self.epoll, &mut event, 1, -1) } {
+				-1 => {
+					let error = io::Error::last_os_error();
+					if error.kind() != io::ErrorKind::Interrupted {
+						return Err(error);
+					}
+				},
+				0 => (),
+				_ if event.u64 == SHUTDOWN_TOKEN => return Ok(None),
+				_ => return Ok(Some(event.u64 as usize))
+			}
+		}
+	}
+
+	// release all current and future calls to wait()
+	pub fn shutdown(&self) -> io::Result<()> {
+		let value: u64 = 1;
+		let len = unsafe {
+			libc::write(self.shutdown, &value as *const u64 as *const c_void, mem::size_of::<u64>())
+		};
+		match len {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for Poller {
+	fn drop(&mut self) {
+		unsafe {
+			libc::close(self.shutdown);
+			libc::close(self.epoll);
+		}
+	}
+}
+
+// epoll is Linux-specific, so the reactor is unsupported on other Unix
+// platforms
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub struct Poller(std::convert::Infallible);
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Poller {
+	pub fn new() -> io::Result<Self> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"the reactor is not supported on this platform"))
+	}
+
+	pub fn add(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn rearm(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn remove(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn wait(&self) -> io::Result<Option<usize>> {
+		match self.0 {}
+	}
+
+	pub fn shutdown(&self) -> io::Result<()> {
+		match self.0 {}
+	}
+}
@@ -0,0 +1,149 @@
+#[cfg(target_os = "linux")]
+extern crate udev;
+
+use std::ffi::OsStr;
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+use std::io;
+
+#[cfg(target_os = "linux")]
+use crate::UsbInfo;
+use crate::{DeviceInfo, DeviceKind, PortStatus};
+
+// device enumeration of the Unix backend (feature enumerate). Linux queries
+// udev, whereas other platforms scan /dev for common device names.
+
+// probing failures (e.g., devices without a node) do not fail enumeration
+fn status(path: &OsStr) -> PortStatus {
+	super::SerialPort::probe(path).unwrap_or(PortStatus::Present)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub type Devices = std::vec::IntoIter<DeviceInfo>;
+
+// node names of callout devices on macOS and of USB and native UARTs on the
+// BSDs, which are recognized without IOKit or devd support
+#[cfg(not(target_os = "linux"))]
+const DEVICE_PREFIXES: [&str; 4] = ["cu.", "ttyU", "dtyU", "ttyS"];
+
+#[cfg(not(target_os = "linux"))]
+fn classify(name: &str) -> DeviceKind {
+	let lowercase = name.to_ascii_lowercase();
+	if name.starts_with("ttyU") || name.starts_with("dtyU") || lowercase.contains("usb") {
+		DeviceKind::Usb
+	} else if lowercase.contains("bluetooth") {
+		DeviceKind::Bluetooth
+	} else {
+		DeviceKind::Unknown
+	}
+}
+
+#[cfg(target_os = "linux")]
+pub struct Devices(std::vec::IntoIter<udev::Device>);
+
+#[cfg(target_os = "linux")]
+impl Iterator for Devices {
+	type Item = DeviceInfo;
+
+	fn next(&mut self) -> Option<DeviceInfo> {
+		self.0.by_ref().find_map(|device| device_info(&device))
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn device_info(device: &udev::Device) -> Option<DeviceInfo> {
+	// skip this device if it doesn't have a device name (e.g. /dev/ttyACM0)
+	let devname = device.property_value("DEVNAME")?;
+
+	// classify by bus or, for Bluetooth RFCOMM and virtual null-modem
+	// drivers (e.g., tty0tty), by device name
+	let name = devname.to_string_lossy();
+	let kind = if device.property_value("ID_BUS").map_or(false, |bus| bus == "usb") {
+		DeviceKind::Usb
+	} else if name.starts_with("/dev/rfcomm") {
+		DeviceKind::Bluetooth
+	} else if name.starts_with("/dev/tnt") {
+		DeviceKind::Virtual
+	} else if device.parent().map_or(false, |parent| matches!(
+			parent.subsystem().and_then(|s| s.to_str()),
+			Some("platform") | Some("pnp") | Some("amba") | Some("serial-base"))) {
+		DeviceKind::Native
+	} else {
+		DeviceKind::Unknown
+	};
+
+	Some(DeviceInfo {
+		path: devname.to_os_string(),
+		status: status(devname),
+		kind,
+		usb: usb_info(device),
+		// drivers bind to the parent of the TTY device, e.g., the USB
+		// interface (cdc_acm) or the port of a USB serial converter (ftdi_sio)
+		driver: device.parent()
+			.and_then(|parent| parent.driver().map(|driver| driver.to_string_lossy().into_owned())),
+		// symlinks created by udev, separated by spaces
+		id: device.property_value("DEVLINKS")
+			.and_then(|links| links.to_str())
+			.and_then(|links| links.split(' ').find(|link| link.starts_with("/dev/serial/by-id/")))
+			.map(OsString::from)
+	})
+}
+
+// IDs of USB devices as imported by udev's usb_id builtin
+#[cfg(target_os = "linux")]
+fn usb_info(device: &udev::Device) -> Option<UsbInfo> {
+	if device.property_value("ID_BUS")? != "usb" {
+		return None;
+	}
+	let id = |property| device.property_value(property)
+		.and_then(|id| id.to_str())
+		.and_then(|id| u16::from_str_radix(id, 16).ok());
+	Some(UsbInfo {
+		vendor_id: id("ID_VENDOR_ID")?,
+		product_id: id("ID_MODEL_ID")?,
+		serial_number: device.property_value("ID_SERIAL_SHORT")
+			.map(|serial_number| serial_number.to_string_lossy().into_owned()),
+		// the kernel names USB devices by bus and port numbers
+		port_path: device.parent_with_subsystem_devtype("usb", "usb_device").ok().flatten()
+			.map(|usb_device| usb_device.sysname().to_string_lossy().into_owned())
+	})
+}
+
+// scan /dev for common serial device names, skipping the FreeBSD
+// initial-state and lock-state nodes (e.g., ttyU0.init)
+#[cfg(not(target_os = "linux"))]
+pub fn devices() -> io::Result<Devices> {
+	let mut devices = Vec::new();
+	for entry in std::fs::read_dir("/dev")? {
+		let entry = entry?;
+		let name = entry.file_name();
+		let kind = match name.to_str() {
+			Some(name) if DEVICE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+				&& !name.ends_with(".init") && !name.ends_with(".lock") => classify(name),
+			_ => continue
+		};
+		let path = entry.path().into_os_string();
+		devices.push(DeviceInfo {
+			status: status(&path),
+			path,
+			kind,
+			usb: None,
+			driver: None,
+			id: None
+		});
+	}
+
+	devices.sort_by(|a, b| a.path.cmp(&b.path));
+	Ok(devices.into_iter())
+}
+
+// scanning is done up front, but each device is only classified when
+// the iterator reaches it
+#[cfg(target_os = "linux")]
+pub fn devices() -> io::Result<Devices> {
+	// iterate over all TTY devices
+	let mut enumerator = udev::Enumerator::new()?;
+	enumerator.match_subsystem("tty")?;
+	let devices: Vec<udev::Device> = enumerator.scan_devices()?.collect();
+	Ok(Devices(devices.into_iter()))
+}
@@ -0,0 +1,267 @@
+// placeholder for targets without OS serial ports (e.g., wasm32 in a web
+// browser). opening always fails, so the other methods are unreachable.
+
+use std::ffi::OsStr;
+use std::io;
+use std::time::Duration;
+
+use crate::{DataBits, FlowControl, LineMode, Parity, PortStatus, StopBits, TerminalOptions};
+
+#[cfg(feature = "enumerate")]
+pub type Devices = std::iter::Empty<crate::DeviceInfo>;
+
+enum Void {}
+
+pub fn is_busy(_error: &io::Error) -> bool {
+	false
+}
+
+pub struct SerialPort(Void);
+
+pub type SavedSettings = std::convert::Infallible;
+
+impl SerialPort {
+	pub fn open<T>(_dev_path: &T, _timeout: Option<Duration>) -> io::Result<Self>
+			where T: AsRef<OsStr> + ?Sized {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"OS serial ports are not supported on this platform"))
+	}
+
+	pub fn open_saving<T>(dev_path: &T, timeout: Option<Duration>)
+			-> io::Result<(Self, Option<SavedSettings>)>
+			where T: AsRef<OsStr> + ?Sized {
+		Self::open(dev_path, timeout).map(|port| (port, None))
+	}
+
+	pub fn restore_settings(&self, _settings: &SavedSettings) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn probe<T>(_dev_path: &T) -> io::Result<PortStatus>
+			where T: AsRef<OsStr> + ?Sized {
+		Ok(PortStatus::Missing)
+	}
+
+	#[cfg(feature = "enumerate")]
+	pub fn devices() -> io::Result<Devices> {
+		Ok(std::iter::empty())
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		match self.0 {}
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		match self.0 {}
+	}
+
+	pub fn read(&self, _buf: &mut [u8]) -> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn write(&self, _buf: &[u8]) -> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn read_with_timeout(&self, _buf: &mut [u8], _timeout: Option<Duration>)
+			-> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn write_with_timeout(&self, _buf: &[u8], _timeout: Option<Duration>)
+			-> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		match self.0 {}
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		match self.0 {}
+	}
+
+	pub fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_rts(&self, _level: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_dtr(&self, _level: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn wait_modem_change(&self, _timeout: Option<Duration>) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		match self.0 {}
+	}
+
+	pub fn set_baud_rate(&self, _baud_rate: u32) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		match self.0 {}
+	}
+
+	pub fn set_data_bits(&self, _data_bits: DataBits) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		match self.0 {}
+	}
+
+	pub fn set_parity(&self, _parity: Parity) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		match self.0 {}
+	}
+
+	pub fn set_stop_bits(&self, _stop_bits: StopBits) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		match self.0 {}
+	}
+
+	pub fn set_flow_control(&self, _flow_control: FlowControl) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn set_local(&self, _local: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn wait_any(ports: &[&Self], _timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+		match ports.first() {
+			Some(port) => match port.0 {},
+			None => Ok(Vec::new())
+		}
+	}
+
+	pub fn parity_marking(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn set_parity_marking(&self, _mark: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_canonical(&self, _mode: Option<LineMode>) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		match self.0 {}
+	}
+
+	pub fn set_terminal_options(&self, _options: TerminalOptions) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_break(&self, _level: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn set_transmit_paused(&self, _paused: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn transmit_immediate(&self, _byte: u8) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn bytes_to_read(&self) -> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn bytes_to_write(&self) -> io::Result<usize> {
+		match self.0 {}
+	}
+
+	pub fn inheritable(&self) -> io::Result<bool> {
+		match self.0 {}
+	}
+
+	pub fn set_inheritable(&self, _inheritable: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn lock(&self) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn unlock(&self) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn purge(&self, _input: bool, _output: bool) -> io::Result<()> {
+		match self.0 {}
+	}
+}
+
+pub struct Poller(Void);
+
+impl Poller {
+	pub fn new() -> io::Result<Self> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"the reactor is not supported on this platform"))
+	}
+
+	pub fn add(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn rearm(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn remove(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn wait(&self) -> io::Result<Option<usize>> {
+		match self.0 {}
+	}
+
+	pub fn shutdown(&self) -> io::Result<()> {
+		match self.0 {}
+	}
+}
@@ -1,10 +1,13 @@
 extern crate windows_sys;
 
-use std::ffi::{c_void, OsStr, OsString};
+use std::ffi::{c_void, OsStr};
 use std::io;
 use std::mem;
 use std::os::windows::ffi::OsStrExt;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::ptr;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
 use windows_sys::Win32::{
@@ -16,11 +19,197 @@ use windows_sys::Win32::{
 	System::WindowsProgramming::*
 };
 
+use super::{windows_dcb, windows_devices};
+#[cfg(feature = "enumerate")]
+pub use super::windows_devices::Devices;
+use crate::{CommTimeouts, DataBits, DeviceKind, FlowControl, LineMode, Parity, PortStatus,
+	StopBits, TerminalOptions};
+use crate::backend::AtomicTimeout;
+
 const MAXDWORD: u32 = u32::MAX;
+const MAXIMUM_WAIT_OBJECTS: usize = 64;
+
+// largest buffer passed to a single ReadFile() or WriteFile(), which take
+// the length as DWORD. serial drivers usually copy the buffer to kernel
+// memory, so even 4 GiB would be excessive.
+const MAX_TRANSFER: usize = 1 << 20;
+
+// convert Duration to COMMTIMEOUTS milliseconds clipped to valid range
+// from 1 to MAXDWORD - 1. COMMTIMEOUTS have millisecond resolution, so
+// round up to never time out early. unlike the experimental backend, which
+// waits with high resolution timers, this backend therefore waits at least
+// 1 ms for nonzero timeouts below a millisecond.
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts#remarks
+fn timeout_ms(dur: Duration) -> u32 {
+	let dur_ms = dur.as_secs() * 1000
+	           + (dur.subsec_nanos() as u64 + 999_999) / 1_000_000;
+
+	if dur_ms < 1 {
+		1
+	} else if dur_ms >= MAXDWORD as u64 {
+		MAXDWORD - 1
+	} else {
+		dur_ms as u32
+	}
+}
+
+// populate COMMTIMEOUTS from Option<Duration>
+// https://docs.microsoft.com/en-us/windows/win32/devio/time-outs
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts
+fn comm_timeouts(timeout_read: Option<Duration>, timeout_write: Option<Duration>)
+		-> CommTimeouts {
+	// all zero values configure blocking write without timeout
+	let mut timeouts = CommTimeouts::default();
+
+	match timeout_read {
+		// all zero values would block until the read buffer is full, so
+		// use the longest timeout that still returns as soon as bytes are
+		// available and let read() retry after it elapsed
+		None => {
+			timeouts.read_interval = MAXDWORD;
+			timeouts.read_total_multiplier = MAXDWORD;
+			timeouts.read_total_constant = MAXDWORD - 1;
+		},
+		// zero timeout returns immediately, even if no bytes are available
+		Some(dur) if dur.is_zero() => timeouts.read_interval = MAXDWORD,
+		Some(dur) => {
+			// return immediately if bytes are available (like POSIX would)
+			// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts#remarks
+			timeouts.read_interval = MAXDWORD;
+			timeouts.read_total_multiplier = MAXDWORD;
+			timeouts.read_total_constant = timeout_ms(dur);
+		}
+	}
+
+	// a zero write timeout cannot be expressed (all zero values disable
+	// the timeout), so write_overlapped() cancels pending writes instead
+	if let Some(dur) = timeout_write.filter(|dur| !dur.is_zero()) {
+		// MAXDWORD is *not* a reserved WriteTotalTimeoutMultiplier
+		// value, i.e., setting it incurs a very long write timeout
+		timeouts.write_total_constant = timeout_ms(dur);
+	}
+
+	timeouts
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-setcommtimeouts
+fn set_comm_timeouts(comdev: HANDLE, timeouts: CommTimeouts) -> io::Result<()> {
+	let timeouts = COMMTIMEOUTS {
+		ReadIntervalTimeout: timeouts.read_interval,
+		ReadTotalTimeoutMultiplier: timeouts.read_total_multiplier,
+		ReadTotalTimeoutConstant: timeouts.read_total_constant,
+		WriteTotalTimeoutMultiplier: timeouts.write_total_multiplier,
+		WriteTotalTimeoutConstant: timeouts.write_total_constant
+	};
+	if unsafe { SetCommTimeouts(comdev, &timeouts) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+// setting the low-order bit of the event handle prevents completions from
+// being posted to an I/O completion port associated with the device, see
+// Poller. the bit is ignored when waiting for the event.
+// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getqueuedcompletionstatus#remarks
+fn unqueued(event: HANDLE) -> HANDLE {
+	event | 1
+}
+
+// event objects for overlapped I/O, shared by a port and its clones. each
+// operation takes its own event, so concurrent operations (e.g., a read and
+// a write on different threads) cannot complete each other's waits. events
+// are returned for reuse afterwards instead of being created per clone.
+struct EventPool(Mutex<Vec<HANDLE>>);
+
+unsafe impl Send for EventPool {}
+unsafe impl Sync for EventPool {}
+
+impl EventPool {
+	fn new() -> Self {
+		Self(Mutex::new(Vec::new()))
+	}
+
+	fn acquire(&self) -> io::Result<PooledEvent<'_>> {
+		let event = self.0.lock().unwrap_or_else(|e| e.into_inner()).pop();
+		let event = match event {
+			Some(event) => event,
+			// manual-reset events are recommended for overlapped I/O. they
+			// are reset when an operation starts, so they can be reused.
+			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
+			None => match unsafe { CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut()) } {
+				0 => return Err(io::Error::last_os_error()),
+				event => event
+			}
+		};
+		Ok(PooledEvent { pool: self, event })
+	}
+}
+
+impl Drop for EventPool {
+	fn drop(&mut self) {
+		for &event in self.0.get_mut().unwrap_or_else(|e| e.into_inner()).iter() {
+			let _res = unsafe { CloseHandle(event) };
+			debug_assert_ne!(_res, 0);
+		}
+	}
+}
+
+// returns the event to its pool when dropped, i.e., once the operation
+// using it completed
+struct PooledEvent<'a> {
+	pool: &'a EventPool,
+	event: HANDLE
+}
+
+impl Drop for PooledEvent<'_> {
+	fn drop(&mut self) {
+		self.pool.0.lock().unwrap_or_else(|e| e.into_inner()).push(self.event);
+	}
+}
 
 pub struct SerialPort {
 	comdev: HANDLE,
-	event: HANDLE
+	events: Arc<EventPool>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout,
+	// COMMTIMEOUTS set directly, which take precedence over the timeouts
+	raw_timeouts: Mutex<Option<CommTimeouts>>,
+	// COMMTIMEOUTS apply to the device, i.e., to all clones, so they are
+	// tracked across clones and reapplied when a clone with different
+	// timeouts starts reading or writing
+	applied: Arc<Mutex<CommTimeouts>>,
+	// the event mask applies to the device as well and changing it completes
+	// a pending WaitCommEvent(), so only one user at a time may wait for
+	// events across clones
+	mask_owner: Arc<Mutex<Option<MaskOwner>>>
+}
+
+// user of the event mask of a device, see SerialPort::claim_event_mask()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MaskOwner {
+	ModemChange,
+	WaitAny,
+	Poller
+}
+
+impl MaskOwner {
+	fn name(self) -> &'static str {
+		match self {
+			Self::ModemChange => "wait_modem_change()",
+			Self::WaitAny => "wait_any()",
+			Self::Poller => "a reactor"
+		}
+	}
+}
+
+// releases the event mask claimed for the duration of an operation
+struct MaskClaim<'a>(&'a SerialPort);
+
+impl Drop for MaskClaim<'_> {
+	fn drop(&mut self) {
+		self.0.release_event_mask();
+	}
 }
 
 // HANDLE is type *mut c_void which does not implement Send and Sync, so
@@ -28,9 +217,19 @@ pub struct SerialPort {
 unsafe impl Send for SerialPort {}
 unsafe impl Sync for SerialPort {}
 
+// settings of a port before it was configured by open_saving()
+pub type SavedSettings = DCB;
+
 impl SerialPort {
 	pub fn open<T>(port_name: &T, timeout: Option<Duration>) -> io::Result<Self>
 			where T: AsRef<OsStr> + ?Sized {
+		Self::open_saving(port_name, timeout).map(|(port, _)| port)
+	}
+
+	// like open(), but also return the settings the port had before
+	pub fn open_saving<T>(port_name: &T, timeout: Option<Duration>)
+			-> io::Result<(Self, Option<SavedSettings>)>
+			where T: AsRef<OsStr> + ?Sized {
 		// construct prefixed COM port name to support COMn with n > 9
 		let mut name = Vec::<u16>::new();
 		name.extend(OsStr::new("\\\\.\\").encode_wide());
@@ -47,19 +246,15 @@ impl SerialPort {
 			return Err(io::Error::last_os_error());
 		}
 
-		// create unnamed event object for asynchronous I/O
-		let event = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
-			CreateEventW(ptr::null_mut(), FALSE, FALSE, ptr::null_mut())
-		};
-		if event == 0 {
-			let error = io::Error::last_os_error();
-
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
+		let original = match windows_dcb::check_comm_device(comdev) {
+			Ok(dcb) => Some(dcb),
+			Err(error) => {
+				let _res = unsafe { CloseHandle(comdev) };
+				debug_assert_ne!(_res, 0);
 
-			return Err(error);
-		}
+				return Err(error);
+			}
+		};
 
 		// configure COM port for raw communication
 		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-dcb
@@ -71,83 +266,52 @@ impl SerialPort {
 		dcb.ByteSize = 8;
 		dcb.StopBits = ONESTOPBIT;
 		dcb.Parity = NOPARITY;
-		if unsafe { SetCommState(comdev, &mut dcb) } == 0 {
+		// virtual ports (e.g., com0com) may reject settings that are
+		// meaningless to them, so they are used as is
+		if unsafe { SetCommState(comdev, &mut dcb) } == 0
+				&& windows_devices::device_kind(port_name.as_ref()) != DeviceKind::Virtual {
 			// close open handles and return original error on failure
 			let error = io::Error::last_os_error();
 
 			let _res = unsafe { CloseHandle(comdev) };
 			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event) };
-			debug_assert_ne!(_res, 0);
 
 			return Err(error);
 		}
 
-		// populate COMMTIMEOUTS struct from Option<Duration>
-		// https://docs.microsoft.com/en-us/windows/win32/devio/time-outs
-		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts
-		let mut timeouts = if let Some(dur) = timeout {
-			let mut dur_ms = dur.as_secs() * 1000
-			               + dur.subsec_millis() as u64;
-
-			// clip dur_ms to valid range from 1 to MAXDWORD - 1
-			// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts#remarks
-			if dur_ms < 1 {
-				dur_ms = 1;
-			} else if dur_ms >= MAXDWORD as u64 {
-				dur_ms = (MAXDWORD - 1) as u64;
-			}
-
-			COMMTIMEOUTS {
-				// return immediately if bytes are available (like POSIX would)
-				// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts#remarks
-				ReadIntervalTimeout: MAXDWORD,
-				ReadTotalTimeoutMultiplier: MAXDWORD,
-				ReadTotalTimeoutConstant: dur_ms as u32,
-				// MAXDWORD is *not* a reserved WriteTotalTimeoutMultiplier
-				// value, i.e., setting it incurs a very long write timeout
-				WriteTotalTimeoutMultiplier: 0,
-				WriteTotalTimeoutConstant: dur_ms as u32,
-			}
-		} else {
-			// blocking read/write without timeout
-			// FIXME: read() blocks until the read buffer is full
-			COMMTIMEOUTS {
-				ReadIntervalTimeout: 0,
-				ReadTotalTimeoutMultiplier: 0,
-				ReadTotalTimeoutConstant: 0,
-				WriteTotalTimeoutMultiplier: 0,
-				WriteTotalTimeoutConstant: 0,
-			}
-		};
-
 		// set timeouts
-		if unsafe { SetCommTimeouts(comdev, &mut timeouts) } == 0 {
+		let timeouts = comm_timeouts(timeout, timeout);
+		if let Err(error) = set_comm_timeouts(comdev, timeouts) {
 			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-
 			let _res = unsafe { CloseHandle(comdev) };
 			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event) };
-			debug_assert_ne!(_res, 0);
 
 			return Err(error);
 		}
 
-		Ok(Self { comdev, event })
+		Ok((Self {
+			comdev,
+			events: Arc::new(EventPool::new()),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout),
+			raw_timeouts: Mutex::new(None),
+			applied: Arc::new(Mutex::new(timeouts)),
+			mask_owner: Arc::new(Mutex::new(None))
+		}, original))
 	}
 
 	pub fn try_clone(&self) -> io::Result<Self> {
-		// create new unnamed event object for asynchronous I/O
-		let event = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
-			CreateEventW(ptr::null_mut(), FALSE, FALSE, ptr::null_mut())
-		};
-		if event == 0 {
-			return Err(io::Error::last_os_error());
-		}
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
 
-		// duplicate communications device handle
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
+		// duplicate communications device handle, whereas the events are
+		// shared
 		let mut comdev = INVALID_HANDLE_VALUE;
 		let process = unsafe { GetCurrentProcess() };
 		let res = unsafe {
@@ -157,49 +321,93 @@ impl SerialPort {
 		};
 
 		if res == 0 {
-			let error = io::Error::last_os_error();
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(Self {
+				comdev,
+				events: self.events.clone(),
+				timeout_read,
+				timeout_write,
+				raw_timeouts: Mutex::new(self.raw_timeouts()),
+				applied: self.applied.clone(),
+				mask_owner: self.mask_owner.clone()
+			})
+		}
+	}
 
-			let _res = unsafe { CloseHandle(event) };
-			debug_assert_ne!(_res, 0);
+	pub fn probe<T>(port_name: &T) -> io::Result<PortStatus>
+			where T: AsRef<OsStr> + ?Sized {
+		windows_devices::probe(port_name.as_ref())
+	}
 
-			Err(error)
-		} else {
-			Ok(Self { comdev, event })
+	#[cfg(feature = "enumerate")]
+	pub fn devices() -> io::Result<Devices> {
+		windows_devices::devices()
+	}
+
+	fn raw_timeouts(&self) -> Option<CommTimeouts> {
+		*self.raw_timeouts.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// this instance's COMMTIMEOUTS
+	fn timeouts(&self) -> CommTimeouts {
+		self.raw_timeouts().unwrap_or_else(||
+			comm_timeouts(self.timeout_read.load(), self.timeout_write.load()))
+	}
+
+	// apply the given COMMTIMEOUTS unless they are already applied. the
+	// returned guard must be held until the read or write is queued, because
+	// the request uses the COMMTIMEOUTS applied when it starts.
+	fn lock_timeouts(&self, timeouts: CommTimeouts) -> io::Result<MutexGuard<'_, CommTimeouts>> {
+		let mut applied = self.applied.lock().unwrap_or_else(|e| e.into_inner());
+		if *applied != timeouts {
+			set_comm_timeouts(self.comdev, timeouts)?;
+			*applied = timeouts;
 		}
+		Ok(applied)
 	}
 
-	pub fn list_devices() -> Vec<OsString> {
-		let mut devices = Vec::new();
-		let mut path_wide = [0u16; 1024];
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		let indefinite = self.timeout_read.load().is_none() && self.raw_timeouts().is_none();
+		self.read_with(buf, self.timeouts(), indefinite)
+	}
 
-		// check result of QueryDosDeviceW() for COM0 thru COM255 to find
-		// existing COM ports (see: https://stackoverflow.com/a/18691898)
-		for n in 0 ..= 255 {
-			// construct wide string for COMn
-			let name = OsString::from(format!("COM{}", n));
-			let mut name_wide: Vec<u16> = name.encode_wide().collect();
-			name_wide.push(0);
+	// like read(), but with the given timeout instead of the read timeout.
+	// COMMTIMEOUTS set directly are not changed, but reapplied by the next
+	// read() or write() (see lock_timeouts()).
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		let timeouts = comm_timeouts(timeout, self.timeout_write.load());
+		self.read_with(buf, timeouts, timeout.is_none())
+	}
 
-			// QueryDosDeviceW() returns 0 if the COM port does not exist
-			let len = unsafe { QueryDosDeviceW(name_wide.as_ptr(),
-				path_wide.as_mut_ptr(),	path_wide.len() as u32) } as usize;
-			if len > 0 {
-				devices.push(name);
+	fn read_with(&self, buf: &mut [u8], timeouts: CommTimeouts, indefinite: bool)
+			-> io::Result<usize> {
+		// reading less than requested is fine, so larger buffers are capped
+		let len = buf.len().min(MAX_TRANSFER);
+		let buf = &mut buf[..len];
+		loop {
+			match self.read_overlapped(buf, timeouts) {
+				// reads without timeout still time out after MAXDWORD - 1 ms
+				// (see set_comm_timeouts()), so keep waiting
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut && indefinite => (),
+				res => return res.map_err(windows_devices::map_invalidated)
 			}
 		}
-
-		devices
 	}
 
-	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+	fn read_overlapped(&self, buf: &mut [u8], timeouts: CommTimeouts) -> io::Result<usize> {
 		// queue async read
+		let event = self.events.acquire()?;
+		let timeouts = self.lock_timeouts(timeouts)?;
 		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
-		overlapped.hEvent = self.event;
+		overlapped.hEvent = unqueued(event.event);
 		let res: BOOL = unsafe {
 			// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-readfile
 			ReadFile(self.comdev, buf.as_mut_ptr() as *mut c_void,
 				buf.len() as u32, ptr::null_mut(), &mut overlapped)
 		};
+		drop(timeouts);
 
 		// async read request can (theoretically) succeed immediately, queue
 		// successfully, or fail. even if it returns TRUE, the number of bytes
@@ -226,15 +434,63 @@ impl SerialPort {
 		}
 	}
 
+	// write larger buffers in chunks until one is not written completely
+	// (e.g., due to the write timeout, which applies to each chunk). errors
+	// after the first chunk are reported by the next write. writes of clones
+	// are not serialized, but the driver completes them in the order they
+	// were queued, so writers are served fairly and those queued between the
+	// chunks of a large write are not starved by it.
 	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_with(buf, self.timeouts(), self.timeout_write.load())
+	}
+
+	// like write(), but with the given timeout instead of the write timeout
+	// (see read_with_timeout())
+	pub fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		let timeouts = comm_timeouts(self.timeout_read.load(), timeout);
+		self.write_with(buf, timeouts, timeout)
+	}
+
+	fn write_with(&self, buf: &[u8], timeouts: CommTimeouts, timeout: Option<Duration>)
+			-> io::Result<usize> {
+		// a zero timeout cannot be expressed via COMMTIMEOUTS (see
+		// comm_timeouts()), so such writes are cancelled instead of waited for
+		let nonblocking = timeout.map_or(false, |dur| dur.is_zero());
+		if buf.is_empty() {
+			return self.write_overlapped(buf, timeouts, nonblocking)
+				.map_err(windows_devices::map_invalidated);
+		}
+
+		let mut written = 0;
+		for chunk in buf.chunks(MAX_TRANSFER) {
+			match self.write_overlapped(chunk, timeouts, nonblocking) {
+				Ok(len) => {
+					written += len;
+					if len < chunk.len() {
+						break;
+					}
+				},
+				Err(_) if written > 0 => break,
+				Err(e) => return Err(windows_devices::map_invalidated(e))
+			}
+		}
+		Ok(written)
+	}
+
+	fn write_overlapped(&self, buf: &[u8], timeouts: CommTimeouts, nonblocking: bool)
+			-> io::Result<usize> {
 		// queue async write
+		let event = self.events.acquire()?;
+		let timeouts = self.lock_timeouts(timeouts)?;
 		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
-		overlapped.hEvent = self.event;
+		overlapped.hEvent = unqueued(event.event);
 		let res: BOOL = unsafe {
 			// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-writefile
 			WriteFile(self.comdev, buf.as_ptr(),
 				buf.len() as u32, ptr::null_mut(), &mut overlapped)
 		};
+		drop(timeouts);
 
 		// async write request can (theoretically) succeed immediately, queue
 		// successfully, or fail. even if it returns TRUE, the number of bytes
@@ -243,12 +499,25 @@ impl SerialPort {
 			return Err(io::Error::last_os_error());
 		}
 
-		// wait for completion
+		// wait for completion, unless the write timeout is zero
 		let mut len: u32 = 0;
-		let res: BOOL = unsafe {
+		let mut res: BOOL = unsafe {
 			// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getoverlappedresult
-			GetOverlappedResult(self.comdev, &mut overlapped, &mut len, TRUE)
+			GetOverlappedResult(self.comdev, &mut overlapped, &mut len,
+				if nonblocking { FALSE } else { TRUE })
 		};
+		if res == FALSE && nonblocking && unsafe { GetLastError() } == ERROR_IO_INCOMPLETE {
+			// cancel the pending write, which still references overlapped
+			// and buf, and retrieve the number of bytes written so far
+			// https://docs.microsoft.com/en-us/windows/win32/fileio/cancelioex-func
+			let _res = unsafe { CancelIoEx(self.comdev, &overlapped) };
+			res = unsafe {
+				GetOverlappedResult(self.comdev, &mut overlapped, &mut len, TRUE)
+			};
+			if res == FALSE && unsafe { GetLastError() } == ERROR_OPERATION_ABORTED {
+				res = TRUE;
+			}
+		}
 		if res == FALSE {
 			// minimum supported rust version (MSRV) is 1.46, because WriteFile()
 			// may fail with ERROR_SEM_TIMEOUT, which is
@@ -273,6 +542,378 @@ impl SerialPort {
 			_ => Ok(()),
 		}
 	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	// timeouts are applied by the next read or write (see lock_timeouts())
+	pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		*self.raw_timeouts.lock().unwrap_or_else(|e| e.into_inner()) = None;
+		Ok(())
+	}
+
+	pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		*self.raw_timeouts.lock().unwrap_or_else(|e| e.into_inner()) = None;
+		Ok(())
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getcommtimeouts
+	pub fn comm_timeouts(&self) -> io::Result<CommTimeouts> {
+		let mut timeouts: COMMTIMEOUTS = unsafe { mem::zeroed() };
+		if unsafe { GetCommTimeouts(self.comdev, &mut timeouts) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		Ok(CommTimeouts {
+			read_interval: timeouts.ReadIntervalTimeout,
+			read_total_multiplier: timeouts.ReadTotalTimeoutMultiplier,
+			read_total_constant: timeouts.ReadTotalTimeoutConstant,
+			write_total_multiplier: timeouts.WriteTotalTimeoutMultiplier,
+			write_total_constant: timeouts.WriteTotalTimeoutConstant
+		})
+	}
+
+	pub fn set_comm_timeouts(&self, timeouts: CommTimeouts) -> io::Result<()> {
+		let mut applied = self.applied.lock().unwrap_or_else(|e| e.into_inner());
+		set_comm_timeouts(self.comdev, timeouts)?;
+		*applied = timeouts;
+		*self.raw_timeouts.lock().unwrap_or_else(|e| e.into_inner()) = Some(timeouts);
+		Ok(())
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-escapecommfunction
+	fn escape_comm_function(&self, function: ESCAPE_COMM_FUNCTION) -> io::Result<()> {
+		match unsafe { EscapeCommFunction(self.comdev, function) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getcommmodemstatus
+	fn get_modem_status(&self) -> io::Result<MODEM_STATUS_FLAGS> {
+		let mut status: MODEM_STATUS_FLAGS = 0;
+		match unsafe { GetCommModemStatus(self.comdev, &mut status) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(status)
+		}
+	}
+
+	pub fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.escape_comm_function(if level { SETRTS } else { CLRRTS })
+	}
+
+	pub fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.escape_comm_function(if level { SETDTR } else { CLRDTR })
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_CTS_ON != 0)
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_DSR_ON != 0)
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_RING_ON != 0)
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_RLSD_ON != 0)
+	}
+
+	// block until any modem status input (CTS, DSR, RI, or CD) changes or
+	// the timeout expires. fails while another clone waits for events.
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-waitcommevent
+	pub fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		let _claim = self.claim_scoped(MaskOwner::ModemChange)?;
+		if unsafe { SetCommMask(self.comdev, EV_CTS | EV_DSR | EV_RING | EV_RLSD) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		let event = self.events.acquire()?;
+		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+		overlapped.hEvent = unqueued(event.event);
+		let mut evt_mask: COMM_EVENT_MASK = 0;
+		if unsafe {
+			WaitCommEvent(self.comdev, &mut evt_mask, &mut overlapped)
+		} == FALSE && unsafe { GetLastError() } != ERROR_IO_PENDING {
+			return Err(io::Error::last_os_error());
+		}
+
+		// wait for completion or cancel the pending WaitCommEvent() on timeout,
+		// which still references overlapped and evt_mask
+		let wait_ms = timeout.map_or(INFINITE, timeout_ms);
+		let res = match unsafe { WaitForSingleObject(event.event, wait_ms) } {
+			WAIT_FAILED => Err(io::Error::last_os_error()),
+			WAIT_TIMEOUT => {
+				// https://docs.microsoft.com/en-us/windows/win32/fileio/cancelioex-func
+				let _res = unsafe { CancelIoEx(self.comdev, &overlapped) };
+				Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for modem status change timed out"))
+			},
+			_ => Ok(())
+		};
+		let mut _undef: u32 = 0;
+		if unsafe {
+			GetOverlappedResult(self.comdev, &mut overlapped, &mut _undef, TRUE)
+		} == FALSE && res.is_ok() {
+			return Err(windows_devices::map_invalidated(io::Error::last_os_error()));
+		}
+		res
+	}
+
+	// claim the event mask of the device, which fails if a clone (or this
+	// port) already waits for events via another operation
+	fn claim_event_mask(&self, owner: MaskOwner) -> io::Result<()> {
+		let mut current = self.mask_owner.lock().unwrap_or_else(|e| e.into_inner());
+		if let Some(current) = *current {
+			return Err(io::Error::new(io::ErrorKind::AddrInUse, format!(
+				"COM port events are already waited for by {}", current.name())));
+		}
+		*current = Some(owner);
+		Ok(())
+	}
+
+	fn release_event_mask(&self) {
+		*self.mask_owner.lock().unwrap_or_else(|e| e.into_inner()) = None;
+	}
+
+	fn claim_scoped(&self, owner: MaskOwner) -> io::Result<MaskClaim<'_>> {
+		self.claim_event_mask(owner)?;
+		Ok(MaskClaim(self))
+	}
+
+	fn bytes_available(&self) -> io::Result<u32> {
+		windows_dcb::queued(self.comdev)
+			.map(|(input, _)| input)
+			.map_err(windows_devices::map_invalidated)
+	}
+
+	// wait until any of ports has received data, see
+	// crate::SerialPort::wait_any(). fails if another clone of any of the
+	// ports waits for events, or if ports contains a device twice.
+	// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitformultipleobjects
+	pub fn wait_any(ports: &[&Self], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+		if ports.len() > MAXIMUM_WAIT_OBJECTS {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"cannot wait for more than 64 COM ports"));
+		}
+		let _claims = ports.iter()
+			.map(|port| port.claim_scoped(MaskOwner::WaitAny))
+			.collect::<io::Result<Vec<_>>>()?;
+
+		// setting the mask before checking the input queue ensures that
+		// bytes received in between complete WaitCommEvent()
+		for port in ports {
+			if unsafe { SetCommMask(port.comdev, EV_RXCHAR) } == 0 {
+				return Err(windows_devices::map_invalidated(io::Error::last_os_error()));
+			}
+		}
+		let ready = Self::readable(ports)?;
+		if !ready.is_empty() {
+			return Ok(ready);
+		}
+
+		// issue overlapped WaitCommEvent() for every port
+		let events = ports.iter()
+			.map(|port| port.events.acquire())
+			.collect::<io::Result<Vec<_>>>()?;
+		let mut overlapped: Vec<OVERLAPPED> = vec![unsafe { mem::zeroed() }; ports.len()];
+		let mut evt_masks: Vec<COMM_EVENT_MASK> = vec![0; ports.len()];
+		let mut pending = 0;
+		let mut res = Ok(());
+		for (i, port) in ports.iter().enumerate() {
+			overlapped[i].hEvent = unqueued(events[i].event);
+			if unsafe {
+				WaitCommEvent(port.comdev, &mut evt_masks[i], &mut overlapped[i])
+			} == FALSE && unsafe { GetLastError() } != ERROR_IO_PENDING {
+				res = Err(windows_devices::map_invalidated(io::Error::last_os_error()));
+				break;
+			}
+			pending += 1;
+		}
+
+		if res.is_ok() {
+			let handles: Vec<HANDLE> = events.iter().map(|event| event.event).collect();
+			let wait_ms = timeout.map_or(INFINITE, timeout_ms);
+			res = match unsafe {
+				WaitForMultipleObjects(handles.len() as u32, handles.as_ptr(), FALSE, wait_ms)
+			} {
+				WAIT_FAILED => Err(io::Error::last_os_error()),
+				WAIT_TIMEOUT => Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for COM ports timed out")),
+				_ => Ok(())
+			};
+		}
+
+		// changing the event mask completes pending WaitCommEvent(), which
+		// must finish before the OVERLAPPED structs go out of scope
+		for (i, port) in ports[..pending].iter().enumerate() {
+			unsafe { SetCommMask(port.comdev, 0) };
+			let mut _undef: u32 = 0;
+			unsafe { GetOverlappedResult(port.comdev, &mut overlapped[i], &mut _undef, TRUE) };
+		}
+
+		res?;
+		Self::readable(ports)
+	}
+
+	fn readable(ports: &[&Self]) -> io::Result<Vec<usize>> {
+		let mut ready = Vec::new();
+		for (i, port) in ports.iter().enumerate() {
+			if port.bytes_available()? > 0 {
+				ready.push(i);
+			}
+		}
+		Ok(ready)
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		windows_dcb::baud_rate(self.comdev)
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		windows_dcb::set_baud_rate(self.comdev, baud_rate)
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		windows_dcb::data_bits(self.comdev)
+	}
+
+	pub fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		windows_dcb::set_data_bits(self.comdev, data_bits)
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		windows_dcb::parity(self.comdev)
+	}
+
+	pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		windows_dcb::set_parity(self.comdev, parity)
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		windows_dcb::stop_bits(self.comdev)
+	}
+
+	pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		windows_dcb::set_stop_bits(self.comdev, stop_bits)
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		windows_dcb::flow_control(self.comdev)
+	}
+
+	pub fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		windows_dcb::set_flow_control(self.comdev, flow_control)
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		windows_dcb::local(self.comdev)
+	}
+
+	pub fn set_local(&self, local: bool) -> io::Result<()> {
+		windows_dcb::set_local(self.comdev, local)
+	}
+
+	pub fn parity_marking(&self) -> io::Result<bool> {
+		windows_dcb::parity_marking(self.comdev)
+	}
+
+	pub fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		windows_dcb::set_parity_marking(self.comdev, mark)
+	}
+
+	// see line::LineSerialPort for an emulation
+	pub fn set_canonical(&self, _mode: Option<LineMode>) -> io::Result<()> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"canonical mode is not supported on Windows"))
+	}
+
+	// COM ports never echo or process output
+	pub fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		Ok(TerminalOptions::default())
+	}
+
+	// see line::LineSerialPort for an emulation
+	pub fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		if options != TerminalOptions::default() {
+			return Err(io::Error::new(io::ErrorKind::Unsupported,
+				"terminal options are not supported on Windows"));
+		}
+		Ok(())
+	}
+
+	pub fn set_break(&self, level: bool) -> io::Result<()> {
+		windows_dcb::set_break(self.comdev, level)
+	}
+
+	pub fn restore_settings(&self, settings: &SavedSettings) -> io::Result<()> {
+		let mut dcb = *settings;
+		windows_dcb::set_dcb(self.comdev, &mut dcb)
+	}
+
+	pub fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.escape_comm_function(if paused { SETXOFF } else { SETXON })
+	}
+
+	#[cfg(feature = "config-dialog")]
+	pub fn config_dialog(&self, port_name: &OsStr, parent: HWND)
+			-> io::Result<Option<crate::DefaultCommConfig>> {
+		windows_dcb::config_dialog(self.comdev, port_name, parent)
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-transmitcommchar
+	pub fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		match unsafe { TransmitCommChar(self.comdev, byte as _) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	pub fn bytes_to_read(&self) -> io::Result<usize> {
+		windows_dcb::queued(self.comdev).map(|(input, _)| input as usize)
+	}
+
+	pub fn bytes_to_write(&self) -> io::Result<usize> {
+		windows_dcb::queued(self.comdev).map(|(_, output)| output as usize)
+	}
+
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		windows_dcb::purge(self.comdev, input, output)
+	}
+
+	pub fn raw_handle(&self) -> HANDLE {
+		self.comdev
+	}
+
+	pub fn inheritable(&self) -> io::Result<bool> {
+		windows_dcb::inheritable(self.comdev)
+	}
+
+	pub fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+		windows_dcb::set_inheritable(self.comdev, inheritable)
+	}
+
+	// COM ports are opened without sharing, which Windows cannot relax for
+	// an open handle. to hand a port to another process, let it inherit the
+	// handle instead.
+	pub fn lock(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	pub fn unlock(&self) -> io::Result<()> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"COM ports cannot be shared while open on Windows"))
+	}
 }
 
 impl Drop for SerialPort {
@@ -281,7 +922,231 @@ impl Drop for SerialPort {
 		// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-closehandle
 		let _res = unsafe { CloseHandle(self.comdev) };
 		debug_assert_ne!(_res, 0);
-		let _res = unsafe { CloseHandle(self.event) };
+	}
+}
+
+// readiness notification for reactor::Reactor via an I/O completion port.
+// each registered port has an overlapped WaitCommEvent() for EV_RXCHAR
+// pending, which completes into the completion port once bytes are
+// received. like epoll with EPOLLONESHOT, a ready port is reported to a
+// single thread and must be rearmed after it was serviced. tokens must not
+// be reused.
+// https://docs.microsoft.com/en-us/windows/win32/fileio/i-o-completion-ports
+pub struct Poller {
+	iocp: HANDLE,
+	waits: Mutex<HashMap<usize, Box<CommWait>>>
+}
+
+// boxed, so the OVERLAPPED struct keeps its address while the completion is
+// queued. a pending WaitCommEvent() is completed and waited for before the
+// struct is dropped (see cancel()), so the kernel never writes to freed
+// memory. completions of dropped waits may still be queued, but they are
+// only compared by address and ignored in wait().
+struct CommWait {
+	overlapped: OVERLAPPED,
+	evt_mask: COMM_EVENT_MASK,
+	// a completion is queued or WaitCommEvent() is in progress
+	pending: bool,
+	// duplicate of the port's handle, so the wait can be completed even
+	// after the port was closed
+	comdev: HANDLE,
+	// signaled upon completion, so cancel() does not depend on the
+	// completion port
+	event: HANDLE
+}
+
+impl CommWait {
+	fn new(port: &SerialPort) -> io::Result<Box<Self>> {
+		let mut comdev = INVALID_HANDLE_VALUE;
+		let process = unsafe { GetCurrentProcess() };
+		if unsafe {
+			DuplicateHandle(process, port.comdev, process, &mut comdev,
+				0, FALSE, DUPLICATE_SAME_ACCESS)
+		} == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let event = unsafe { CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut()) };
+		if event == 0 {
+			let error = io::Error::last_os_error();
+			unsafe { CloseHandle(comdev) };
+			return Err(error);
+		}
+		Ok(Box::new(Self {
+			overlapped: unsafe { mem::zeroed() },
+			evt_mask: 0,
+			pending: false,
+			comdev,
+			event
+		}))
+	}
+
+	// complete a pending WaitCommEvent() by changing the event mask and wait
+	// until the kernel is done with the OVERLAPPED struct. completions posted
+	// by add() have a zeroed Internal status, so GetOverlappedResult()
+	// returns immediately for them.
+	fn cancel(&mut self) {
+		if !self.pending {
+			return;
+		}
+		unsafe { SetCommMask(self.comdev, 0) };
+		let mut _undef: u32 = 0;
+		unsafe { GetOverlappedResult(self.comdev, &mut self.overlapped, &mut _undef, TRUE) };
+		self.pending = false;
+	}
+}
+
+impl Drop for CommWait {
+	fn drop(&mut self) {
+		self.cancel();
+		unsafe {
+			CloseHandle(self.event);
+			CloseHandle(self.comdev);
+		}
+	}
+}
+
+const SHUTDOWN_KEY: usize = usize::MAX;
+
+unsafe impl Send for Poller {}
+unsafe impl Sync for Poller {}
+
+impl Poller {
+	pub fn new() -> io::Result<Self> {
+		// https://docs.microsoft.com/en-us/windows/win32/fileio/createiocompletionport
+		let iocp = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, 0, 0, 0) };
+		if iocp == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self { iocp, waits: Mutex::new(HashMap::new()) })
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HashMap<usize, Box<CommWait>>> {
+		self.waits.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// the port keeps the event mask of its device claimed until it is
+	// removed, so a device cannot be registered twice (e.g., via a clone)
+	pub fn add(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		let claim = port.claim_scoped(MaskOwner::Poller)?;
+		self.register(port, token)?;
+		// released by remove()
+		mem::forget(claim);
+		Ok(())
+	}
+
+	fn register(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		if unsafe { CreateIoCompletionPort(port.comdev, self.iocp, token, 0) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// setting the mask clears the events that occurred before, so bytes
+		// already received would not complete WaitCommEvent()
+		if unsafe { SetCommMask(port.comdev, EV_RXCHAR) } == 0 {
+			return Err(windows_devices::map_invalidated(io::Error::last_os_error()));
+		}
+
+		let available = port.bytes_available()?;
+		let mut waits = self.lock();
+		let wait = match waits.entry(token) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => entry.insert(CommWait::new(port)?)
+		};
+		if available == 0 {
+			drop(waits);
+			return self.rearm(port, token);
+		}
+
+		// report the port as ready right away instead
+		wait.overlapped = unsafe { mem::zeroed() };
+		wait.pending = true;
+		// https://docs.microsoft.com/en-us/windows/win32/fileio/postqueuedcompletionstatus
+		if unsafe { PostQueuedCompletionStatus(self.iocp, 0, token, &wait.overlapped) } == 0 {
+			wait.pending = false;
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	}
+
+	// events occurring while no WaitCommEvent() is pending are retained
+	// until the next one, which completes immediately then
+	pub fn rearm(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		let mut waits = self.lock();
+		let wait = waits.get_mut(&token).ok_or_else(|| io::Error::new(
+			io::ErrorKind::NotFound, "port is not registered"))?;
+		wait.overlapped = unsafe { mem::zeroed() };
+		// without the low-order bit, the completion is still queued to the
+		// completion port, see unqueued()
+		wait.overlapped.hEvent = wait.event;
+		wait.pending = true;
+		if unsafe {
+			WaitCommEvent(port.comdev, &mut wait.evt_mask, &mut wait.overlapped)
+		} == FALSE && unsafe { GetLastError() } != ERROR_IO_PENDING {
+			wait.pending = false;
+			return Err(windows_devices::map_invalidated(io::Error::last_os_error()));
+		}
+		Ok(())
+	}
+
+	// changing the event mask completes the pending WaitCommEvent(), which
+	// is waited for before the OVERLAPPED struct is dropped
+	pub fn remove(&self, port: &SerialPort, token: usize) -> io::Result<()> {
+		if self.lock().remove(&token).is_none() {
+			return Ok(());
+		}
+		port.release_event_mask();
+		Ok(())
+	}
+
+	// block until a port is ready and return its token, or None after
+	// shutdown(). a failed WaitCommEvent() (e.g., of a removed device)
+	// counts as ready, so reading reports the error.
+	// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getqueuedcompletionstatus
+	pub fn wait(&self) -> io::Result<Option<usize>> {
+		loop {
+			let mut len: u32 = 0;
+			let mut key: usize = 0;
+			let mut overlapped: *mut OVERLAPPED = ptr::null_mut();
+			let res = unsafe {
+				GetQueuedCompletionStatus(self.iocp, &mut len, &mut key, &mut overlapped, INFINITE)
+			};
+			if overlapped.is_null() {
+				if res == FALSE {
+					return Err(io::Error::last_os_error());
+				}
+				if key == SHUTDOWN_KEY {
+					// pass the shutdown on to the next waiting thread
+					unsafe { PostQueuedCompletionStatus(self.iocp, 0, SHUTDOWN_KEY, ptr::null()) };
+					return Ok(None);
+				}
+				continue;
+			}
+
+			let mut waits = self.lock();
+			match waits.get_mut(&key) {
+				Some(wait) if ptr::eq(&wait.overlapped, overlapped) => {
+					wait.pending = false;
+					return Ok(Some(key));
+				},
+				// completions of removed ports and of other I/O on the device
+				// (e.g., by handles not created by SerialPort) are ignored
+				_ => ()
+			}
+		}
+	}
+
+	// release all current and future calls to wait()
+	pub fn shutdown(&self) -> io::Result<()> {
+		if unsafe { PostQueuedCompletionStatus(self.iocp, 0, SHUTDOWN_KEY, ptr::null()) } == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(())
+	}
+}
+
+impl Drop for Poller {
+	fn drop(&mut self) {
+		// complete the waits of ports that were not removed first
+		self.waits.get_mut().unwrap_or_else(|e| e.into_inner()).clear();
+		let _res = unsafe { CloseHandle(self.iocp) };
 		debug_assert_ne!(_res, 0);
 	}
 }
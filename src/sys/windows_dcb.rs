@@ -0,0 +1,395 @@
+// line settings shared by the regular and the experimental Windows backend
+
+use std::ffi::OsStr;
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+
+use windows_sys::Win32::{
+	Devices::Communication::*,
+	Foundation::*,
+	Storage::FileSystem::{GetFileType, FILE_TYPE_CHAR}
+};
+
+use crate::{DataBits, DefaultCommConfig, FlowControl, NotASerialPort, Parity, StopBits};
+use crate::parity::ERROR_CHAR;
+
+// DCB bitfield flags, see:
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-dcb
+const DCB_F_BINARY: u32 = 1 << 0;
+const DCB_F_PARITY: u32 = 1 << 1;
+const DCB_F_OUTX_CTS_FLOW: u32 = 1 << 2;
+const DCB_F_DSR_SENSITIVITY: u32 = 1 << 6;
+const DCB_F_OUTX: u32 = 1 << 8;
+const DCB_F_INX: u32 = 1 << 9;
+const DCB_F_ERROR_CHAR: u32 = 1 << 10;
+const DCB_F_RTS_CONTROL_SHIFT: u32 = 12;
+const DCB_F_RTS_CONTROL_MASK: u32 = 0b11 << DCB_F_RTS_CONTROL_SHIFT;
+const RTS_CONTROL_ENABLE: u32 = 0x01;
+const RTS_CONTROL_HANDSHAKE: u32 = 0x02;
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getcommstate
+pub fn get_dcb(comdev: HANDLE) -> io::Result<DCB> {
+	let mut dcb: DCB = unsafe { mem::zeroed() };
+	dcb.DCBlength = mem::size_of::<DCB>() as u32;
+	if unsafe { GetCommState(comdev, &mut dcb) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(dcb)
+}
+
+// reject handles of anything but COM ports (e.g., files, pipes, or \\.\NUL),
+// for which configuring them would fail with confusing errors. returns the
+// port's current settings.
+// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getfiletype
+pub fn check_comm_device(comdev: HANDLE) -> io::Result<DCB> {
+	if unsafe { GetFileType(comdev) } != FILE_TYPE_CHAR {
+		return Err(NotASerialPort.into());
+	}
+	get_dcb(comdev).map_err(|_| NotASerialPort.into())
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-setcommstate
+pub fn set_dcb(comdev: HANDLE, dcb: &mut DCB) -> io::Result<()> {
+	// always enforce binary mode, as Windows does not support anything else
+	dcb._bitfield |= DCB_F_BINARY;
+	if unsafe { SetCommState(comdev, dcb) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+// read-modify-write DCB settings
+pub fn update_dcb<F>(comdev: HANDLE, update: F) -> io::Result<()>
+		where F: FnOnce(&mut DCB) {
+	let mut dcb = get_dcb(comdev)?;
+	update(&mut dcb);
+	set_dcb(comdev, &mut dcb)
+}
+
+pub fn baud_rate(comdev: HANDLE) -> io::Result<u32> {
+	get_dcb(comdev).map(|dcb| dcb.BaudRate)
+}
+
+pub fn set_baud_rate(comdev: HANDLE, baud_rate: u32) -> io::Result<()> {
+	update_dcb(comdev, |dcb| dcb.BaudRate = baud_rate)
+}
+
+pub fn data_bits(comdev: HANDLE) -> io::Result<DataBits> {
+	get_dcb(comdev).map(|dcb| decode_data_bits(&dcb))
+}
+
+pub fn set_data_bits(comdev: HANDLE, data_bits: DataBits) -> io::Result<()> {
+	update_dcb(comdev, |dcb| encode_data_bits(dcb, data_bits))
+}
+
+pub fn parity(comdev: HANDLE) -> io::Result<Parity> {
+	get_dcb(comdev).map(|dcb| decode_parity(&dcb))
+}
+
+pub fn set_parity(comdev: HANDLE, parity: Parity) -> io::Result<()> {
+	update_dcb(comdev, |dcb| encode_parity(dcb, parity))
+}
+
+pub fn stop_bits(comdev: HANDLE) -> io::Result<StopBits> {
+	get_dcb(comdev).map(|dcb| decode_stop_bits(&dcb))
+}
+
+pub fn set_stop_bits(comdev: HANDLE, stop_bits: StopBits) -> io::Result<()> {
+	update_dcb(comdev, |dcb| encode_stop_bits(dcb, stop_bits))
+}
+
+pub fn flow_control(comdev: HANDLE) -> io::Result<FlowControl> {
+	get_dcb(comdev).map(|dcb| decode_flow_control(&dcb))
+}
+
+pub fn set_flow_control(comdev: HANDLE, flow_control: FlowControl) -> io::Result<()> {
+	update_dcb(comdev, |dcb| encode_flow_control(dcb, flow_control))
+}
+
+// conversions between DCB fields and line settings, shared by ports and
+// their default configuration
+
+fn decode_data_bits(dcb: &DCB) -> DataBits {
+	match dcb.ByteSize {
+		5 => DataBits::Five,
+		6 => DataBits::Six,
+		7 => DataBits::Seven,
+		_ => DataBits::Eight
+	}
+}
+
+fn encode_data_bits(dcb: &mut DCB, data_bits: DataBits) {
+	dcb.ByteSize = match data_bits {
+		DataBits::Five => 5,
+		DataBits::Six => 6,
+		DataBits::Seven => 7,
+		DataBits::Eight => 8
+	};
+}
+
+fn decode_parity(dcb: &DCB) -> Parity {
+	match dcb.Parity {
+		ODDPARITY => Parity::Odd,
+		EVENPARITY => Parity::Even,
+		MARKPARITY => Parity::Mark,
+		SPACEPARITY => Parity::Space,
+		_ => Parity::None
+	}
+}
+
+fn encode_parity(dcb: &mut DCB, parity: Parity) {
+	dcb.Parity = match parity {
+		Parity::None => NOPARITY,
+		Parity::Odd => ODDPARITY,
+		Parity::Even => EVENPARITY,
+		Parity::Mark => MARKPARITY,
+		Parity::Space => SPACEPARITY
+	};
+	// enable parity checking for anything but no parity
+	if parity == Parity::None {
+		dcb._bitfield &= !DCB_F_PARITY;
+	} else {
+		dcb._bitfield |= DCB_F_PARITY;
+	}
+}
+
+fn decode_stop_bits(dcb: &DCB) -> StopBits {
+	match dcb.StopBits {
+		ONESTOPBIT => StopBits::One,
+		_ => StopBits::Two
+	}
+}
+
+fn encode_stop_bits(dcb: &mut DCB, stop_bits: StopBits) {
+	dcb.StopBits = match stop_bits {
+		StopBits::One => ONESTOPBIT,
+		StopBits::Two => TWOSTOPBITS
+	};
+}
+
+fn decode_flow_control(dcb: &DCB) -> FlowControl {
+	if dcb._bitfield & DCB_F_OUTX_CTS_FLOW != 0 {
+		FlowControl::Hardware
+	} else if dcb._bitfield & (DCB_F_OUTX | DCB_F_INX) != 0 {
+		FlowControl::Software
+	} else {
+		FlowControl::None
+	}
+}
+
+fn encode_flow_control(dcb: &mut DCB, flow_control: FlowControl) {
+	dcb._bitfield &= !(DCB_F_OUTX_CTS_FLOW | DCB_F_OUTX | DCB_F_INX
+		| DCB_F_RTS_CONTROL_MASK);
+	match flow_control {
+		FlowControl::None => {
+			dcb._bitfield |= RTS_CONTROL_ENABLE << DCB_F_RTS_CONTROL_SHIFT;
+		},
+		FlowControl::Software => {
+			dcb._bitfield |= DCB_F_OUTX | DCB_F_INX
+				| RTS_CONTROL_ENABLE << DCB_F_RTS_CONTROL_SHIFT;
+			dcb.XonChar = XON;
+			dcb.XoffChar = XOFF;
+		},
+		FlowControl::Hardware => {
+			dcb._bitfield |= DCB_F_OUTX_CTS_FLOW
+				| RTS_CONTROL_HANDSHAKE << DCB_F_RTS_CONTROL_SHIFT;
+		}
+	}
+}
+
+// Windows has no equivalent of CLOCAL. honoring the modem control lines
+// maps to DSR sensitivity instead, i.e., the driver discards received bytes
+// while DSR is deasserted.
+pub fn local(comdev: HANDLE) -> io::Result<bool> {
+	get_dcb(comdev).map(|dcb| dcb._bitfield & DCB_F_DSR_SENSITIVITY == 0)
+}
+
+pub fn set_local(comdev: HANDLE, local: bool) -> io::Result<()> {
+	update_dcb(comdev, |dcb| if local {
+		dcb._bitfield &= !DCB_F_DSR_SENSITIVITY;
+	} else {
+		dcb._bitfield |= DCB_F_DSR_SENSITIVITY;
+	})
+}
+
+// the equivalent of PARMRK is replacing bytes with parity errors by an error
+// character. parity checking itself is enabled by set_parity().
+pub fn parity_marking(comdev: HANDLE) -> io::Result<bool> {
+	get_dcb(comdev).map(|dcb| dcb._bitfield & DCB_F_ERROR_CHAR != 0)
+}
+
+pub fn set_parity_marking(comdev: HANDLE, mark: bool) -> io::Result<()> {
+	update_dcb(comdev, |dcb| if mark {
+		dcb._bitfield |= DCB_F_ERROR_CHAR;
+		dcb.ErrorChar = ERROR_CHAR;
+	} else {
+		dcb._bitfield &= !DCB_F_ERROR_CHAR;
+	})
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-setcommbreak
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-clearcommbreak
+pub fn set_break(comdev: HANDLE, level: bool) -> io::Result<()> {
+	let res = if level {
+		unsafe { SetCommBreak(comdev) }
+	} else {
+		unsafe { ClearCommBreak(comdev) }
+	};
+	match res {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-purgecomm
+pub fn purge(comdev: HANDLE, input: bool, output: bool) -> io::Result<()> {
+	let mut flags = 0;
+	if input {
+		flags |= PURGE_RXCLEAR;
+	}
+	if output {
+		flags |= PURGE_TXCLEAR;
+	}
+	if flags == 0 {
+		return Ok(());
+	}
+
+	match unsafe { PurgeComm(comdev, flags) } {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+// number of bytes in the input and output queue of the driver. also clears
+// pending communication errors.
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-clearcommerror
+pub fn queued(comdev: HANDLE) -> io::Result<(u32, u32)> {
+	let mut errors: CLEAR_COMM_ERROR_FLAGS = 0;
+	let mut comstat: COMSTAT = unsafe { mem::zeroed() };
+	match unsafe { ClearCommError(comdev, &mut errors, &mut comstat) } {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok((comstat.cbInQue, comstat.cbOutQue))
+	}
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-gethandleinformation
+pub fn inheritable(comdev: HANDLE) -> io::Result<bool> {
+	let mut flags: u32 = 0;
+	match unsafe { GetHandleInformation(comdev, &mut flags) } {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(flags & HANDLE_FLAG_INHERIT != 0)
+	}
+}
+
+pub fn set_inheritable(comdev: HANDLE, inheritable: bool) -> io::Result<()> {
+	let flags = if inheritable { HANDLE_FLAG_INHERIT } else { 0 };
+	match unsafe { SetHandleInformation(comdev, HANDLE_FLAG_INHERIT, flags) } {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+// COMMCONFIG is followed by provider-specific data, so it is stored in a
+// buffer of the size the provider requires (aligned like COMMCONFIG)
+struct CommConfigBuffer {
+	buf: Vec<u64>,
+	size: u32
+}
+
+impl CommConfigBuffer {
+	fn config(&mut self) -> &mut COMMCONFIG {
+		unsafe { &mut *(self.buf.as_mut_ptr() as *mut COMMCONFIG) }
+	}
+}
+
+fn wide_name(port_name: &OsStr) -> Vec<u16> {
+	port_name.encode_wide().chain(iter::once(0)).collect()
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getdefaultcommconfigw
+fn get_default_comm_config(name: &[u16]) -> io::Result<CommConfigBuffer> {
+	let mut size = mem::size_of::<COMMCONFIG>() as u32;
+	loop {
+		let words = (size as usize + mem::size_of::<u64>() - 1) / mem::size_of::<u64>();
+		let mut buffer = CommConfigBuffer { buf: vec![0u64; words], size };
+		let res = unsafe {
+			GetDefaultCommConfigW(name.as_ptr(), buffer.config(), &mut size)
+		};
+		if res != 0 {
+			return Ok(buffer);
+		}
+		// size was updated to the required one
+		let errcode = unsafe { GetLastError() };
+		if errcode != ERROR_INSUFFICIENT_BUFFER || size <= buffer.size {
+			return Err(io::Error::from_raw_os_error(errcode as i32));
+		}
+	}
+}
+
+pub fn default_comm_config(port_name: &OsStr) -> io::Result<DefaultCommConfig> {
+	let mut buffer = get_default_comm_config(&wide_name(port_name))?;
+	let dcb = &buffer.config().dcb;
+	Ok(DefaultCommConfig {
+		baud_rate: dcb.BaudRate,
+		data_bits: decode_data_bits(dcb),
+		parity: decode_parity(dcb),
+		stop_bits: decode_stop_bits(dcb),
+		flow_control: decode_flow_control(dcb)
+	})
+}
+
+// read-modify-write the default configuration, so provider-specific data
+// and DCB fields without equivalent in DefaultCommConfig are retained
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-setdefaultcommconfigw
+pub fn set_default_comm_config(port_name: &OsStr, config: &DefaultCommConfig) -> io::Result<()> {
+	let name = wide_name(port_name);
+	let mut buffer = get_default_comm_config(&name)?;
+	let dcb = &mut buffer.config().dcb;
+	dcb._bitfield |= DCB_F_BINARY;
+	dcb.BaudRate = config.baud_rate;
+	encode_data_bits(dcb, config.data_bits);
+	encode_parity(dcb, config.parity);
+	encode_stop_bits(dcb, config.stop_bits);
+	encode_flow_control(dcb, config.flow_control);
+	let size = buffer.size;
+	match unsafe { SetDefaultCommConfigW(name.as_ptr(), buffer.config(), size) } {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+// show the system's configuration dialog for the port, initialized with its
+// current settings, and apply the user's choices. returns None if the user
+// cancelled the dialog.
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-commconfigdialogw
+#[cfg(feature = "config-dialog")]
+pub fn config_dialog(comdev: HANDLE, port_name: &OsStr, parent: HWND)
+		-> io::Result<Option<DefaultCommConfig>> {
+	let mut config: COMMCONFIG = unsafe { mem::zeroed() };
+	config.dwSize = mem::size_of::<COMMCONFIG>() as u32;
+	config.wVersion = 1;
+	config.dcb = get_dcb(comdev)?;
+	let name = wide_name(port_name);
+	if unsafe { CommConfigDialogW(name.as_ptr(), parent, &mut config) } == 0 {
+		let errcode = unsafe { GetLastError() };
+		if errcode == ERROR_CANCELLED {
+			return Ok(None);
+		}
+		return Err(io::Error::from_raw_os_error(errcode as i32));
+	}
+
+	set_dcb(comdev, &mut config.dcb)?;
+	let dcb = &config.dcb;
+	Ok(Some(DefaultCommConfig {
+		baud_rate: dcb.BaudRate,
+		data_bits: decode_data_bits(dcb),
+		parity: decode_parity(dcb),
+		stop_bits: decode_stop_bits(dcb),
+		flow_control: decode_flow_control(dcb)
+	}))
+}
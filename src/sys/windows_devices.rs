@@ -0,0 +1,351 @@
+extern crate windows_sys;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::iter;
+use std::mem;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::ptr;
+
+use windows_sys::Win32::Devices::DeviceAndDriverInstallation::{
+	SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW,
+	SetupDiGetDeviceInstanceIdW, SetupDiGetDeviceRegistryPropertyW, SetupDiOpenDevRegKey,
+	DICS_FLAG_GLOBAL, DIGCF_PRESENT, DIREG_DEV, GUID_DEVCLASS_PORTS, HDEVINFO,
+	SPDRP_LOCATION_INFORMATION, SPDRP_SERVICE, SP_DEVINFO_DATA
+};
+use windows_sys::Win32::Foundation::{
+	GetLastError, ERROR_ACCESS_DENIED, ERROR_BAD_COMMAND,
+	ERROR_DEVICE_NOT_CONNECTED, ERROR_DEVICE_REMOVED, ERROR_FILE_NOT_FOUND,
+	ERROR_GEN_FAILURE, ERROR_INSUFFICIENT_BUFFER, ERROR_INVALID_HANDLE, ERROR_SUCCESS,
+	INVALID_HANDLE_VALUE
+};
+use windows_sys::Win32::Storage::FileSystem::QueryDosDeviceW;
+use windows_sys::Win32::System::Registry::{RegCloseKey, RegQueryValueExW, KEY_READ};
+
+use crate::{DeviceInfo, DeviceKind, PortStatus, UsbInfo};
+
+// device enumeration shared by the Windows backends
+
+pub fn invalidated_error() -> io::Error {
+	io::Error::new(io::ErrorKind::NotConnected,
+		"COM port handle became invalid (device removed or system resumed from sleep)")
+}
+
+// I/O on an open handle fails with one of these errors after the device was
+// removed or, for drivers without power management support, after the system
+// resumed from sleep. map them to a distinct error, so applications can
+// reopen the port instead of treating it like any other I/O error.
+pub fn map_invalidated(error: io::Error) -> io::Error {
+	match error.raw_os_error().map(|code| code as u32) {
+		Some(ERROR_INVALID_HANDLE) | Some(ERROR_BAD_COMMAND) | Some(ERROR_GEN_FAILURE)
+			| Some(ERROR_DEVICE_NOT_CONNECTED) | Some(ERROR_DEVICE_REMOVED) => invalidated_error(),
+		_ => error
+	}
+}
+
+// whether opening failed because another process uses the COM port, which
+// Windows reports as access being denied
+pub fn is_busy(error: &io::Error) -> bool {
+	error.raw_os_error().map(|code| code as u32) == Some(ERROR_ACCESS_DENIED)
+}
+
+// query the target paths of an MS-DOS device name, or all MS-DOS device
+// names if name is None
+// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-querydosdevicew
+fn query_dos_device(name: Option<&OsStr>) -> io::Result<Vec<OsString>> {
+	let name_wide: Option<Vec<u16>> = name
+		.map(|name| name.encode_wide().chain(iter::once(0)).collect());
+	let name_ptr = name_wide.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+
+	// grow buffer until the result fits
+	let mut buf = vec![0u16; 4096];
+	loop {
+		let len = unsafe {
+			QueryDosDeviceW(name_ptr, buf.as_mut_ptr(), buf.len() as u32)
+		} as usize;
+		if len > 0 {
+			buf.truncate(len);
+			break;
+		}
+		let errcode = unsafe { GetLastError() };
+		if errcode != ERROR_INSUFFICIENT_BUFFER || buf.len() >= 1 << 24 {
+			return Err(io::Error::from_raw_os_error(errcode as i32));
+		}
+		buf.resize(buf.len() * 2, 0);
+	}
+
+	// result is a list of null-terminated strings, terminated by another null
+	Ok(buf.split(|&c| c == 0)
+		.filter(|s| !s.is_empty())
+		.map(OsString::from_wide)
+		.collect())
+}
+
+// split name into prefix and number, e.g., "COM10" into ("COM", 10)
+fn split_port_name(name: &str) -> Option<(&str, u32)> {
+	let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+	let number = name[prefix.len()..].parse().ok()?;
+	Some((prefix, number))
+}
+
+// COM ports and ports of com0com virtual port pairs, which are named CNCAn
+// and CNCBn unless renamed to COMn
+fn is_port_name(name: &str) -> bool {
+	matches!(split_port_name(name), Some(("COM", _)) | Some(("CNCA", _)) | Some(("CNCB", _)))
+}
+
+// classify port by the name of the device object it links to, which is
+// derived from the driver name, e.g., "\Device\VCP0" for FTDI adapters
+fn classify(target: &OsStr) -> DeviceKind {
+	let target = target.to_string_lossy().to_ascii_lowercase();
+	let object = target.strip_prefix("\\device\\").unwrap_or(&target);
+
+	if object.starts_with("com0com") {
+		DeviceKind::Virtual
+	} else if object.starts_with("bthmodem") {
+		DeviceKind::Bluetooth
+	} else if object.starts_with("serial") {
+		DeviceKind::Native
+	} else if ["usbser", "vcp", "silabser", "prolificserial"].iter()
+			.any(|driver| object.starts_with(driver)) {
+		DeviceKind::Usb
+	} else {
+		DeviceKind::Unknown
+	}
+}
+
+// ports that cannot be queried are classified as unknown
+pub fn device_kind(name: &OsStr) -> DeviceKind {
+	query_dos_device(Some(name)).ok()
+		.and_then(|targets| targets.first().map(|target| classify(target)))
+		.unwrap_or(DeviceKind::Unknown)
+}
+
+// trim a wide string at its first null, if any
+fn from_wide_nul(buf: &[u16]) -> OsString {
+	let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+	OsString::from_wide(&buf[..len])
+}
+
+// value PortName of a device's hardware key, e.g., "COM3"
+// https://docs.microsoft.com/en-us/windows/win32/api/setupapi/nf-setupapi-setupdiopendevregkey
+fn port_name(set: HDEVINFO, info: &SP_DEVINFO_DATA) -> Option<OsString> {
+	let key = unsafe { SetupDiOpenDevRegKey(set, info, DICS_FLAG_GLOBAL, 0, DIREG_DEV, KEY_READ) };
+	if key == INVALID_HANDLE_VALUE {
+		return None;
+	}
+	let value: Vec<u16> = OsStr::new("PortName").encode_wide().chain(iter::once(0)).collect();
+	let mut buf = [0u16; 64];
+	let mut size = mem::size_of_val(&buf) as u32;
+	let res = unsafe {
+		RegQueryValueExW(key, value.as_ptr(), ptr::null(), ptr::null_mut(),
+			buf.as_mut_ptr() as *mut u8, &mut size)
+	};
+	unsafe { RegCloseKey(key) };
+	match res {
+		ERROR_SUCCESS => Some(from_wide_nul(&buf[..size as usize / 2])),
+		_ => None
+	}
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/api/setupapi/nf-setupapi-setupdigetdeviceinstanceidw
+fn instance_id(set: HDEVINFO, info: &SP_DEVINFO_DATA) -> Option<OsString> {
+	// exceeds MAX_DEVICE_ID_LEN (200)
+	let mut buf = [0u16; 256];
+	let mut _required = 0;
+	match unsafe {
+		SetupDiGetDeviceInstanceIdW(set, info, buf.as_mut_ptr(), buf.len() as u32, &mut _required)
+	} {
+		0 => None,
+		_ => Some(from_wide_nul(&buf))
+	}
+}
+
+// string property of a device, e.g., SPDRP_SERVICE
+// https://docs.microsoft.com/en-us/windows/win32/api/setupapi/nf-setupapi-setupdigetdeviceregistrypropertyw
+fn registry_property(set: HDEVINFO, info: &SP_DEVINFO_DATA, property: u32) -> Option<String> {
+	let mut buf = [0u16; 256];
+	let mut _required = 0;
+	match unsafe {
+		SetupDiGetDeviceRegistryPropertyW(set, info, property, ptr::null_mut(),
+			buf.as_mut_ptr() as *mut u8, mem::size_of_val(&buf) as u32, &mut _required)
+	} {
+		0 => None,
+		_ => Some(from_wide_nul(&buf).to_string_lossy().into_owned())
+	}
+}
+
+// port as seen by SetupAPI
+struct PortDevice {
+	// https://docs.microsoft.com/en-us/windows-hardware/drivers/install/device-instance-ids
+	instance_id: OsString,
+	// name of the driver's service, e.g., "usbser" or "FTSER2K"
+	service: Option<String>,
+	// e.g., "Port_#0002.Hub_#0003" for USB devices. ports of drivers like
+	// FTDI's, which are children of the USB device, may lack it.
+	location: Option<String>
+}
+
+// present ports (device class Ports) by port name. ports not managed by
+// SetupAPI are missing.
+fn port_devices() -> HashMap<OsString, PortDevice> {
+	let mut ports = HashMap::new();
+	let set = unsafe { SetupDiGetClassDevsW(&GUID_DEVCLASS_PORTS, ptr::null(), 0, DIGCF_PRESENT) };
+	if set == INVALID_HANDLE_VALUE {
+		return ports;
+	}
+
+	let mut info: SP_DEVINFO_DATA = unsafe { mem::zeroed() };
+	info.cbSize = mem::size_of::<SP_DEVINFO_DATA>() as u32;
+	let mut index = 0;
+	while unsafe { SetupDiEnumDeviceInfo(set, index, &mut info) } != 0 {
+		index += 1;
+		if let (Some(name), Some(instance_id)) = (port_name(set, &info), instance_id(set, &info)) {
+			ports.insert(name, PortDevice {
+				instance_id,
+				service: registry_property(set, &info, SPDRP_SERVICE),
+				location: registry_property(set, &info, SPDRP_LOCATION_INFORMATION)
+			});
+		}
+	}
+
+	unsafe { SetupDiDestroyDeviceInfoList(set) };
+	ports
+}
+
+// parse the IDs of USB devices from instance IDs like
+// "USB\VID_2341&PID_0043\85736323838351F0B1A1" or, for FTDI's bus driver,
+// "FTDIBUS\VID_0403+PID_6001+A600XYZAA\0000" (serial number A600XYZA plus
+// the letter of the adapter's port). the last component of USB instance IDs
+// is only a serial number if it contains no ampersand, which marks IDs that
+// Windows generates for devices without serial number.
+fn usb_info(port: &PortDevice) -> Option<UsbInfo> {
+	let instance_id = port.instance_id.to_str()?.to_ascii_uppercase();
+	let mut components = instance_id.split('\\');
+	let bus = components.next()?;
+	let hardware_id = components.next()?;
+	let instance = components.next()?;
+
+	let mut fields = hardware_id.split(&['&', '+'][..]);
+	let id = |field: Option<&str>, prefix: &str| field
+		.and_then(|field| field.strip_prefix(prefix))
+		.and_then(|id| u16::from_str_radix(id, 16).ok());
+	let vendor_id = id(fields.next(), "VID_")?;
+	let product_id = id(fields.next(), "PID_")?;
+	let serial_number = match bus {
+		"USB" if !instance.contains('&') => Some(instance.to_string()),
+		"FTDIBUS" => fields.next()
+			.filter(|field| field.len() > 1)
+			.map(|field| field[..field.len() - 1].to_string()),
+		_ => None
+	};
+	Some(UsbInfo {
+		vendor_id,
+		product_id,
+		serial_number,
+		port_path: port.location.clone()
+	})
+}
+
+// ports exist if their MS-DOS device name does. COM ports can only be opened
+// once, so opening (without configuring) a port in use fails.
+pub fn probe(name: &OsStr) -> io::Result<PortStatus> {
+	use windows_sys::Win32::{Foundation::*, Storage::FileSystem::*};
+
+	match query_dos_device(Some(name)) {
+		Ok(_) => (),
+		Err(ref e) if e.raw_os_error() == Some(ERROR_FILE_NOT_FOUND as i32) =>
+			return Ok(PortStatus::Missing),
+		Err(e) => return Err(e)
+	}
+
+	let path: Vec<u16> = OsStr::new("\\\\.\\").encode_wide()
+		.chain(name.encode_wide())
+		.chain(iter::once(0))
+		.collect();
+	let comdev = unsafe {
+		CreateFileW(path.as_ptr(), GENERIC_READ | GENERIC_WRITE, 0, ptr::null_mut(),
+			OPEN_EXISTING, 0, 0 as HANDLE)
+	};
+	if comdev != INVALID_HANDLE_VALUE {
+		unsafe { CloseHandle(comdev) };
+		return Ok(PortStatus::Present);
+	}
+	let error = io::Error::last_os_error();
+	Ok(match error.raw_os_error().map(|code| code as u32) {
+		Some(ERROR_ACCESS_DENIED) => PortStatus::Busy,
+		Some(ERROR_FILE_NOT_FOUND) => PortStatus::Missing,
+		_ => return Err(error)
+	})
+}
+
+// COM port names and their SetupAPI devices are queried up front, but each
+// port is only classified (and probed) when the iterator reaches it, which
+// takes further queries per port
+pub struct Devices {
+	names: std::vec::IntoIter<OsString>,
+	ports: HashMap<OsString, PortDevice>
+}
+
+impl Iterator for Devices {
+	type Item = DeviceInfo;
+
+	fn next(&mut self) -> Option<DeviceInfo> {
+		let name = self.names.next()?;
+		let port = self.ports.get(&name);
+		Some(DeviceInfo {
+			kind: device_kind(&name),
+			usb: port.and_then(usb_info),
+			driver: port.and_then(|port| port.service.clone()),
+			status: probe(&name).unwrap_or(PortStatus::Present),
+			id: port.map(|port| port.instance_id.clone()),
+			path: name
+		})
+	}
+}
+
+pub fn devices() -> io::Result<Devices> {
+	let mut names: Vec<OsString> = query_dos_device(None)?.into_iter()
+		.filter(|name| name.to_str().map_or(false, is_port_name))
+		.collect();
+
+	// MS-DOS device names are returned in no particular order
+	names.sort_by_key(|name| name.to_str()
+		.and_then(split_port_name)
+		.map(|(prefix, number)| (prefix.to_string(), number)));
+	Ok(Devices { names: names.into_iter(), ports: port_devices() })
+}
+
+// connected com0com port pairs, e.g., ("CNCA0", "CNCB0"). the ports of pair n
+// link to the device objects \Device\com0com1n and \Device\com0com2n.
+pub fn com0com_pairs() -> io::Result<Vec<(OsString, OsString)>> {
+	let mut pairs: BTreeMap<u32, (Option<OsString>, Option<OsString>)> = BTreeMap::new();
+	for name in devices()?.names {
+		let target = query_dos_device(Some(name.as_os_str())).ok()
+			.and_then(|targets| targets.into_iter().next());
+		let target = match target {
+			Some(target) => target.to_string_lossy().to_ascii_lowercase(),
+			None => continue
+		};
+		let object = match target.strip_prefix("\\device\\com0com") {
+			Some(object) => object,
+			None => continue
+		};
+		let number = match object.get(1..).and_then(|number| number.parse().ok()) {
+			Some(number) => number,
+			None => continue
+		};
+		let pair = pairs.entry(number).or_default();
+		match object.get(..1) {
+			Some("1") => pair.0 = Some(name),
+			Some("2") => pair.1 = Some(name),
+			_ => ()
+		}
+	}
+
+	// ports of incomplete pairs (e.g., one side being disabled) are ignored
+	Ok(pairs.into_values()
+		.filter_map(|(a, b)| a.zip(b))
+		.collect())
+}
@@ -1,11 +1,13 @@
 extern crate windows_sys;
 
-use std::ffi::{c_int, c_void, OsStr, OsString};
+use std::ffi::{c_void, OsStr};
 use std::io;
 use std::mem;
 use std::os::windows::ffi::OsStrExt;
 use std::ptr;
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use windows_sys::Win32::{
 	Devices::Communication::*,
@@ -16,25 +18,197 @@ use windows_sys::Win32::{
 	System::WindowsProgramming::*
 };
 
+use super::{windows_dcb, windows_devices};
+use super::fair::FairQueue;
+#[cfg(feature = "enumerate")]
+pub use super::windows_devices::Devices;
+use crate::{CommEvents, CommTimeouts, DataBits, DeviceKind, FlowControl, LineMode, Parity,
+	PortStatus, StopBits, TerminalOptions};
+use crate::backend::AtomicTimeout;
+use crate::clock;
+
 const MAXDWORD: u32 = u32::MAX;
 
-pub struct SerialPort {
+// largest buffer passed to a single ReadFile() or WriteFile(), which take
+// the length as DWORD. serial drivers usually copy the buffer to kernel
+// memory, so even 4 GiB would be excessive.
+const MAX_TRANSFER: usize = 1 << 20;
+
+// longest step of waiting for modem status changes, see wait_modem_change()
+const MODEM_WAIT_STEP: Duration = Duration::from_millis(50);
+
+// compute timeout in milliseconds for WaitForSingleObject() and
+// GetOverlappedResultEx()
+// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitforsingleobject#parameters
+fn wait_timeout_ms(timeout: Option<Duration>) -> u32 {
+	match timeout {
+		None => INFINITE,
+		Some(dur) if dur == Duration::new(0, 0) => 0,
+		Some(dur) if dur <= Duration::from_millis(1) => 1,
+		// clip timeouts at INFINITE - 1 == MAXDWORD - 1
+		Some(dur) if dur >= Duration::from_millis(INFINITE as u64) => INFINITE - 1,
+		Some(dur) => dur.as_millis() as u32
+	}
+}
+
+// WaitForSingleObject() and GetOverlappedResultEx() wait for whole
+// milliseconds only, so timeouts with a fractional millisecond are waited
+// for via a waitable timer instead
+fn is_whole_ms(timeout: Duration) -> bool {
+	timeout.subsec_nanos() % 1_000_000 == 0
+}
+
+// create a waitable timer with the highest resolution available. high
+// resolution timers require Windows 10 version 1803 or later.
+// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createwaitabletimerexw
+fn create_timer() -> HANDLE {
+	let timer = unsafe { CreateWaitableTimerExW(ptr::null(), ptr::null(),
+		CREATE_WAITABLE_TIMER_HIGH_RESOLUTION, TIMER_ALL_ACCESS) };
+	if timer != 0 {
+		return timer;
+	}
+	unsafe { CreateWaitableTimerExW(ptr::null(), ptr::null(), 0, TIMER_ALL_ACCESS) }
+}
+
+// wait until event is signaled or timeout expires like WaitForSingleObject(),
+// but with the timer's resolution
+// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-setwaitabletimer
+// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitformultipleobjects
+fn wait_precise(event: HANDLE, timer: HANDLE, timeout: Duration) -> u32 {
+	// negative due times are relative and given in 100 ns intervals
+	let due_time = -(((timeout.as_nanos() + 99) / 100).clamp(1, i64::MAX as u128) as i64);
+	if unsafe { SetWaitableTimer(timer, &due_time, 0, None, ptr::null(), FALSE) } == FALSE {
+		return WAIT_FAILED;
+	}
+
+	let handles = [event, timer];
+	match unsafe { WaitForMultipleObjects(2, handles.as_ptr(), FALSE, INFINITE) } {
+		WAIT_OBJECT_0 => {
+			// keep the timer from expiring while no one is waiting
+			let _res = unsafe { CancelWaitableTimer(timer) };
+			WAIT_OBJECT_0
+		},
+		wait if wait == WAIT_OBJECT_0 + 1 => WAIT_TIMEOUT,
+		wait => wait
+	}
+}
+
+// configure COMMTIMEOUTS for non-blocking reads and blocking writes. read
+// and write timeouts are handled via WaitForSingleObject() and
+// GetOverlappedResultEx(), respectively, so they apply per instance instead
+// of to all clones.
+// https://docs.microsoft.com/en-us/windows/win32/devio/time-outs
+// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts
+fn set_comm_timeouts(comdev: HANDLE) -> io::Result<()> {
+	let mut timeouts = COMMTIMEOUTS {
+		// return immediately with any data that is available
+		ReadIntervalTimeout: MAXDWORD,
+		ReadTotalTimeoutMultiplier: 0,
+		ReadTotalTimeoutConstant: 0,
+		// zero is no (i.e., infinite) timeout
+		WriteTotalTimeoutMultiplier: 0,
+		WriteTotalTimeoutConstant: 0,
+	};
+
+	if unsafe { SetCommTimeouts(comdev, &mut timeouts) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+// holds a read or write mutex and releases it when dropped
+struct MutexGuard(HANDLE);
+
+impl MutexGuard {
+	fn acquire(mutex: HANDLE, timeout_ms: u32) -> io::Result<Self> {
+		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitforsingleobject
+		match unsafe { WaitForSingleObject(mutex, timeout_ms) } {
+			WAIT_FAILED => Err(io::Error::last_os_error()),
+			// WAIT_ABANDONED indicates that a thread terminated while holding
+			// the mutex, which transfers ownership to the calling thread. the
+			// mutexes only serialize I/O and guard no data, so proceed.
+			WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(Self(mutex)),
+			WAIT_TIMEOUT => Err(io::Error::new(io::ErrorKind::TimedOut,
+				"waiting for concurrent I/O timed out")),
+			_ if cfg!(debug_assertions) => panic!("illegal WaitForSingleObject() return value"),
+			_ => unreachable!()
+		}
+	}
+}
+
+impl Drop for MutexGuard {
+	fn drop(&mut self) {
+		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-releasemutex
+		let _res = unsafe { ReleaseMutex(self.0) };
+		debug_assert_ne!(_res, 0);
+	}
+}
+
+// handles shared by a port and all its clones. reads and writes are each
+// serialized by a mutex, so a single event per direction suffices and all
+// clones use the same completion mechanism.
+struct Handles {
 	comdev: HANDLE,
 	event_read: HANDLE,
 	event_write: HANDLE,
 	mutex_read: HANDLE,
-	timeout_read: Option<Duration>,
-	timeout_read_ms: u32
+	mutex_write: HANDLE,
+	// waitable timers for sub-millisecond timeouts, used under the mutexes
+	timer_read: HANDLE,
+	timer_write: HANDLE,
+	// set once the handle was found to be invalid (e.g., due to an unplugged
+	// USB adapter or system sleep), so all clones fail immediately instead
+	// of timing out
+	disconnected: AtomicBool,
+	// serves writers in FIFO order, which the mutex does not guarantee
+	write_queue: FairQueue
 }
 
 // HANDLE is type *mut c_void which does not implement Send and Sync, so
 // implement it here, because sharing HANDLEs is inherently thread-safe
-unsafe impl Send for SerialPort {}
-unsafe impl Sync for SerialPort {}
+unsafe impl Send for Handles {}
+unsafe impl Sync for Handles {}
+
+impl Drop for Handles {
+	fn drop(&mut self) {
+		// close all handles, some of which may not have been created if
+		// opening failed
+		// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-closehandle
+		for &handle in &[self.comdev, self.event_read, self.event_write,
+				self.mutex_read, self.mutex_write, self.timer_read, self.timer_write] {
+			if handle != 0 {
+				let _res = unsafe { CloseHandle(handle) };
+				debug_assert_ne!(_res, 0);
+			}
+		}
+	}
+}
+
+fn comm_timeouts_unsupported() -> io::Error {
+	io::Error::new(io::ErrorKind::Unsupported,
+		"COMMTIMEOUTS are managed internally by the experimental backend")
+}
+
+pub struct SerialPort {
+	handles: Arc<Handles>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+// settings of a port before it was configured by open_saving()
+pub type SavedSettings = DCB;
 
 impl SerialPort {
 	pub fn open<T>(port_name: &T, timeout: Option<Duration>) -> io::Result<Self>
 			where T: AsRef<OsStr> + ?Sized {
+		Self::open_saving(port_name, timeout).map(|(port, _)| port)
+	}
+
+	// like open(), but also return the settings the port had before
+	pub fn open_saving<T>(port_name: &T, timeout: Option<Duration>)
+			-> io::Result<(Self, Option<SavedSettings>)>
+			where T: AsRef<OsStr> + ?Sized {
 		// construct prefixed COM port name to support COMn with n > 9
 		let mut name = Vec::<u16>::new();
 		name.extend(OsStr::new("\\\\.\\").encode_wide());
@@ -51,6 +225,21 @@ impl SerialPort {
 			return Err(io::Error::last_os_error());
 		}
 
+		// dropping handles closes all handles created so far on failure
+		let mut handles = Handles {
+			comdev,
+			event_read: 0,
+			event_write: 0,
+			mutex_read: 0,
+			mutex_write: 0,
+			timer_read: 0,
+			timer_write: 0,
+			disconnected: AtomicBool::new(false),
+			write_queue: FairQueue::default()
+		};
+
+		let original = Some(windows_dcb::check_comm_device(comdev)?);
+
 		// configure COM port for raw communication
 		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-dcb
 		let mut dcb: DCB = unsafe { mem::zeroed() };
@@ -61,383 +250,188 @@ impl SerialPort {
 		dcb.ByteSize = 8;
 		dcb.StopBits = ONESTOPBIT;
 		dcb.Parity = NOPARITY;
-		if unsafe { SetCommState(comdev, &mut dcb) } == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
-		}
-
-		// compute read timeout in millisecons for WaitForSingleObject()
-		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitforsingleobject#parameters
-		let timeout_read_ms: u32 = match timeout {
-			None => INFINITE,
-			Some(dur) if dur == Duration::new(0, 0) => 0,
-			Some(dur) if dur <= Duration::from_millis(1) => 1,
-			// clip read timeouts at INFINITE - 1 == MAXDWORD - 1
-			Some(dur) if dur >= Duration::from_millis(INFINITE as u64) => INFINITE - 1,
-			Some(dur) => dur.as_millis() as u32
-		};
-
-		// compute write timeout in millisecons for COMMTIMEOUTS
-		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts#members
-		let timeout_write_ms: u32 = match timeout {
-			// zero is no (i.e., infinite) timeout
-			None => 0,
-			// COMMTIMEOUTS does not support non-blocking write, so set
-			// smallest possible timeout (1 ms) instead for all Durations
-			// up to 1 ms, including zero Duration
-			Some(dur) if dur <= Duration::from_millis(1) => 1,
-			Some(dur) if dur >= Duration::from_millis(MAXDWORD as u64) => MAXDWORD,
-			Some(dur) => dur.as_millis() as u32
-		};
-
-		// populate COMMTIMEOUTS struct
-		// https://docs.microsoft.com/en-us/windows/win32/devio/time-outs
-		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/ns-winbase-commtimeouts
-		let mut timeouts = COMMTIMEOUTS {
-			// read timeouts are handled via WaitForSingleObject(), so
-			// configure non-blocking read regardless of timeout_read_ms value
-			ReadIntervalTimeout: MAXDWORD,
-			ReadTotalTimeoutMultiplier: 0,
-			ReadTotalTimeoutConstant: 0,
-			// set write timeout computed above
-			WriteTotalTimeoutMultiplier: 0,
-			WriteTotalTimeoutConstant: timeout_write_ms as u32,
-		};
-
-		// set timeouts
-		if unsafe { SetCommTimeouts(comdev, &mut timeouts) } == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
+		// virtual ports (e.g., com0com) may reject settings that are
+		// meaningless to them, so they are used as is
+		if unsafe { SetCommState(comdev, &mut dcb) } == 0
+				&& windows_devices::device_kind(port_name.as_ref()) != DeviceKind::Virtual {
+			return Err(io::Error::last_os_error());
 		}
 
-		// set event mask to EV_RXCHAR, so WaitCommEvent() can be used to wait
-		// until input is available
-		if unsafe { SetCommMask(comdev, EV_RXCHAR) } == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
+		// configure non-blocking reads and blocking writes, which are
+		// limited by timeouts handled on a per-call basis
+		set_comm_timeouts(comdev)?;
+
+		// enable all events supported by wait_for_event() once, because
+		// changing the event mask would complete pending WaitCommEvent()
+		// calls. read() ignores events other than EV_RXCHAR.
+		if unsafe { SetCommMask(comdev, CommEvents::all().bits()) } == 0 {
+			return Err(io::Error::last_os_error());
 		}
 
 		// create unnamed event objects for asynchronous I/O
-		let event_read = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
+		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
+		handles.event_read = unsafe {
 			CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut())
 		};
-		if event_read == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
-		}
-		let event_write = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
+		if handles.event_read == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		handles.event_write = unsafe {
 			CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut())
 		};
-		if event_write == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event_read) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
-		}
-
-		// create unnamed mutex object for reading from COM port
-		let mutex_read = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createmutexw
+		if handles.event_write == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		// create unnamed mutex objects for reading from and writing to COM port
+		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createmutexw
+		handles.mutex_read = unsafe {
+			CreateMutexW(ptr::null_mut(), FALSE, ptr::null_mut())
+		};
+		if handles.mutex_read == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		handles.mutex_write = unsafe {
 			CreateMutexW(ptr::null_mut(), FALSE, ptr::null_mut())
 		};
-		if mutex_read == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(comdev) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event_read) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event_write) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
+		if handles.mutex_write == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		handles.timer_read = create_timer();
+		if handles.timer_read == 0 {
+			return Err(io::Error::last_os_error());
 		}
+		handles.timer_write = create_timer();
+		if handles.timer_write == 0 {
+			return Err(io::Error::last_os_error());
+		}
+
+		Ok((Self {
+			handles: Arc::new(handles),
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		}, original))
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
 
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
+
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Self> {
+		// clones share all handles, timeouts are per instance unless shared
 		Ok(Self {
-			comdev,
-			event_read,
-			event_write,
-			mutex_read,
-			timeout_read: timeout,
-			timeout_read_ms
+			handles: self.handles.clone(),
+			timeout_read,
+			timeout_write
 		})
 	}
 
-	pub fn try_clone(&self) -> io::Result<Self> {
-		// create new unnamed event objects for asynchronous I/O
-		let event_read = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
-			CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut())
-		};
-		if event_read == 0 {
-			return Err(io::Error::last_os_error());
-		}
-		let event_write = unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createeventw
-			CreateEventW(ptr::null_mut(), TRUE, FALSE, ptr::null_mut())
-		};
-		if event_write == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(event_read) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
-		}
-
-		// duplicate mutex object
-		// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-duplicatehandle
-		let mut mutex_read = INVALID_HANDLE_VALUE;
-		let process = unsafe { GetCurrentProcess() };
-		if unsafe { DuplicateHandle(
-			process,
-			self.mutex_read,
-			process,
-			&mut mutex_read,
-			0,
-			FALSE,
-			DUPLICATE_SAME_ACCESS
-		)} == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(event_read) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event_write) };
-			debug_assert_ne!(_res, 0);
-			return Err(error)
-		}
-
-		// duplicate communications device handle
-		// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-duplicatehandle
-		let mut comdev = INVALID_HANDLE_VALUE;
-		if unsafe { DuplicateHandle(
-			process,
-			self.comdev,
-			process,
-			&mut comdev,
-			0,
-			FALSE,
-			DUPLICATE_SAME_ACCESS
-		)} == 0 {
-			// close open handles and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { CloseHandle(event_read) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(event_write) };
-			debug_assert_ne!(_res, 0);
-			let _res = unsafe { CloseHandle(mutex_read) };
-			debug_assert_ne!(_res, 0);
-			Err(error)
-		} else {
-			// return cloned self on success
-			Ok(Self {
-				comdev,
-				event_read,
-				event_write,
-				mutex_read,
-				timeout_read: self.timeout_read,
-				timeout_read_ms: self.timeout_read_ms
-			})
-		}
-	}
-
-	pub fn list_devices() -> Vec<OsString> {
-		let mut devices = Vec::new();
-		let mut path_wide = [0u16; 1024];
-
-		// check result of QueryDosDeviceW() for COM0 thru COM255 to find
-		// existing COM ports (see: https://stackoverflow.com/a/18691898)
-		for n in 0 ..= 255 {
-			// construct wide string for COMn
-			let name = OsString::from(format!("COM{}", n));
-			let mut name_wide: Vec<u16> = name.encode_wide().collect();
-			name_wide.push(0);
-
-			// QueryDosDeviceW() returns 0 if the COM port does not exist
-			if unsafe { QueryDosDeviceW(
-				name_wide.as_ptr(),
-				path_wide.as_mut_ptr(),
-				path_wide.len() as u32
-			) as usize } > 0 {
-				devices.push(name);
-			}
-		}
+	pub fn probe<T>(port_name: &T) -> io::Result<PortStatus>
+			where T: AsRef<OsStr> + ?Sized {
+		windows_devices::probe(port_name.as_ref())
+	}
 
-		devices
+	#[cfg(feature = "enumerate")]
+	pub fn devices() -> io::Result<Devices> {
+		windows_devices::devices()
 	}
 
 	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-		// get time before acquiring mutex to update read timeout later
-		let entry = Instant::now();
-
-		// acquire read mutex (may block up to self.timeout_read_ms)
-		match unsafe {
-			WaitForSingleObject(self.mutex_read, self.timeout_read_ms)
-		} {
-			WAIT_FAILED => return Err(io::Error::last_os_error()),
-			WAIT_OBJECT_0 => (),
-			WAIT_TIMEOUT => {
-				return Err(io::Error::new(io::ErrorKind::TimedOut,
-					"WaitForSingleObject() timed out"))
-			},
-			WAIT_ABANDONED => unimplemented!("WAIT_ABANDONED occurred"),
-			_ if cfg!(debug_assertions) => panic!("illegal WaitForSingleObject() return value"),
-			_ => unreachable!()
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout_read: Option<Duration>)
+			-> io::Result<usize> {
+		// get time before acquiring mutex to account for time spent waiting
+		let entry = clock::now();
+
+		// reading less than requested is fine, so larger buffers are capped
+		let len = buf.len().min(MAX_TRANSFER);
+		let buf = &mut buf[..len];
+
+		// serialize reads, because WaitCommEvent() cannot be used by multiple
+		// threads simultaneously (may block up to the read timeout)
+		let _guard = MutexGuard::acquire(self.handles.mutex_read, wait_timeout_ms(timeout_read))?;
+		if self.handles.disconnected.load(Ordering::Relaxed) {
+			return Err(windows_devices::invalidated_error());
 		}
 
-		// even when holding the mutex, WaitCommEvent() may return spuriously
-		// with a subsequent ReadFile(self.comdev, ...) returning 0, indicating
-		// that a timeout occurred. to counter this, call ReadFile() until
-		// a read succeeds or the read times out.
+		// WaitCommEvent() may return spuriously (e.g., for characters that
+		// were already read by a previous call) and data may arrive between
+		// ReadFile() and WaitCommEvent(), so alternate between non-blocking
+		// reads and waiting until data was read or the read times out
 		loop {
-			// compute read timeout in ms, accounting for time already elapsed
-			let elapsed = entry.elapsed();
-			let timeout_ms: c_int = match self.timeout_read {
-				None => INFINITE as i32,
-				Some(timeout) if elapsed > timeout => {
-					return Err(io::Error::new(io::ErrorKind::TimedOut,
-						"reading from COM port timed out"));
-				},
-				Some(timeout) if timeout - elapsed <= Duration::from_millis(1) => 1,
-				Some(timeout) if timeout - elapsed >= Duration::from_millis(INFINITE as u64) => INFINITE as i32 - 1,
-				Some(timeout) => (timeout - elapsed).as_millis() as c_int
+			let len = self.read_available(buf).map_err(|e| self.check_error(e))?;
+			if len > 0 || buf.is_empty() {
+				return Ok(len);
+			}
+
+			// compute remaining read timeout, accounting for time elapsed
+			let remaining = match timeout_read {
+				None => None,
+				Some(timeout) => match timeout.checked_sub(clock::elapsed(entry)) {
+					Some(remaining) => Some(remaining),
+					None => return Err(io::Error::new(io::ErrorKind::TimedOut,
+						"reading from COM port timed out"))
+				}
 			};
-		}
 
-		// call WaitCommEvent() to issue overlapped I/O request blocking until
-		// EV_RXCHAR event occurs
-		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
-		overlapped.hEvent = self.event_read;
-		let mut evt_mask: u32 = 0;
-		match unsafe {
-			// implicitly resets event to non-signaled before returning
-			WaitCommEvent(self.comdev, &mut evt_mask, &mut overlapped)
-		} {
-			FALSE if unsafe { GetLastError() } != ERROR_IO_PENDING => {
-				// release mutex and return original error on failure
-				let error = io::Error::last_os_error();
-				let _res = unsafe { ReleaseMutex(self.mutex_read) };
-				debug_assert_ne!(_res, 0);
-				return Err(error);
-			},
-			FALSE => (),
-			// FIXME: if WaitCommEvent() returns TRUE, the subsequent
-			//        WaitForSingleObject() may be superfluous
-			TRUE => unimplemented!("WaitCommEvent() returned TRUE: {:}", evt_mask),
-			_ => unreachable!()
+			// a removed device completes WaitCommEvent() with EV_ERR or
+			// without any event, so check whether it is still present
+			// instead of waiting until the read times out
+			let events = self.wait_comm_event(remaining).map_err(|e| self.check_error(e))?;
+			if events.is_empty() || events.contains(CommEvents::ERR) {
+				self.check_connected()?;
+			}
 		}
+	}
 
-		// compute updated read timeout, accounting for time spent waiting for
-		// read mutex, so total timeout does not exceed self.timeout_read_ms
-		let waited_ms = entry.elapsed().as_millis();
-		let timeout_read_ms = if waited_ms < self.timeout_read_ms as u128 {
-			self.timeout_read_ms - waited_ms as u32
-		} else {
-			0
-		};
-
-		// wait for WaitCommEvent() to complete or timeout to occur
-		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitforsingleobject
-		match unsafe { WaitForSingleObject(self.event_read, timeout_read_ms) } {
-			WAIT_FAILED => {
-				// release mutex and return original error on failure
-				let error = io::Error::last_os_error();
-				let _res = unsafe { ReleaseMutex(self.mutex_read) };
-				debug_assert_ne!(_res, 0);
-				return Err(error);
-			},
-			WAIT_OBJECT_0 => {
-				let mut _undef: u32 = 0;
-				if unsafe { GetOverlappedResult(
-					self.comdev,
-					&mut overlapped,
-					&mut _undef,
-					FALSE
-				)} == 0 {
-					// release mutex and return original error on failure
-					let error = io::Error::last_os_error();
-					let _res = unsafe { ReleaseMutex(self.mutex_read) };
-					debug_assert_ne!(_res, 0);
-					return Err(error);
-				}
-			},
-			WAIT_TIMEOUT => {
-				// waiting for WaitCommEvent() timed out, but the overlapped
-				// I/O requests issued by WaitCommEvent() is still pending.
-				// Because the OVERLAPPED structure goes out of scope when
-				// this function returns, the request must be cancelled now to
-				// prevent undefined behavior (e.g., future WaitCommEvent()
-				// calls returning prematurely, likely because a zeroed
-				// OVERLAPPED struct at the same address is used).
-				// NOTE: CancelIo() only cancels I/O requests issued by the
-				//       calling thread.
-				// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-cancelio
-				if unsafe { CancelIo(self.comdev) } == 0 {
-					// release mutex and return original error on failure
-					let error = io::Error::last_os_error();
-					let _res = unsafe { ReleaseMutex(self.mutex_read) };
-					debug_assert_ne!(_res, 0);
-					return Err(error);
-				}
-				// Check if I/O operation was actually cancelled or
-				// if it raced to completion before cancellation
-				// occurred.
-				// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-cancelio#remarks
-				let mut _undef: u32 = 0;
-				if unsafe { GetOverlappedResult(
-					self.comdev,
-					&mut overlapped,
-					&mut _undef,
-					FALSE
-				)} == 0 {
-					// release mutex and return original error on failure
-					let errcode = unsafe { GetLastError() };
-					if errcode != ERROR_OPERATION_ABORTED {
-						// release mutex and return original error on failure
-						let _res = unsafe { ReleaseMutex(self.mutex_read) };
-						debug_assert_ne!(_res, 0);
-						return Err(io::Error::from_raw_os_error(errcode as i32));
-					}
-				} else {
-					println!("WaitCommEvent() cancelled but succeeded: evt_mask={:}", evt_mask);
-				}
+	pub fn wait_for_event(&self, events: CommEvents, timeout: Option<Duration>)
+			-> io::Result<CommEvents> {
+		// get time before acquiring mutex to account for time spent waiting
+		let entry = clock::now();
 
-				// release mutex
-				let _res = unsafe { ReleaseMutex(self.mutex_read) };
-				debug_assert_ne!(_res, 0);
+		// WaitCommEvent() is also used by read(), so acquire read mutex
+		let _guard = MutexGuard::acquire(self.handles.mutex_read, wait_timeout_ms(timeout))?;
 
+		// events not requested (e.g., EV_RXCHAR due to read()) may occur while
+		// waiting, so wait until a requested one occurs or the wait times out
+		loop {
+			let remaining = timeout.map(|timeout| timeout.saturating_sub(clock::elapsed(entry)));
+			let occurred = self.wait_comm_event(remaining)
+				.map_err(|e| self.check_error(e))?;
+			if occurred.is_empty() || occurred.contains(CommEvents::ERR) {
+				self.check_connected()?;
+			}
+			let occurred = occurred & events;
+			if !occurred.is_empty() {
+				return Ok(occurred);
+			}
+			if remaining.map_or(false, |remaining| remaining.is_zero()) {
 				return Err(io::Error::new(io::ErrorKind::TimedOut,
-					"WaitCommEvent() timed out"))
-			},
-			// WAIT_ABANDONED must not occur, because self.comdev isn't a mutex
-			_ if cfg!(debug_assertions) => panic!("illegal WaitForSingleObject() return value"),
-			_ => unreachable!()
+					"waiting for COM port event timed out"));
+			}
 		}
+	}
 
-		// queue async read
+	// read whatever data is available without blocking, as configured via
+	// COMMTIMEOUTS in set_comm_timeouts()
+	fn read_available(&self, buf: &mut [u8]) -> io::Result<usize> {
 		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
-		overlapped.hEvent = self.event_read;
+		overlapped.hEvent = self.handles.event_read;
 		// async read request can (theoretically) succeed immediately, queue
 		// successfully, or fail. even if it returns TRUE, the number of bytes
-		// written should be retrieved via GetOverlappedResult().
+		// read should be retrieved via GetOverlappedResult().
 		// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-readfile
 		if unsafe { ReadFile(
-			self.comdev,
+			self.handles.comdev,
 			buf.as_mut_ptr() as *mut c_void,
 			buf.len() as u32,
 			ptr::null_mut(),
@@ -445,48 +439,151 @@ impl SerialPort {
 		)} == FALSE {
 			let errcode = unsafe { GetLastError() };
 			if errcode != ERROR_IO_PENDING {
-				// release mutex and return original error on failure
-				let _res = unsafe { ReleaseMutex(self.mutex_read) };
-				debug_assert_ne!(_res, 0);
 				return Err(io::Error::from_raw_os_error(errcode as i32));
 			}
 		}
 
-		// wait for completion
+		// wait for completion, which is immediate for non-blocking reads
+		// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getoverlappedresult
 		let mut len: u32 = 0;
 		if unsafe {
-			// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getoverlappedresult
-			GetOverlappedResult(self.comdev, &mut overlapped, &mut len, FALSE)
+			GetOverlappedResult(self.handles.comdev, &mut overlapped, &mut len, TRUE)
 		} == FALSE {
-			// release mutex and return original error on failure
-			let error = io::Error::last_os_error();
-			let _res = unsafe { ReleaseMutex(self.mutex_read) };
-			debug_assert_ne!(_res, 0);
-			return Err(error);
+			return Err(io::Error::last_os_error());
 		}
 
-		// release mutex
-		let _res = unsafe { ReleaseMutex(self.mutex_read) };
-		debug_assert_ne!(_res, 0);
+		Ok(len as usize)
+	}
 
-		match len {
-			0 if buf.len() == 0 => Ok(0),
-			0 => Err(io::Error::new(io::ErrorKind::TimedOut,
-					"ReadFile() timed out (0 bytes read)")),
-			_ => Ok(len as usize)
+	// wait until any event of the event mask occurs or the timeout expires
+	// and return the events that occurred (none if the wait timed out). the
+	// caller must hold the read mutex.
+	fn wait_comm_event(&self, timeout: Option<Duration>) -> io::Result<CommEvents> {
+		// call WaitCommEvent() to issue overlapped I/O request completing
+		// when an event occurs
+		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+		overlapped.hEvent = self.handles.event_read;
+		let mut evt_mask: u32 = 0;
+		// implicitly resets event to non-signaled before returning
+		if unsafe {
+			WaitCommEvent(self.handles.comdev, &mut evt_mask, &mut overlapped)
+		} != FALSE {
+			// event occurred before the call, i.e., request completed
+			return Ok(CommEvents::from_bits(evt_mask));
+		}
+		let errcode = unsafe { GetLastError() };
+		if errcode != ERROR_IO_PENDING {
+			return Err(io::Error::from_raw_os_error(errcode as i32));
+		}
+
+		// wait for WaitCommEvent() to complete or timeout to occur
+		// https://docs.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-waitforsingleobject
+		let wait = match timeout {
+			Some(timeout) if !is_whole_ms(timeout) =>
+				wait_precise(self.handles.event_read, self.handles.timer_read, timeout),
+			timeout => unsafe {
+				WaitForSingleObject(self.handles.event_read, wait_timeout_ms(timeout))
+			}
+		};
+		let wait_error = match wait {
+			WAIT_OBJECT_0 | WAIT_TIMEOUT => None,
+			WAIT_FAILED => Some(io::Error::last_os_error()),
+			// WAIT_ABANDONED must not occur, because self.handles.event_read isn't a mutex
+			_ if cfg!(debug_assertions) => panic!("illegal WaitForSingleObject() return value"),
+			_ => unreachable!()
+		};
+
+		// unless it completed, the request is still pending and references
+		// the OVERLAPPED struct, which goes out of scope when this function
+		// returns. so the request must be cancelled to prevent undefined
+		// behavior. cancellation fails with ERROR_NOT_FOUND if the request
+		// raced to completion, which is fine.
+		// https://docs.microsoft.com/en-us/windows/win32/fileio/cancelioex-func
+		if wait != WAIT_OBJECT_0 {
+			let _res = unsafe { CancelIoEx(self.handles.comdev, &overlapped) };
+		}
+
+		// block until the request completed or its cancellation took effect
+		let mut _undef: u32 = 0;
+		if unsafe {
+			GetOverlappedResult(self.handles.comdev, &mut overlapped, &mut _undef, TRUE)
+		} == FALSE {
+			let errcode = unsafe { GetLastError() };
+			if errcode != ERROR_OPERATION_ABORTED {
+				return Err(wait_error.unwrap_or_else(||
+					io::Error::from_raw_os_error(errcode as i32)));
+			}
+			// no event occurred before cancellation
+			evt_mask = 0;
+		}
+
+		match wait_error {
+			Some(error) => Err(error),
+			None => Ok(CommEvents::from_bits(evt_mask))
 		}
 	}
 
 	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_with_timeout(buf, self.timeout_write.load())
+	}
+
+	// like write(), but with the given timeout instead of the write timeout
+	pub fn write_with_timeout(&self, buf: &[u8], timeout_write: Option<Duration>)
+			-> io::Result<usize> {
+		// get time before acquiring mutex to account for time spent waiting
+		let entry = clock::now();
+
+		// serialize writes, so overlapped writes of clones cannot interleave
+		// (may block up to the write timeout)
+		let turn = self.handles.write_queue.enter(timeout_write)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut,
+				"waiting for concurrent I/O timed out"))?;
+		let _guard = MutexGuard::acquire(self.handles.mutex_write, wait_timeout_ms(timeout_write))?;
+		if self.handles.disconnected.load(Ordering::Relaxed) {
+			return Err(windows_devices::invalidated_error());
+		}
+
+		// compute remaining write timeout. if waiting for the mutex (or
+		// previous chunks) took up all of it, still write whatever can be
+		// written without blocking.
+		let remaining = || timeout_write
+			.map(|timeout| timeout.saturating_sub(clock::elapsed(entry)));
+		if buf.is_empty() {
+			return self.write_overlapped(buf, remaining()).map_err(|e| self.check_error(e));
+		}
+
+		// write larger buffers in chunks until one is not written completely
+		// or other writers are waiting, which are served before the rest.
+		// errors after the first chunk are reported by the next write.
+		let mut written = 0;
+		for chunk in buf.chunks(MAX_TRANSFER) {
+			if written > 0 && turn.contended() {
+				break;
+			}
+			match self.write_overlapped(chunk, remaining()) {
+				Ok(len) => {
+					written += len;
+					if len < chunk.len() {
+						break;
+					}
+				},
+				Err(_) if written > 0 => break,
+				Err(e) => return Err(self.check_error(e))
+			}
+		}
+		Ok(written)
+	}
+
+	fn write_overlapped(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
 		// queue async write
 		let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
-		overlapped.hEvent = self.event_write;
+		overlapped.hEvent = self.handles.event_write;
 		// async write request can (theoretically) succeed immediately, queue
 		// successfully, or fail. even if it returns TRUE, the number of bytes
 		// written should be retrieved via GetOverlappedResult().
 		// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-writefile
 		if unsafe { WriteFile(
-			self.comdev,
+			self.handles.comdev,
 			buf.as_ptr(),
 			buf.len() as u32,
 			ptr::null_mut(),
@@ -498,21 +595,48 @@ impl SerialPort {
 			}
 		}
 
-		// wait for completion
-		// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getoverlappedresult
+		// fractional milliseconds are waited for via the timer, after which
+		// the result is checked without waiting (also if the wait failed)
+		let timeout_ms = match timeout {
+			Some(timeout) if !is_whole_ms(timeout) => {
+				let _wait = wait_precise(self.handles.event_write, self.handles.timer_write, timeout);
+				0
+			},
+			timeout => wait_timeout_ms(timeout)
+		};
+
+		// wait for completion or timeout (requires Windows 8 or later). a
+		// zero timeout returns ERROR_IO_INCOMPLETE instead of WAIT_TIMEOUT.
+		// https://docs.microsoft.com/en-us/windows/win32/api/ioapiset/nf-ioapiset-getoverlappedresultex
 		let mut len: u32 = 0;
-		if unsafe { GetOverlappedResult(
-			self.comdev,
-			&mut overlapped,
+		if unsafe { GetOverlappedResultEx(
+			self.handles.comdev,
+			&overlapped,
 			&mut len,
-			TRUE
+			timeout_ms,
+			FALSE
 		)} == FALSE {
-			// minimum supported rust version (MSRV) is 1.46, because WriteFile()
-			// may fail with ERROR_SEM_TIMEOUT, which is
-			// std::io::ErrorKind::TimedOut only since Rust 1.46, see:
-			// https://github.com/rust-lang/rust/pull/71756
 			let errcode = unsafe { GetLastError() };
-			return Err(io::Error::from_raw_os_error(errcode as i32));
+			if errcode != WAIT_TIMEOUT && errcode != ERROR_IO_INCOMPLETE {
+				return Err(io::Error::from_raw_os_error(errcode as i32));
+			}
+
+			// the request is still pending and references the OVERLAPPED
+			// struct and buf, so it must be cancelled before returning. the
+			// cancelled request reports the number of bytes written so far.
+			// https://docs.microsoft.com/en-us/windows/win32/fileio/cancelioex-func
+			let _res = unsafe { CancelIoEx(self.handles.comdev, &overlapped) };
+			if unsafe { GetOverlappedResult(
+				self.handles.comdev,
+				&overlapped,
+				&mut len,
+				TRUE
+			)} == FALSE {
+				let errcode = unsafe { GetLastError() };
+				if errcode != ERROR_OPERATION_ABORTED {
+					return Err(io::Error::from_raw_os_error(errcode as i32));
+				}
+			}
 		}
 
 		match len {
@@ -526,24 +650,302 @@ impl SerialPort {
 	pub fn flush(&self) -> io::Result<()> {
 		// https://docs.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-flushfilebuffers
 		// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-purgecomm#remarks
-		match unsafe { FlushFileBuffers(self.comdev) } {
+		match unsafe { FlushFileBuffers(self.handles.comdev) } {
 			0 => Err(io::Error::last_os_error()),
 			_ => Ok(()),
 		}
 	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	// this backend implements timeouts itself on top of non-blocking reads
+	// and writes, which raw COMMTIMEOUTS would break
+	pub fn comm_timeouts(&self) -> io::Result<CommTimeouts> {
+		Err(comm_timeouts_unsupported())
+	}
+
+	pub fn set_comm_timeouts(&self, _timeouts: CommTimeouts) -> io::Result<()> {
+		Err(comm_timeouts_unsupported())
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-escapecommfunction
+	fn escape_comm_function(&self, function: ESCAPE_COMM_FUNCTION) -> io::Result<()> {
+		match unsafe { EscapeCommFunction(self.handles.comdev, function) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	// returns an error if the handle became invalid, which is detected via
+	// GetCommModemStatus() failing (e.g., after device removal)
+	fn check_connected(&self) -> io::Result<()> {
+		if self.handles.disconnected.load(Ordering::Relaxed) {
+			return Err(windows_devices::invalidated_error());
+		}
+		if self.get_modem_status().is_err() {
+			self.handles.disconnected.store(true, Ordering::Relaxed);
+			return Err(windows_devices::invalidated_error());
+		}
+		Ok(())
+	}
+
+	// replace I/O errors caused by an invalid handle with a consistent error
+	fn check_error(&self, error: io::Error) -> io::Error {
+		match self.check_connected() {
+			Ok(()) => error,
+			Err(disconnected) => disconnected
+		}
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-getcommmodemstatus
+	fn get_modem_status(&self) -> io::Result<MODEM_STATUS_FLAGS> {
+		let mut status: MODEM_STATUS_FLAGS = 0;
+		match unsafe { GetCommModemStatus(self.handles.comdev, &mut status) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(status)
+		}
+	}
+
+	pub fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.escape_comm_function(if level { SETRTS } else { CLRRTS })
+	}
+
+	pub fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.escape_comm_function(if level { SETDTR } else { CLRDTR })
+	}
+
+	pub fn read_cts(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_CTS_ON != 0)
+	}
+
+	pub fn read_dsr(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_DSR_ON != 0)
+	}
+
+	pub fn read_ri(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_RING_ON != 0)
+	}
+
+	pub fn read_cd(&self) -> io::Result<bool> {
+		self.get_modem_status().map(|status| status & MS_RLSD_ON != 0)
+	}
+
+	// WaitCommEvent() is shared with read(), which is blocked while the read
+	// mutex is held, so wait in short steps releasing it in between. changes
+	// whose events a read consumed meanwhile are detected by comparing the
+	// modem status after each step.
+	pub fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		let events = CommEvents::CTS | CommEvents::DSR | CommEvents::RING | CommEvents::RLSD;
+		let entry = clock::now();
+		let initial = self.get_modem_status()?;
+		loop {
+			let remaining = timeout.map(|timeout| timeout.saturating_sub(clock::elapsed(entry)));
+			let step = remaining.map_or(MODEM_WAIT_STEP, |remaining| remaining.min(MODEM_WAIT_STEP));
+			match self.wait_for_event(events, Some(step)) {
+				Ok(_) => return Ok(()),
+				Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+				Err(e) => return Err(e)
+			}
+			if self.get_modem_status()? != initial {
+				return Ok(());
+			}
+			if remaining.map_or(false, |remaining| remaining <= MODEM_WAIT_STEP) {
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"waiting for modem status change timed out"));
+			}
+		}
+	}
+
+	pub fn baud_rate(&self) -> io::Result<u32> {
+		windows_dcb::baud_rate(self.handles.comdev)
+	}
+
+	pub fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		windows_dcb::set_baud_rate(self.handles.comdev, baud_rate)
+	}
+
+	pub fn data_bits(&self) -> io::Result<DataBits> {
+		windows_dcb::data_bits(self.handles.comdev)
+	}
+
+	pub fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		windows_dcb::set_data_bits(self.handles.comdev, data_bits)
+	}
+
+	pub fn parity(&self) -> io::Result<Parity> {
+		windows_dcb::parity(self.handles.comdev)
+	}
+
+	pub fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		windows_dcb::set_parity(self.handles.comdev, parity)
+	}
+
+	pub fn stop_bits(&self) -> io::Result<StopBits> {
+		windows_dcb::stop_bits(self.handles.comdev)
+	}
+
+	pub fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		windows_dcb::set_stop_bits(self.handles.comdev, stop_bits)
+	}
+
+	pub fn flow_control(&self) -> io::Result<FlowControl> {
+		windows_dcb::flow_control(self.handles.comdev)
+	}
+
+	pub fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		windows_dcb::set_flow_control(self.handles.comdev, flow_control)
+	}
+
+	pub fn local(&self) -> io::Result<bool> {
+		windows_dcb::local(self.handles.comdev)
+	}
+
+	// WaitCommEvent() is shared with read() and the event mask is fixed, so
+	// waiting on multiple ports would block their reads
+	pub fn wait_any(_ports: &[&Self], _timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"waiting for multiple ports is not supported by the experimental backend"))
+	}
+
+	pub fn set_local(&self, local: bool) -> io::Result<()> {
+		windows_dcb::set_local(self.handles.comdev, local)
+	}
+
+	pub fn parity_marking(&self) -> io::Result<bool> {
+		windows_dcb::parity_marking(self.handles.comdev)
+	}
+
+	pub fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		windows_dcb::set_parity_marking(self.handles.comdev, mark)
+	}
+
+	// see line::LineSerialPort for an emulation
+	pub fn set_canonical(&self, _mode: Option<LineMode>) -> io::Result<()> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"canonical mode is not supported on Windows"))
+	}
+
+	// COM ports never echo or process output
+	pub fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		Ok(TerminalOptions::default())
+	}
+
+	// see line::LineSerialPort for an emulation
+	pub fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		if options != TerminalOptions::default() {
+			return Err(io::Error::new(io::ErrorKind::Unsupported,
+				"terminal options are not supported on Windows"));
+		}
+		Ok(())
+	}
+
+	pub fn set_break(&self, level: bool) -> io::Result<()> {
+		windows_dcb::set_break(self.handles.comdev, level)
+	}
+
+	pub fn restore_settings(&self, settings: &SavedSettings) -> io::Result<()> {
+		let mut dcb = *settings;
+		windows_dcb::set_dcb(self.handles.comdev, &mut dcb)
+	}
+
+	pub fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.escape_comm_function(if paused { SETXOFF } else { SETXON })
+	}
+
+	#[cfg(feature = "config-dialog")]
+	pub fn config_dialog(&self, port_name: &OsStr, parent: HWND)
+			-> io::Result<Option<crate::DefaultCommConfig>> {
+		windows_dcb::config_dialog(self.handles.comdev, port_name, parent)
+	}
+
+	// https://docs.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-transmitcommchar
+	pub fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		match unsafe { TransmitCommChar(self.handles.comdev, byte as _) } {
+			0 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	pub fn bytes_to_read(&self) -> io::Result<usize> {
+		windows_dcb::queued(self.handles.comdev).map(|(input, _)| input as usize)
+	}
+
+	pub fn bytes_to_write(&self) -> io::Result<usize> {
+		windows_dcb::queued(self.handles.comdev).map(|(_, output)| output as usize)
+	}
+
+	pub fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		windows_dcb::purge(self.handles.comdev, input, output)
+	}
+
+	pub fn raw_handle(&self) -> HANDLE {
+		self.handles.comdev
+	}
+
+	pub fn inheritable(&self) -> io::Result<bool> {
+		windows_dcb::inheritable(self.handles.comdev)
+	}
+
+	pub fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+		windows_dcb::set_inheritable(self.handles.comdev, inheritable)
+	}
+
+	// COM ports are opened without sharing, which Windows cannot relax for
+	// an open handle. to hand a port to another process, let it inherit the
+	// handle instead.
+	pub fn lock(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	pub fn unlock(&self) -> io::Result<()> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"COM ports cannot be shared while open on Windows"))
+	}
 }
 
-impl Drop for SerialPort {
-	fn drop(&mut self) {
-		// close all handles
-		// https://docs.microsoft.com/en-us/windows/win32/api/handleapi/nf-handleapi-closehandle
-		let _res = unsafe { CloseHandle(self.comdev) };
-		debug_assert_ne!(_res, 0);
-		let _res = unsafe { CloseHandle(self.event_read) };
-		debug_assert_ne!(_res, 0);
-		let _res = unsafe { CloseHandle(self.event_write) };
-		debug_assert_ne!(_res, 0);
-		let _res = unsafe { CloseHandle(self.mutex_read) };
-		debug_assert_ne!(_res, 0);
+// read() relies on WaitCommEvent() with a fixed event mask, which conflicts
+// with the readiness notification of the reactor
+pub struct Poller(std::convert::Infallible);
+
+impl Poller {
+	pub fn new() -> io::Result<Self> {
+		Err(io::Error::new(io::ErrorKind::Unsupported,
+			"the reactor is not supported by the experimental backend"))
+	}
+
+	pub fn add(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn rearm(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn remove(&self, _port: &SerialPort, _token: usize) -> io::Result<()> {
+		match self.0 {}
+	}
+
+	pub fn wait(&self) -> io::Result<Option<usize>> {
+		match self.0 {}
+	}
+
+	pub fn shutdown(&self) -> io::Result<()> {
+		match self.0 {}
 	}
 }
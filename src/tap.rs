@@ -0,0 +1,606 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{DataBits, FlowControl, LineMode, Parity, SerialPort, StopBits, TerminalOptions};
+use crate::backend::Backend;
+
+// pcapng block types and the link type of captured serial data, see:
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+const PCAPNG_OPT_EPB_FLAGS: u16 = 2;
+const PCAPNG_EPB_FLAGS_INBOUND: u32 = 0b01;
+const PCAPNG_EPB_FLAGS_OUTBOUND: u32 = 0b10;
+// LINKTYPE_USER0, reserved for private use
+const PCAPNG_LINKTYPE: u16 = 147;
+
+// raw records start with a timestamp (microseconds since the Unix epoch), the
+// direction, and the payload length, all little-endian
+pub(crate) const RAW_HEADER_LEN: usize = 8 + 1 + 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+	Rx,
+	Tx
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+	// binary records of timestamp (u64 microseconds since the Unix epoch),
+	// direction (u8, 0 = RX, 1 = TX), length (u32), and data
+	Raw,
+	// one line per transfer, e.g., "1690000000.123456 TX 48 65 6c 6c 6f"
+	Hex,
+	// pcapng with link type USER0, RX inbound and TX outbound, which
+	// Wireshark can dissect with a custom Lua dissector
+	PcapNg,
+	// human-readable hex and ASCII dump like xxd, preceded by a line with
+	// timestamp, direction, and length
+	HexDump
+}
+
+struct Capture {
+	writer: Box<dyn Write + Send>,
+	format: CaptureFormat,
+	// first capture error, which must not affect the wrapped port's I/O
+	error: Option<io::Error>
+}
+
+// transparent wrapper that captures all bytes read from and written to a
+// serial port along with timestamps. clones share the same capture.
+pub struct TapSerialPort {
+	port: SerialPort,
+	capture: Arc<Mutex<Capture>>
+}
+
+impl Capture {
+	fn write_header(&mut self) -> io::Result<()> {
+		if self.format != CaptureFormat::PcapNg {
+			return Ok(());
+		}
+
+		let mut shb = Vec::with_capacity(28);
+		shb.extend_from_slice(&PCAPNG_SECTION_HEADER.to_ne_bytes());
+		shb.extend_from_slice(&28u32.to_ne_bytes());
+		shb.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_ne_bytes());
+		shb.extend_from_slice(&1u16.to_ne_bytes());
+		shb.extend_from_slice(&0u16.to_ne_bytes());
+		// section length unknown
+		shb.extend_from_slice(&(-1i64).to_ne_bytes());
+		shb.extend_from_slice(&28u32.to_ne_bytes());
+		self.writer.write_all(&shb)?;
+
+		// default timestamp resolution of microseconds and no snap length
+		let mut idb = Vec::with_capacity(20);
+		idb.extend_from_slice(&PCAPNG_INTERFACE_DESCRIPTION.to_ne_bytes());
+		idb.extend_from_slice(&20u32.to_ne_bytes());
+		idb.extend_from_slice(&PCAPNG_LINKTYPE.to_ne_bytes());
+		idb.extend_from_slice(&0u16.to_ne_bytes());
+		idb.extend_from_slice(&0u32.to_ne_bytes());
+		idb.extend_from_slice(&20u32.to_ne_bytes());
+		self.writer.write_all(&idb)?;
+		self.writer.flush()
+	}
+
+	fn record(&mut self, direction: Direction, data: &[u8]) {
+		if data.is_empty() || self.error.is_some() {
+			return;
+		}
+
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+			.unwrap_or_default();
+		let res = match self.format {
+			CaptureFormat::Raw => self.record_raw(timestamp, direction, data),
+			CaptureFormat::Hex => self.record_hex(timestamp, direction, data),
+			CaptureFormat::PcapNg => self.record_pcapng(timestamp, direction, data),
+			CaptureFormat::HexDump => self.record_hexdump(timestamp, direction, data)
+		}.and_then(|()| self.writer.flush());
+
+		if let Err(e) = res {
+			self.error = Some(e);
+		}
+	}
+
+	fn record_raw(&mut self, timestamp: Duration, direction: Direction, data: &[u8])
+			-> io::Result<()> {
+		let mut header = [0u8; RAW_HEADER_LEN];
+		header[0..8].copy_from_slice(&(timestamp.as_micros() as u64).to_le_bytes());
+		header[8] = match direction {
+			Direction::Rx => 0,
+			Direction::Tx => 1
+		};
+		header[9..13].copy_from_slice(&(data.len() as u32).to_le_bytes());
+		self.writer.write_all(&header)?;
+		self.writer.write_all(data)
+	}
+
+	fn record_hex(&mut self, timestamp: Duration, direction: Direction, data: &[u8])
+			-> io::Result<()> {
+		let direction = match direction {
+			Direction::Rx => "RX",
+			Direction::Tx => "TX"
+		};
+		write!(self.writer, "{}.{:06} {}", timestamp.as_secs(),
+			timestamp.subsec_micros(), direction)?;
+		for byte in data {
+			write!(self.writer, " {:02x}", byte)?;
+		}
+		writeln!(self.writer)
+	}
+
+	fn record_hexdump(&mut self, timestamp: Duration, direction: Direction, data: &[u8])
+			-> io::Result<()> {
+		let direction = match direction {
+			Direction::Rx => "RX",
+			Direction::Tx => "TX"
+		};
+		writeln!(self.writer, "{}.{:06} {} {} bytes", timestamp.as_secs(),
+			timestamp.subsec_micros(), direction, data.len())?;
+
+		for (i, line) in data.chunks(16).enumerate() {
+			write!(self.writer, "{:08x}:", i * 16)?;
+			for pos in 0..16 {
+				if pos % 2 == 0 {
+					write!(self.writer, " ")?;
+				}
+				match line.get(pos) {
+					Some(byte) => write!(self.writer, "{:02x}", byte)?,
+					None => write!(self.writer, "  ")?
+				}
+			}
+			write!(self.writer, "  ")?;
+			for &byte in line {
+				let c = if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' };
+				write!(self.writer, "{}", c)?;
+			}
+			writeln!(self.writer)?;
+		}
+		Ok(())
+	}
+
+	fn record_pcapng(&mut self, timestamp: Duration, direction: Direction, data: &[u8])
+			-> io::Result<()> {
+		let padded_len = (data.len() + 3) & !3;
+		let block_len = (32 + padded_len + 12) as u32;
+		let timestamp = timestamp.as_micros() as u64;
+		let flags = match direction {
+			Direction::Rx => PCAPNG_EPB_FLAGS_INBOUND,
+			Direction::Tx => PCAPNG_EPB_FLAGS_OUTBOUND
+		};
+
+		let mut epb = Vec::with_capacity(block_len as usize);
+		epb.extend_from_slice(&PCAPNG_ENHANCED_PACKET.to_ne_bytes());
+		epb.extend_from_slice(&block_len.to_ne_bytes());
+		// interface ID
+		epb.extend_from_slice(&0u32.to_ne_bytes());
+		epb.extend_from_slice(&((timestamp >> 32) as u32).to_ne_bytes());
+		epb.extend_from_slice(&(timestamp as u32).to_ne_bytes());
+		epb.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+		epb.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+		epb.extend_from_slice(data);
+		epb.resize(28 + padded_len, 0);
+		epb.extend_from_slice(&PCAPNG_OPT_EPB_FLAGS.to_ne_bytes());
+		epb.extend_from_slice(&4u16.to_ne_bytes());
+		epb.extend_from_slice(&flags.to_ne_bytes());
+		epb.extend_from_slice(&PCAPNG_OPT_ENDOFOPT.to_ne_bytes());
+		epb.extend_from_slice(&0u16.to_ne_bytes());
+		epb.extend_from_slice(&block_len.to_ne_bytes());
+		self.writer.write_all(&epb)
+	}
+}
+
+// collects a record's output and emits it as a single event on flush(),
+// which follows every record
+#[cfg(feature = "tracing")]
+struct TracingWriter(Vec<u8>);
+
+#[cfg(feature = "tracing")]
+impl Write for TracingWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		if !self.0.is_empty() {
+			let dump = String::from_utf8_lossy(&self.0);
+			tracing::debug!(target: "serial::tap", "{}", dump.trim_end());
+			self.0.clear();
+		}
+		Ok(())
+	}
+}
+
+impl TapSerialPort {
+	// wrap a port and capture its traffic to the given writer
+	pub fn new<W>(port: SerialPort, writer: W, format: CaptureFormat) -> io::Result<Self>
+			where W: Write + Send + 'static {
+		let mut capture = Capture {
+			writer: Box::new(writer),
+			format,
+			error: None
+		};
+		capture.write_header()?;
+
+		Ok(Self {
+			port,
+			capture: Arc::new(Mutex::new(capture))
+		})
+	}
+
+	// wrap a port and log its traffic as hex dumps via tracing debug events
+	// (target "serial::tap"), with timestamps added by the subscriber
+	#[cfg(feature = "tracing")]
+	pub fn with_tracing(port: SerialPort) -> Self {
+		Self::new(port, TracingWriter(Vec::new()), CaptureFormat::HexDump)
+			.expect("hex dumps do not write a header")
+	}
+
+	// wrap a port and capture its traffic to a newly created file
+	pub fn create<P: AsRef<Path>>(port: SerialPort, path: P, format: CaptureFormat)
+			-> io::Result<Self> {
+		let file = BufWriter::new(File::create(path)?);
+		Self::new(port, file, format)
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone()?,
+			capture: self.capture.clone()
+		})
+	}
+
+	pub fn try_clone_shared(&self) -> io::Result<Self> {
+		Ok(Self {
+			port: self.port.try_clone_shared()?,
+			capture: self.capture.clone()
+		})
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Capture> {
+		self.capture.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	// capture errors do not interfere with the port's I/O, so they are
+	// reported separately. returns the first error that occurred (if any),
+	// capturing is suspended until the error is taken.
+	pub fn take_capture_error(&self) -> Option<io::Error> {
+		self.lock().error.take()
+	}
+
+	pub fn get_ref(&self) -> &SerialPort {
+		&self.port
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		let len = (&self.port).read(buf)?;
+		self.lock().record(Direction::Rx, &buf[..len]);
+		Ok(len)
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		let len = (&self.port).write(buf)?;
+		self.lock().record(Direction::Tx, &buf[..len]);
+		Ok(len)
+	}
+
+	pub fn flush(&self) -> io::Result<()> {
+		(&self.port).flush()
+	}
+}
+
+impl Backend for TapSerialPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		TapSerialPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		TapSerialPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		let len = self.port.read_with_timeout(buf, timeout)?;
+		self.lock().record(Direction::Rx, &buf[..len]);
+		Ok(len)
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		let len = self.port.write_with_timeout(buf, timeout)?;
+		self.lock().record(Direction::Tx, &buf[..len]);
+		Ok(len)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		TapSerialPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		TapSerialPort::try_clone(self).map(|tap| Box::new(tap) as Box<dyn Backend>)
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		TapSerialPort::try_clone_shared(self).map(|tap| Box::new(tap) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.port.read_timeout()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.port.write_timeout()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_read_timeout(timeout)
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.set_write_timeout(timeout)
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.port.set_rts(level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.port.set_dtr(level)
+	}
+
+	fn read_cts(&self) -> io::Result<bool> {
+		self.port.read_cts()
+	}
+
+	fn read_dsr(&self) -> io::Result<bool> {
+		self.port.read_dsr()
+	}
+
+	fn read_ri(&self) -> io::Result<bool> {
+		self.port.read_ri()
+	}
+
+	fn read_cd(&self) -> io::Result<bool> {
+		self.port.read_cd()
+	}
+
+	fn wait_modem_change(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.port.wait_modem_change(timeout)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		self.port.baud_rate()
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.port.set_baud_rate(baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		self.port.data_bits()
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		self.port.set_data_bits(data_bits)
+	}
+
+	fn parity(&self) -> io::Result<Parity> {
+		self.port.parity()
+	}
+
+	fn set_parity(&self, parity: Parity) -> io::Result<()> {
+		self.port.set_parity(parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<StopBits> {
+		self.port.stop_bits()
+	}
+
+	fn set_stop_bits(&self, stop_bits: StopBits) -> io::Result<()> {
+		self.port.set_stop_bits(stop_bits)
+	}
+
+	fn flow_control(&self) -> io::Result<FlowControl> {
+		self.port.flow_control()
+	}
+
+	fn set_flow_control(&self, flow_control: FlowControl) -> io::Result<()> {
+		self.port.set_flow_control(flow_control)
+	}
+
+	fn local(&self) -> io::Result<bool> {
+		self.port.local()
+	}
+
+	fn set_local(&self, local: bool) -> io::Result<()> {
+		self.port.set_local(local)
+	}
+
+	fn parity_marking(&self) -> io::Result<bool> {
+		self.port.parity_marking()
+	}
+
+	fn set_parity_marking(&self, mark: bool) -> io::Result<()> {
+		self.port.set_parity_marking(mark)
+	}
+
+	fn set_canonical(&self, mode: Option<LineMode>) -> io::Result<()> {
+		self.port.set_canonical(mode)
+	}
+
+	fn terminal_options(&self) -> io::Result<TerminalOptions> {
+		self.port.terminal_options()
+	}
+
+	fn set_terminal_options(&self, options: TerminalOptions) -> io::Result<()> {
+		self.port.set_terminal_options(options)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		self.port.set_break(level)
+	}
+
+	fn set_transmit_paused(&self, paused: bool) -> io::Result<()> {
+		self.port.set_transmit_paused(paused)
+	}
+
+	fn transmit_immediate(&self, byte: u8) -> io::Result<()> {
+		self.port.transmit_immediate(byte)
+	}
+
+	fn bytes_to_read(&self) -> io::Result<usize> {
+		self.port.bytes_to_read()
+	}
+
+	fn bytes_to_write(&self) -> io::Result<usize> {
+		self.port.bytes_to_write()
+	}
+
+	fn lock(&self) -> io::Result<()> {
+		self.port.lock()
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		self.port.unlock()
+	}
+
+	fn purge(&self, input: bool, output: bool) -> io::Result<()> {
+		self.port.purge(input, output)
+	}
+}
+
+impl io::Read for TapSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		TapSerialPort::read(self, buf)
+	}
+}
+
+impl io::Read for &TapSerialPort {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		TapSerialPort::read(self, buf)
+	}
+}
+
+impl io::Write for TapSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		TapSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		TapSerialPort::flush(self)
+	}
+}
+
+impl io::Write for &TapSerialPort {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		TapSerialPort::write(self, buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		TapSerialPort::flush(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::replay;
+	use crate::sim::SimulatedSerialPort;
+
+	// writer whose output remains accessible after it was moved into a tap
+	#[derive(Clone, Default)]
+	struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for SharedWriter {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	struct FailingWriter;
+
+	impl Write for FailingWriter {
+		fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+			Err(io::Error::new(io::ErrorKind::Other, "disk full"))
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	// capture a request and its reply in the given format
+	fn capture(format: CaptureFormat) -> Vec<u8> {
+		let sim = SimulatedSerialPort::new(Some(Duration::from_secs(1)));
+		sim.on(b"ping", b"0123456789abcdef\r\n", Duration::ZERO);
+		let writer = SharedWriter::default();
+		let tap = TapSerialPort::new(sim.into_serial_port(), writer.clone(), format).unwrap();
+
+		tap.write(b"ping").unwrap();
+		let mut buf = [0u8; 32];
+		assert_eq!(tap.read(&mut buf).unwrap(), 18);
+		assert!(tap.take_capture_error().is_none());
+
+		let output = writer.0.lock().unwrap().clone();
+		output
+	}
+
+	// all formats can be loaded for replay
+	#[test]
+	fn formats_round_trip() {
+		for format in [CaptureFormat::Raw, CaptureFormat::Hex, CaptureFormat::PcapNg,
+				CaptureFormat::HexDump] {
+			let output = capture(format);
+			let records = match format {
+				CaptureFormat::Raw => replay::parse_raw(&output[..]),
+				CaptureFormat::Hex => replay::parse_hex(&output[..]),
+				CaptureFormat::PcapNg => replay::parse_pcapng(&output[..]),
+				CaptureFormat::HexDump => replay::parse_hexdump(&output[..])
+			}.unwrap();
+
+			assert_eq!(records.len(), 2, "{:?}", format);
+			assert_eq!(records[0].direction, Direction::Tx);
+			assert_eq!(records[0].data, b"ping");
+			assert_eq!(records[1].direction, Direction::Rx);
+			assert_eq!(records[1].data, b"0123456789abcdef\r\n");
+			assert!(records[0].timestamp <= records[1].timestamp);
+		}
+	}
+
+	#[test]
+	fn hexdump_layout() {
+		let output = String::from_utf8(capture(CaptureFormat::HexDump)).unwrap();
+		let lines: Vec<&str> = output.lines().collect();
+		assert_eq!(lines.len(), 5);
+		assert!(lines[0].ends_with(" TX 4 bytes"));
+		assert_eq!(lines[1], format!("00000000: 7069 6e67{}  ping", " ".repeat(30)));
+		assert!(lines[2].ends_with(" RX 18 bytes"));
+		assert_eq!(lines[3], "00000000: 3031 3233 3435 3637 3839 6162 6364 6566  0123456789abcdef");
+		assert_eq!(lines[4], format!("00000010: 0d0a{}  ..", " ".repeat(35)));
+	}
+
+	// capture errors are reported separately from the port's I/O
+	#[test]
+	fn capture_errors_do_not_affect_io() {
+		let sim = SimulatedSerialPort::new(Some(Duration::ZERO));
+		let tap = TapSerialPort::new(sim.into_serial_port(), FailingWriter,
+			CaptureFormat::Hex).unwrap();
+		assert_eq!(tap.write(b"ab").unwrap(), 2);
+		assert_eq!(tap.write(b"cd").unwrap(), 2);
+		assert_eq!(tap.take_capture_error().unwrap().kind(), io::ErrorKind::Other);
+		assert!(tap.take_capture_error().is_none());
+	}
+}
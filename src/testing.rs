@@ -0,0 +1,433 @@
+// test support for automated tests of this crate and of applications using
+// it, which drives a virtual port pair (a pseudoterminal pair on Unix, a
+// com0com pair on Windows) like a device connected to the port under test,
+// e.g.:
+//   let pty = PtyPair::open(Some(Duration::from_millis(100)))?;
+//   let pusher = pty.push(&[(Duration::from_millis(10), b"OK\r\n")])?;
+//   let port = pty.port.try_clone()?;  // hand the port to the code under test
+//   expect(&pty.device, b"AT\r\n", Duration::from_secs(1))?;
+// failed expectations are returned as errors, so tests can use the ? operator.
+
+use std::collections::VecDeque;
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::SerialPort;
+use crate::backend::{AtomicTimeout, Backend};
+
+// slack allowed for timeouts to expire late, e.g., due to scheduling
+pub const TIMING_TOLERANCE: Duration = Duration::from_millis(50);
+
+// a pseudoterminal pair, whose master side plays the device connected to the
+// port under test, i.e., its slave side
+#[cfg(unix)]
+pub struct PtyPair {
+	pub device: SerialPort,
+	pub port: SerialPort,
+	path: OsString
+}
+
+#[cfg(unix)]
+impl PtyPair {
+	// open a pair with timeout applied to both sides
+	pub fn open(timeout: Option<Duration>) -> io::Result<Self> {
+		let (device, path) = SerialPort::open_pty(timeout)?;
+		let port = SerialPort::open(&path, timeout)?;
+		Ok(Self { device, port, path })
+	}
+
+	// path of the slave device, e.g., to open it again from the code under
+	// test
+	pub fn path(&self) -> &OsStr {
+		&self.path
+	}
+
+	// send each chunk to the port after its delay (relative to the previous
+	// chunk) from a background thread, which returns the first error
+	pub fn push(&self, script: &[(Duration, &[u8])]) -> io::Result<JoinHandle<io::Result<()>>> {
+		push(&self.device, script)
+	}
+
+	// read what the port sent, see expect()
+	pub fn expect(&self, expected: &[u8], timeout: Duration) -> io::Result<()> {
+		expect(&self.device, expected, timeout)
+	}
+
+	// check that the port sent nothing for duration
+	pub fn expect_silence(&self, duration: Duration) -> io::Result<()> {
+		expect_silence(&self.device, duration)
+	}
+}
+
+// connected com0com port pairs installed on this machine, e.g.,
+// ("CNCA0", "CNCB0"), see https://com0com.sourceforge.net/
+#[cfg(windows)]
+pub fn com0com_pairs() -> io::Result<Vec<(OsString, OsString)>> {
+	crate::sys::com0com_pairs()
+}
+
+// the first com0com pair, whose first port plays the device connected to the
+// port under test, i.e., its second port
+#[cfg(windows)]
+pub fn open_com0com(timeout: Option<Duration>) -> io::Result<(SerialPort, SerialPort)> {
+	let (device, port) = com0com_pairs()?.into_iter().next()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no com0com port pair installed"))?;
+	Ok((SerialPort::open(&device, timeout)?, SerialPort::open(&port, timeout)?))
+}
+
+// a connected port pair (device, port) backed by the OS, if available, i.e.,
+// a pseudoterminal pair on Unix or the first com0com pair on Windows. falls
+// back to memory_pair() otherwise, e.g., on Windows machines without com0com,
+// so tests run everywhere, but only exercise the OS backend where possible.
+pub fn virtual_pair(timeout: Option<Duration>) -> io::Result<(SerialPort, SerialPort)> {
+	#[cfg(unix)]
+	let pair = PtyPair::open(timeout).map(|pty| (pty.device, pty.port));
+	#[cfg(windows)]
+	let pair = open_com0com(timeout);
+	#[cfg(not(any(unix, windows)))]
+	let pair = Err(io::Error::new(io::ErrorKind::Unsupported, "no virtual port pairs"));
+
+	pair.or_else(|_| Ok(memory_pair(timeout)))
+}
+
+// one direction of a memory pair
+#[derive(Default)]
+struct Pipe {
+	data: Mutex<VecDeque<u8>>,
+	available: Condvar
+}
+
+impl Pipe {
+	fn lock(&self) -> MutexGuard<'_, VecDeque<u8>> {
+		self.data.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+// port of a memory pair, which honors read timeouts like an OS port, but
+// never blocks writing
+struct MemoryPort {
+	rx: Arc<Pipe>,
+	tx: Arc<Pipe>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+// two ports connected in memory, e.g., to run the checks where no virtual
+// port pair is available
+pub fn memory_pair(timeout: Option<Duration>) -> (SerialPort, SerialPort) {
+	let (a, b) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+	let port = |rx, tx| SerialPort::from_backend(Box::new(MemoryPort {
+		rx,
+		tx,
+		timeout_read: AtomicTimeout::new(timeout),
+		timeout_write: AtomicTimeout::new(timeout)
+	}));
+	(port(a.clone(), b.clone()), port(b, a))
+}
+
+impl MemoryPort {
+	fn clone_with(&self, timeout_read: AtomicTimeout, timeout_write: AtomicTimeout)
+			-> io::Result<Box<dyn Backend>> {
+		Ok(Box::new(Self {
+			rx: self.rx.clone(),
+			tx: self.tx.clone(),
+			timeout_read,
+			timeout_write
+		}))
+	}
+}
+
+impl Backend for MemoryPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		let deadline = timeout.map(|timeout| Instant::now() + timeout);
+		let mut data = self.rx.lock();
+		while data.is_empty() {
+			data = match deadline {
+				Some(deadline) => {
+					let now = Instant::now();
+					if now >= deadline {
+						return Err(io::Error::new(io::ErrorKind::TimedOut,
+							"reading from memory pair timed out"));
+					}
+					self.rx.available.wait_timeout(data, deadline - now)
+						.unwrap_or_else(|e| e.into_inner()).0
+				},
+				None => self.rx.available.wait(data).unwrap_or_else(|e| e.into_inner())
+			};
+		}
+		let len = data.len().min(buf.len());
+		for (dst, src) in buf.iter_mut().zip(data.drain(..len)) {
+			*dst = src;
+		}
+		Ok(len)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.tx.lock().extend(buf);
+		self.tx.available.notify_all();
+		Ok(buf.len())
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], _timeout: Option<Duration>) -> io::Result<usize> {
+		self.write(buf)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		self.clone_with(self.timeout_read.copy(), self.timeout_write.copy())
+	}
+
+	fn try_clone_shared(&self) -> io::Result<Box<dyn Backend>> {
+		self.clone_with(self.timeout_read.share(), self.timeout_write.share())
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	// written data is received immediately, so purging output is a no-op
+	fn purge(&self, input: bool, _output: bool) -> io::Result<()> {
+		if input {
+			self.rx.lock().clear();
+		}
+		Ok(())
+	}
+}
+
+// write each chunk of script to port after its delay (relative to the
+// previous chunk) from a background thread, which returns the first error
+pub fn push(port: &SerialPort, script: &[(Duration, &[u8])]) -> io::Result<JoinHandle<io::Result<()>>> {
+	let mut port = port.try_clone()?;
+	let script: Vec<(Duration, Vec<u8>)> = script.iter()
+		.map(|(delay, data)| (*delay, data.to_vec()))
+		.collect();
+	thread::Builder::new()
+		.name("serial-test-push".into())
+		.spawn(move || {
+			for (delay, data) in script {
+				thread::sleep(delay);
+				port.write_all(&data)?;
+			}
+			port.flush()
+		})
+}
+
+// read exactly the expected bytes from port within timeout. fails as soon as
+// a byte differs, so unexpected output is reported without waiting.
+pub fn expect(port: &SerialPort, expected: &[u8], timeout: Duration) -> io::Result<()> {
+	let deadline = Instant::now() + timeout;
+	let mut received = Vec::with_capacity(expected.len());
+	let mut buf = [0u8; 256];
+	while received.len() < expected.len() {
+		let now = Instant::now();
+		if now >= deadline {
+			return Err(io::Error::new(io::ErrorKind::TimedOut, format!(
+				"expected {:?}, received {:?} before timeout",
+				String::from_utf8_lossy(expected), String::from_utf8_lossy(&received))));
+		}
+		let len = (expected.len() - received.len()).min(buf.len());
+		match (&*port).read(&mut buf[..len]) {
+			Ok(len) => received.extend_from_slice(&buf[..len]),
+			Err(ref e) if e.kind() == io::ErrorKind::TimedOut
+				|| e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		}
+		if !expected.starts_with(&received) {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+				"expected {:?}, received {:?}",
+				String::from_utf8_lossy(expected), String::from_utf8_lossy(&received))));
+		}
+	}
+	Ok(())
+}
+
+// check that nothing can be read from port for duration
+pub fn expect_silence(port: &SerialPort, duration: Duration) -> io::Result<()> {
+	let deadline = Instant::now() + duration;
+	let mut buf = [0u8; 256];
+	loop {
+		let now = Instant::now();
+		if now >= deadline {
+			return Ok(());
+		}
+		match (&*port).read(&mut buf) {
+			Ok(0) => (),
+			Ok(len) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+				"expected silence, received {:?}", String::from_utf8_lossy(&buf[..len])))),
+			Err(ref e) if e.kind() == io::ErrorKind::TimedOut
+				|| e.kind() == io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e)
+		}
+	}
+}
+
+// check that reading from port times out after its read timeout (within
+// TIMING_TOLERANCE) without returning data
+pub fn expect_read_timeout(port: &SerialPort) -> io::Result<()> {
+	let timeout = port.read_timeout().ok_or_else(|| io::Error::new(
+		io::ErrorKind::InvalidInput, "port has no read timeout"))?;
+	let start = Instant::now();
+	let res = (&*port).read(&mut [0u8; 1]);
+	let elapsed = start.elapsed();
+	match res {
+		Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+		Err(e) => return Err(e),
+		Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+			"expected read to time out, but it returned data"))
+	}
+	if elapsed < timeout || elapsed > timeout + TIMING_TOLERANCE {
+		return Err(io::Error::new(io::ErrorKind::Other, format!(
+			"read timeout of {:?} expired after {:?}", timeout, elapsed)));
+	}
+	Ok(())
+}
+
+// regression checks of the crate's read, write, clone, and timeout logic
+// against the port connected to device. returns the first failure, prefixed
+// with the name of the check.
+pub fn run_checks(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	type Check = fn(&SerialPort, &SerialPort) -> io::Result<()>;
+	let checks: [(&str, Check); 6] = [
+		("write", check_write),
+		("read", check_read),
+		("read timeout", check_read_timeout),
+		("zero timeout", check_zero_timeout),
+		("delayed read", check_delayed_read),
+		("clone", check_clone)
+	];
+	let timeouts = (device.read_timeout(), port.read_timeout());
+	for (name, check) in checks {
+		// checks may change timeouts, so every check starts from scratch
+		device.set_read_timeout(Some(Duration::from_millis(100)))?;
+		port.set_read_timeout(Some(Duration::from_millis(100)))?;
+		port.purge(true, true)?;
+		device.purge(true, true)?;
+		check(device, port).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", name, e)))?;
+	}
+	device.set_read_timeout(timeouts.0)?;
+	port.set_read_timeout(timeouts.1)
+}
+
+// run the checks against virtual_pair()
+pub fn run_virtual_checks() -> io::Result<()> {
+	let (device, port) = virtual_pair(None)?;
+	run_checks(&device, &port)
+}
+
+fn check_write(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	let data: Vec<u8> = (0..=255).cycle().take(4096).collect();
+	(&*port).write_all(&data)?;
+	(&*port).flush()?;
+	expect(device, &data, Duration::from_secs(1))
+}
+
+fn check_read(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	(&*device).write_all(b"hello")?;
+	let mut buf = [0u8; 5];
+	(&*port).read_exact(&mut buf)?;
+	if &buf != b"hello" {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "received data differs"));
+	}
+	expect_silence(port, Duration::from_millis(50))
+}
+
+fn check_read_timeout(_device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	expect_read_timeout(port)?;
+	port.set_read_timeout(Some(Duration::from_millis(250)))?;
+	expect_read_timeout(port)
+}
+
+// a zero timeout returns immediately, with or without data
+fn check_zero_timeout(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	port.set_read_timeout(Some(Duration::ZERO))?;
+	let start = Instant::now();
+	match (&*port).read(&mut [0u8; 1]) {
+		Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+		Err(e) => return Err(e),
+		Ok(_) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+			"read returned data that was never sent"))
+	}
+	if start.elapsed() > TIMING_TOLERANCE {
+		return Err(io::Error::new(io::ErrorKind::Other, "zero timeout blocked"));
+	}
+
+	(&*device).write_all(b"x")?;
+	thread::sleep(Duration::from_millis(20));
+	expect(port, b"x", TIMING_TOLERANCE)
+}
+
+// data arriving while a read blocks is returned before the timeout expires
+fn check_delayed_read(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	port.set_read_timeout(Some(Duration::from_secs(1)))?;
+	let pusher = push(device, &[(Duration::from_millis(100), b"late")])?;
+	let start = Instant::now();
+	expect(port, b"late", Duration::from_secs(1))?;
+	let elapsed = start.elapsed();
+	pusher.join().map_err(|_| io::Error::new(io::ErrorKind::Other, "push thread panicked"))??;
+	if elapsed < Duration::from_millis(100) || elapsed > Duration::from_millis(100) + TIMING_TOLERANCE {
+		return Err(io::Error::new(io::ErrorKind::Other, format!(
+			"data pushed after 100ms was read after {:?}", elapsed)));
+	}
+	Ok(())
+}
+
+// clones share the device, but not their timeouts
+fn check_clone(device: &SerialPort, port: &SerialPort) -> io::Result<()> {
+	let clone = port.try_clone()?;
+	clone.set_read_timeout(Some(Duration::from_millis(20)))?;
+	if port.read_timeout() != Some(Duration::from_millis(100)) {
+		return Err(io::Error::new(io::ErrorKind::Other, "clone changed timeout of original"));
+	}
+	expect_read_timeout(&clone)?;
+
+	(&clone).write_all(b"from clone")?;
+	expect(device, b"from clone", Duration::from_secs(1))?;
+	(&*device).write_all(b"to original")?;
+	expect(port, b"to original", Duration::from_secs(1))?;
+
+	let shared = port.try_clone_shared()?;
+	shared.set_read_timeout(Some(Duration::from_millis(30)))?;
+	if port.read_timeout() != Some(Duration::from_millis(30)) {
+		return Err(io::Error::new(io::ErrorKind::Other, "shared clone did not change timeout"));
+	}
+	Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn virtual_checks() {
+		run_virtual_checks().unwrap();
+	}
+}
@@ -0,0 +1,554 @@
+extern crate async_io;
+extern crate futures_lite;
+extern crate nusb;
+
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use self::futures_lite::future;
+use self::nusb::Interface;
+use self::nusb::descriptors::EndpointType;
+use self::nusb::transfer::{Control, ControlType, Direction, Queue, Recipient,
+	RequestBuffer, TransferError};
+
+use crate::{clock, DataBits, SerialPort};
+use crate::backend::{AtomicTimeout, Backend};
+use crate::sys::FairQueue;
+
+// USB communications device class (CDC) constants, see "Universal Serial Bus
+// Class Definitions for Communications Devices" and its PSTN subclass spec
+const CDC_CLASS_COMM: u8 = 0x02;
+const CDC_CLASS_DATA: u8 = 0x0A;
+const CDC_SET_LINE_CODING: u8 = 0x20;
+const CDC_GET_LINE_CODING: u8 = 0x21;
+const CDC_SET_CONTROL_LINE_STATE: u8 = 0x22;
+const CDC_SEND_BREAK: u8 = 0x23;
+const CDC_CONTROL_DTR: u16 = 0x0001;
+const CDC_CONTROL_RTS: u16 = 0x0002;
+// SEND_BREAK duration that sends a break until the next SEND_BREAK
+const CDC_BREAK_ON: u16 = 0xFFFF;
+
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(1000);
+const BULK_IN_LEN: usize = 4096;
+
+// bCharFormat and bParityType values of the CDC line coding structure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopBits {
+	One = 0,
+	OnePointFive = 1,
+	Two = 2
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+	None = 0,
+	Odd = 1,
+	Even = 2,
+	Mark = 3,
+	Space = 4
+}
+
+// settings of SET_LINE_CODING, cached to change them individually
+#[derive(Clone, Copy)]
+struct LineCoding {
+	baud_rate: u32,
+	data_bits: u8,
+	parity: Parity,
+	stop_bits: StopBits
+}
+
+impl LineCoding {
+	// the common default of devices not reporting their line coding
+	const DEFAULT: Self = Self {
+		baud_rate: 9600,
+		data_bits: 8,
+		parity: Parity::None,
+		stop_bits: StopBits::One
+	};
+
+	fn decode(data: &[u8; 7]) -> Self {
+		let stop_bits = match data[4] {
+			1 => StopBits::OnePointFive,
+			2 => StopBits::Two,
+			_ => StopBits::One
+		};
+		let parity = match data[5] {
+			1 => Parity::Odd,
+			2 => Parity::Even,
+			3 => Parity::Mark,
+			4 => Parity::Space,
+			_ => Parity::None
+		};
+		Self {
+			baud_rate: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+			data_bits: data[6],
+			parity,
+			stop_bits
+		}
+	}
+
+	fn encode(&self) -> [u8; 7] {
+		let mut data = [0u8; 7];
+		data[0..4].copy_from_slice(&self.baud_rate.to_le_bytes());
+		data[4] = self.stop_bits as u8;
+		data[5] = self.parity as u8;
+		data[6] = self.data_bits;
+		data
+	}
+}
+
+struct Shared {
+	comm: Interface,
+	comm_number: u8,
+	// keep claimed data interface alive for the lifetime of its queues
+	_data: Interface,
+	// single in-flight IN transfer and data that didn't fit the read buffer.
+	// readers take turns in read_queue before locking it.
+	bulk_in: Mutex<(Queue<RequestBuffer>, Vec<u8>)>,
+	read_queue: FairQueue,
+	bulk_out: Mutex<Queue<Vec<u8>>>,
+	control_lines: Mutex<u16>,
+	line_coding: Mutex<LineCoding>
+}
+
+// CDC-ACM device accessed directly via USB, bypassing the OS's TTY/COM port
+// driver. clones share all USB transfer queues.
+pub struct CdcAcmPort {
+	shared: Arc<Shared>,
+	timeout_read: AtomicTimeout,
+	timeout_write: AtomicTimeout
+}
+
+fn usb_error(error: TransferError) -> io::Error {
+	let kind = match error {
+		TransferError::Cancelled => io::ErrorKind::Interrupted,
+		TransferError::Stall => io::ErrorKind::BrokenPipe,
+		TransferError::Disconnected => io::ErrorKind::UnexpectedEof,
+		_ => io::ErrorKind::Other
+	};
+	io::Error::new(kind, error)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// block on a future, giving up after the timeout (if any). the future is
+// dropped on timeout, but transfers submitted to a queue remain pending.
+fn block_on_timeout<T, F>(fut: F, timeout: Option<Duration>) -> Option<T>
+		where F: future::Future<Output = T> {
+	match timeout {
+		None => Some(future::block_on(fut)),
+		Some(timeout) => future::block_on(future::or(
+			async { Some(fut.await) },
+			async {
+				async_io::Timer::after(timeout).await;
+				None
+			}
+		))
+	}
+}
+
+impl CdcAcmPort {
+	// open the first CDC-ACM device with the given vendor/product ID and
+	// (optionally) USB serial number
+	pub fn open(vendor_id: u16, product_id: u16, serial_number: Option<&str>,
+			timeout: Option<Duration>) -> io::Result<Self> {
+		let info = nusb::list_devices()?
+			.find(|info| info.vendor_id() == vendor_id
+				&& info.product_id() == product_id
+				&& (serial_number.is_none() || info.serial_number() == serial_number))
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+				"no matching USB device found"))?;
+		let device = info.open()?;
+
+		// locate communication and data interfaces and the data interface's
+		// bulk endpoints in the active configuration
+		let config = device.active_configuration()
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+		let mut comm_number = None;
+		let mut data = None;
+		for group in config.interfaces() {
+			for alt in group.alt_settings() {
+				if alt.class() == CDC_CLASS_COMM && comm_number.is_none() {
+					comm_number = Some(alt.interface_number());
+				} else if alt.class() == CDC_CLASS_DATA && data.is_none() {
+					let mut ep_in = None;
+					let mut ep_out = None;
+					for ep in alt.endpoints() {
+						if ep.transfer_type() != EndpointType::Bulk {
+							continue;
+						}
+						match ep.direction() {
+							Direction::In => ep_in = Some(ep.address()),
+							Direction::Out => ep_out = Some(ep.address())
+						}
+					}
+					if let (Some(ep_in), Some(ep_out)) = (ep_in, ep_out) {
+						data = Some((alt.interface_number(), ep_in, ep_out));
+					}
+				}
+			}
+		}
+		let (comm_number, (data_number, ep_in, ep_out)) = match (comm_number, data) {
+			(Some(comm), Some(data)) => (comm, data),
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+				"USB device is not a CDC-ACM device"))
+		};
+
+		// detach OS driver (if any) to gain exclusive access
+		let comm = device.detach_and_claim_interface(comm_number)?;
+		let data = device.detach_and_claim_interface(data_number)?;
+
+		// GET_LINE_CODING is optional, so assume the default if it fails
+		let mut line_coding = [0u8; 7];
+		let line_coding = match comm.control_in_blocking(Control {
+			control_type: ControlType::Class,
+			recipient: Recipient::Interface,
+			request: CDC_GET_LINE_CODING,
+			value: 0,
+			index: comm_number as u16
+		}, &mut line_coding, CONTROL_TIMEOUT) {
+			Ok(len) if len == line_coding.len() => LineCoding::decode(&line_coding),
+			_ => LineCoding::DEFAULT
+		};
+
+		let shared = Arc::new(Shared {
+			bulk_in: Mutex::new((data.bulk_in_queue(ep_in), Vec::new())),
+			read_queue: FairQueue::default(),
+			bulk_out: Mutex::new(data.bulk_out_queue(ep_out)),
+			comm,
+			comm_number,
+			_data: data,
+			control_lines: Mutex::new(0),
+			line_coding: Mutex::new(line_coding)
+		});
+
+		Ok(Self {
+			shared,
+			timeout_read: AtomicTimeout::new(timeout),
+			timeout_write: AtomicTimeout::new(timeout)
+		})
+	}
+
+	pub fn try_clone(&self) -> io::Result<Self> {
+		Ok(Self {
+			shared: self.shared.clone(),
+			timeout_read: AtomicTimeout::new(self.timeout_read.load()),
+			timeout_write: AtomicTimeout::new(self.timeout_write.load())
+		})
+	}
+
+	fn control_out(&self, request: u8, value: u16, data: &[u8]) -> io::Result<()> {
+		self.shared.comm.control_out_blocking(Control {
+			control_type: ControlType::Class,
+			recipient: Recipient::Interface,
+			request,
+			value,
+			index: self.shared.comm_number as u16
+		}, data, CONTROL_TIMEOUT).map(|_| ()).map_err(usb_error)
+	}
+
+	// configure baud rate and framing via SET_LINE_CODING
+	pub fn set_line_coding(&self, baud_rate: u32, data_bits: u8, parity: Parity,
+			stop_bits: StopBits) -> io::Result<()> {
+		self.update_line_coding(|line_coding| *line_coding = LineCoding {
+			baud_rate,
+			data_bits,
+			parity,
+			stop_bits
+		})
+	}
+
+	// send the line coding as changed by update and cache it if successful
+	fn update_line_coding<F: FnOnce(&mut LineCoding)>(&self, update: F) -> io::Result<()> {
+		let mut line_coding = lock(&self.shared.line_coding);
+		let mut updated = *line_coding;
+		update(&mut updated);
+		self.control_out(CDC_SET_LINE_CODING, 0, &updated.encode())?;
+		*line_coding = updated;
+		Ok(())
+	}
+
+	fn line_coding(&self) -> LineCoding {
+		*lock(&self.shared.line_coding)
+	}
+
+	// send break for the given duration (up to 65534 ms) via SEND_BREAK
+	pub fn send_break(&self, duration: Duration) -> io::Result<()> {
+		let duration_ms = duration.as_millis().min(0xFFFE) as u16;
+		self.control_out(CDC_SEND_BREAK, duration_ms, &[])
+	}
+
+	fn set_control_line(&self, line: u16, level: bool) -> io::Result<()> {
+		let mut control_lines = lock(&self.shared.control_lines);
+		let state = if level { *control_lines | line } else { *control_lines & !line };
+		self.control_out(CDC_SET_CONTROL_LINE_STATE, state, &[])?;
+		*control_lines = state;
+		Ok(())
+	}
+
+	pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		self.read_with_timeout(buf, self.timeout_read.load())
+	}
+
+	// like read(), but with the given timeout instead of the read timeout
+	pub fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		// waiting for the turn of this thread counts towards the timeout. the
+		// lock is not contended then, since only the front of the queue
+		// takes it.
+		let entry = clock::now();
+		let _turn = self.shared.read_queue.enter(timeout)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut,
+				"reading from USB device timed out"))?;
+		let mut guard = lock(&self.shared.bulk_in);
+		let (queue, pending) = &mut *guard;
+
+		// serve data left over from a previous transfer first. zero-length
+		// packets carry no data, so wait for the next transfer within the
+		// remaining timeout.
+		while pending.is_empty() {
+			// keep a single IN transfer in flight. it remains pending across
+			// timeouts, so no data is lost by cancelling it.
+			if queue.pending() == 0 {
+				queue.submit(RequestBuffer::new(BULK_IN_LEN));
+			}
+
+			let remaining = timeout.map(|timeout| timeout.saturating_sub(clock::elapsed(entry)));
+			let completion = match block_on_timeout(queue.next_complete(), remaining) {
+				Some(completion) => completion,
+				None => return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"reading from USB device timed out"))
+			};
+			completion.status.map_err(usb_error)?;
+			*pending = completion.data;
+		}
+
+		let len = pending.len().min(buf.len());
+		buf[..len].copy_from_slice(&pending[..len]);
+		pending.drain(..len);
+		Ok(len)
+	}
+
+	pub fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		self.write_with_timeout(buf, self.timeout_write.load())
+	}
+
+	// like write(), but with the given timeout instead of the write timeout
+	pub fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>)
+			-> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		let mut queue = lock(&self.shared.bulk_out);
+		queue.submit(buf.to_vec());
+
+		match block_on_timeout(queue.next_complete(), timeout) {
+			Some(completion) => {
+				completion.status.map_err(usb_error)?;
+				Ok(buf.len())
+			},
+			None => {
+				// cancel the transfer and wait for the cancellation to
+				// complete, so the queue does not report stale completions
+				queue.cancel_all();
+				let completion = future::block_on(queue.next_complete());
+				match completion.status {
+					// transfer raced to completion before being cancelled
+					Ok(()) => Ok(buf.len()),
+					Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut,
+						"writing to USB device timed out"))
+				}
+			}
+		}
+	}
+
+	// bulk OUT transfers complete once the device acknowledged the data
+	pub fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	pub fn into_serial_port(self) -> SerialPort {
+		SerialPort::from_backend(Box::new(self))
+	}
+}
+
+// open "usb://VID:PID" or "usb://VID:PID/SERIALNUMBER" backend with IDs
+// given in hexadecimal
+pub(crate) fn open_backend(path: &str, timeout: Option<Duration>)
+		-> io::Result<Box<dyn Backend>> {
+	let invalid = || io::Error::new(io::ErrorKind::InvalidInput,
+		"expected USB device path of form VID:PID[/SERIALNUMBER]");
+
+	let (ids, serial_number) = match path.split_once('/') {
+		Some((ids, serial_number)) => (ids, Some(serial_number)),
+		None => (path, None)
+	};
+	let (vid, pid) = ids.split_once(':').ok_or_else(invalid)?;
+	let vid = u16::from_str_radix(vid, 16).map_err(|_| invalid())?;
+	let pid = u16::from_str_radix(pid, 16).map_err(|_| invalid())?;
+
+	CdcAcmPort::open(vid, pid, serial_number, timeout)
+		.map(|port| Box::new(port) as Box<dyn Backend>)
+}
+
+impl Backend for CdcAcmPort {
+	fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		CdcAcmPort::read(self, buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		CdcAcmPort::write(self, buf)
+	}
+
+	fn read_with_timeout(&self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<usize> {
+		CdcAcmPort::read_with_timeout(self, buf, timeout)
+	}
+
+	fn write_with_timeout(&self, buf: &[u8], timeout: Option<Duration>) -> io::Result<usize> {
+		CdcAcmPort::write_with_timeout(self, buf, timeout)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		CdcAcmPort::flush(self)
+	}
+
+	fn try_clone(&self) -> io::Result<Box<dyn Backend>> {
+		CdcAcmPort::try_clone(self).map(|port| Box::new(port) as Box<dyn Backend>)
+	}
+
+	fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read.load()
+	}
+
+	fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write.load()
+	}
+
+	fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read.store(timeout);
+		Ok(())
+	}
+
+	fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write.store(timeout);
+		Ok(())
+	}
+
+	fn set_rts(&self, level: bool) -> io::Result<()> {
+		self.set_control_line(CDC_CONTROL_RTS, level)
+	}
+
+	fn set_dtr(&self, level: bool) -> io::Result<()> {
+		self.set_control_line(CDC_CONTROL_DTR, level)
+	}
+
+	fn baud_rate(&self) -> io::Result<u32> {
+		Ok(self.line_coding().baud_rate)
+	}
+
+	fn set_baud_rate(&self, baud_rate: u32) -> io::Result<()> {
+		self.update_line_coding(|line_coding| line_coding.baud_rate = baud_rate)
+	}
+
+	fn data_bits(&self) -> io::Result<DataBits> {
+		match self.line_coding().data_bits {
+			5 => Ok(DataBits::Five),
+			6 => Ok(DataBits::Six),
+			7 => Ok(DataBits::Seven),
+			8 => Ok(DataBits::Eight),
+			_ => Err(io::Error::new(io::ErrorKind::InvalidData,
+				"USB device uses unsupported number of data bits"))
+		}
+	}
+
+	fn set_data_bits(&self, data_bits: DataBits) -> io::Result<()> {
+		let data_bits = match data_bits {
+			DataBits::Five => 5,
+			DataBits::Six => 6,
+			DataBits::Seven => 7,
+			DataBits::Eight => 8
+		};
+		self.update_line_coding(|line_coding| line_coding.data_bits = data_bits)
+	}
+
+	fn parity(&self) -> io::Result<crate::Parity> {
+		Ok(match self.line_coding().parity {
+			Parity::None => crate::Parity::None,
+			Parity::Odd => crate::Parity::Odd,
+			Parity::Even => crate::Parity::Even,
+			Parity::Mark => crate::Parity::Mark,
+			Parity::Space => crate::Parity::Space
+		})
+	}
+
+	fn set_parity(&self, parity: crate::Parity) -> io::Result<()> {
+		let parity = match parity {
+			crate::Parity::None => Parity::None,
+			crate::Parity::Odd => Parity::Odd,
+			crate::Parity::Even => Parity::Even,
+			crate::Parity::Mark => Parity::Mark,
+			crate::Parity::Space => Parity::Space
+		};
+		self.update_line_coding(|line_coding| line_coding.parity = parity)
+	}
+
+	fn stop_bits(&self) -> io::Result<crate::StopBits> {
+		match self.line_coding().stop_bits {
+			StopBits::One => Ok(crate::StopBits::One),
+			StopBits::Two => Ok(crate::StopBits::Two),
+			StopBits::OnePointFive => Err(io::Error::new(io::ErrorKind::InvalidData,
+				"USB device uses 1.5 stop bits"))
+		}
+	}
+
+	fn set_stop_bits(&self, stop_bits: crate::StopBits) -> io::Result<()> {
+		let stop_bits = match stop_bits {
+			crate::StopBits::One => StopBits::One,
+			crate::StopBits::Two => StopBits::Two
+		};
+		self.update_line_coding(|line_coding| line_coding.stop_bits = stop_bits)
+	}
+
+	fn set_break(&self, level: bool) -> io::Result<()> {
+		self.control_out(CDC_SEND_BREAK, if level { CDC_BREAK_ON } else { 0 }, &[])
+	}
+
+	// modem status inputs are reported via the interrupt endpoint's
+	// SERIAL_STATE notification, which is not evaluated yet, so reading
+	// them falls back to the Unsupported default
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn line_coding_round_trip() {
+		// 115200 Bd 7E2
+		let data = [0x00, 0xc2, 0x01, 0x00, 2, 2, 7];
+		let line_coding = LineCoding::decode(&data);
+		assert_eq!(line_coding.baud_rate, 115_200);
+		assert_eq!(line_coding.data_bits, 7);
+		assert_eq!(line_coding.parity, Parity::Even);
+		assert_eq!(line_coding.stop_bits, StopBits::Two);
+		assert_eq!(line_coding.encode(), data);
+
+		assert_eq!(LineCoding::DEFAULT.encode(), [0x80, 0x25, 0, 0, 0, 0, 8]);
+	}
+
+	#[test]
+	fn block_with_timeout() {
+		assert_eq!(block_on_timeout(future::ready(1), None), Some(1));
+		assert_eq!(block_on_timeout(future::ready(1), Some(Duration::ZERO)), Some(1));
+		let pending = future::pending::<()>();
+		assert_eq!(block_on_timeout(pending, Some(Duration::from_millis(10))), None);
+	}
+}
@@ -0,0 +1,348 @@
+// serial ports in web browsers via the Web Serial API, see:
+// https://developer.mozilla.org/en-US/docs/Web/API/Web_Serial_API
+//
+// browsers forbid blocking the JavaScript event loop, so all I/O is async
+// instead of implementing io::Read/io::Write. web-sys only exposes the Web
+// Serial API with RUSTFLAGS="--cfg=web_sys_unstable_apis".
+
+extern crate futures_lite;
+extern crate js_sys;
+extern crate wasm_bindgen;
+extern crate wasm_bindgen_futures;
+extern crate web_sys;
+
+use std::cell::RefCell;
+use std::io;
+use std::time::Duration;
+
+use self::futures_lite::future;
+use self::js_sys::{Function, Promise, Reflect, Uint8Array};
+use self::wasm_bindgen::{JsCast, JsValue};
+use self::wasm_bindgen::convert::FromWasmAbi;
+use self::wasm_bindgen_futures::JsFuture;
+use self::web_sys::{FlowControlType, ParityType, ReadableStreamDefaultReader,
+	Serial, SerialInputSignals, SerialOptions, SerialOutputSignals, SerialPortFilter,
+	SerialPortRequestOptions, WritableStreamDefaultWriter};
+
+use crate::{DataBits, FlowControl, Parity, StopBits};
+
+// map DOMExceptions (see Web Serial spec) to I/O errors
+fn js_error(value: JsValue) -> io::Error {
+	let name = Reflect::get(&value, &"name".into()).ok()
+		.and_then(|name| name.as_string())
+		.unwrap_or_default();
+	let kind = match name.as_str() {
+		"NotFoundError" => io::ErrorKind::NotFound,
+		"SecurityError" => io::ErrorKind::PermissionDenied,
+		"InvalidStateError" => io::ErrorKind::AddrInUse,
+		"NetworkError" => io::ErrorKind::BrokenPipe,
+		"TypeError" => io::ErrorKind::InvalidInput,
+		// BreakError, FramingError, ParityError, BufferOverrunError
+		_ => io::ErrorKind::Other
+	};
+	let msg = Reflect::get(&value, &"message".into()).ok()
+		.and_then(|msg| msg.as_string())
+		.unwrap_or_else(|| format!("{:?}", value));
+	io::Error::new(kind, msg)
+}
+
+async fn await_promise<T>(promise: Promise<T>) -> io::Result<T>
+		where T: FromWasmAbi + 'static {
+	JsFuture::from(promise).await.map_err(js_error)
+}
+
+fn get(object: &JsValue, key: &str) -> io::Result<JsValue> {
+	Reflect::get(object, &key.into()).map_err(js_error)
+}
+
+// navigator.serial of the window or worker, which is undefined if the browser
+// does not support Web Serial or the page is not a secure context
+fn serial() -> io::Result<Serial> {
+	let navigator = get(&js_sys::global(), "navigator")?;
+	let serial = get(&navigator, "serial")?;
+	if serial.is_undefined() {
+		return Err(io::Error::new(io::ErrorKind::Unsupported,
+			"Web Serial API is not available"));
+	}
+	Ok(serial.unchecked_into())
+}
+
+// resolves after the given duration via setTimeout(), which is available in
+// windows and workers alike
+async fn sleep(dur: Duration) {
+	let ms = dur.as_millis().min(i32::MAX as u128) as i32;
+	let promise = Promise::new(&mut |resolve, _reject| {
+		let set_timeout = get(&js_sys::global(), "setTimeout")
+			.map(|f| f.unchecked_into::<Function>());
+		if let Ok(set_timeout) = set_timeout {
+			let _res = set_timeout.call2(&JsValue::NULL, &resolve, &ms.into());
+		}
+	});
+	let _res = JsFuture::from(promise).await;
+}
+
+pub struct WebSerialPort {
+	port: web_sys::SerialPort,
+	// stream readers are replaced after read errors (e.g., parity errors),
+	// which close the port's current readable stream
+	reader: RefCell<Option<ReadableStreamDefaultReader>>,
+	writer: WritableStreamDefaultWriter,
+	// read() in progress across timeouts and data that didn't fit the buffer
+	read_pending: RefCell<Option<JsFuture>>,
+	rx_buf: RefCell<Vec<u8>>,
+	timeout_read: Option<Duration>,
+	timeout_write: Option<Duration>
+}
+
+impl WebSerialPort {
+	// let the user choose a port, optionally restricted to the given USB
+	// vendor IDs and (optional) product IDs. browsers only allow this in
+	// response to user activation, e.g., a click event handler.
+	pub async fn request(filters: &[(u16, Option<u16>)]) -> io::Result<web_sys::SerialPort> {
+		let serial = serial()?;
+		let promise = if filters.is_empty() {
+			serial.request_port()
+		} else {
+			let filters: Vec<SerialPortFilter> = filters.iter()
+				.map(|&(vendor_id, product_id)| {
+					let filter = SerialPortFilter::new();
+					filter.set_usb_vendor_id(vendor_id);
+					if let Some(product_id) = product_id {
+						filter.set_usb_product_id(product_id);
+					}
+					filter
+				})
+				.collect();
+			let options = SerialPortRequestOptions::new();
+			options.set_filters(&filters);
+			serial.request_port_with_options(&options)
+		};
+		await_promise(promise).await
+	}
+
+	// ports the user previously granted access to this origin
+	pub async fn list_devices() -> io::Result<Vec<web_sys::SerialPort>> {
+		let ports = await_promise(serial()?.get_ports()).await?;
+		Ok(ports.iter().collect())
+	}
+
+	// open a port obtained via request() or list_devices(). Web Serial
+	// applies line settings on open only and supports 7 or 8 data bits,
+	// no/odd/even parity, and no/hardware flow control.
+	pub async fn open(port: web_sys::SerialPort, baud_rate: u32, data_bits: DataBits,
+			parity: Parity, stop_bits: StopBits, flow_control: FlowControl,
+			timeout: Option<Duration>) -> io::Result<Self> {
+		let unsupported = |what| io::Error::new(io::ErrorKind::InvalidInput,
+			format!("{} not supported by Web Serial", what));
+
+		let options = SerialOptions::new(baud_rate);
+		options.set_data_bits(match data_bits {
+			DataBits::Seven => 7,
+			DataBits::Eight => 8,
+			_ => return Err(unsupported("data bits"))
+		});
+		options.set_parity(match parity {
+			Parity::None => ParityType::None,
+			Parity::Odd => ParityType::Odd,
+			Parity::Even => ParityType::Even,
+			_ => return Err(unsupported("parity"))
+		});
+		options.set_stop_bits(match stop_bits {
+			StopBits::One => 1,
+			StopBits::Two => 2
+		});
+		options.set_flow_control(match flow_control {
+			FlowControl::None => FlowControlType::None,
+			FlowControl::Hardware => FlowControlType::Hardware,
+			FlowControl::Software => return Err(unsupported("flow control"))
+		});
+		await_promise(port.open(&options)).await?;
+
+		let reader = ReadableStreamDefaultReader::new(&port.readable()).map_err(js_error)?;
+		let writer = WritableStreamDefaultWriter::new(&port.writable()).map_err(js_error)?;
+		Ok(Self {
+			port,
+			reader: RefCell::new(Some(reader)),
+			writer,
+			read_pending: RefCell::new(None),
+			rx_buf: RefCell::new(Vec::new()),
+			timeout_read: timeout,
+			timeout_write: timeout
+		})
+	}
+
+	// release stream locks and close the port
+	pub async fn close(self) -> io::Result<()> {
+		let reader = self.reader.borrow_mut().take();
+		if let Some(reader) = reader {
+			let _res = await_promise(reader.cancel()).await;
+			reader.release_lock();
+		}
+		let _res = await_promise(self.writer.close()).await;
+		self.writer.release_lock();
+		await_promise(self.port.close()).await.map(|_| ())
+	}
+
+	pub fn port(&self) -> &web_sys::SerialPort {
+		&self.port
+	}
+
+	pub fn read_timeout(&self) -> Option<Duration> {
+		self.timeout_read
+	}
+
+	pub fn write_timeout(&self) -> Option<Duration> {
+		self.timeout_write
+	}
+
+	pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_read = timeout;
+		Ok(())
+	}
+
+	pub fn set_write_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+		self.timeout_write = timeout;
+		Ok(())
+	}
+
+	pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		// serve data left over from a previous chunk first
+		if self.rx_buf.borrow().is_empty() {
+			let chunk = self.read_chunk().await?;
+			*self.rx_buf.borrow_mut() = chunk;
+		}
+
+		let mut rx_buf = self.rx_buf.borrow_mut();
+		let len = rx_buf.len().min(buf.len());
+		buf[..len].copy_from_slice(&rx_buf[..len]);
+		rx_buf.drain(..len);
+		Ok(len)
+	}
+
+	async fn read_chunk(&self) -> io::Result<Vec<u8>> {
+		// keep a single read() in flight. it remains pending across timeouts,
+		// so no data is lost by abandoning it.
+		let mut pending = match self.read_pending.borrow_mut().take() {
+			Some(pending) => pending,
+			None => {
+				let mut reader = self.reader.borrow_mut();
+				if reader.is_none() {
+					*reader = Some(ReadableStreamDefaultReader::new(&self.port.readable())
+						.map_err(js_error)?);
+				}
+				JsFuture::from(reader.as_ref().unwrap().read())
+			}
+		};
+
+		let result = match self.timeout_read {
+			None => Some((&mut pending).await),
+			Some(timeout) => future::or(
+				async { Some((&mut pending).await) },
+				async {
+					sleep(timeout).await;
+					None
+				}
+			).await
+		};
+
+		let result = match result {
+			Some(Ok(result)) => result,
+			Some(Err(e)) => {
+				// the stream errored, so obtain a new reader next time
+				self.reader.borrow_mut().take();
+				return Err(js_error(e));
+			},
+			None => {
+				*self.read_pending.borrow_mut() = Some(pending);
+				return Err(io::Error::new(io::ErrorKind::TimedOut,
+					"reading from Web Serial port timed out"));
+			}
+		};
+
+		if get(&result, "done")?.as_bool().unwrap_or(false) {
+			self.reader.borrow_mut().take();
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+				"Web Serial port was closed"));
+		}
+		Ok(Uint8Array::new(&get(&result, "value")?).to_vec())
+	}
+
+	pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		let chunk = Uint8Array::from(buf);
+		let write = JsFuture::from(self.writer.write_with_chunk(&chunk));
+		let result = match self.timeout_write {
+			None => Some(write.await),
+			Some(timeout) => future::or(
+				async { Some(write.await) },
+				async {
+					sleep(timeout).await;
+					None
+				}
+			).await
+		};
+
+		// chunks are queued by the stream, so a timed out write may still
+		// complete later
+		match result {
+			Some(Ok(_)) => Ok(buf.len()),
+			Some(Err(e)) => Err(js_error(e)),
+			None => Err(io::Error::new(io::ErrorKind::TimedOut,
+				"writing to Web Serial port timed out"))
+		}
+	}
+
+	// wait until all queued chunks were passed to the device
+	pub async fn flush(&self) -> io::Result<()> {
+		await_promise(self.writer.ready()).await.map(|_| ())
+	}
+
+	async fn set_signals(&self, signals: &SerialOutputSignals) -> io::Result<()> {
+		await_promise(self.port.set_signals_with_signals(signals)).await.map(|_| ())
+	}
+
+	pub async fn set_rts(&self, level: bool) -> io::Result<()> {
+		let signals = SerialOutputSignals::new();
+		signals.set_request_to_send(level);
+		self.set_signals(&signals).await
+	}
+
+	pub async fn set_dtr(&self, level: bool) -> io::Result<()> {
+		let signals = SerialOutputSignals::new();
+		signals.set_data_terminal_ready(level);
+		self.set_signals(&signals).await
+	}
+
+	pub async fn set_break(&self, level: bool) -> io::Result<()> {
+		let signals = SerialOutputSignals::new();
+		signals.set_break(level);
+		self.set_signals(&signals).await
+	}
+
+	async fn input_signals(&self) -> io::Result<SerialInputSignals> {
+		await_promise(self.port.get_signals()).await
+	}
+
+	pub async fn read_cts(&self) -> io::Result<bool> {
+		self.input_signals().await.map(|s| s.get_clear_to_send())
+	}
+
+	pub async fn read_dsr(&self) -> io::Result<bool> {
+		self.input_signals().await.map(|s| s.get_data_set_ready())
+	}
+
+	pub async fn read_ri(&self) -> io::Result<bool> {
+		self.input_signals().await.map(|s| s.get_ring_indicator())
+	}
+
+	pub async fn read_cd(&self) -> io::Result<bool> {
+		self.input_signals().await.map(|s| s.get_data_carrier_detect())
+	}
+}